@@ -1,6 +1,7 @@
 NETWORK="local"
 CREATE_MINTER=false
 CREATE_LEDGER=false
+MOCK_RPC=false
 OWNER_PRINCIPAL_NAME="p5mpn-lgd4x-logst-tftlf-ocwbj-b53ah-5mgue-b665z-lv63u-mmvdj-iqe"
 
 while [[ "$#" -gt 0 ]]; do
@@ -27,6 +28,9 @@ while [[ "$#" -gt 0 ]]; do
         --ledger)
             CREATE_LEDGER=true
             ;;
+        --mock-rpc)
+            MOCK_RPC=true
+            ;;
         *) 
             echo "Unknown option: $1"
             exit 1
@@ -99,7 +103,6 @@ if $CREATE_MINTER; then
     "
     --network="$NETWORK"
     --identity="$OWNER_PRINCIPAL_NAME"
-    --upgrade-unchanged
   )
 
   # Add --mode argument if --reinstall flag is present
@@ -108,5 +111,11 @@ if $CREATE_MINTER; then
   fi
 
   # Deploy minter canister
-  dfx deploy "${MINTER_ARGUMENTS[@]}" 
+  if $MOCK_RPC; then
+    # Solana RPC calls are answered from canned fixtures, see src/minter/src/sol_rpc_client/mock.rs
+    cargo build --target wasm32-unknown-unknown --release -p minter --features mock-rpc
+    dfx canister install "${MINTER_ARGUMENTS[@]}" --wasm target/wasm32-unknown-unknown/release/minter.wasm
+  else
+    dfx deploy "${MINTER_ARGUMENTS[@]}" --upgrade-unchanged
+  fi
 fi
\ No newline at end of file
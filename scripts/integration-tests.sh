@@ -0,0 +1,15 @@
+#!/usr/bin/env bash
+# Builds the minter and the mock canisters, then runs the PocketIC integration tests.
+# Requires the PocketIC server, whose path is read from POCKET_IC_BIN:
+# https://github.com/dfinity/pocketic
+
+set -e
+
+cargo build --target wasm32-unknown-unknown --release \
+    --package minter \
+    --package mock_ledger \
+    --package mock_btown
+
+export POCKET_IC_BIN="${POCKET_IC_BIN:-$(pwd)/pocket-ic}"
+
+cargo test --package integration_tests "$@"
@@ -3,8 +3,6 @@
 # deploy minter canister
 dfx deploy minter --upgrade-unchanged --argument "
   (variant {
-    Upgrade = record {
-      solana_rpc_url = opt \"\";
-    }
+    Upgrade = record {}
   })
 " --yes --identity="$OWNER_PRINCIPAL_NAME"
@@ -0,0 +1,285 @@
+//! PocketIC harness deploying the minter together with a mock ledger, a mock BTown
+//! NFT canister and a stub Solana JSON-RPC answering its HTTPS outcalls.
+//!
+//! The canisters are loaded from the wasm files built by `scripts/integration-tests.sh`,
+//! their paths can be overridden with the `MINTER_WASM_PATH`, `MOCK_LEDGER_WASM_PATH`
+//! and `MOCK_BTOWN_WASM_PATH` environment variables.
+
+pub mod solana;
+
+use candid::utils::ArgumentEncoder;
+use candid::{
+    decode_one, encode_args, encode_one, CandidType, Deserialize, IDLValue, Nat, Principal,
+};
+use pocket_ic::common::rest::{CanisterHttpReply, CanisterHttpResponse, MockCanisterHttpResponse};
+use pocket_ic::{PocketIc, PocketIcBuilder, UserError, WasmResult};
+use serde_bytes::ByteBuf;
+use serde_json::Value;
+use std::time::Duration;
+
+pub use solana::MockSolana;
+
+/// Principal of the BTown NFT canister for minters built for a local network.
+pub const BTOWN_CANISTER_LOCAL: Principal =
+    Principal::from_slice(&[128, 0, 0, 0, 0, 16, 0, 12, 1, 1]);
+
+pub const MINIMUM_WITHDRAWAL_AMOUNT: u64 = 20_000_000;
+
+const INITIAL_CYCLES: u128 = 100_000_000_000_000;
+const MAX_TICKS: usize = 100;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct InitArg {
+    pub solana_rpc_url: String,
+    pub solana_contract_address: String,
+    pub solana_initial_signature: String,
+    pub ecdsa_key_name: String,
+    pub minimum_withdrawal_amount: Nat,
+    pub ledger_id: Option<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum MinterArg {
+    Init(InitArg),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Coupon {
+    pub message: String,
+    pub message_hash: String,
+    pub signature_hex: String,
+    pub icp_public_key_hex: String,
+    pub recovery_id: Option<u8>,
+}
+
+/// Subset of the fields of the minter's `MinterState`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MinterState {
+    pub invalid_events: u64,
+    pub minted_events: u64,
+    pub accepted_events: u64,
+}
+
+pub struct MinterSetup {
+    pub env: PocketIc,
+    pub minter: Principal,
+    pub ledger: Principal,
+    pub btown: Principal,
+    pub controller: Principal,
+    pub solana: MockSolana,
+}
+
+impl Default for MinterSetup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MinterSetup {
+    pub fn new() -> Self {
+        // the fiduciary subnet holds the threshold ECDSA test keys
+        let env = PocketIcBuilder::new()
+            .with_application_subnet()
+            .with_fiduciary_subnet()
+            .build();
+        let controller = Principal::from_slice(&[1; 29]);
+
+        let ledger = env.create_canister_with_settings(Some(controller), None);
+        env.add_cycles(ledger, INITIAL_CYCLES);
+        env.install_canister(
+            ledger,
+            wasm("MOCK_LEDGER_WASM_PATH", "mock_ledger"),
+            encode_args(()).unwrap(),
+            Some(controller),
+        );
+
+        let btown = env
+            .create_canister_with_id(Some(controller), None, BTOWN_CANISTER_LOCAL)
+            .expect("the BTown canister id should be available");
+        env.add_cycles(btown, INITIAL_CYCLES);
+        env.install_canister(
+            btown,
+            wasm("MOCK_BTOWN_WASM_PATH", "mock_btown"),
+            encode_args(()).unwrap(),
+            Some(controller),
+        );
+
+        let solana = MockSolana::default();
+        let minter = env.create_canister_with_settings(Some(controller), None);
+        env.add_cycles(minter, INITIAL_CYCLES);
+        let init_arg = MinterArg::Init(InitArg {
+            solana_rpc_url: "https://api.devnet.solana.com".to_string(),
+            solana_contract_address: solana::CONTRACT_ADDRESS.to_string(),
+            solana_initial_signature: solana.initial_signature(),
+            ecdsa_key_name: "dfx_test_key".to_string(),
+            minimum_withdrawal_amount: Nat::from(MINIMUM_WITHDRAWAL_AMOUNT),
+            ledger_id: Some(ledger),
+        });
+        env.install_canister(
+            minter,
+            wasm("MINTER_WASM_PATH", "minter"),
+            encode_one(init_arg).unwrap(),
+            Some(controller),
+        );
+
+        let setup = Self {
+            env,
+            minter,
+            ledger,
+            btown,
+            controller,
+            solana,
+        };
+        // let the timers set up on install fetch the ECDSA key and scan for deposits
+        setup.tick();
+        setup
+    }
+
+    /// Executes a round, then answers the HTTPS outcalls it issued.
+    pub fn tick(&self) {
+        self.env.advance_time(Duration::from_secs(1));
+        self.env.tick();
+        self.serve_http_outcalls();
+    }
+
+    /// Executes rounds until `done` holds.
+    pub fn run_until(&self, what: &str, done: impl Fn(&Self) -> bool) {
+        for _ in 0..MAX_TICKS {
+            if done(self) {
+                return;
+            }
+            self.tick();
+        }
+        panic!("timed out waiting for {what}");
+    }
+
+    fn serve_http_outcalls(&self) {
+        for request in self.env.get_canister_http() {
+            let body: Value = serde_json::from_slice(&request.body)
+                .expect("the minter should send JSON-RPC requests");
+            let reply = serde_json::to_vec(&self.solana.respond(&body)).unwrap();
+
+            self.env
+                .mock_canister_http_response(MockCanisterHttpResponse {
+                    subnet_id: request.subnet_id,
+                    request_id: request.request_id,
+                    response: CanisterHttpResponse::CanisterHttpReply(CanisterHttpReply {
+                        status: 200,
+                        headers: vec![],
+                        body: reply,
+                    }),
+                    additional_responses: vec![],
+                });
+        }
+    }
+
+    /// Scans for new deposits and mints them.
+    pub fn trigger_check(&self) -> Result<(), String> {
+        self.update(self.minter, self.controller, "trigger_check", ())
+    }
+
+    pub fn withdraw(
+        &self,
+        caller: Principal,
+        solana_address: &str,
+        amount: u64,
+        burn_block_index: u64,
+    ) -> Result<Coupon, IDLValue> {
+        self.update(
+            self.minter,
+            caller,
+            "withdraw",
+            (
+                solana_address.to_string(),
+                Nat::from(amount),
+                None::<String>,
+                Some(burn_block_index),
+            ),
+        )
+    }
+
+    pub fn verify(&self, coupon: &Coupon) -> Result<bool, IDLValue> {
+        self.query(self.minter, self.controller, "verify", (coupon.clone(),))
+    }
+
+    pub fn minter_state(&self) -> MinterState {
+        self.query(self.minter, self.controller, "get_minter_state", ())
+    }
+
+    /// Burns `amount` gSOL of `from` on the mock ledger for a withdrawal to
+    /// `solana_address`, returns the index of the burn block.
+    pub fn burn(&self, from: Principal, amount: u64, solana_address: &str) -> u64 {
+        let memo = bs58::decode(solana_address)
+            .into_vec()
+            .expect("the destination should be a base58 address");
+        self.update(
+            self.ledger,
+            self.controller,
+            "burn",
+            (from, Nat::from(amount), ByteBuf::from(memo)),
+        )
+    }
+
+    /// Signatures of the deposits received by the mock BTown canister.
+    pub fn minted_deposits(&self) -> Vec<String> {
+        self.query(self.btown, self.controller, "get_minted_deposits", ())
+    }
+
+    pub fn update<T>(
+        &self,
+        canister: Principal,
+        sender: Principal,
+        method: &str,
+        args: impl ArgumentEncoder,
+    ) -> T
+    where
+        T: CandidType + for<'de> Deserialize<'de>,
+    {
+        let payload = encode_args(args).unwrap();
+        decode_reply(
+            method,
+            self.env.update_call(canister, sender, method, payload),
+        )
+    }
+
+    pub fn query<T>(
+        &self,
+        canister: Principal,
+        sender: Principal,
+        method: &str,
+        args: impl ArgumentEncoder,
+    ) -> T
+    where
+        T: CandidType + for<'de> Deserialize<'de>,
+    {
+        let payload = encode_args(args).unwrap();
+        decode_reply(
+            method,
+            self.env.query_call(canister, sender, method, payload),
+        )
+    }
+}
+
+fn decode_reply<T>(method: &str, result: Result<WasmResult, UserError>) -> T
+where
+    T: CandidType + for<'de> Deserialize<'de>,
+{
+    match result {
+        Ok(WasmResult::Reply(bytes)) => decode_one(&bytes)
+            .unwrap_or_else(|e| panic!("failed to decode the reply of {method}: {e}")),
+        Ok(WasmResult::Reject(msg)) => panic!("{method} was rejected: {msg}"),
+        Err(e) => panic!("{method} failed: {e}"),
+    }
+}
+
+fn wasm(env_var: &str, name: &str) -> Vec<u8> {
+    let path = std::env::var(env_var).unwrap_or_else(|_| {
+        format!(
+            "{}/../../target/wasm32-unknown-unknown/release/{name}.wasm",
+            env!("CARGO_MANIFEST_DIR")
+        )
+    });
+    std::fs::read(&path).unwrap_or_else(|e| {
+        panic!("failed to read the {name} wasm at {path}: {e}, run scripts/integration-tests.sh")
+    })
+}
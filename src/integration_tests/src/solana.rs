@@ -0,0 +1,145 @@
+//! Stub of the Solana JSON-RPC answering the HTTPS outcalls of the minter.
+
+use base64::prelude::*;
+use candid::Principal;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+pub const CONTRACT_ADDRESS: &str = "AyaWMjCctfJoqzo7ppPhYzuBHcvoHPzo3Np4rhtMdTWi";
+
+const BLOCK_TIME: u64 = 1_700_000_000;
+
+/// Transactions of the bridge contract, answered in the format of a Solana RPC node.
+pub struct MockSolana {
+    // newest first, the last one is the initial signature of the minter
+    signatures: Vec<String>,
+    transactions: HashMap<String, Value>,
+}
+
+impl Default for MockSolana {
+    fn default() -> Self {
+        Self {
+            signatures: vec![signature(0)],
+            transactions: HashMap::new(),
+        }
+    }
+}
+
+impl MockSolana {
+    pub fn initial_signature(&self) -> String {
+        self.signatures
+            .last()
+            .expect("there is an initial signature")
+            .clone()
+    }
+
+    /// Records a deposit of `amount` lamports from `from` to `to`, returns its signature.
+    pub fn add_deposit(&mut self, from: &str, to: Principal, amount: u64) -> String {
+        self.add_transaction(
+            from,
+            vec![
+                format!("Program {CONTRACT_ADDRESS} invoke [1]"),
+                "Program log: Instruction: Deposit".to_string(),
+                format!("Program data: {}", deposit_data(to, amount)),
+                format!("Program {CONTRACT_ADDRESS} success"),
+            ],
+        )
+    }
+
+    /// Records a transaction of the contract with the given log messages, returns its signature.
+    pub fn add_transaction(&mut self, from: &str, log_messages: Vec<String>) -> String {
+        let sig = signature(self.signatures.len() as u8);
+        let slot = self.signatures.len() as u64;
+        let transaction = json!({
+            "blockTime": BLOCK_TIME + slot,
+            "slot": slot,
+            "meta": {
+                "err": null,
+                "logMessages": log_messages,
+            },
+            "transaction": {
+                "message": { "accountKeys": [from, CONTRACT_ADDRESS] },
+                "signatures": [sig],
+            },
+        });
+
+        self.signatures.insert(0, sig.clone());
+        self.transactions.insert(sig.clone(), transaction);
+        sig
+    }
+
+    /// Answers a single or batched JSON-RPC request.
+    pub fn respond(&self, request: &Value) -> Value {
+        match request {
+            Value::Array(requests) => requests.iter().map(|r| self.respond_one(r)).collect(),
+            request => self.respond_one(request),
+        }
+    }
+
+    fn respond_one(&self, request: &Value) -> Value {
+        let id = request["id"].clone();
+        let params = &request["params"];
+        let result = match request["method"].as_str() {
+            Some("getSignaturesForAddress") => self.get_signatures_for_address(&params[1]),
+            Some("getTransaction") => params[0]
+                .as_str()
+                .and_then(|sig| self.transactions.get(sig))
+                .cloned()
+                .unwrap_or(Value::Null),
+            Some("getBalance") => json!({ "context": { "slot": 0 }, "value": 0 }),
+            method => {
+                return json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32601, "message": format!("Method not found: {method:?}") },
+                })
+            }
+        };
+
+        json!({ "jsonrpc": "2.0", "id": id, "result": result })
+    }
+
+    // signatures are returned newest first, `before` and `until` are exclusive
+    fn get_signatures_for_address(&self, options: &Value) -> Value {
+        let position = |key: &str| {
+            options[key]
+                .as_str()
+                .and_then(|sig| self.signatures.iter().position(|s| s == sig))
+        };
+        let start = position("before").map_or(0, |index| index + 1);
+        let end = position("until")
+            .unwrap_or(self.signatures.len())
+            .max(start);
+        let limit = options["limit"].as_u64().unwrap_or(1_000) as usize;
+
+        self.signatures[start..end]
+            .iter()
+            .take(limit)
+            .map(|sig| {
+                json!({
+                    "blockTime": self.transactions.get(sig).map_or(BLOCK_TIME, |tx| tx["blockTime"].as_u64().unwrap()),
+                    "confirmationStatus": "finalized",
+                    "err": null,
+                    "memo": null,
+                    "signature": sig,
+                    "slot": 0,
+                })
+            })
+            .collect()
+    }
+}
+
+fn signature(index: u8) -> String {
+    bs58::encode([index + 1; 64]).into_string()
+}
+
+// Program data of the Deposit event of the contract: an 8 byte discriminator, the
+// length prefixed principal text and the little endian amount.
+fn deposit_data(to: Principal, amount: u64) -> String {
+    let principal = to.to_text();
+    let mut data = vec![0u8; 8];
+    data.extend((principal.len() as u32).to_le_bytes());
+    data.extend(principal.as_bytes());
+    data.extend(amount.to_le_bytes());
+    BASE64_STANDARD.encode(data)
+}
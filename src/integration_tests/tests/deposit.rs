@@ -0,0 +1,41 @@
+use candid::Principal;
+use integration_tests::MinterSetup;
+
+const DEPOSITOR: &str = "HS6NTv6GBVSLct8dsimRWRvjczJTAgfgDJt8VpR8wtGm";
+
+#[test]
+fn should_mint_deposit_found_on_solana() {
+    let mut setup = MinterSetup::new();
+    let user = Principal::from_slice(&[2; 29]);
+
+    let sig = setup.solana.add_deposit(DEPOSITOR, user, 1_000_000_000);
+    setup
+        .trigger_check()
+        .expect("the first check should be accepted");
+
+    setup.run_until("the deposit to be minted", |s| {
+        s.minted_deposits().contains(&sig)
+    });
+    setup.run_until("the mint to be recorded", |s| {
+        s.minter_state().minted_events == 1
+    });
+    assert_eq!(setup.minter_state().accepted_events, 0);
+}
+
+#[test]
+fn should_record_contract_transaction_without_deposit_as_invalid() {
+    let mut setup = MinterSetup::new();
+
+    setup.solana.add_transaction(
+        DEPOSITOR,
+        vec!["Program log: Instruction: Initialize".to_string()],
+    );
+    setup
+        .trigger_check()
+        .expect("the first check should be accepted");
+
+    setup.run_until("the transaction to be rejected", |s| {
+        s.minter_state().invalid_events == 1
+    });
+    assert!(setup.minted_deposits().is_empty());
+}
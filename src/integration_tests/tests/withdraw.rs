@@ -0,0 +1,38 @@
+use integration_tests::{MinterSetup, MINIMUM_WITHDRAWAL_AMOUNT};
+
+const DESTINATION: &str = "HS6NTv6GBVSLct8dsimRWRvjczJTAgfgDJt8VpR8wtGm";
+const AMOUNT: u64 = 5 * MINIMUM_WITHDRAWAL_AMOUNT;
+
+#[test]
+fn should_issue_verifiable_coupon_for_burn() {
+    let setup = MinterSetup::new();
+
+    let block_index = setup.burn(setup.controller, AMOUNT, DESTINATION);
+    let coupon = setup
+        .withdraw(setup.controller, DESTINATION, AMOUNT, block_index)
+        .expect("the withdrawal should succeed");
+
+    assert_eq!(setup.verify(&coupon), Ok(true));
+}
+
+#[test]
+fn should_reject_withdrawal_of_unknown_burn() {
+    let setup = MinterSetup::new();
+
+    let result = setup.withdraw(setup.controller, DESTINATION, AMOUNT, 42);
+
+    assert!(result.is_err(), "unexpected coupon: {result:?}");
+}
+
+#[test]
+fn should_reject_second_withdrawal_of_the_same_burn() {
+    let setup = MinterSetup::new();
+
+    let block_index = setup.burn(setup.controller, AMOUNT, DESTINATION);
+    setup
+        .withdraw(setup.controller, DESTINATION, AMOUNT, block_index)
+        .expect("the first withdrawal should succeed");
+    let result = setup.withdraw(setup.controller, DESTINATION, AMOUNT, block_index);
+
+    assert!(result.is_err(), "the burn was redeemed twice: {result:?}");
+}
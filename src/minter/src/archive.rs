@@ -0,0 +1,120 @@
+//! Archiving of the event log. With an event archive configured, the blocks preceding
+//! the latest checkpoint are pushed to the archive canister before the log is compacted,
+//! and ICRC-3 queries for compacted blocks are pointed to the archive.
+//!
+//! The archive must implement `append_blocks : (vec BlockWithId) -> ()` and serve the
+//! blocks through `icrc3_get_blocks`. Blocks are identified by their id, a batch may be
+//! pushed again if the minter did not record its archival.
+
+use crate::{
+    constants::MAX_ARCHIVE_BATCH_BYTES,
+    icrc3::{self, ArchiveInfo, ArchivedBlocks, BlockWithId, GetBlocksArgs},
+    logs::INFO,
+    state::{audit::process_event, event::EventType, mutate_state, read_state, State},
+    storage,
+};
+
+use candid::{Nat, Principal};
+
+/// Pushes the blocks preceding the latest checkpoint to `archive`, in batches below the
+/// message size limit. Returns true once all of them are archived, so that the log can
+/// be compacted.
+pub async fn archive_blocks(archive: Principal) -> bool {
+    let Some(target) = storage::last_checkpoint_block() else {
+        return false;
+    };
+
+    loop {
+        let start = read_state(State::archived_blocks_end).max(storage::compacted_event_count());
+        if start >= target {
+            return true;
+        }
+        let blocks = batch(start, target);
+        if blocks.is_empty() {
+            return false;
+        }
+        let end = start + blocks.len() as u64;
+
+        match ic_cdk::call::<_, ()>(archive, "append_blocks", (blocks,)).await {
+            Ok(()) => mutate_state(|s| {
+                process_event(
+                    s,
+                    EventType::EventsArchived {
+                        archive,
+                        start,
+                        end,
+                    },
+                )
+            }),
+            Err((code, msg)) => {
+                crate::logs::log!(
+                    INFO,
+                    "[archive]: failed to push blocks {start}..{end} to {archive}: {code:?}: {msg}"
+                );
+                return false;
+            }
+        }
+    }
+}
+
+// blocks from `start` on, before `end`, the first block is included regardless of its size
+fn batch(start: u64, end: u64) -> Vec<BlockWithId> {
+    let mut blocks = vec![];
+    let mut size = 0;
+    for id in start..end {
+        let Some((event, phash)) = storage::get_block(id) else {
+            break;
+        };
+        size += minicbor::to_vec(&event)
+            .expect("event encoding should always succeed")
+            .len();
+        if size > MAX_ARCHIVE_BATCH_BYTES && !blocks.is_empty() {
+            break;
+        }
+        blocks.push(BlockWithId {
+            id: Nat::from(id),
+            block: icrc3::event_block(&event, phash),
+        });
+    }
+    blocks
+}
+
+/// Pointers to the archives holding the compacted blocks among the blocks `start..end`.
+pub fn archived_block_pointers(start: u64, end: u64) -> Vec<ArchivedBlocks> {
+    let end = end.min(storage::compacted_event_count());
+    read_state(|s| {
+        s.archived_blocks
+            .iter()
+            .filter_map(|range| {
+                let (from, to) = (start.max(range.start), end.min(range.end));
+                (from < to).then(|| ArchivedBlocks {
+                    args: vec![GetBlocksArgs {
+                        start: Nat::from(from),
+                        length: Nat::from(to - from),
+                    }],
+                    callback: candid::Func {
+                        principal: range.archive,
+                        method: "icrc3_get_blocks".to_string(),
+                    },
+                })
+            })
+            .collect()
+    })
+}
+
+/// Ranges of blocks held by archives, following the last range of `from` if given.
+pub fn archives(from: Option<Principal>) -> Vec<ArchiveInfo> {
+    read_state(|s| {
+        let skip = from
+            .and_then(|from| s.archived_blocks.iter().rposition(|r| r.archive == from))
+            .map_or(0, |index| index + 1);
+        s.archived_blocks[skip..]
+            .iter()
+            .map(|range| ArchiveInfo {
+                canister_id: range.archive,
+                start: Nat::from(range.start),
+                end: Nat::from(range.end - 1),
+            })
+            .collect()
+    })
+}
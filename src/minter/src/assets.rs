@@ -0,0 +1,34 @@
+use crate::spl::decode_pubkey;
+
+use candid::{CandidType, Deserialize, Nat, Principal};
+use minicbor::{Decode, Encode};
+
+/// SPL token bridged to an ICRC ledger, withdrawn to the associated token account
+/// of the destination.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct BridgedAsset {
+    #[n(0)]
+    pub spl_mint: String,
+    /// Ledger holding the burns that back withdrawals of the asset.
+    #[cbor(n(1), with = "crate::cbor::principal")]
+    pub ledger_id: Principal,
+    #[cbor(n(2), with = "crate::cbor::nat")]
+    pub minimum_withdrawal_amount: Nat,
+    /// Retained by the minter from every withdrawal, the coupon pays out the remainder.
+    #[cbor(n(3), with = "crate::cbor::nat")]
+    pub fee: Nat,
+}
+
+impl BridgedAsset {
+    pub fn validate(&self) -> Result<(), String> {
+        decode_pubkey(&self.spl_mint)
+            .map_err(|e| format!("invalid SPL mint {}: {e}", self.spl_mint))?;
+        if self.minimum_withdrawal_amount <= self.fee {
+            return Err(format!(
+                "minimum withdrawal amount {} must exceed the fee {}",
+                self.minimum_withdrawal_amount, self.fee
+            ));
+        }
+        Ok(())
+    }
+}
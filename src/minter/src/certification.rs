@@ -0,0 +1,379 @@
+use crate::{
+    icrc3::DataCertificate,
+    state::{read_state, State},
+    storage,
+    withdraw::Coupon,
+};
+
+use candid::{CandidType, Deserialize};
+use minicbor::{encode, Encoder};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+
+// Issued coupons are committed to a binary Merkle tree; an odd node at the end of a
+// level is paired with itself. The tree is built in burn id order at upgrade, then
+// updated along the path of each changed leaf: new coupons are appended, and the last
+// leaf takes the place of a coupon that is no longer certified.
+//
+// The canister's certified data is the digest of an IC hash tree holding the Merkle
+// root under the `coupons` label and, once a block is hashed, the ICRC-3 tip under
+// the `last_block_hash` and `last_block_index` labels.
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+type Hash = [u8; 32];
+
+thread_local! {
+    /// Merkle tree of the issued coupons, its root is certified along with the ICRC-3 tip.
+    static COUPONS_TREE: RefCell<CouponsTree> = RefCell::new(CouponsTree::default());
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProofNode {
+    pub hash: ByteBuf,
+    // whether the sibling is the left input of the parent hash
+    pub is_left: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CertifiedCoupon {
+    /// IC certificate holding the digest of `hash_tree` as the canister's certified data.
+    pub certificate: ByteBuf,
+    /// CBOR encoded hash tree holding the Merkle root under the `coupons` label.
+    pub hash_tree: ByteBuf,
+    pub root: ByteBuf,
+    pub leaf: ByteBuf,
+    pub proof: Vec<MerkleProofNode>,
+}
+
+pub fn coupon_leaf(coupon: &Coupon) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(coupon.message_hash.as_bytes());
+    hasher.update(coupon.signature_hex.as_bytes());
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+// leaf of the coupon of the burn id, None unless it is issued and not revoked
+fn issued_coupon_leaf(state: &State, burn_id: u64) -> Option<Hash> {
+    if state.is_revoked_coupon(burn_id) {
+        return None;
+    }
+    state
+        .withdrawal_redeemed_events
+        .get(&burn_id)
+        .and_then(|event| event.get_coupon().map(coupon_leaf))
+}
+
+struct CouponsTree {
+    // levels from the leaves up to the root
+    levels: Vec<Vec<Hash>>,
+    // burn id of each leaf
+    burn_ids: Vec<u64>,
+    // index of the leaf of each burn id
+    positions: BTreeMap<u64, usize>,
+}
+
+impl Default for CouponsTree {
+    fn default() -> Self {
+        Self {
+            levels: vec![vec![]],
+            burn_ids: vec![],
+            positions: BTreeMap::new(),
+        }
+    }
+}
+
+impl CouponsTree {
+    fn root(&self) -> Hash {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or([0; 32])
+    }
+
+    fn leaves(&self) -> &[Hash] {
+        &self.levels[0]
+    }
+
+    // Replaces, appends or removes the leaf of the burn id.
+    fn set(&mut self, burn_id: u64, leaf: Option<Hash>) {
+        match (self.positions.get(&burn_id).copied(), leaf) {
+            (Some(index), Some(leaf)) => {
+                self.levels[0][index] = leaf;
+                self.update_path(index);
+            }
+            (None, Some(leaf)) => {
+                self.positions.insert(burn_id, self.burn_ids.len());
+                self.burn_ids.push(burn_id);
+                self.levels[0].push(leaf);
+                self.update_path(self.burn_ids.len() - 1);
+            }
+            (Some(index), None) => {
+                self.positions.remove(&burn_id);
+                let last_leaf = self.levels[0]
+                    .pop()
+                    .expect("a positioned leaf should exist");
+                let last_burn_id = self.burn_ids.pop().expect("a positioned leaf should exist");
+                let last = self.burn_ids.len();
+                // the sizes of the levels are updated along the path of the removed last leaf
+                self.update_path(last);
+                if index < last {
+                    self.levels[0][index] = last_leaf;
+                    self.burn_ids[index] = last_burn_id;
+                    self.positions.insert(last_burn_id, index);
+                    self.update_path(index);
+                }
+            }
+            (None, None) => {}
+        }
+    }
+
+    // Recomputes the parents of the node at the index of the leaves, which may be the
+    // index past the last leaf once it is removed.
+    fn update_path(&mut self, mut index: usize) {
+        let mut depth = 0;
+        while self.levels[depth].len() > 1 {
+            if self.levels.len() == depth + 1 {
+                self.levels.push(vec![]);
+            }
+            let (lower, upper) = self.levels.split_at_mut(depth + 1);
+            let (level, parents) = (&lower[depth], &mut upper[0]);
+            parents.resize(level.len().div_ceil(2), [0; 32]);
+
+            index /= 2;
+            if let Some(parent) = parents.get_mut(index) {
+                let left = &level[2 * index];
+                *parent = node_hash(left, level.get(2 * index + 1).unwrap_or(left));
+            }
+            depth += 1;
+        }
+        self.levels.truncate(depth + 1);
+    }
+
+    fn proof(&self, mut index: usize) -> Vec<MerkleProofNode> {
+        let mut proof = Vec::new();
+
+        for level in self.levels.iter().take(self.levels.len() - 1) {
+            let sibling = if index % 2 == 0 {
+                level.get(index + 1).unwrap_or(&level[index])
+            } else {
+                &level[index - 1]
+            };
+            proof.push(MerkleProofNode {
+                hash: ByteBuf::from(sibling.to_vec()),
+                is_left: index % 2 == 1,
+            });
+            index /= 2;
+        }
+
+        proof
+    }
+}
+
+// IC hash tree, see the interface specification of the Internet Computer
+enum HashTree {
+    Fork(Box<HashTree>, Box<HashTree>),
+    Labeled(&'static str, Box<HashTree>),
+    Leaf(Vec<u8>),
+    Pruned(Hash),
+}
+
+impl HashTree {
+    fn labeled(label: &'static str, value: Vec<u8>) -> Self {
+        HashTree::Labeled(label, Box::new(HashTree::Leaf(value)))
+    }
+
+    fn fork(left: HashTree, right: HashTree) -> Self {
+        HashTree::Fork(Box::new(left), Box::new(right))
+    }
+
+    fn digest(&self) -> Hash {
+        fn domain_hasher(separator: &str) -> Sha256 {
+            let mut hasher = Sha256::new();
+            hasher.update([separator.len() as u8]);
+            hasher.update(separator.as_bytes());
+            hasher
+        }
+
+        match self {
+            HashTree::Fork(left, right) => {
+                let mut hasher = domain_hasher("ic-hashtree-fork");
+                hasher.update(left.digest());
+                hasher.update(right.digest());
+                hasher.finalize().into()
+            }
+            HashTree::Labeled(label, tree) => {
+                let mut hasher = domain_hasher("ic-hashtree-labeled");
+                hasher.update(label.as_bytes());
+                hasher.update(tree.digest());
+                hasher.finalize().into()
+            }
+            HashTree::Leaf(value) => {
+                let mut hasher = domain_hasher("ic-hashtree-leaf");
+                hasher.update(value);
+                hasher.finalize().into()
+            }
+            HashTree::Pruned(digest) => *digest,
+        }
+    }
+
+    fn encode(&self, e: &mut Encoder<Vec<u8>>) -> Result<(), encode::Error<Infallible>> {
+        match self {
+            HashTree::Fork(left, right) => {
+                e.array(3)?.u8(1)?;
+                left.encode(e)?;
+                right.encode(e)
+            }
+            HashTree::Labeled(label, tree) => {
+                e.array(3)?.u8(2)?.bytes(label.as_bytes())?;
+                tree.encode(e)
+            }
+            HashTree::Leaf(value) => e.array(2)?.u8(3)?.bytes(value).map(|_| ()),
+            HashTree::Pruned(digest) => e.array(2)?.u8(4)?.bytes(digest).map(|_| ()),
+        }
+    }
+
+    fn to_cbor(&self) -> Vec<u8> {
+        let mut e = Encoder::new(vec![]);
+        self.encode(&mut e)
+            .expect("encoding to a vector should succeed");
+        e.into_writer()
+    }
+}
+
+fn leb128(mut value: u64) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn coupons_tree() -> HashTree {
+    HashTree::labeled(
+        "coupons",
+        COUPONS_TREE.with(|tree| tree.borrow().root()).to_vec(),
+    )
+}
+
+// None until a block is hashed
+fn tip_tree() -> Option<HashTree> {
+    storage::last_hashed_block().map(|(id, hash)| {
+        HashTree::fork(
+            HashTree::labeled("last_block_hash", hash.to_vec()),
+            HashTree::labeled("last_block_index", leb128(id)),
+        )
+    })
+}
+
+// labels are sorted, as the hash tree requires
+fn certified_tree(coupons: HashTree, tip: Option<HashTree>) -> HashTree {
+    match tip {
+        Some(tip) => HashTree::fork(coupons, tip),
+        None => coupons,
+    }
+}
+
+fn set_certified_tree() {
+    ic_cdk::api::set_certified_data(&certified_tree(coupons_tree(), tip_tree()).digest());
+}
+
+/// Rebuilds the Merkle tree of the issued coupons and certifies its root.
+pub fn update_certified_coupons() {
+    let mut tree = CouponsTree::default();
+    read_state(|s| {
+        // redeemed events are iterated in burn id order, revoked coupons are no longer certified
+        s.withdrawal_redeemed_events.with_iter(|iter| {
+            for (burn_id, event) in iter {
+                if !s.is_revoked_coupon(burn_id) {
+                    if let Some(coupon) = event.get_coupon() {
+                        tree.positions.insert(burn_id, tree.burn_ids.len());
+                        tree.burn_ids.push(burn_id);
+                        tree.levels[0].push(coupon_leaf(coupon));
+                    }
+                }
+            }
+        })
+    });
+    while tree.levels.last().is_some_and(|level| level.len() > 1) {
+        let level = tree.levels.last().expect("the tree should have a level");
+        let parents = level
+            .chunks(2)
+            .map(|pair| node_hash(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        tree.levels.push(parents);
+    }
+    COUPONS_TREE.with(|cell| *cell.borrow_mut() = tree);
+    set_certified_tree();
+}
+
+/// Updates the leaves of the coupons of the burn ids in the Merkle tree, along their
+/// paths to the root, and certifies the new root.
+pub fn update_certified_coupons_of(burn_ids: &[u64]) {
+    COUPONS_TREE.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        read_state(|s| {
+            for burn_id in burn_ids {
+                tree.set(*burn_id, issued_coupon_leaf(s, *burn_id));
+            }
+        })
+    });
+    set_certified_tree();
+}
+
+/// Certifies the last hashed block as the ICRC-3 tip.
+pub fn update_certified_tip() {
+    set_certified_tree();
+}
+
+/// Returns the ICRC-3 tip certificate, None until a block is hashed.
+pub fn tip_certificate() -> Option<DataCertificate> {
+    let tip = tip_tree()?;
+    let witness = certified_tree(HashTree::Pruned(coupons_tree().digest()), Some(tip));
+    Some(DataCertificate {
+        certificate: ByteBuf::from(ic_cdk::api::data_certificate()?),
+        hash_tree: ByteBuf::from(witness.to_cbor()),
+    })
+}
+
+/// Returns the Merkle proof of the coupon together with the IC certificate, or None
+/// if the coupon was not issued by the minter.
+pub fn certify_coupon(coupon: &Coupon) -> Option<CertifiedCoupon> {
+    let leaf = coupon_leaf(coupon);
+    let (root, proof) = COUPONS_TREE.with(|tree| {
+        let tree = tree.borrow();
+        let index = tree.leaves().iter().position(|l| *l == leaf)?;
+        Some((tree.root(), tree.proof(index)))
+    })?;
+
+    let witness = certified_tree(
+        coupons_tree(),
+        tip_tree().map(|tip| HashTree::Pruned(tip.digest())),
+    );
+    Some(CertifiedCoupon {
+        certificate: ByteBuf::from(ic_cdk::api::data_certificate()?),
+        hash_tree: ByteBuf::from(witness.to_cbor()),
+        root: ByteBuf::from(root.to_vec()),
+        leaf: ByteBuf::from(leaf.to_vec()),
+        proof,
+    })
+}
@@ -14,13 +14,113 @@ use std::time::Duration;
 // vec![1],
 pub const DERIVATION_PATH: Vec<ByteBuf> = vec![];
 
+// Appended to the coupon derivation path to derive the key cosigning high-value coupons
+pub const COSIGNER_DERIVATION_PATH_SUFFIX: &[u8] = b"coupon_cosigner";
+
 pub const GET_LATEST_SOLANA_SIGNATURE: Duration = Duration::from_secs(24 * 60 * 60);
 pub const SCRAPPING_SOLANA_SIGNATURE_RANGES: Duration = Duration::from_secs(24 * 60 * 60);
 pub const SCRAPPING_SOLANA_SIGNATURES: Duration = Duration::from_secs(24 * 60 * 60);
 pub const MINT_GSOL: Duration = Duration::from_secs(24 * 60 * 60);
+pub const CHECK_INVARIANTS: Duration = Duration::from_secs(24 * 60 * 60);
+pub const RECONCILE_SUPPLY: Duration = Duration::from_secs(6 * 60 * 60);
+pub const PRUNE_TERMINAL_EVENTS: Duration = Duration::from_secs(24 * 60 * 60);
+pub const CHECKPOINT_STATE: Duration = Duration::from_secs(60 * 60);
+pub const RELEASE_STALE_LOCKS: Duration = Duration::from_secs(10 * 60);
+pub const SIGN_PENDING_COUPONS: Duration = Duration::from_secs(30);
+pub const MONITOR_CYCLES: Duration = Duration::from_secs(60 * 60);
+pub const PUSH_EVENTS: Duration = Duration::from_secs(30);
+
+// Periodic timers start after a random delay of up to 1/TIMER_JITTER_RATIO of their interval.
+pub const TIMER_JITTER_RATIO: u64 = 10;
+
+pub const MAX_EVENTS_PER_RESPONSE: u64 = 100;
+// Events of the log examined by one call of get_events_filtered.
+pub const MAX_EXAMINED_EVENTS_PER_QUERY: u64 = 10_000;
+pub const MAX_LOGS_PER_RESPONSE: u64 = 100;
+// Completed timer task runs kept for get_task_traces.
+pub const MAX_TASK_TRACES: usize = 200;
+// Event subscriptions, each one costs an inter-canister call per delivery.
+pub const MAX_SUBSCRIBERS: usize = 20;
+pub const MAX_PUSHED_EVENTS_PER_BATCH: usize = 100;
+// Events of the log examined for a subscriber by one run of the event pushing.
+pub const MAX_EXAMINED_EVENTS_PER_PUSH: u64 = 1_000;
+// Consecutive failed deliveries after which a subscriber is unsubscribed.
+pub const MAX_DELIVERY_FAILURES: u32 = 10;
+// Time after which a delivery still waiting for the subscriber's reply counts as failed.
+pub const SUBSCRIBER_REPLY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+// Stays below the 2MB reply size limit, leaving room for the candid envelope.
+pub const MAX_EXPORT_CHUNK_BYTES: usize = 1_500_000;
+pub const MAX_LOGS_BODY_BYTES: usize = 1_500_000;
+pub const MAX_ARCHIVE_BATCH_BYTES: usize = 1_500_000;
+
+// Longest time prepare_upgrade waits for in-flight work to finish.
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(60);
 
 pub const LAST_CHECK_MIN: Duration = Duration::from_secs(5 * 60);
 
-pub const SOLANA_SIGNATURE_RANGES_RETRY_LIMIT: u8 = 100;
-pub const SOLANA_SIGNATURE_RETRY_LIMIT: u8 = 100;
-pub const MINT_GSOL_RETRY_LIMIT: u8 = 100;
+// Withdrawals of a single principal that may be processed at the same time.
+pub const DEFAULT_MAX_WITHDRAWALS_PER_PRINCIPAL: usize = 5;
+
+// Timer task locks held longer than this were leaked by a trapped task.
+pub const DEFAULT_TASK_LOCK_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+// Projected cycles runway below which the balance is reported as low.
+pub const DEFAULT_MIN_CYCLES_RUNWAY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+// Invalid events recorded longer ago are moved to stable memory.
+pub const DEFAULT_INVALID_EVENTS_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+// Invalid events kept on the heap, the oldest ones are archived regardless of their age.
+pub const MAX_INVALID_EVENTS_IN_MEMORY: usize = 1_000;
+// Smallest deposit minted, smaller deposits are recorded as invalid events.
+pub const MINIMUM_DEPOSIT_LAMPORTS: u64 = 1;
+// Minted and redeemed events dropped from the state by one PruneTerminalEvents event.
+pub const MAX_TERMINAL_EVENTS_PER_PRUNE: usize = 1_000;
+// ICRC-3 blocks hashed at once, the remaining ones are hashed by the next timer tick.
+pub const MAX_BLOCKS_HASHED_PER_BATCH: u64 = 1_000;
+// Events copied at once by a compaction, the remaining ones are copied by the next timer tick.
+pub const MAX_EVENTS_COPIED_PER_COMPACTION: u64 = 5_000;
+// Time between proposing and executing a sensitive config change.
+pub const DEFAULT_CONFIG_TIMELOCK: Duration = Duration::from_secs(2 * 24 * 60 * 60);
+// Time before a destination added to an address book can receive withdrawals.
+pub const DEFAULT_DESTINATION_ACTIVATION_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+// Time between the burn and the coupon of withdrawals above the release delay threshold.
+pub const DEFAULT_RELEASE_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+// A checkpoint is recorded once this many events or this much time follow the previous one.
+pub const DEFAULT_CHECKPOINT_INTERVAL_EVENTS: u64 = 10_000;
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+// Withdrawal locks held longer than this were leaked by a trapped or failed call.
+pub const WITHDRAWAL_LOCK_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+// Suggested delay for callers that hit a busy withdrawal guard.
+pub const WITHDRAW_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+// Failed attempts after which scraping and minting stop retrying an entry.
+pub const DEFAULT_RETRY_MAX_ATTEMPTS: u8 = 100;
+
+// getTransaction lookups answered with null, the transaction not being indexed yet, after
+// which a signature counts a failed attempt.
+pub const MAX_NOT_FOUND_LOOKUPS: u8 = 100;
+
+// Coupons signed at the same time, further withdrawals are queued for the signing task.
+pub const MAX_CONCURRENT_SIGNINGS: usize = 10;
+
+// Decimals of native SOL amounts (lamports) and of the gSOL ledger, see lamports.rs.
+pub const SOL_DECIMALS: u32 = 9;
+pub const GSOL_DECIMALS: u32 = 9;
+
+// Deposits sent to the BTown NFT canister in a single mint call.
+pub const MAX_DEPOSITS_PER_MINT_CALL: usize = 50;
+// Mint calls in flight at the same time.
+pub const DEFAULT_MINT_PARALLELISM: usize = 4;
+// Age after which the cached transfer fee of the gSOL ledger is queried again.
+pub const LEDGER_FEE_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+// Signature ranges scraped at the same time, each one issues its own RPC outcalls.
+pub const MAX_CONCURRENT_RANGE_SCRAPES: usize = 5;
+
+// Cycles attached by ic-cdk to every sign_with_ecdsa call.
+pub const SIGN_WITH_ECDSA_CYCLES: u128 = 26_153_846_153;
+
+// Domain-separator tag hashed into the chain tag of every signed coupon payload.
+// The payload layout carries its own version byte, see `coupon_payload`.
+pub const COUPON_DOMAIN_SEPARATOR: &str = "galactic-bridge:coupon:v1";
@@ -0,0 +1,127 @@
+//! Canonical binary layout of the message signed into a coupon. The Solana program and
+//! the clients decode coupons with this layout, the minter encodes it in this module only.
+//!
+//! Version 1, integers are little endian:
+//!
+//! | Offset | Size | Field                                                              |
+//! |--------|------|--------------------------------------------------------------------|
+//! | 0      | 1    | version, `1`                                                       |
+//! | 1      | 32   | chain tag, SHA-256 of `<domain>/<network>/<minter id>`             |
+//! | 33     | 8    | burn id                                                            |
+//! | 41     | 8    | amount paid out, in base units of the token                        |
+//! | 49     | 32   | recipient Solana address                                           |
+//! | 81     | 8    | expiry in seconds since the epoch, `0` if the coupon does not expire |
+//! | 89     | 1    | asset, `0` for native SOL, `1` for an SPL token                    |
+//! | 90     | 32   | SPL token only: mint                                               |
+//! | 122    | 32   | SPL token only: associated token account of the recipient          |
+//!
+//! The chain tag binds the coupon to one minter on one network, the burn id makes it
+//! unique for that minter.
+
+use candid::Principal;
+use sha2::{Digest, Sha256};
+
+#[cfg(test)]
+mod tests;
+
+pub const COUPON_PAYLOAD_VERSION: u8 = 1;
+/// Expiry of coupons that can be redeemed at any time.
+pub const NO_EXPIRY: u64 = 0;
+
+const NATIVE_SOL: u8 = 0;
+const SPL_TOKEN: u8 = 1;
+const ASSET_OFFSET: usize = 89;
+const NATIVE_SOL_LENGTH: usize = ASSET_OFFSET + 1;
+const SPL_TOKEN_LENGTH: usize = NATIVE_SOL_LENGTH + 2 * 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CouponPayloadError {
+    #[error("coupon payload version {0} is not supported")]
+    UnsupportedVersion(u8),
+    #[error("coupon payload of {0} bytes does not match its layout")]
+    InvalidLength(usize),
+    #[error("coupon payload holds the unknown asset kind {0}")]
+    UnknownAsset(u8),
+}
+
+/// Token accounts of a withdrawal of a bridged SPL token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplPayout {
+    pub mint: [u8; 32],
+    pub token_account: [u8; 32],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CouponPayload {
+    pub chain_tag: [u8; 32],
+    pub burn_id: u64,
+    pub amount: u64,
+    pub recipient: [u8; 32],
+    pub expiry: u64,
+    /// None for withdrawals of native SOL.
+    pub spl: Option<SplPayout>,
+}
+
+/// Identifies the minter `minter_id` on `network` in coupons signed for the `domain`.
+pub fn chain_tag(domain: &str, network: &str, minter_id: &Principal) -> [u8; 32] {
+    Sha256::digest(format!("{domain}/{network}/{minter_id}").as_bytes()).into()
+}
+
+impl CouponPayload {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SPL_TOKEN_LENGTH);
+        bytes.push(COUPON_PAYLOAD_VERSION);
+        bytes.extend_from_slice(&self.chain_tag);
+        bytes.extend_from_slice(&self.burn_id.to_le_bytes());
+        bytes.extend_from_slice(&self.amount.to_le_bytes());
+        bytes.extend_from_slice(&self.recipient);
+        bytes.extend_from_slice(&self.expiry.to_le_bytes());
+        match &self.spl {
+            None => bytes.push(NATIVE_SOL),
+            Some(spl) => {
+                bytes.push(SPL_TOKEN);
+                bytes.extend_from_slice(&spl.mint);
+                bytes.extend_from_slice(&spl.token_account);
+            }
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, CouponPayloadError> {
+        match bytes.first() {
+            Some(&COUPON_PAYLOAD_VERSION) => {}
+            Some(version) => return Err(CouponPayloadError::UnsupportedVersion(*version)),
+            None => return Err(CouponPayloadError::InvalidLength(0)),
+        }
+        if bytes.len() < NATIVE_SOL_LENGTH {
+            return Err(CouponPayloadError::InvalidLength(bytes.len()));
+        }
+
+        let spl = match (bytes[ASSET_OFFSET], bytes.len()) {
+            (NATIVE_SOL, NATIVE_SOL_LENGTH) => None,
+            (SPL_TOKEN, SPL_TOKEN_LENGTH) => Some(SplPayout {
+                mint: field(&bytes[90..122]),
+                token_account: field(&bytes[122..154]),
+            }),
+            (NATIVE_SOL | SPL_TOKEN, length) => {
+                return Err(CouponPayloadError::InvalidLength(length))
+            }
+            (asset, _) => return Err(CouponPayloadError::UnknownAsset(asset)),
+        };
+
+        Ok(Self {
+            chain_tag: field(&bytes[1..33]),
+            burn_id: u64::from_le_bytes(field(&bytes[33..41])),
+            amount: u64::from_le_bytes(field(&bytes[41..49])),
+            recipient: field(&bytes[49..81]),
+            expiry: u64::from_le_bytes(field(&bytes[81..89])),
+            spl,
+        })
+    }
+}
+
+fn field<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    bytes
+        .try_into()
+        .expect("the layout fixes the length of the field")
+}
@@ -0,0 +1,99 @@
+use super::{chain_tag, CouponPayload, CouponPayloadError, SplPayout, NO_EXPIRY};
+use crate::spl::decode_pubkey;
+
+use candid::Principal;
+
+// Test vectors shared with the Solana program, any change to them is a breaking change
+// of the coupon layout.
+const CHAIN_TAG: &str = "b9e2d9fb71f4899db8a8235b9fbcb7999e90f65bf752d217a238ea28d8a0a4ad";
+const NATIVE_SOL_PAYLOAD: &str = "01b9e2d9fb71f4899db8a8235b9fbcb7999e90f65bf752d217a238ea28d8a0a4ad0200000000000000a086010000000000f428953fe13f4b5478221cc8529c53143d1604962d5738ac58f7bbf21696bd5e000000000000000000";
+const SPL_TOKEN_PAYLOAD: &str = "01b9e2d9fb71f4899db8a8235b9fbcb7999e90f65bf752d217a238ea28d8a0a4ad070000000000000040787d0100000000f428953fe13f4b5478221cc8529c53143d1604962d5738ac58f7bbf21696bd5e00b955690000000001c6fa7af3bedbad3a3d65f36aabc97431b1bbe4c2d2f6e0e47ca60203452f5d6173ad821836d6482c31bd4e6fae8908b66aadd3f33e4fe2f7148db79772bd78a7";
+
+fn pubkey(address: &str) -> [u8; 32] {
+    decode_pubkey(address).expect("valid address")
+}
+
+fn tag() -> [u8; 32] {
+    chain_tag(
+        "galactic-bridge:coupon:v1",
+        "ic",
+        &Principal::management_canister(),
+    )
+}
+
+fn native_sol_payload() -> CouponPayload {
+    CouponPayload {
+        chain_tag: tag(),
+        burn_id: 2,
+        amount: 100_000,
+        recipient: pubkey("HS6NTv6GBVSLct8dsimRWRvjczJTAgfgDJt8VpR8wtGm"),
+        expiry: NO_EXPIRY,
+        spl: None,
+    }
+}
+
+fn spl_token_payload() -> CouponPayload {
+    CouponPayload {
+        chain_tag: tag(),
+        burn_id: 7,
+        amount: 25_000_000,
+        recipient: pubkey("HS6NTv6GBVSLct8dsimRWRvjczJTAgfgDJt8VpR8wtGm"),
+        expiry: 1_767_225_600,
+        spl: Some(SplPayout {
+            mint: pubkey("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
+            token_account: pubkey("8nZLXraZUARNmU3P8PKbJMS7NYs7aEyw6d1aQx1km3t2"),
+        }),
+    }
+}
+
+#[test]
+fn should_derive_chain_tag() {
+    assert_eq!(hex::encode(tag()), CHAIN_TAG);
+}
+
+#[test]
+fn should_encode_test_vectors() {
+    assert_eq!(
+        hex::encode(native_sol_payload().encode()),
+        NATIVE_SOL_PAYLOAD
+    );
+    assert_eq!(hex::encode(spl_token_payload().encode()), SPL_TOKEN_PAYLOAD);
+}
+
+#[test]
+fn should_decode_test_vectors() {
+    let decode = |vector: &str| CouponPayload::decode(&hex::decode(vector).unwrap());
+
+    assert_eq!(decode(NATIVE_SOL_PAYLOAD), Ok(native_sol_payload()));
+    assert_eq!(decode(SPL_TOKEN_PAYLOAD), Ok(spl_token_payload()));
+}
+
+#[test]
+fn should_reject_malformed_payloads() {
+    let mut bytes = hex::decode(NATIVE_SOL_PAYLOAD).unwrap();
+
+    assert_eq!(
+        CouponPayload::decode(&bytes[..bytes.len() - 1]),
+        Err(CouponPayloadError::InvalidLength(89))
+    );
+    assert_eq!(
+        CouponPayload::decode(&[bytes.as_slice(), &[0; 64]].concat()),
+        Err(CouponPayloadError::InvalidLength(154))
+    );
+    assert_eq!(
+        CouponPayload::decode(&[]),
+        Err(CouponPayloadError::InvalidLength(0))
+    );
+
+    bytes[89] = 2;
+    assert_eq!(
+        CouponPayload::decode(&bytes),
+        Err(CouponPayloadError::UnknownAsset(2))
+    );
+
+    bytes[0] = 2;
+    assert_eq!(
+        CouponPayload::decode(&bytes),
+        Err(CouponPayloadError::UnsupportedVersion(2))
+    );
+}
@@ -0,0 +1,309 @@
+use crate::{
+    constants::DEFAULT_MIN_CYCLES_RUNWAY,
+    guard::TimerGuard,
+    logs::INFO,
+    state::{audit::process_event, event::EventType, mutate_state, read_state, TaskType},
+};
+
+use candid::{CandidType, Deserialize, Nat, Principal};
+use icrc_ledger_types::icrc1::{
+    account::{Account, Subaccount},
+    transfer::{Memo, TransferArg, TransferError},
+};
+use minicbor::{Decode, Encode};
+use num_traits::ToPrimitive;
+
+const NANOS_PER_DAY: u128 = 24 * 60 * 60 * 1_000_000_000;
+const SECONDS_PER_DAY: u128 = 24 * 60 * 60;
+// Memo the cycles minting canister expects on ICP transfers topping up a canister ("TPUP").
+const MEMO_TOP_UP_CANISTER: u64 = 0x50555054;
+
+/// Thresholds of the cycles balance monitoring.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct CyclesMonitorConfig {
+    /// Projected runway below which the balance is reported as low.
+    #[n(0)]
+    pub min_runway_secs: u64,
+    /// Whether non-essential timer tasks are skipped while the balance is low.
+    #[n(1)]
+    pub freeze_non_essential_tasks: bool,
+}
+
+impl Default for CyclesMonitorConfig {
+    fn default() -> Self {
+        Self {
+            min_runway_secs: DEFAULT_MIN_CYCLES_RUNWAY.as_secs(),
+            freeze_non_essential_tasks: false,
+        }
+    }
+}
+
+/// Account the minter draws cycles from when its balance falls below the floor.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub enum CyclesFundingSource {
+    /// Cycles wallet the minter is a custodian of.
+    #[n(0)]
+    Wallet {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        wallet_id: Principal,
+        /// Cycles sent per top-up.
+        #[n(1)]
+        cycles: u64,
+    },
+    /// ICP of the minter's ledger account, converted by the cycles minting canister.
+    #[n(1)]
+    Ledger {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        ledger_id: Principal,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        cmc_id: Principal,
+        /// ICP converted per top-up, in e8s.
+        #[n(2)]
+        amount_e8s: u64,
+    },
+}
+
+/// Automatic top-up of the minter's cycles balance.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct CyclesTopUpConfig {
+    /// Balance below which the minter tops itself up, 0 disables top-ups.
+    #[n(0)]
+    pub floor: u64,
+    #[n(1)]
+    pub source: CyclesFundingSource,
+}
+
+impl CyclesTopUpConfig {
+    pub fn is_valid(&self) -> bool {
+        match &self.source {
+            CyclesFundingSource::Wallet { cycles, .. } => *cycles > 0,
+            CyclesFundingSource::Ledger { amount_e8s, .. } => *amount_e8s > 0,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct WalletSendArg {
+    canister: Principal,
+    amount: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct NotifyTopUpArg {
+    block_index: u64,
+    canister_id: Principal,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+enum NotifyError {
+    Refunded {
+        reason: String,
+        block_index: Option<u64>,
+    },
+    Processing,
+    TransactionTooOld(u64),
+    InvalidTransaction(String),
+    Other {
+        error_code: u64,
+        error_message: String,
+    },
+}
+
+fn call_error((code, msg): (ic_cdk::api::call::RejectionCode, String)) -> String {
+    format!("{code:?}: {msg}")
+}
+
+// subaccount of the cycles minting canister credited to the canister's top-ups
+fn top_up_subaccount(canister_id: Principal) -> Subaccount {
+    let bytes = canister_id.as_slice();
+    let mut subaccount = [0; 32];
+    subaccount[0] = bytes.len() as u8;
+    subaccount[1..=bytes.len()].copy_from_slice(bytes);
+    subaccount
+}
+
+// returns the cycles added to the minter's balance
+async fn top_up(source: &CyclesFundingSource) -> Result<u128, String> {
+    let minter_id = ic_cdk::id();
+
+    match source {
+        CyclesFundingSource::Wallet { wallet_id, cycles } => {
+            let arg = WalletSendArg {
+                canister: minter_id,
+                amount: *cycles,
+            };
+            let (result,): (Result<(), String>,) = ic_cdk::call(*wallet_id, "wallet_send", (arg,))
+                .await
+                .map_err(call_error)?;
+            result?;
+            Ok(*cycles as u128)
+        }
+        CyclesFundingSource::Ledger {
+            ledger_id,
+            cmc_id,
+            amount_e8s,
+        } => {
+            let transfer = TransferArg {
+                from_subaccount: None,
+                to: Account {
+                    owner: *cmc_id,
+                    subaccount: Some(top_up_subaccount(minter_id)),
+                },
+                fee: None,
+                created_at_time: None,
+                memo: Some(Memo::from(MEMO_TOP_UP_CANISTER.to_le_bytes().to_vec())),
+                amount: Nat::from(*amount_e8s),
+            };
+            let (result,): (Result<Nat, TransferError>,) =
+                ic_cdk::call(*ledger_id, "icrc1_transfer", (transfer,))
+                    .await
+                    .map_err(call_error)?;
+            let block_index = result
+                .map_err(|error| format!("failed to transfer ICP: {error:?}"))?
+                .0
+                .to_u64()
+                .ok_or("block index does not fit into u64")?;
+
+            let arg = NotifyTopUpArg {
+                block_index,
+                canister_id: minter_id,
+            };
+            let (result,): (Result<Nat, NotifyError>,) =
+                ic_cdk::call(*cmc_id, "notify_top_up", (arg,))
+                    .await
+                    .map_err(|error| {
+                        format!(
+                            "failed to notify the top-up of block {block_index}: {}",
+                            call_error(error)
+                        )
+                    })?;
+            let cycles = result.map_err(|error| {
+                format!("failed to notify the top-up of block {block_index}: {error:?}")
+            })?;
+            Ok(cycles.0.to_u128().unwrap_or(u128::MAX))
+        }
+    }
+}
+
+/// Cycles balance and burn rate observed by a monitoring run.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CyclesReport {
+    pub timestamp: u64,
+    pub balance: u128,
+    /// Cycles attached to HTTP outcalls and signature requests since the last upgrade.
+    pub outcall_cycles: u128,
+    /// Cycles attached to outcalls per day since the previous report.
+    pub burn_rate_per_day: u128,
+    /// Seconds until the balance is spent at the current burn rate, None if nothing was burned.
+    pub runway_secs: Option<u64>,
+    pub low_balance: bool,
+}
+
+impl CyclesReport {
+    pub fn new(
+        timestamp: u64,
+        balance: u128,
+        outcall_cycles: u128,
+        previous: Option<&CyclesReport>,
+        min_runway_secs: u64,
+    ) -> Self {
+        let burn_rate_per_day = match previous {
+            Some(previous)
+                if timestamp > previous.timestamp && outcall_cycles >= previous.outcall_cycles =>
+            {
+                (outcall_cycles - previous.outcall_cycles) * NANOS_PER_DAY
+                    / (timestamp - previous.timestamp) as u128
+            }
+            _ => 0,
+        };
+
+        let runway_secs = (burn_rate_per_day > 0).then(|| {
+            u64::try_from(balance.saturating_mul(SECONDS_PER_DAY) / burn_rate_per_day)
+                .unwrap_or(u64::MAX)
+        });
+
+        Self {
+            timestamp,
+            balance,
+            outcall_cycles,
+            burn_rate_per_day,
+            runway_secs,
+            low_balance: runway_secs.is_some_and(|runway| runway < min_runway_secs),
+        }
+    }
+}
+
+// record the cycles balance, raise an alert when the projected runway gets short
+// and top the balance up when it falls below the floor
+pub async fn monitor_cycles() {
+    let guard = match TimerGuard::new(TaskType::MonitorCycles) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    let (previous, min_runway_secs, outcall_cycles, top_up_config) = read_state(|s| {
+        (
+            s.last_cycles_report.clone(),
+            s.cycles_monitor.min_runway_secs,
+            s.outcall_cycles(),
+            s.cycles_top_up.clone(),
+        )
+    });
+
+    let report = CyclesReport::new(
+        ic_cdk::api::time(),
+        ic_cdk::api::canister_balance128(),
+        outcall_cycles,
+        previous.as_ref(),
+        min_runway_secs,
+    );
+
+    if report.low_balance {
+        crate::logs::log!(
+            INFO,
+            "\nCycles balance {} lasts {:?}s at {} cycles per day",
+            report.balance,
+            report.runway_secs,
+            report.burn_rate_per_day
+        );
+
+        // alert once per low balance period
+        if !previous.is_some_and(|previous| previous.low_balance) {
+            mutate_state(|s| {
+                process_event(
+                    s,
+                    EventType::LowCyclesBalance {
+                        balance: Nat::from(report.balance),
+                        runway_secs: report.runway_secs.unwrap_or_default(),
+                    },
+                )
+            });
+        }
+    }
+
+    let balance = report.balance;
+    mutate_state(|s| s.last_cycles_report = Some(report));
+
+    let config = match top_up_config {
+        Some(config) if balance < config.floor as u128 => config,
+        _ => return,
+    };
+    match top_up(&config.source).await {
+        Ok(cycles) => {
+            crate::logs::log!(INFO, "\nTopped up the cycles balance by {cycles}");
+            mutate_state(|s| {
+                process_event(
+                    s,
+                    EventType::CyclesToppedUp {
+                        cycles: Nat::from(cycles),
+                        balance: Nat::from(balance),
+                    },
+                )
+            });
+        }
+        Err(error) => {
+            crate::logs::log!(INFO, "\nFailed to top up the cycles balance: {error}");
+            guard.record_error(error);
+        }
+    }
+}
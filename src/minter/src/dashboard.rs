@@ -0,0 +1,280 @@
+use crate::{
+    endpoints::SupplyStats,
+    events::{DepositEvent, WithdrawalEvent},
+    state::{read_state, State},
+};
+
+use std::fmt::Write;
+
+// number of deposits and withdrawals listed in the recent tables
+const RECENT_EVENTS_LIMIT: usize = 20;
+
+const STYLE: &str = "body{font-family:monospace;margin:2em}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left}\
+th{background:#eee}";
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+struct Table {
+    out: String,
+}
+
+impl Table {
+    fn new(title: &str, headers: &[&str]) -> Self {
+        let mut out = format!("<h3>{}</h3><table><tr>", escape(title));
+        for header in headers {
+            _ = write!(out, "<th>{}</th>", escape(header));
+        }
+        out.push_str("</tr>");
+        Self { out }
+    }
+
+    fn row(&mut self, cells: &[String]) {
+        self.out.push_str("<tr>");
+        for cell in cells {
+            _ = write!(self.out, "<td>{}</td>", escape(cell));
+        }
+        self.out.push_str("</tr>");
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("</table>");
+        self.out
+    }
+}
+
+fn config_table(state: &State) -> String {
+    let mut table = Table::new("Configuration", &["Parameter", "Value"]);
+    let rows = [
+        ("Solana RPC URL", state.solana_rpc_url.to_string()),
+        (
+            "Solana contract address",
+            state.solana_contract_address.clone(),
+        ),
+        (
+            "Solana initial signature",
+            state.solana_initial_signature.clone(),
+        ),
+        (
+            "Solana last known signature",
+            state.get_solana_last_known_signature(),
+        ),
+        (
+            "Solana vault address",
+            optional(state.solana_vault_address.as_ref()),
+        ),
+        ("Paused", optional(state.paused.as_ref())),
+        ("Ledger", optional(state.ledger_id)),
+        ("ECDSA key name", state.ecdsa_key_name.clone()),
+        (
+            "Minimum withdrawal amount",
+            state.minimum_withdrawal_amount.to_string(),
+        ),
+        (
+            "Max withdrawals per principal",
+            state.max_withdrawals_per_principal.to_string(),
+        ),
+        (
+            "Terminal events retention",
+            optional(state.terminal_events_retention),
+        ),
+        (
+            "Terminal events max age (s)",
+            optional(state.terminal_events_max_age_secs),
+        ),
+        (
+            "Retry policy",
+            format!(
+                "max {} attempts, backoff {:?}s",
+                state.retry_policy.max_attempts, state.retry_policy.backoff_secs
+            ),
+        ),
+        (
+            "Cycle balance",
+            ic_cdk::api::canister_balance128().to_string(),
+        ),
+    ];
+    for (name, value) in rows {
+        table.row(&[name.to_string(), value]);
+    }
+    table.finish()
+}
+
+fn supply_table(state: &State) -> String {
+    let stats = SupplyStats::from(state);
+    let mut table = Table::new(
+        "Supply",
+        &[
+            "Total minted",
+            "Total burned",
+            "Total fees",
+            "Ledger fees",
+            "Outstanding",
+        ],
+    );
+    table.row(&[
+        stats.total_minted.to_string(),
+        stats.total_burned.to_string(),
+        stats.total_fees.to_string(),
+        stats.total_ledger_fees.to_string(),
+        stats.outstanding.to_string(),
+    ]);
+    table.finish()
+}
+
+fn backlog_table(state: &State) -> String {
+    let mut table = Table::new("Backlog", &["Queue", "Size"]);
+    let rows = [
+        (
+            "Solana signature ranges",
+            state.solana_signature_ranges.len() as u64,
+        ),
+        ("Solana signatures", state.solana_signatures.len() as u64),
+        ("Invalid events", state.invalid_events.len() as u64),
+        ("Accepted deposits", state.accepted_events.len() as u64),
+        ("Dead-lettered deposits", state.dead_letters.len() as u64),
+        ("Minted deposits", state.minted_events.len()),
+        (
+            "Burned withdrawals",
+            state.withdrawal_burned_events.len() as u64,
+        ),
+        (
+            "Withdrawals awaiting approval",
+            state.withdrawals_awaiting_approval().len() as u64,
+        ),
+        (
+            "Redeemed withdrawals",
+            state.withdrawal_redeemed_events.len(),
+        ),
+    ];
+    for (name, size) in rows {
+        table.row(&[name.to_string(), size.to_string()]);
+    }
+    table.finish()
+}
+
+fn deposits_table(state: &State) -> String {
+    let mut deposits: Vec<(&str, DepositEvent)> = state
+        .accepted_events
+        .values()
+        .map(|e| ("accepted", e.clone()))
+        .chain(
+            state
+                .minted_events
+                .values()
+                .into_iter()
+                .map(|e| ("minted", e)),
+        )
+        .collect();
+    deposits.sort_by_key(|(_, e)| std::cmp::Reverse(e.id));
+
+    let mut table = Table::new(
+        "Recent deposits",
+        &[
+            "Id",
+            "Status",
+            "Solana signature",
+            "From",
+            "To",
+            "Amount",
+            "Mint block",
+        ],
+    );
+    for (status, deposit) in deposits.into_iter().take(RECENT_EVENTS_LIMIT) {
+        table.row(&[
+            deposit.id.to_string(),
+            status.to_string(),
+            deposit.sol_sig.clone(),
+            deposit.from_sol_address.clone(),
+            deposit.to_icp_address.to_string(),
+            deposit.amount.to_string(),
+            optional(deposit.get_mint_block_index()),
+        ]);
+    }
+    table.finish()
+}
+
+fn withdrawals_table(state: &State) -> String {
+    let mut withdrawals: Vec<(&str, WithdrawalEvent)> = state
+        .withdrawal_burned_events
+        .values()
+        .map(|e| ("burned", e.clone()))
+        .chain(
+            state
+                .withdrawal_redeemed_events
+                .values()
+                .into_iter()
+                .map(|e| ("redeemed", e)),
+        )
+        .collect();
+    withdrawals.sort_by_key(|(_, e)| std::cmp::Reverse(e.get_burn_id()));
+
+    let mut table = Table::new(
+        "Recent withdrawals",
+        &["Burn id", "Status", "From", "To", "Amount", "Burn block"],
+    );
+    for (status, withdrawal) in withdrawals.into_iter().take(RECENT_EVENTS_LIMIT) {
+        table.row(&[
+            withdrawal.get_burn_id().to_string(),
+            status.to_string(),
+            withdrawal.from_icp_address.to_string(),
+            withdrawal.to_sol_address.to_string(),
+            withdrawal.amount.to_string(),
+            optional(withdrawal.get_icp_burn_block_index()),
+        ]);
+    }
+    table.finish()
+}
+
+fn invalid_events_table(state: &State) -> String {
+    let mut signatures: Vec<_> = state.invalid_events.values().collect();
+    signatures.sort_by(|a, b| a.sol_sig.cmp(&b.sol_sig));
+
+    let mut table = Table::new("Invalid events", &["Solana signature", "Retries"]);
+    for signature in signatures {
+        table.row(&[
+            signature.sol_sig.clone(),
+            signature.retry.get_retries().to_string(),
+        ]);
+    }
+    table.finish()
+}
+
+/// Renders the human-readable status page of the minter.
+pub fn render() -> String {
+    read_state(|s| {
+        let mut out = String::new();
+        _ = write!(
+            out,
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>gSOL minter</title><style>{STYLE}</style></head><body><h1>gSOL minter</h1>"
+        );
+        out.push_str(&config_table(s));
+        out.push_str(&supply_table(s));
+        out.push_str(&backlog_table(s));
+        out.push_str(&deposits_table(s));
+        out.push_str(&withdrawals_table(s));
+        out.push_str(&invalid_events_table(s));
+        out.push_str("</body></html>");
+        out
+    })
+}
@@ -1,125 +1,173 @@
 use crate::{
     constants::{
-        MINT_GSOL_RETRY_LIMIT, SOLANA_SIGNATURE_RANGES_RETRY_LIMIT, SOLANA_SIGNATURE_RETRY_LIMIT,
+        LEDGER_FEE_CACHE_TTL, MAX_CONCURRENT_RANGE_SCRAPES, MAX_DEPOSITS_PER_MINT_CALL,
+        MAX_NOT_FOUND_LOOKUPS,
+    },
+    endpoints::{CandidDepositEvent, SimulatedParse},
+    events::{
+        DepositEvent, DepositEventError, InvalidReason, SolanaBlock, SolanaSignature,
+        SolanaSignatureRange,
     },
-    events::{DepositEvent, DepositEventError, SolanaSignature, SolanaSignatureRange},
     get_btown_nft_canister,
     guard::TimerGuard,
-    logs::{DEBUG, INFO},
+    logs::{is_debug_enabled, DEBUG, INFO},
     sol_rpc_client::{responses::GetTransactionResponse, SolRpcClient, SolRpcError},
-    state::{audit::process_event, event::EventType, mutate_state, read_state, State, TaskType},
+    state::{
+        audit::process_event, event::EventType, mutate_state, read_state, RetryTarget, State,
+        TaskType,
+    },
     utils::{HashMapUtils, VecUtils},
 };
 
-use candid::Nat;
+use candid::{CandidType, Nat};
+use ic_cdk::api::call::RejectionCode;
 use icrc_ledger_types::icrc1::transfer::TransferError;
 use std::collections::HashMap;
 
 const GET_SIGNATURES_BY_ADDRESS_LIMIT: u8 = 10;
 const GET_TRANSACTIONS_LIMIT: u8 = 10;
+// Pages of signatures scanned per range and tick, larger ranges resume on the next tick.
+const MAX_SIGNATURE_PAGES_PER_RANGE: u32 = 10;
+// ICRC-1 transfer errors that fail every retry until the ledger or the minting call changes,
+// e.g. a BadFee after a ledger upgrade changed the fee
+const PERMANENT_MINT_ERRORS: [&str; 2] = ["BadFee", "BadBurn"];
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum DepositError {
+    #[error("{0:?}")]
     RpcCallFailed(SolRpcError),
+    #[error("Signature {sig} : failed with {err:?}")]
     SignatureFailed { sig: String, err: SolRpcError },
+    #[error("Signature {0} : transaction not found")]
     SignatureNotFound(String),
+    #[error("Signature {0} : invalid deposit data")]
     InvalidDepositData(String),
+    #[error("Signature {0} : non-Deposit transaction found")]
     NonDepositTransaction(String),
+    #[error("Signature {sig} : unsupported deposit instruction {instruction}")]
+    UnsupportedDepositInstruction { sig: String, instruction: String },
+    #[error("Signature {sig} : deposit sent from the blocked address {address}")]
+    BlockedSender { sig: String, address: String },
+    #[error("Failed to mint gSOL: {0:?}")]
     MintingGSolFailed(TransferError),
+    #[error("Failed to send a message to the ledger {id}: {code:?}: {msg}")]
     SendingMessageToLedgerFailed { id: String, code: i32, msg: String },
+    #[error("Signature {sig} : {err}")]
     DepositEventFailed { sig: String, err: DepositEventError },
 }
 
-impl std::fmt::Display for DepositError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl DepositError {
+    /// Why a transaction failing with this error is recorded as an invalid event.
+    pub fn invalid_reason(&self) -> InvalidReason {
         match self {
-            DepositError::RpcCallFailed(err) => {
-                write!(f, "{err:?}")
-            }
-            DepositError::SignatureFailed { sig, err } => {
-                write!(f, "Signature {sig} : failed with {err:?}")
-            }
-            DepositError::SignatureNotFound(sig) => {
-                write!(f, "Signature {sig} : transaction not found")
-            }
-            DepositError::InvalidDepositData(sig) => {
-                write!(f, "Signature {sig} : invalid deposit data")
-            }
-            DepositError::NonDepositTransaction(sig) => {
-                write!(f, "Signature {sig} : non-Deposit transaction found")
-            }
-            DepositError::MintingGSolFailed(err) => {
-                write!(f, "Failed to mint gSOL: {err:?}")
-            }
-            DepositError::SendingMessageToLedgerFailed { id, code, msg } => {
-                write!(
-                    f,
-                    "Failed to send a message to the ledger {id}: {code:?}: {msg}",
-                )
-            }
-            DepositError::DepositEventFailed { sig, err } => {
-                write!(f, "Signature {sig} : {err:?}")
-            }
+            DepositError::DepositEventFailed {
+                err: DepositEventError::InvalidPrincipal(reason),
+                ..
+            } => InvalidReason::BadPrincipal(*reason),
+            DepositError::DepositEventFailed {
+                err: DepositEventError::ZeroAmount,
+                ..
+            } => InvalidReason::BelowMinimum,
+            DepositError::NonDepositTransaction(_) => InvalidReason::NotADeposit,
+            DepositError::UnsupportedDepositInstruction { .. } => InvalidReason::UnsupportedVersion,
+            DepositError::BlockedSender { .. } => InvalidReason::Blocked,
+            _ => InvalidReason::ParseFailure,
         }
     }
 }
 
+/// Input rejected by [dry_run_parse] before the parser runs.
+#[derive(CandidType, Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SimulateParseError {
+    #[error("invalid transaction JSON: {0}")]
+    InvalidJson(String),
+    #[error("not a getTransaction response: {0}")]
+    NotATransaction(String),
+    #[error("the transaction has no signature or account keys")]
+    MissingSignatureOrAccountKeys,
+}
+
 // fetch newest signature and push a new range to the state
 pub async fn get_latest_signature() {
-    let _guard = match TimerGuard::new(TaskType::GetLatestSignature) {
+    let guard = match TimerGuard::new(TaskType::GetLatestSignature) {
         Ok(guard) => guard,
         Err(_) => return,
     };
 
-    ic_canister_log::log!(DEBUG, "\nSearching for new signatures ...");
+    crate::logs::log!(DEBUG, "\nSearching for new signatures ...");
 
     let until_signature = read_state(|s| s.get_solana_last_known_signature());
+    // a lagging node must not report the history as it was before the latest observed block
+    let min_context_slot = read_state(|s| s.latest_solana_block.map(|block| block.slot));
 
     // RPC call underneath is exclusive, so until_signature is not included in the result
     match read_state(SolRpcClient::from_state)
-        .get_signatures_for_address(1, None, &until_signature)
+        .for_task(TaskType::GetLatestSignature)
+        .get_signatures_for_address(1, None, Some(&until_signature), min_context_slot)
         .await
     {
         Ok(signatures) => match signatures.len() {
             0 => {
-                ic_canister_log::log!(DEBUG, "\nNo new signatures found");
+                crate::logs::log!(DEBUG, "\nNo new signatures found");
             }
             1 => {
+                guard.record_items(1);
                 let newest_sig = signatures[0].signature.to_string();
-                process_new_solana_signature_range(&newest_sig, &until_signature);
+                mutate_state(|s| {
+                    s.observe_solana_block(SolanaBlock {
+                        slot: signatures[0].slot,
+                        block_time: signatures[0].block_time,
+                    })
+                });
+                process_new_solana_signature_range(
+                    &newest_sig,
+                    &until_signature,
+                    signatures[0].slot,
+                );
             }
             _ => {
-                ic_canister_log::log!(INFO, "\nUnexpected behaviour");
+                crate::logs::log!(INFO, "\nUnexpected behaviour");
             }
         },
         Err(error) => {
-            ic_canister_log::log!(INFO, "\nFailed to get signatures for address: {error:?}");
+            crate::logs::log!(INFO, "\nFailed to get signatures for address: {error:?}");
+            guard.record_error(error);
         }
     }
 }
 
 pub async fn scrap_signature_range() {
-    let _guard = match TimerGuard::new(TaskType::ScrapSignatureRanges) {
+    let guard = match TimerGuard::new(TaskType::ScrapSignatureRanges) {
         Ok(guard) => guard,
         Err(_) => return,
     };
 
-    let rpc_client = read_state(SolRpcClient::from_state);
-    // filter out all events that have reached the retry limit
-    let filtered_ranges =
-        HashMapUtils::filter(&read_state(|s| s.solana_signature_ranges.clone()), |s| {
-            !s.retry
-                .is_retry_limit_reached(SOLANA_SIGNATURE_RANGES_RETRY_LIMIT)
-        });
+    let rpc_client = read_state(SolRpcClient::from_state).for_task(TaskType::ScrapSignatureRanges);
+    let now = ic_cdk::api::time();
+    // filter out all events that the retry policy does not allow to attempt now
+    let filtered_ranges = read_state(|s| {
+        HashMapUtils::filter(&s.solana_signature_ranges, |r| {
+            s.is_retry_due(&RetryTarget::range(r), &r.retry, now)
+        })
+    });
+    record_attempts(filtered_ranges.values().map(RetryTarget::range), now);
+    guard.record_items(filtered_ranges.len());
 
-    ic_canister_log::log!(
+    crate::logs::log!(
         DEBUG,
         "\nProcessing ranges:\n{}",
         HashMapUtils::format_keys_as_string(&filtered_ranges)
     );
 
-    for (_, v) in &filtered_ranges {
-        process_signature_range_with_limit(&rpc_client, v.clone(), None).await;
+    // ranges do not overlap, so they can be scanned in parallel
+    let ranges: Vec<SolanaSignatureRange> = filtered_ranges.into_values().collect();
+    for chunk in ranges.chunks(MAX_CONCURRENT_RANGE_SCRAPES) {
+        futures::future::join_all(
+            chunk
+                .iter()
+                .map(|range| process_signature_range_with_limit(&rpc_client, range.clone(), None)),
+        )
+        .await;
     }
 }
 
@@ -134,16 +182,22 @@ async fn process_signature_range_with_limit(
 
     let mut result: Vec<String> = Vec::new();
     let mut at_least_one_successful_call = false; // Flag to track if at least one call was successful
+    let mut pages = 0;
 
     loop {
-        ic_canister_log::log!(
+        crate::logs::log!(
             DEBUG,
             "\nScanning range:\n\tbefore: {before_signature}\n\tuntil: {until_signature}\n\tlimit: {limit}",
         );
 
         // get signatures for chunk
         match rpc_client
-            .get_signatures_for_address(limit, Some(&before_signature), &until_signature)
+            .get_signatures_for_address(
+                limit,
+                Some(&before_signature),
+                Some(&until_signature),
+                range.observed_slot,
+            )
             .await
         {
             Ok(signatures) => {
@@ -156,6 +210,13 @@ async fn process_signature_range_with_limit(
 
                 // if no signatures are available, we are done
                 if signatures.is_empty() {
+                    check_range_end(
+                        rpc_client,
+                        &before_signature,
+                        &until_signature,
+                        range.observed_slot,
+                    )
+                    .await;
                     remove_solana_signature_range(&range);
                     break;
                 }
@@ -165,6 +226,15 @@ async fn process_signature_range_with_limit(
                 let last_signature = signatures.last().unwrap();
                 before_signature = last_signature.signature.to_string();
                 result.extend(signatures.iter().map(|s| s.signature.to_string()));
+
+                // persist the progress and resume on the next tick, the last signature
+                // becomes the first element of the remaining range
+                pages += 1;
+                if pages >= MAX_SIGNATURE_PAGES_PER_RANGE {
+                    result.pop();
+                    advance_solana_signature_range(&range, &before_signature);
+                    break;
+                }
             }
             Err(error) => {
                 // if RPC call failed to get signatures, retry later
@@ -180,28 +250,85 @@ async fn process_signature_range_with_limit(
         }
     }
 
-    // Only process the signatures if at least one successful call was made
+    // Only process the signatures if at least one successful call was made, rescanned
+    // ranges can hold deposits that were processed already
     if at_least_one_successful_call {
         result
             .iter()
+            .filter(|s| !read_state(|state| state.is_processed_signature(s)))
             .for_each(|s| process_solana_signature(&SolanaSignature::new(s.to_string()), None));
     }
 }
 
+// A provider truncating getSignaturesForAddress ends the scan of a range early and the
+// skipped deposits are never seen. The scan is complete only if the signature following
+// the oldest scanned one is the end of the range. Without a following signature, the end
+// of the range is not in the history of the contract and nothing can be checked.
+async fn check_range_end(
+    rpc_client: &SolRpcClient,
+    oldest_signature: &String,
+    until_signature: &String,
+    min_context_slot: Option<u64>,
+) {
+    let next_signature = match rpc_client
+        .get_signatures_for_address(1, Some(oldest_signature), None, min_context_slot)
+        .await
+    {
+        Ok(signatures) => match signatures.into_iter().next() {
+            Some(next) => next.signature,
+            None => return,
+        },
+        Err(error) => {
+            crate::logs::log!(
+                DEBUG,
+                "\nFailed to check the end of the range until {until_signature}: {error:?}"
+            );
+            return;
+        }
+    };
+
+    if next_signature == *until_signature {
+        return;
+    }
+
+    crate::logs::log!(
+        INFO,
+        "\nSignature gap suspected between {oldest_signature} and {until_signature}, starting at {next_signature}"
+    );
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::SignatureGapDetected {
+                before_sol_sig: oldest_signature.to_string(),
+                until_sol_sig: until_signature.to_string(),
+                next_sol_sig: next_signature,
+            },
+        )
+    });
+}
+
 pub async fn scrap_signatures() {
-    let _guard = match TimerGuard::new(TaskType::ScrapSignatures) {
+    let guard = match TimerGuard::new(TaskType::ScrapSignatures) {
         Ok(guard) => guard,
         Err(_) => return,
     };
 
-    let rpc_client = read_state(SolRpcClient::from_state);
-    // filter out all events that have reached the retry limit
-    let filtered_signatures =
-        HashMapUtils::filter(&read_state(|s| s.solana_signatures.clone()), |s| {
-            !s.retry.is_retry_limit_reached(SOLANA_SIGNATURE_RETRY_LIMIT)
-        });
+    let rpc_client = read_state(SolRpcClient::from_state).for_task(TaskType::ScrapSignatures);
+    let now = ic_cdk::api::time();
+    // filter out all events that the retry policy does not allow to attempt now
+    let filtered_signatures = read_state(|s| {
+        HashMapUtils::filter(&s.solana_signatures, |sig| s.is_signature_due(sig, now))
+    });
+    record_attempts(
+        filtered_signatures
+            .keys()
+            .cloned()
+            .map(RetryTarget::Signature),
+        now,
+    );
+    guard.record_items(filtered_signatures.len());
 
-    ic_canister_log::log!(
+    crate::logs::log!(
         DEBUG,
         "\nProcessing signatures:\n{}",
         HashMapUtils::format_keys_as_string(&filtered_signatures)
@@ -209,7 +336,7 @@ pub async fn scrap_signatures() {
 
     let transactions = process_signatures_with_limit(&rpc_client, &filtered_signatures, None).await;
 
-    ic_canister_log::log!(
+    crate::logs::log!(
         DEBUG,
         "\nProcessing transactions:\n{}",
         VecUtils::format_keys_as_string(&transactions)
@@ -242,6 +369,11 @@ async fn process_signatures_with_limit(
                                 Some(DepositError::SignatureFailed { sig: key, err }),
                             );
                         }
+                        // usually not indexed yet, counted as a failed attempt only once
+                        // the lookups are exhausted
+                        Ok(None) if signature.get_not_found_lookups() < MAX_NOT_FOUND_LOOKUPS => {
+                            process_transaction_not_found(&signature);
+                        }
                         Ok(None) => {
                             process_solana_signature(
                                 &signature,
@@ -268,7 +400,18 @@ async fn process_signatures_with_limit(
 
 fn parse_log_messages(transactions: &Vec<(SolanaSignature, GetTransactionResponse)>) {
     for (signature, transaction) in transactions {
+        mutate_state(|s| s.observe_solana_block(solana_block(transaction)));
         match process_transaction_logs(transaction) {
+            // deposits of blocked senders wait in the dead letters until the sender is
+            // unblocked and an operator requeues them
+            Ok(deposit) if read_state(|s| s.is_blocked_address(&deposit.from_sol_address)) => {
+                let err = DepositError::BlockedSender {
+                    sig: deposit.sol_sig.clone(),
+                    address: deposit.from_sol_address.clone(),
+                };
+                process_accepted_event(&deposit, None);
+                process_dead_letter(&deposit, err);
+            }
             Ok(deposit) => {
                 process_accepted_event(&deposit, None);
             }
@@ -281,18 +424,72 @@ fn parse_log_messages(transactions: &Vec<(SolanaSignature, GetTransactionRespons
 
 fn process_transaction_logs(
     transaction: &GetTransactionResponse,
+) -> Result<DepositEvent, DepositError> {
+    let contract_address = read_state(|s| s.solana_contract_address.clone());
+    parse_transaction_logs(transaction, &contract_address, || {
+        mutate_state(State::next_deposit_id)
+    })
+}
+
+/// Runs the deposit parser on a raw getTransaction response, either the JSON-RPC
+/// response or its `result`, without changing the state. A deposit is given the id
+/// the next accepted deposit would get.
+pub fn dry_run_parse(raw_transaction_json: &str) -> Result<SimulatedParse, SimulateParseError> {
+    let value: serde_json::Value = serde_json::from_str(raw_transaction_json)
+        .map_err(|e| SimulateParseError::InvalidJson(e.to_string()))?;
+    let result = value.get("result").cloned().unwrap_or(value);
+    let transaction: GetTransactionResponse = serde_json::from_value(result)
+        .map_err(|e| SimulateParseError::NotATransaction(e.to_string()))?;
+    if transaction.transaction.signatures.is_empty()
+        || transaction.transaction.message.account_keys.is_empty()
+    {
+        return Err(SimulateParseError::MissingSignatureOrAccountKeys);
+    }
+
+    let (contract_address, next_deposit_id) =
+        read_state(|s| (s.solana_contract_address.clone(), s.deposit_id_counter));
+    Ok(
+        match parse_transaction_logs(&transaction, &contract_address, || next_deposit_id) {
+            Ok(deposit) => SimulatedParse::Deposit(CandidDepositEvent::from(&deposit)),
+            Err(err) => SimulatedParse::Invalid {
+                reason: err.to_string(),
+                code: err.invalid_reason(),
+            },
+        },
+    )
+}
+
+fn solana_block(transaction: &GetTransactionResponse) -> SolanaBlock {
+    SolanaBlock {
+        slot: transaction.slot,
+        block_time: transaction.block_time,
+    }
+}
+
+// the deposit id is only taken once the transaction was found to be a deposit
+fn parse_transaction_logs(
+    transaction: &GetTransactionResponse,
+    contract_address: &str,
+    next_deposit_id: impl FnOnce() -> u64,
 ) -> Result<DepositEvent, DepositError> {
     let deposit_msg = "Program log: Instruction: Deposit";
-    let success_msg = &format!(
-        "Program {} success",
-        read_state(|s| s.solana_contract_address.clone())
-    );
+    let success_msg = &format!("Program {contract_address} success");
     let program_data_msg = "Program data: ";
 
     let signature = &transaction.transaction.signatures[0];
     let solana_address = &transaction.transaction.message.account_keys[0];
     let msgs = &transaction.meta.log_messages;
 
+    // later versions of the bridge program log their own deposit instructions
+    if !msgs.contains(&String::from(deposit_msg)) {
+        if let Some(instruction) = msgs.iter().find(|s| s.starts_with(deposit_msg)) {
+            return Err(DepositError::UnsupportedDepositInstruction {
+                sig: signature.to_string(),
+                instruction: instruction.trim_start_matches("Program log: ").to_string(),
+            });
+        }
+    }
+
     if msgs.contains(&String::from(deposit_msg))
         && msgs.contains(&String::from(success_msg))
         && msgs.iter().any(|s| s.starts_with(program_data_msg))
@@ -300,7 +497,7 @@ fn process_transaction_logs(
         if let Some(program_data) = msgs.iter().find(|s| s.starts_with(program_data_msg)) {
             let base64_data = program_data.trim_start_matches(program_data_msg);
             let deposit: Result<DepositEvent, DepositEventError> = DepositEvent::new(
-                mutate_state(State::next_deposit_id),
+                next_deposit_id(),
                 signature.as_str(),
                 solana_address.as_str(),
                 base64_data,
@@ -308,7 +505,7 @@ fn process_transaction_logs(
 
             match deposit {
                 Ok(deposit) => {
-                    return Ok(deposit);
+                    return Ok(deposit.with_solana_block(solana_block(transaction)));
                 }
                 Err(err) => {
                     return Err(DepositError::DepositEventFailed {
@@ -326,18 +523,44 @@ fn process_transaction_logs(
 }
 
 pub async fn mint_gsol() {
-    let _guard = match TimerGuard::new(TaskType::MintGSol) {
+    let guard = match TimerGuard::new(TaskType::MintGSol) {
         Ok(guard) => guard,
         Err(_) => return,
     };
 
-    // filter out all events that have reached the retry limit
-    let filtered_events = HashMapUtils::filter(&read_state(|s| s.accepted_events.clone()), |e| {
-        !e.retry.is_retry_limit_reached(MINT_GSOL_RETRY_LIMIT)
+    if read_state(|s| s.is_paused()) {
+        crate::logs::log!(DEBUG, "\nMinting is paused");
+        return;
+    }
+
+    let now = ic_cdk::api::time();
+    // filter out all events that the retry policy does not allow to attempt now
+    let filtered_events = read_state(|s| {
+        HashMapUtils::filter(&s.accepted_events, |e| {
+            s.is_retry_due(&RetryTarget::Deposit(e.sol_sig.clone()), &e.retry, now)
+        })
+    });
+    // deposits above the cap stay accepted until the cap is raised
+    let (filtered_events, capped_events) = read_state(|s| {
+        (
+            HashMapUtils::filter(&filtered_events, |e| s.is_within_deposit_cap(&e.amount)),
+            HashMapUtils::filter(&filtered_events, |e| !s.is_within_deposit_cap(&e.amount)),
+        )
     });
+    if !capped_events.is_empty() {
+        crate::logs::log!(
+            DEBUG,
+            "\nDeposits above the cap:\n{}",
+            HashMapUtils::format_keys_as_string(&capped_events)
+        );
+    }
+    record_attempts(
+        filtered_events.keys().cloned().map(RetryTarget::Deposit),
+        now,
+    );
 
     if filtered_events.is_empty() {
-        ic_canister_log::log!(
+        crate::logs::log!(
             DEBUG,
             "\nMinting gSOL:\n{}",
             HashMapUtils::format_keys_as_string(&filtered_events)
@@ -345,51 +568,37 @@ pub async fn mint_gsol() {
         return;
     }
 
-    ic_canister_log::log!(
+    crate::logs::log!(
         DEBUG,
         "\nMinting gSOL:\n{}",
         HashMapUtils::format_keys_as_string(&filtered_events)
     );
 
-    let array_events: Vec<DepositEvent> = filtered_events.values().cloned().collect();
-    let bton_events = serde_cbor::to_vec(&array_events).unwrap();
-
-    ic_cdk::println!("bton_events: {:?}", array_events);
-    ic_cdk::println!("bton_events_len: {:?}", bton_events.len());
-    ic_cdk::println!("bton_events_hex: {:?}", hex::encode(bton_events.as_slice()));
-
-    let (result,): (Vec<Result<String, (String, String)>>,) = match ic_cdk::call(
-        get_btown_nft_canister(),
-        "deposit_coins_from_solana",
-        (bton_events.as_slice(),),
-    )
-    .await
-    {
-        Ok(res) => res,
-        Err(err) => {
-            ic_canister_log::log!(DEBUG, "failed to mint bton, error: {:?}", err);
-            return;
-        }
-    };
+    // mints from the minting account are fee-free under ICRC-1, the fee is only cached
+    // for get_minter_info
+    refresh_ledger_fee(&guard, now).await;
 
-    for res in result {
-        match res {
-            Ok(sig) => {
-                let mut event = filtered_events.get(&sig).unwrap().clone(); // Clone the event to make it mutable
-                event.update_mint_block_index(0);
-                process_minted_event(&event);
-            }
-            Err((sig, err)) => {
-                let event = filtered_events.get(&sig).unwrap().clone(); // Clone the event to make it mutable
-                process_accepted_event(
-                    &event,
-                    Some(DepositError::MintingGSolFailed(
-                        TransferError::GenericError {
-                            error_code: Nat::from(0u8),
-                            message: err,
-                        },
-                    )),
-                );
+    let array_events: Vec<DepositEvent> = filtered_events.values().cloned().collect();
+    guard.record_items(array_events.len());
+
+    // the NFT canister deduplicates deposits by their signature, so batches are
+    // independent and can be minted in parallel
+    let parallelism = read_state(|s| s.mint_parallelism);
+    let batches: Vec<&[DepositEvent]> = array_events.chunks(MAX_DEPOSITS_PER_MINT_CALL).collect();
+    for round in batches.chunks(parallelism) {
+        let results = futures::future::join_all(round.iter().map(|batch| {
+            guard.record_outcall();
+            mint_batch(batch)
+        }))
+        .await;
+
+        for result in results {
+            match result {
+                Ok(result) => process_mint_results(&filtered_events, result),
+                Err(err) => {
+                    crate::logs::log!(DEBUG, "failed to mint bton, error: {:?}", err);
+                    guard.record_error(format!("failed to mint bton: {err:?}"));
+                }
             }
         }
     }
@@ -437,9 +646,91 @@ pub async fn mint_gsol() {
     // }
 }
 
+// queries the transfer fee of the gSOL ledger again once the cached one is older than
+// LEDGER_FEE_CACHE_TTL, the cached one is kept when the query fails
+async fn refresh_ledger_fee(guard: &TimerGuard, now: u64) {
+    let ledger_id = match read_state(|s| s.ledger_id) {
+        Some(ledger_id) => ledger_id,
+        None => return,
+    };
+    if let Some((_, queried_at)) = read_state(|s| s.ledger_fee.clone()) {
+        if now.saturating_sub(queried_at) < LEDGER_FEE_CACHE_TTL.as_nanos() as u64 {
+            return;
+        }
+    }
+
+    guard.record_outcall();
+    match ic_cdk::call::<_, (Nat,)>(ledger_id, "icrc1_fee", ()).await {
+        Ok((fee,)) => mutate_state(|s| s.ledger_fee = Some((fee, now))),
+        Err((code, msg)) => {
+            crate::logs::log!(INFO, "\nFailed to get the ledger fee: {code:?}: {msg}");
+        }
+    }
+}
+
+pub async fn mint_batch(
+    events: &[DepositEvent],
+) -> Result<Vec<Result<String, (String, String)>>, (RejectionCode, String)> {
+    let bton_events = serde_cbor::to_vec(events).unwrap();
+
+    if is_debug_enabled() {
+        ic_cdk::println!("bton_events: {:?}", events);
+        ic_cdk::println!("bton_events_len: {:?}", bton_events.len());
+        ic_cdk::println!("bton_events_hex: {:?}", hex::encode(bton_events.as_slice()));
+    }
+
+    let (result,): (Vec<Result<String, (String, String)>>,) = ic_cdk::call(
+        get_btown_nft_canister(),
+        "deposit_coins_from_solana",
+        (bton_events.as_slice(),),
+    )
+    .await?;
+
+    Ok(result)
+}
+
+fn process_mint_results(
+    filtered_events: &HashMap<String, DepositEvent>,
+    result: Vec<Result<String, (String, String)>>,
+) {
+    for res in result {
+        match res {
+            Ok(sig) => {
+                let mut event = filtered_events.get(&sig).unwrap().clone(); // Clone the event to make it mutable
+                event.update_mint_block_index(0);
+                event.update_minted_at(ic_cdk::api::time());
+                process_minted_event(&event);
+            }
+            Err((sig, err)) => {
+                let event = filtered_events.get(&sig).unwrap().clone(); // Clone the event to make it mutable
+                let permanent = is_permanent_mint_error(&err);
+                let err = DepositError::MintingGSolFailed(TransferError::GenericError {
+                    error_code: Nat::from(0u8),
+                    message: err,
+                });
+                if permanent {
+                    process_dead_letter(&event, err);
+                } else {
+                    process_accepted_event(&event, Some(err));
+                }
+            }
+        }
+    }
+}
+
+// remember when the entries were attempted, so that their next retry can be delayed
+fn record_attempts(targets: impl Iterator<Item = RetryTarget>, now: u64) {
+    mutate_state(|s| {
+        s.prune_attempts();
+        for target in targets {
+            s.record_attempt(target, now);
+        }
+    });
+}
+
 /// Process events
 fn process_minted_event(event: &DepositEvent) {
-    ic_canister_log::log!(
+    crate::logs::log!(
         DEBUG,
         "\nProcessed Signature: {}\n\tMinted amount: {}\n\tto {}\n\tin block {}",
         event.sol_sig,
@@ -460,9 +751,9 @@ fn process_minted_event(event: &DepositEvent) {
 
 fn process_accepted_event(event: &DepositEvent, err: Option<DepositError>) {
     if let Some(err) = err.clone() {
-        ic_canister_log::log!(DEBUG, "{err}");
+        crate::logs::log!(DEBUG, "{err}");
     } else {
-        ic_canister_log::log!(
+        crate::logs::log!(
             DEBUG,
             "\nSignature {} : Deposit transaction found",
             event.sol_sig
@@ -480,15 +771,39 @@ fn process_accepted_event(event: &DepositEvent, err: Option<DepositError>) {
     });
 }
 
+// the NFT canister reports the ledger errors of the mints as text
+fn is_permanent_mint_error(error: &str) -> bool {
+    PERMANENT_MINT_ERRORS
+        .iter()
+        .any(|permanent| error.contains(permanent))
+}
+
+fn process_dead_letter(event: &DepositEvent, err: DepositError) {
+    crate::logs::log!(INFO, "\nSignature {} : dead-lettered: {err}", event.sol_sig);
+
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::DepositDeadLettered {
+                sol_sig: event.sol_sig.clone(),
+                error: err.to_string(),
+            },
+        )
+    });
+}
+
 fn process_invalid_event(signature: &SolanaSignature, err: DepositError) {
-    ic_canister_log::log!(DEBUG, "\nSignature {} : {err}", signature.sol_sig);
+    crate::logs::log!(DEBUG, "\nSignature {} : {err}", signature.sol_sig);
 
+    let mut signature = signature.clone();
+    signature.update_invalidated_at(ic_cdk::api::time());
     mutate_state(|s| {
         process_event(
             s,
             EventType::InvalidEvent {
-                signature: signature.clone(),
+                signature,
                 fail_reason: err.to_string(),
+                reason: Some(err.invalid_reason()),
             },
         );
     });
@@ -496,9 +811,9 @@ fn process_invalid_event(signature: &SolanaSignature, err: DepositError) {
 
 fn process_solana_signature(signature: &SolanaSignature, err: Option<DepositError>) {
     if let Some(err) = err.clone() {
-        ic_canister_log::log!(DEBUG, "{err}");
+        crate::logs::log!(DEBUG, "{err}");
     } else {
-        ic_canister_log::log!(
+        crate::logs::log!(
             INFO,
             "\nSignature {} : Transaction found",
             signature.sol_sig
@@ -516,8 +831,25 @@ fn process_solana_signature(signature: &SolanaSignature, err: Option<DepositErro
     });
 }
 
-fn process_new_solana_signature_range(newest_signature: &str, until_signature: &str) {
-    ic_canister_log::log!(DEBUG, "\nNew signature found: {newest_signature}",);
+fn process_transaction_not_found(signature: &SolanaSignature) {
+    crate::logs::log!(
+        DEBUG,
+        "\nSignature {} : transaction not indexed yet",
+        signature.sol_sig
+    );
+
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::SolanaTransactionNotFound {
+                sol_sig: signature.sol_sig.clone(),
+            },
+        )
+    });
+}
+
+fn process_new_solana_signature_range(newest_signature: &str, until_signature: &str, slot: u64) {
+    crate::logs::log!(DEBUG, "\nNew signature found: {newest_signature}",);
 
     mutate_state(|s| {
         process_event(
@@ -526,10 +858,13 @@ fn process_new_solana_signature_range(newest_signature: &str, until_signature: &
         );
         process_event(
             s,
-            EventType::NewSolanaSignatureRange(SolanaSignatureRange::new(
-                newest_signature.to_string(),
-                until_signature.to_string(),
-            )),
+            EventType::NewSolanaSignatureRange(
+                SolanaSignatureRange::new(
+                    newest_signature.to_string(),
+                    until_signature.to_string(),
+                )
+                .with_observed_slot(Some(slot)),
+            ),
         );
     });
 }
@@ -541,25 +876,46 @@ fn process_retry_solana_signature_range(
     error: DepositError,
 ) {
     let error_msg = format!("\nFailed to get signatures for address:\n\tbefore: {before_signature}\n\tuntil: {until_signature}\n\terror: {error:?}");
-    ic_canister_log::log!(DEBUG, "{error_msg}");
+    crate::logs::log!(DEBUG, "{error_msg}");
 
     mutate_state(|s| {
         process_event(
             s,
             EventType::RetrySolanaSignatureRange {
                 range: range.clone(),
-                failed_sub_range: Some(SolanaSignatureRange::new(
-                    before_signature.to_string(),
-                    until_signature.to_string(),
-                )),
+                failed_sub_range: Some(
+                    SolanaSignatureRange::new(
+                        before_signature.to_string(),
+                        until_signature.to_string(),
+                    )
+                    .with_observed_slot(range.observed_slot),
+                ),
                 fail_reason: error_msg,
             },
         )
     });
 }
 
+fn advance_solana_signature_range(range: &SolanaSignatureRange, before_signature: &str) {
+    crate::logs::log!(
+        DEBUG,
+        "\nRange paused:\n\tbefore: {before_signature}\n\tuntil: {}",
+        range.until_sol_sig,
+    );
+
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::SolanaSignatureRangeAdvanced {
+                range: range.clone(),
+                before_sol_sig: before_signature.to_string(),
+            },
+        );
+    });
+}
+
 fn remove_solana_signature_range(range: &SolanaSignatureRange) {
-    ic_canister_log::log!(
+    crate::logs::log!(
         DEBUG,
         "\nRange completed:\n\tbefore: {}\n\tuntil: {}",
         range.before_sol_sig,
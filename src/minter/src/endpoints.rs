@@ -0,0 +1,1241 @@
+use crate::assets::BridgedAsset;
+use crate::constants::MINIMUM_DEPOSIT_LAMPORTS;
+use crate::cycles::{CyclesMonitorConfig, CyclesReport, CyclesTopUpConfig};
+use crate::events::{
+    DeadLetter, DepositEvent, InvalidReason, PrincipalEncoding, RetryPolicy, SolanaSignature,
+    SolanaSignatureRange, WithdrawalEvent,
+};
+use crate::governance::ConfigProposal;
+use crate::guard::RateLimit;
+use crate::lifecycle::{InitArg, UpgradeArg, UpgradeAudit};
+use crate::logs::LogLevel;
+use crate::reconciliation::SupplyReconciliation;
+use crate::sol_rpc_client::{types::ConfirmationStatus, ProxyTokenConfig};
+use crate::state::event::{Event, EventType};
+use crate::state::{
+    DestinationAddress, EcdsaKeyError, PreviousEcdsaKey, RoleGrant, State, TaskType, TimerIntervals,
+};
+use crate::subscriptions::Subscription;
+use crate::withdraw::Coupon;
+
+use candid::{CandidType, Deserialize, Nat, Principal};
+use num_bigint::BigUint;
+use serde_bytes::ByteBuf;
+use strum::IntoEnumIterator;
+
+// Candid representation of the event log, following the ckETH minter `get_events` interface.
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetEventsArg {
+    pub start: u64,
+    pub length: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetEventsResult {
+    pub events: Vec<CandidEvent>,
+    pub total_event_count: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetFilteredEventsResult {
+    /// In the order they were recorded.
+    pub events: Vec<CandidEvent>,
+    /// Block id the next page resumes the scan from, None once the time range is scanned
+    /// to the end of the log.
+    pub next_start: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetInvalidEventsResult {
+    /// Ordered by Solana signature.
+    pub signatures: Vec<CandidSolanaSignature>,
+    /// Number of invalid events matching the filter.
+    pub total_count: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetArchivedInvalidEventsResult {
+    /// In the order of their archival.
+    pub signatures: Vec<CandidSolanaSignature>,
+    pub total_count: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CandidEvent {
+    pub timestamp: u64,
+    pub payload: EventPayload,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CandidSolanaSignatureRange {
+    pub before_sol_sig: String,
+    pub until_sol_sig: String,
+    pub retries: u8,
+    pub observed_slot: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CandidSolanaSignature {
+    pub sol_sig: String,
+    pub retries: u8,
+    pub not_found_lookups: u8,
+    /// Only set on invalid events.
+    pub invalid_reason: Option<InvalidReason>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CandidDepositEvent {
+    pub id: u64,
+    pub from_sol_address: String,
+    pub to_icp_address: Principal,
+    pub amount: Nat,
+    pub sol_sig: String,
+    pub icp_mint_block_index: Option<u64>,
+    pub retries: u8,
+    pub solana_slot: Option<u64>,
+    pub solana_block_time: Option<u64>,
+    pub ledger_fee: Option<Nat>,
+    pub principal_encoding: Option<PrincipalEncoding>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CandidDeadLetter {
+    pub deposit: CandidDepositEvent,
+    /// Error the last mint attempt failed with.
+    pub error: String,
+}
+
+/// Classification of a transaction by the deposit parser.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum SimulatedParse {
+    Deposit(CandidDepositEvent),
+    Invalid { reason: String, code: InvalidReason },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CandidWithdrawalEvent {
+    pub burn_id: u64,
+    pub from_icp_address: Principal,
+    pub to_sol_address: String,
+    pub amount: Nat,
+    pub burn_timestamp: Option<u64>,
+    pub icp_burn_block_index: Option<u64>,
+    pub spl_mint: Option<String>,
+    pub to_token_account: Option<String>,
+    pub fee: Option<Nat>,
+    pub coupon: Option<Coupon>,
+    pub retries: u8,
+    pub solana_slot: Option<u64>,
+    pub solana_block_time: Option<u64>,
+}
+
+/// Deposit whose gSOL was minted.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MintedDeposit {
+    pub deposit_id: u64,
+    pub sol_sig: String,
+    pub from_sol_address: String,
+    pub to_icp_address: Principal,
+    pub amount: Nat,
+    /// Transfer fee recorded by earlier minters, mints from the minting account are
+    /// fee-free.
+    pub ledger_fee: Option<Nat>,
+    pub mint_block_index: Option<u64>,
+    pub solana_slot: Option<u64>,
+    pub solana_block_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MintedDepositsPage {
+    /// Ordered by deposit id, i.e. in minting order.
+    pub deposits: Vec<MintedDeposit>,
+    /// Cursor of the next page, None on the last page.
+    pub next_cursor: Option<u64>,
+}
+
+/// Withdrawal whose coupon was redeemed on Solana.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RedeemedWithdrawal {
+    pub burn_id: u64,
+    pub from_icp_address: Principal,
+    pub to_sol_address: String,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub spl_mint: Option<String>,
+    pub to_token_account: Option<String>,
+    pub burn_timestamp: Option<u64>,
+    pub burn_block_index: Option<u64>,
+    /// Latest Solana block observed when the coupon was issued.
+    pub solana_slot: Option<u64>,
+    pub solana_block_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RedeemedWithdrawalsPage {
+    /// Ordered by burn id.
+    pub withdrawals: Vec<RedeemedWithdrawal>,
+    /// Cursor of the next page, None on the last page.
+    pub next_cursor: Option<u64>,
+}
+
+/// Burned withdrawal whose coupon is not issued before `release_at`.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TimeLockedWithdrawal {
+    pub withdrawal: CandidWithdrawalEvent,
+    pub release_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum EventPayload {
+    Init(InitArg),
+    Upgrade(UpgradeArg),
+    LastKnownSolanaSignature(String),
+    LastDepositIdCounter(u64),
+    LastBurnIdCounter(u64),
+    NewSolanaSignatureRange(CandidSolanaSignatureRange),
+    RemoveSolanaSignatureRange(CandidSolanaSignatureRange),
+    RetrySolanaSignatureRange {
+        range: CandidSolanaSignatureRange,
+        failed_sub_range: Option<CandidSolanaSignatureRange>,
+        fail_reason: String,
+    },
+    SolanaSignature {
+        signature: CandidSolanaSignature,
+        fail_reason: Option<String>,
+    },
+    InvalidEvent {
+        signature: CandidSolanaSignature,
+        fail_reason: String,
+        reason: Option<InvalidReason>,
+    },
+    AcceptedEvent {
+        event_source: CandidDepositEvent,
+        fail_reason: Option<String>,
+    },
+    MintedEvent {
+        event_source: CandidDepositEvent,
+    },
+    WithdrawalBurnedEvent {
+        event_source: CandidWithdrawalEvent,
+        fail_reason: Option<String>,
+    },
+    WithdrawalRedeemedEvent {
+        event_source: CandidWithdrawalEvent,
+    },
+    AddBlockedAddress(String),
+    RemoveBlockedAddress(String),
+    WithdrawalRejected {
+        from: Principal,
+        to_sol_address: String,
+        reason: String,
+    },
+    Checkpoint {
+        deposit_id_counter: u64,
+        burn_id_counter: u64,
+    },
+    PruneTerminalEvents {
+        minted: Vec<String>,
+        redeemed: Vec<u64>,
+    },
+    SupplyDriftDetected {
+        vault_balance: Nat,
+        total_supply: Nat,
+        tolerance: Nat,
+    },
+    InvariantViolated {
+        invariant: String,
+        details: String,
+    },
+    GrantRole(RoleGrant),
+    RevokeRole(Principal),
+    Paused {
+        reason: String,
+    },
+    Resumed {
+        by: Principal,
+    },
+    EcdsaKeyRotated {
+        key_name: String,
+        previous: PreviousEcdsaKey,
+    },
+    CouponResigned {
+        event_source: CandidWithdrawalEvent,
+    },
+    EcdsaPublicKeyFetched {
+        key_name: String,
+        public_key: String,
+        proxy_public_key: String,
+        cosigner_public_key: Option<String>,
+    },
+    TimerIntervalsChanged {
+        intervals: TimerIntervals,
+    },
+    LowCyclesBalance {
+        balance: Nat,
+        runway_secs: u64,
+    },
+    CyclesToppedUp {
+        cycles: Nat,
+        balance: Nat,
+    },
+    StateDigest {
+        sha256: String,
+        version: Option<u32>,
+    },
+    LogLevelChanged {
+        level: LogLevel,
+    },
+    AssetRegistered(BridgedAsset),
+    AssetRemoved {
+        spl_mint: String,
+    },
+    Subscribed(Subscription),
+    Unsubscribed {
+        subscriber: Principal,
+        reason: Option<String>,
+    },
+    EventsDelivered {
+        subscriber: Principal,
+        cursor: u64,
+    },
+    ConfigChangeProposed(ConfigProposal),
+    ConfigChangeExecuted {
+        id: u64,
+        executor: Principal,
+    },
+    ConfigChangeCancelled {
+        id: u64,
+        canceller: Principal,
+    },
+    SolanaSignatureRangeAdvanced {
+        range: CandidSolanaSignatureRange,
+        before_sol_sig: String,
+    },
+    ArchiveInvalidEvents {
+        signatures: Vec<String>,
+    },
+    LastKnownSolanaSignatureOverridden {
+        signature: String,
+        previous: String,
+        caller: Principal,
+    },
+    SignatureGapDetected {
+        before_sol_sig: String,
+        until_sol_sig: String,
+        next_sol_sig: String,
+    },
+    SolanaSignatureRangeRescanRequested {
+        range: CandidSolanaSignatureRange,
+        caller: Principal,
+    },
+    SolanaTransactionNotFound {
+        sol_sig: String,
+    },
+    DepositDeadLettered {
+        sol_sig: String,
+        error: String,
+    },
+    DeadLetterRequeued {
+        sol_sig: String,
+        caller: Principal,
+    },
+    WithdrawalHeldForApproval {
+        burn_id: u64,
+    },
+    WithdrawalApproved {
+        burn_id: u64,
+        caller: Principal,
+    },
+    WithdrawalApprovalRejected {
+        burn_id: u64,
+        reason: String,
+        caller: Principal,
+    },
+    DestinationAddressAdded {
+        owner: Principal,
+        destination: DestinationAddress,
+    },
+    DestinationAddressRemoved {
+        owner: Principal,
+        address: String,
+    },
+    WithdrawalTimeLocked {
+        burn_id: u64,
+        release_at: u64,
+    },
+    WithdrawalCancelled {
+        burn_id: u64,
+        reason: String,
+        caller: Principal,
+        cancelled_at: Option<u64>,
+    },
+    CancelledWithdrawalReminted {
+        burn_id: u64,
+        block_index: Option<u64>,
+    },
+    CouponRevoked {
+        burn_id: u64,
+        caller: Principal,
+    },
+    CouponRevocationLifted {
+        burn_id: u64,
+        caller: Principal,
+    },
+    EventsArchived {
+        archive: Principal,
+        start: u64,
+        end: u64,
+    },
+    RemintAttempted {
+        burn_id: u64,
+        created_at: Option<u64>,
+    },
+    RemintFailed {
+        burn_id: u64,
+        reason: String,
+    },
+    CouponRedemptionReported {
+        burn_id: u64,
+        caller: Principal,
+    },
+}
+
+impl From<&SolanaSignatureRange> for CandidSolanaSignatureRange {
+    fn from(range: &SolanaSignatureRange) -> Self {
+        Self {
+            before_sol_sig: range.before_sol_sig.clone(),
+            until_sol_sig: range.until_sol_sig.clone(),
+            retries: range.retry.get_retries(),
+            observed_slot: range.observed_slot,
+        }
+    }
+}
+
+impl From<&SolanaSignature> for CandidSolanaSignature {
+    fn from(signature: &SolanaSignature) -> Self {
+        Self {
+            sol_sig: signature.sol_sig.clone(),
+            retries: signature.retry.get_retries(),
+            not_found_lookups: signature.get_not_found_lookups(),
+            invalid_reason: signature.invalid_reason.clone(),
+        }
+    }
+}
+
+impl From<&DepositEvent> for CandidDepositEvent {
+    fn from(event: &DepositEvent) -> Self {
+        Self {
+            id: event.id,
+            from_sol_address: event.from_sol_address.clone(),
+            to_icp_address: event.to_icp_address,
+            amount: event.amount.clone(),
+            sol_sig: event.sol_sig.clone(),
+            icp_mint_block_index: event.get_mint_block_index(),
+            retries: event.retry.get_retries(),
+            solana_slot: event.solana_block.map(|block| block.slot),
+            solana_block_time: event.solana_block.map(|block| block.block_time),
+            ledger_fee: event.ledger_fee.clone(),
+            principal_encoding: event.principal_encoding,
+        }
+    }
+}
+
+impl From<&DepositEvent> for MintedDeposit {
+    fn from(event: &DepositEvent) -> Self {
+        Self {
+            deposit_id: event.id,
+            sol_sig: event.sol_sig.clone(),
+            from_sol_address: event.from_sol_address.clone(),
+            to_icp_address: event.to_icp_address,
+            amount: event.amount.clone(),
+            ledger_fee: event.ledger_fee.clone(),
+            mint_block_index: event.get_mint_block_index(),
+            solana_slot: event.solana_block.map(|block| block.slot),
+            solana_block_time: event.solana_block.map(|block| block.block_time),
+        }
+    }
+}
+
+impl From<&WithdrawalEvent> for RedeemedWithdrawal {
+    fn from(event: &WithdrawalEvent) -> Self {
+        Self {
+            burn_id: event.get_burn_id(),
+            from_icp_address: event.from_icp_address,
+            to_sol_address: event.to_sol_address.to_string(),
+            amount: event.amount.clone(),
+            fee: event.fee.clone(),
+            spl_mint: event.spl_mint.clone(),
+            to_token_account: event.to_token_account.clone(),
+            burn_timestamp: event.get_burn_timestamp(),
+            burn_block_index: event.get_icp_burn_block_index(),
+            solana_slot: event.solana_block.map(|block| block.slot),
+            solana_block_time: event.solana_block.map(|block| block.block_time),
+        }
+    }
+}
+
+impl From<&DeadLetter> for CandidDeadLetter {
+    fn from(dead_letter: &DeadLetter) -> Self {
+        Self {
+            deposit: CandidDepositEvent::from(&dead_letter.deposit),
+            error: dead_letter.error.clone(),
+        }
+    }
+}
+
+impl From<&WithdrawalEvent> for CandidWithdrawalEvent {
+    fn from(event: &WithdrawalEvent) -> Self {
+        Self {
+            burn_id: event.get_burn_id(),
+            from_icp_address: event.from_icp_address,
+            to_sol_address: event.to_sol_address.to_string(),
+            amount: event.amount.clone(),
+            burn_timestamp: event.get_burn_timestamp(),
+            icp_burn_block_index: event.get_icp_burn_block_index(),
+            spl_mint: event.spl_mint.clone(),
+            to_token_account: event.to_token_account.clone(),
+            fee: event.fee.clone(),
+            coupon: event.get_coupon().cloned(),
+            retries: event.retry.get_retries(),
+            solana_slot: event.solana_block.map(|block| block.slot),
+            solana_block_time: event.solana_block.map(|block| block.block_time),
+        }
+    }
+}
+
+impl From<&EventType> for EventPayload {
+    fn from(payload: &EventType) -> Self {
+        match payload {
+            EventType::Init(arg) => EventPayload::Init(arg.clone()),
+            EventType::Upgrade(arg) => EventPayload::Upgrade(arg.clone()),
+            EventType::LastKnownSolanaSignature(sig) => {
+                EventPayload::LastKnownSolanaSignature(sig.clone())
+            }
+            EventType::LastDepositIdCounter(id) => EventPayload::LastDepositIdCounter(*id),
+            EventType::LastBurnIdCounter(id) => EventPayload::LastBurnIdCounter(*id),
+            EventType::NewSolanaSignatureRange(range) => {
+                EventPayload::NewSolanaSignatureRange(range.into())
+            }
+            EventType::RemoveSolanaSignatureRange(range) => {
+                EventPayload::RemoveSolanaSignatureRange(range.into())
+            }
+            EventType::RetrySolanaSignatureRange {
+                range,
+                failed_sub_range,
+                fail_reason,
+            } => EventPayload::RetrySolanaSignatureRange {
+                range: range.into(),
+                failed_sub_range: failed_sub_range.as_ref().map(Into::into),
+                fail_reason: fail_reason.clone(),
+            },
+            EventType::SolanaSignature {
+                signature,
+                fail_reason,
+            } => EventPayload::SolanaSignature {
+                signature: signature.into(),
+                fail_reason: fail_reason.clone(),
+            },
+            EventType::InvalidEvent {
+                signature,
+                fail_reason,
+                reason,
+            } => EventPayload::InvalidEvent {
+                signature: signature.into(),
+                fail_reason: fail_reason.clone(),
+                reason: reason.clone(),
+            },
+            EventType::AcceptedEvent {
+                event_source,
+                fail_reason,
+            } => EventPayload::AcceptedEvent {
+                event_source: event_source.into(),
+                fail_reason: fail_reason.clone(),
+            },
+            EventType::MintedEvent { event_source } => EventPayload::MintedEvent {
+                event_source: event_source.into(),
+            },
+            EventType::WithdrawalBurnedEvent {
+                event_source,
+                fail_reason,
+            } => EventPayload::WithdrawalBurnedEvent {
+                event_source: event_source.into(),
+                fail_reason: fail_reason.clone(),
+            },
+            EventType::WithdrawalRedeemedEvent { event_source } => {
+                EventPayload::WithdrawalRedeemedEvent {
+                    event_source: event_source.into(),
+                }
+            }
+            EventType::AddBlockedAddress(address) => {
+                EventPayload::AddBlockedAddress(address.clone())
+            }
+            EventType::RemoveBlockedAddress(address) => {
+                EventPayload::RemoveBlockedAddress(address.clone())
+            }
+            EventType::WithdrawalRejected {
+                from,
+                to_sol_address,
+                reason,
+            } => EventPayload::WithdrawalRejected {
+                from: *from,
+                to_sol_address: to_sol_address.clone(),
+                reason: reason.clone(),
+            },
+            EventType::Checkpoint(snapshot) => EventPayload::Checkpoint {
+                deposit_id_counter: snapshot.deposit_id_counter,
+                burn_id_counter: snapshot.burn_id_counter,
+            },
+            EventType::PruneTerminalEvents { minted, redeemed } => {
+                EventPayload::PruneTerminalEvents {
+                    minted: minted.clone(),
+                    redeemed: redeemed.clone(),
+                }
+            }
+            EventType::SupplyDriftDetected {
+                vault_balance,
+                total_supply,
+                tolerance,
+            } => EventPayload::SupplyDriftDetected {
+                vault_balance: vault_balance.clone(),
+                total_supply: total_supply.clone(),
+                tolerance: tolerance.clone(),
+            },
+            EventType::InvariantViolated { invariant, details } => {
+                EventPayload::InvariantViolated {
+                    invariant: invariant.clone(),
+                    details: details.clone(),
+                }
+            }
+            EventType::GrantRole(grant) => EventPayload::GrantRole(grant.clone()),
+            EventType::RevokeRole(principal) => EventPayload::RevokeRole(*principal),
+            EventType::Paused { reason } => EventPayload::Paused {
+                reason: reason.clone(),
+            },
+            EventType::Resumed { by } => EventPayload::Resumed { by: *by },
+            EventType::EcdsaKeyRotated { key_name, previous } => EventPayload::EcdsaKeyRotated {
+                key_name: key_name.clone(),
+                previous: previous.clone(),
+            },
+            EventType::CouponResigned { event_source } => EventPayload::CouponResigned {
+                event_source: event_source.into(),
+            },
+            EventType::EcdsaPublicKeyFetched { key_name, key } => {
+                EventPayload::EcdsaPublicKeyFetched {
+                    key_name: key_name.clone(),
+                    public_key: hex::encode(&key.public_key),
+                    proxy_public_key: key.proxy_public_key.clone(),
+                    cosigner_public_key: key.cosigner_public_key_hex.clone(),
+                }
+            }
+            EventType::TimerIntervalsChanged { intervals } => EventPayload::TimerIntervalsChanged {
+                intervals: intervals.clone(),
+            },
+            EventType::LowCyclesBalance {
+                balance,
+                runway_secs,
+            } => EventPayload::LowCyclesBalance {
+                balance: balance.clone(),
+                runway_secs: *runway_secs,
+            },
+            EventType::CyclesToppedUp { cycles, balance } => EventPayload::CyclesToppedUp {
+                cycles: cycles.clone(),
+                balance: balance.clone(),
+            },
+            EventType::StateDigest { sha256, version } => EventPayload::StateDigest {
+                sha256: hex::encode(sha256),
+                version: *version,
+            },
+            EventType::LogLevelChanged { level } => EventPayload::LogLevelChanged { level: *level },
+            EventType::AssetRegistered(asset) => EventPayload::AssetRegistered(asset.clone()),
+            EventType::AssetRemoved { spl_mint } => EventPayload::AssetRemoved {
+                spl_mint: spl_mint.clone(),
+            },
+            EventType::Subscribed(subscription) => EventPayload::Subscribed(subscription.clone()),
+            EventType::Unsubscribed { subscriber, reason } => EventPayload::Unsubscribed {
+                subscriber: *subscriber,
+                reason: reason.clone(),
+            },
+            EventType::EventsDelivered { subscriber, cursor } => EventPayload::EventsDelivered {
+                subscriber: *subscriber,
+                cursor: *cursor,
+            },
+            EventType::ConfigChangeProposed(proposal) => {
+                EventPayload::ConfigChangeProposed(proposal.clone())
+            }
+            EventType::ConfigChangeExecuted { id, executor } => {
+                EventPayload::ConfigChangeExecuted {
+                    id: *id,
+                    executor: *executor,
+                }
+            }
+            EventType::ConfigChangeCancelled { id, canceller } => {
+                EventPayload::ConfigChangeCancelled {
+                    id: *id,
+                    canceller: *canceller,
+                }
+            }
+            EventType::SolanaSignatureRangeAdvanced {
+                range,
+                before_sol_sig,
+            } => EventPayload::SolanaSignatureRangeAdvanced {
+                range: range.into(),
+                before_sol_sig: before_sol_sig.clone(),
+            },
+            EventType::ArchiveInvalidEvents { signatures } => EventPayload::ArchiveInvalidEvents {
+                signatures: signatures.clone(),
+            },
+            EventType::LastKnownSolanaSignatureOverridden {
+                signature,
+                previous,
+                caller,
+            } => EventPayload::LastKnownSolanaSignatureOverridden {
+                signature: signature.clone(),
+                previous: previous.clone(),
+                caller: *caller,
+            },
+            EventType::SignatureGapDetected {
+                before_sol_sig,
+                until_sol_sig,
+                next_sol_sig,
+            } => EventPayload::SignatureGapDetected {
+                before_sol_sig: before_sol_sig.clone(),
+                until_sol_sig: until_sol_sig.clone(),
+                next_sol_sig: next_sol_sig.clone(),
+            },
+            EventType::SolanaTransactionNotFound { sol_sig } => {
+                EventPayload::SolanaTransactionNotFound {
+                    sol_sig: sol_sig.clone(),
+                }
+            }
+            EventType::DepositDeadLettered { sol_sig, error } => {
+                EventPayload::DepositDeadLettered {
+                    sol_sig: sol_sig.clone(),
+                    error: error.clone(),
+                }
+            }
+            EventType::DeadLetterRequeued { sol_sig, caller } => EventPayload::DeadLetterRequeued {
+                sol_sig: sol_sig.clone(),
+                caller: *caller,
+            },
+            EventType::WithdrawalHeldForApproval { burn_id } => {
+                EventPayload::WithdrawalHeldForApproval { burn_id: *burn_id }
+            }
+            EventType::WithdrawalApproved { burn_id, caller } => EventPayload::WithdrawalApproved {
+                burn_id: *burn_id,
+                caller: *caller,
+            },
+            EventType::WithdrawalApprovalRejected {
+                burn_id,
+                reason,
+                caller,
+            } => EventPayload::WithdrawalApprovalRejected {
+                burn_id: *burn_id,
+                reason: reason.clone(),
+                caller: *caller,
+            },
+            EventType::DestinationAddressAdded { owner, destination } => {
+                EventPayload::DestinationAddressAdded {
+                    owner: *owner,
+                    destination: destination.clone(),
+                }
+            }
+            EventType::DestinationAddressRemoved { owner, address } => {
+                EventPayload::DestinationAddressRemoved {
+                    owner: *owner,
+                    address: address.clone(),
+                }
+            }
+            EventType::WithdrawalTimeLocked {
+                burn_id,
+                release_at,
+            } => EventPayload::WithdrawalTimeLocked {
+                burn_id: *burn_id,
+                release_at: *release_at,
+            },
+            EventType::WithdrawalCancelled {
+                burn_id,
+                reason,
+                caller,
+                cancelled_at,
+            } => EventPayload::WithdrawalCancelled {
+                burn_id: *burn_id,
+                reason: reason.clone(),
+                caller: *caller,
+                cancelled_at: *cancelled_at,
+            },
+            EventType::CancelledWithdrawalReminted {
+                burn_id,
+                block_index,
+            } => EventPayload::CancelledWithdrawalReminted {
+                burn_id: *burn_id,
+                block_index: *block_index,
+            },
+            EventType::CouponRevoked { burn_id, caller } => EventPayload::CouponRevoked {
+                burn_id: *burn_id,
+                caller: *caller,
+            },
+            EventType::CouponRevocationLifted { burn_id, caller } => {
+                EventPayload::CouponRevocationLifted {
+                    burn_id: *burn_id,
+                    caller: *caller,
+                }
+            }
+            EventType::EventsArchived {
+                archive,
+                start,
+                end,
+            } => EventPayload::EventsArchived {
+                archive: *archive,
+                start: *start,
+                end: *end,
+            },
+            EventType::RemintAttempted {
+                burn_id,
+                created_at,
+            } => EventPayload::RemintAttempted {
+                burn_id: *burn_id,
+                created_at: *created_at,
+            },
+            EventType::RemintFailed { burn_id, reason } => EventPayload::RemintFailed {
+                burn_id: *burn_id,
+                reason: reason.clone(),
+            },
+            EventType::CouponRedemptionReported { burn_id, caller } => {
+                EventPayload::CouponRedemptionReported {
+                    burn_id: *burn_id,
+                    caller: *caller,
+                }
+            }
+            EventType::SolanaSignatureRangeRescanRequested { range, caller } => {
+                EventPayload::SolanaSignatureRangeRescanRequested {
+                    range: range.into(),
+                    caller: *caller,
+                }
+            }
+        }
+    }
+}
+
+impl From<&Event> for CandidEvent {
+    fn from(event: &Event) -> Self {
+        Self {
+            timestamp: event.timestamp,
+            payload: (&event.payload).into(),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MinterState {
+    // solana config
+    pub solana_rpc_url: String,
+    pub solana_contract_address: String,
+    pub solana_initial_signature: String,
+    pub solana_last_known_signature: String,
+
+    // icp config
+    pub ecdsa_key_name: String,
+    pub ecdsa_public_key: Option<String>,
+    pub ecdsa_proxy_public_key: Option<String>,
+    pub previous_ecdsa_key: Option<PreviousEcdsaKey>,
+    pub minimum_withdrawal_amount: Nat,
+    pub ledger_id: Option<Principal>,
+    pub max_withdrawals_per_principal: u64,
+    pub terminal_events_retention: Option<u64>,
+    pub terminal_events_max_age_secs: Option<u64>,
+    pub solana_vault_address: Option<String>,
+    pub reconciliation_tolerance: Nat,
+    pub last_reconciliation: Option<SupplyReconciliation>,
+    pub cycles_monitor: CyclesMonitorConfig,
+    pub last_cycles_report: Option<CyclesReport>,
+    pub cycles_top_up: Option<CyclesTopUpConfig>,
+    pub upgrade_audit: UpgradeAudit,
+    pub log_level: LogLevel,
+    pub trusted_origins: Vec<String>,
+    pub retry_policy: RetryPolicy,
+    pub rate_limit: Option<RateLimit>,
+    pub task_lock_timeout_secs: u64,
+    pub config_timelock_secs: u64,
+    pub mint_parallelism: u64,
+    pub proxy_token: ProxyTokenConfig,
+    pub timer_intervals: TimerIntervals,
+    pub checkpoint_interval_events: u64,
+    pub checkpoint_interval_secs: u64,
+    pub event_log_max_bytes: Option<u64>,
+    pub event_archive: Option<Principal>,
+    pub commitment: ConfirmationStatus,
+    pub max_deposit_amount: Option<Nat>,
+    pub max_withdrawal_amount: Option<Nat>,
+    pub dual_signature_threshold: Option<Nat>,
+    pub approval_threshold: Option<Nat>,
+    pub release_delay_threshold: Option<Nat>,
+    pub release_delay_secs: u64,
+    pub paused: Option<String>,
+
+    // backlog sizes
+    pub solana_signature_ranges: u64,
+    pub solana_signatures: u64,
+    pub invalid_events: u64,
+    pub archived_invalid_events: u64,
+    pub invalid_events_retention_secs: u64,
+    pub destination_activation_delay_secs: u64,
+    pub address_books: u64,
+    pub accepted_events: u64,
+    pub dead_letters: u64,
+    pub withdrawals_awaiting_approval: u64,
+    pub time_locked_withdrawals: u64,
+    pub cancelled_withdrawals: u64,
+    pub minted_events: u64,
+    pub withdrawal_burned_events: u64,
+    pub withdrawal_redeemed_events: u64,
+
+    pub withdrawing_principals: Vec<(Principal, u64)>,
+    pub blocked_addresses: Vec<String>,
+    pub revoked_coupons: Vec<u64>,
+
+    // counters
+    pub deposit_id_counter: u64,
+    pub burn_id_counter: u64,
+    pub http_request_counter: u64,
+    pub rpc_providers: Vec<RpcProviderMetrics>,
+
+    pub active_tasks: Vec<String>,
+}
+
+/// Outcomes of the JSON-RPC outcalls to a provider since the last upgrade.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RpcProviderMetrics {
+    /// Host of the provider.
+    pub provider: String,
+    pub successes: u64,
+    /// Failed outcalls, timeouts excluded.
+    pub failures: u64,
+    pub timeouts: u64,
+    /// None before the first outcall.
+    pub average_latency_nanos: Option<u64>,
+}
+
+impl From<&State> for MinterState {
+    fn from(state: &State) -> Self {
+        Self {
+            solana_rpc_url: state.solana_rpc_url.to_string(),
+            solana_contract_address: state.solana_contract_address.clone(),
+            solana_initial_signature: state.solana_initial_signature.clone(),
+            solana_last_known_signature: state.get_solana_last_known_signature(),
+            ecdsa_key_name: state.ecdsa_key_name.clone(),
+            ecdsa_public_key: state
+                .ecdsa_public_key
+                .as_ref()
+                .map(|response| hex::encode(&response.public_key)),
+            ecdsa_proxy_public_key: state.ecdsa_proxy_public_key.clone(),
+            previous_ecdsa_key: state.previous_ecdsa_key.clone(),
+            minimum_withdrawal_amount: Nat(state.minimum_withdrawal_amount.clone()),
+            ledger_id: state.ledger_id,
+            max_withdrawals_per_principal: state.max_withdrawals_per_principal as u64,
+            terminal_events_retention: state.terminal_events_retention,
+            terminal_events_max_age_secs: state.terminal_events_max_age_secs,
+            solana_vault_address: state.solana_vault_address.clone(),
+            reconciliation_tolerance: Nat(state.reconciliation_tolerance.clone()),
+            last_reconciliation: state.last_reconciliation.clone(),
+            cycles_monitor: state.cycles_monitor.clone(),
+            last_cycles_report: state.last_cycles_report.clone(),
+            cycles_top_up: state.cycles_top_up.clone(),
+            upgrade_audit: state.upgrade_audit,
+            log_level: state.log_level,
+            trusted_origins: state.trusted_origins.clone(),
+            retry_policy: state.retry_policy.clone(),
+            rate_limit: state.rate_limit.clone(),
+            task_lock_timeout_secs: state.task_lock_timeout_secs,
+            config_timelock_secs: state.config_timelock_secs,
+            mint_parallelism: state.mint_parallelism as u64,
+            proxy_token: state.proxy_token.clone(),
+            timer_intervals: state.timer_intervals.clone(),
+            checkpoint_interval_events: state.checkpoint_interval_events,
+            checkpoint_interval_secs: state.checkpoint_interval_secs,
+            event_log_max_bytes: state.event_log_max_bytes,
+            event_archive: state.event_archive,
+            commitment: state.commitment,
+            max_deposit_amount: state.max_deposit_amount.clone().map(Nat),
+            max_withdrawal_amount: state.max_withdrawal_amount.clone().map(Nat),
+            dual_signature_threshold: state.dual_signature_threshold.clone().map(Nat),
+            approval_threshold: state.approval_threshold.clone().map(Nat),
+            release_delay_threshold: state.release_delay_threshold.clone().map(Nat),
+            release_delay_secs: state.release_delay_secs,
+            paused: state.paused.clone(),
+            solana_signature_ranges: state.solana_signature_ranges.len() as u64,
+            solana_signatures: state.solana_signatures.len() as u64,
+            invalid_events: state.invalid_events.len() as u64,
+            archived_invalid_events: state.archived_invalid_events.len(),
+            invalid_events_retention_secs: state.invalid_events_retention_secs,
+            destination_activation_delay_secs: state.destination_activation_delay_secs,
+            address_books: state.address_books.len() as u64,
+            accepted_events: state.accepted_events.len() as u64,
+            dead_letters: state.dead_letters.len() as u64,
+            withdrawals_awaiting_approval: state.withdrawals_awaiting_approval().len() as u64,
+            time_locked_withdrawals: state.time_locked_withdrawals.len() as u64,
+            cancelled_withdrawals: state.cancelled_withdrawals.len() as u64,
+            minted_events: state.minted_events.len(),
+            withdrawal_burned_events: state.withdrawal_burned_events.len() as u64,
+            withdrawal_redeemed_events: state.withdrawal_redeemed_events.len(),
+            withdrawing_principals: state
+                .withdrawing_principals
+                .iter()
+                .map(|(principal, pending)| (*principal, *pending as u64))
+                .collect(),
+            blocked_addresses: state.blocked_addresses.iter().cloned().collect(),
+            revoked_coupons: state.revoked_coupons.iter().copied().collect(),
+            deposit_id_counter: state.deposit_id_counter,
+            burn_id_counter: state.burn_id_counter,
+            http_request_counter: state.http_request_counter,
+            rpc_providers: state
+                .rpc_provider_stats
+                .iter()
+                .map(|(provider, stats)| RpcProviderMetrics {
+                    provider: provider.clone(),
+                    successes: stats.successes,
+                    failures: stats.failures,
+                    timeouts: stats.timeouts,
+                    average_latency_nanos: stats.average_latency_nanos(),
+                })
+                .collect(),
+            active_tasks: state
+                .active_tasks
+                .keys()
+                .map(|task| format!("{task:?}"))
+                .collect(),
+        }
+    }
+}
+
+/// Public configuration of the minter.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MinterInfo {
+    pub solana_contract_address: String,
+    pub solana_vault_address: Option<String>,
+    pub ledger_id: Option<Principal>,
+    pub minimum_withdrawal_amount: Nat,
+    pub max_withdrawal_amount: Option<Nat>,
+    /// Smaller deposits are recorded as invalid events.
+    pub minimum_deposit_lamports: u64,
+    /// Transfer fee of the gSOL ledger when last queried, None until it is.
+    pub ledger_fee: Option<Nat>,
+    /// Fee retained from withdrawals of each bridged SPL token, by mint. Withdrawals of
+    /// native SOL are not charged a fee.
+    pub asset_withdrawal_fees: Vec<(String, Nat)>,
+    /// Larger deposits are not minted until the cap is raised.
+    pub max_deposit_amount: Option<Nat>,
+    /// Coupons paying out at least this amount also carry a cosignature.
+    pub dual_signature_threshold: Option<Nat>,
+    /// Withdrawals paying out at least this amount wait for an operator's approval.
+    pub approval_threshold: Option<Nat>,
+    /// Time before an address added to an address book can receive withdrawals.
+    pub destination_activation_delay_secs: u64,
+    /// Coupons paying out at least this amount are issued `release_delay_secs` after the burn.
+    pub release_delay_threshold: Option<Nat>,
+    pub release_delay_secs: u64,
+    pub commitment: ConfirmationStatus,
+    /// Whether deposits are minted and withdrawals accepted.
+    pub paused: bool,
+    pub pause_reason: Option<String>,
+    pub ecdsa_key_name: String,
+    pub ecdsa_public_key: Option<String>,
+    pub previous_ecdsa_key_name: Option<String>,
+}
+
+impl From<&State> for MinterInfo {
+    fn from(state: &State) -> Self {
+        Self {
+            solana_contract_address: state.solana_contract_address.clone(),
+            solana_vault_address: state.solana_vault_address.clone(),
+            ledger_id: state.ledger_id,
+            minimum_withdrawal_amount: Nat(state.minimum_withdrawal_amount.clone()),
+            max_withdrawal_amount: state.max_withdrawal_amount.clone().map(Nat),
+            minimum_deposit_lamports: MINIMUM_DEPOSIT_LAMPORTS,
+            ledger_fee: state.ledger_fee.as_ref().map(|(fee, _)| fee.clone()),
+            asset_withdrawal_fees: state
+                .assets
+                .values()
+                .map(|asset| (asset.spl_mint.clone(), asset.fee.clone()))
+                .collect(),
+            max_deposit_amount: state.max_deposit_amount.clone().map(Nat),
+            dual_signature_threshold: state.dual_signature_threshold.clone().map(Nat),
+            approval_threshold: state.approval_threshold.clone().map(Nat),
+            destination_activation_delay_secs: state.destination_activation_delay_secs,
+            release_delay_threshold: state.release_delay_threshold.clone().map(Nat),
+            release_delay_secs: state.release_delay_secs,
+            commitment: state.commitment,
+            paused: state.is_paused(),
+            pause_reason: state.paused.clone(),
+            ecdsa_key_name: state.ecdsa_key_name.clone(),
+            ecdsa_public_key: state
+                .ecdsa_public_key
+                .as_ref()
+                .map(|response| hex::encode(&response.public_key)),
+            previous_ecdsa_key_name: state
+                .previous_ecdsa_key
+                .as_ref()
+                .map(|key| key.key_name.clone()),
+        }
+    }
+}
+
+/// Public keys of the threshold ECDSA key signing the coupons, hex encoded.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MinterAddress {
+    /// 33 byte SEC1 compressed key, as returned by the management canister.
+    pub compressed_public_key: String,
+    /// 64 byte uncompressed key, without the SEC1 prefix.
+    pub uncompressed_public_key: String,
+    /// Key of the ECDSA proxy, when one is configured.
+    pub ecdsa_proxy_public_key: Option<String>,
+    /// Uncompressed key of the cosigner derivation path, cosigning high-value coupons.
+    pub cosigner_public_key: Option<String>,
+}
+
+impl TryFrom<&State> for MinterAddress {
+    type Error = EcdsaKeyError;
+
+    fn try_from(state: &State) -> Result<Self, Self::Error> {
+        Ok(Self {
+            compressed_public_key: state.compressed_public_key()?,
+            uncompressed_public_key: state.uncompressed_public_key()?,
+            ecdsa_proxy_public_key: state.ecdsa_proxy_public_key.clone(),
+            cosigner_public_key: state.cosigner_public_key_hex.clone(),
+        })
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SupplyStats {
+    pub total_minted: Nat,
+    pub total_burned: Nat,
+    pub total_fees: Nat,
+    /// Transfer fees recorded with deposits minted before mints were known to be fee-free,
+    /// they are not part of `outstanding`.
+    pub total_ledger_fees: Nat,
+    /// gSOL expected to be in circulation, i.e. minted minus burned.
+    pub outstanding: Nat,
+}
+
+impl From<&State> for SupplyStats {
+    fn from(state: &State) -> Self {
+        let outstanding = if state.total_minted >= state.total_burned {
+            &state.total_minted - &state.total_burned
+        } else {
+            BigUint::default()
+        };
+
+        Self {
+            total_minted: Nat(state.total_minted.clone()),
+            total_burned: Nat(state.total_burned.clone()),
+            total_fees: Nat(state.total_fees.clone()),
+            total_ledger_fees: Nat(state.total_ledger_fees.clone()),
+            outstanding: Nat(outstanding),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TaskStatus {
+    pub task: String,
+    /// Whether the task is currently running.
+    pub held: bool,
+    pub last_started_at: Option<u64>,
+    pub last_finished_at: Option<u64>,
+    /// Duration of the last completed run in nanoseconds.
+    pub last_duration: Option<u64>,
+    /// Error of the last completed run.
+    pub last_error: Option<String>,
+}
+
+/// Returns the status of every timer task.
+pub fn task_statuses(state: &State) -> Vec<TaskStatus> {
+    TaskType::iter()
+        .map(|task| {
+            let finished = state.last_finished_task_runs.get(&task);
+            TaskStatus {
+                task: format!("{task:?}"),
+                held: state.active_tasks.contains_key(&task),
+                last_started_at: state.task_runs.get(&task).map(|r| r.started_at),
+                last_finished_at: finished.and_then(|r| r.finished_at),
+                last_duration: finished.and_then(|r| r.duration()),
+                last_error: finished.and_then(|r| r.last_error.clone()),
+            }
+        })
+        .collect()
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub heap_bytes: u64,
+    pub stable_bytes: u64,
+    pub event_log_index_bytes: u64,
+    pub event_log_data_bytes: u64,
+    pub event_count: u64,
+    /// Number of entries of the larger collections of the state.
+    pub collections: Vec<CollectionSize>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CollectionSize {
+    pub name: String,
+    pub entries: u64,
+    /// Whether the collection lives in stable memory rather than on the heap.
+    pub stable: bool,
+}
+
+impl CollectionSize {
+    pub fn of_state(state: &State) -> Vec<Self> {
+        let heap = |name: &str, entries: usize| Self {
+            name: name.to_string(),
+            entries: entries as u64,
+            stable: false,
+        };
+        let stable = |name: &str, entries: u64| Self {
+            name: name.to_string(),
+            entries,
+            stable: true,
+        };
+        vec![
+            heap(
+                "solana_signature_ranges",
+                state.solana_signature_ranges.len(),
+            ),
+            heap("solana_signatures", state.solana_signatures.len()),
+            heap("invalid_events", state.invalid_events.len()),
+            heap("accepted_events", state.accepted_events.len()),
+            heap("dead_letters", state.dead_letters.len()),
+            heap("pruned_signatures", state.pruned_signatures.len()),
+            heap(
+                "withdrawal_burned_events",
+                state.withdrawal_burned_events.len(),
+            ),
+            heap("signature_cache", state.signature_cache.len()),
+            heap("task_traces", state.task_traces.len()),
+            stable("minted_events", state.minted_events.len()),
+            stable(
+                "withdrawal_redeemed_events",
+                state.withdrawal_redeemed_events.len(),
+            ),
+            stable(
+                "archived_invalid_events",
+                state.archived_invalid_events.len(),
+            ),
+        ]
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EventChunk {
+    /// CBOR array of the exported events, in the minter's stable log encoding.
+    pub cbor: ByteBuf,
+    pub start: u64,
+    pub count: u64,
+    /// Index to request the following chunk from, None once the log is exhausted.
+    pub next_start: Option<u64>,
+    pub total_event_count: u64,
+    /// SHA-256 chain over the events exported so far, `SHA-256(hash || event)` for each
+    /// event in its stable log encoding, to pass as `prev_hash` for the following chunk.
+    pub running_hash: ByteBuf,
+}
@@ -1,3 +1,5 @@
+use crate::constants::SIGN_WITH_ECDSA_CYCLES;
+use crate::state::mutate_state;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD as base64_url, Engine};
 use ciborium::into_writer;
 use ic_cdk::api::management_canister::ecdsa;
@@ -9,7 +11,7 @@ use sha3::{Digest, Sha3_256};
 pub async fn sign_proxy_token(
     key_name: &str,
     expire_at: u64, // UNIX timestamp, in seconds
-    message: &str,  // use RPCAgent.name as message
+    message: &str,  // agent name with the audience and scope claims
 ) -> Result<String, String> {
     let mut buf: Vec<u8> = Vec::new();
     into_writer(&(expire_at, message), &mut buf).expect("failed to encode Token in CBOR format");
@@ -41,6 +43,7 @@ pub async fn sign_with(
         },
     };
 
+    mutate_state(|s| s.record_ecdsa_sign_call(SIGN_WITH_ECDSA_CYCLES));
     let (response,): (ecdsa::SignWithEcdsaResponse,) = ecdsa::sign_with_ecdsa(args)
         .await
         .map_err(|err| format!("sign_with_ecdsa failed {:?}", err))?;
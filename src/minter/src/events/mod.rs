@@ -1,9 +1,25 @@
+use crate::constants::{DEFAULT_RETRY_MAX_ATTEMPTS, MINIMUM_DEPOSIT_LAMPORTS};
+use crate::lamports::Lamports;
+use crate::spl::SolanaAddress;
 use crate::withdraw::Coupon;
 
-use candid::{Nat, Principal};
+use candid::{CandidType, Deserialize, Nat, Principal};
 use minicbor::{Decode, Encode};
-use num_bigint::BigUint;
 use serde::Serialize;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+/// Solana slot and block time, in seconds since the epoch, at which a transaction
+/// was processed.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Encode, Decode, Serialize)]
+pub struct SolanaBlock {
+    #[n(0)]
+    pub slot: u64,
+    #[n(1)]
+    pub block_time: u64,
+}
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Encode, Decode, Serialize)]
 pub struct Retriable(#[n(0)] u8);
@@ -14,7 +30,7 @@ impl Retriable {
     }
 
     pub fn increment_retries(&mut self) {
-        self.0 += 1;
+        self.0 = self.0.saturating_add(1);
     }
 
     pub fn reset_retries(&mut self) {
@@ -26,6 +42,59 @@ impl Retriable {
     }
 }
 
+/// Retry policy shared by the signature scraping and minting tasks.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct RetryPolicy {
+    /// Number of failed attempts after which an entry is no longer retried.
+    #[n(0)]
+    pub max_attempts: u8,
+    /// Delay in seconds before the n-th retry of an entry, the last delay
+    /// applies to all further retries. No delay if empty.
+    #[n(1)]
+    pub backoff_secs: Vec<u64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_RETRY_MAX_ATTEMPTS,
+            backoff_secs: vec![],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retrying an entry that failed `retries` times.
+    pub fn backoff(&self, retries: u8) -> Duration {
+        let index = (retries as usize).saturating_sub(1);
+        let secs = self
+            .backoff_secs
+            .get(index)
+            .or(self.backoff_secs.last())
+            .copied()
+            .unwrap_or_default();
+        Duration::from_secs(secs)
+    }
+
+    /// Whether an entry last attempted at `last_attempt` may be attempted at `now`.
+    pub fn should_retry(&self, retry: &Retriable, last_attempt: Option<u64>, now: u64) -> bool {
+        if retry.is_retry_limit_reached(self.max_attempts) {
+            return false;
+        }
+        self.is_backoff_elapsed(retry.get_retries(), last_attempt, now)
+    }
+
+    /// Whether the backoff after `retries` retries, counted from `last_attempt`, ended at `now`.
+    pub fn is_backoff_elapsed(&self, retries: u8, last_attempt: Option<u64>, now: u64) -> bool {
+        match (retries, last_attempt) {
+            (0, _) | (_, None) => true,
+            (retries, Some(last_attempt)) => {
+                now >= last_attempt.saturating_add(self.backoff(retries).as_nanos() as u64)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Encode, Decode, PartialEq, Clone, Eq)]
 pub struct SolanaSignatureRange {
     #[n(0)]
@@ -34,6 +103,10 @@ pub struct SolanaSignatureRange {
     pub until_sol_sig: String,
     #[n(2)]
     pub retry: Retriable,
+    // slot at which the newest signature of the range was observed, scans of the range
+    // are not answered by nodes that have not reached it yet
+    #[n(3)]
+    pub observed_slot: Option<u64>,
 }
 
 impl SolanaSignatureRange {
@@ -43,8 +116,14 @@ impl SolanaSignatureRange {
             before_sol_sig: before,
             until_sol_sig: until,
             retry: Retriable(0),
+            observed_slot: None,
         }
     }
+
+    pub fn with_observed_slot(mut self, slot: Option<u64>) -> Self {
+        self.observed_slot = slot;
+        self
+    }
 }
 
 #[derive(Debug, Encode, Decode, PartialEq, Clone, Eq)]
@@ -53,6 +132,16 @@ pub struct SolanaSignature {
     pub sol_sig: String,
     #[n(1)]
     pub retry: Retriable,
+    // lookups of a transaction that was not indexed yet, not counted as failed attempts
+    #[n(2)]
+    pub not_found: Option<Retriable>,
+    // set once the transaction is recorded as an invalid event
+    #[n(3)]
+    pub invalid_reason: Option<InvalidReason>,
+    // time the transaction was recorded as an invalid event, None until it is and for events
+    // invalidated before it was tracked
+    #[n(4)]
+    invalidated_at: Option<u64>,
 }
 
 impl SolanaSignature {
@@ -61,8 +150,29 @@ impl SolanaSignature {
         SolanaSignature {
             sol_sig: signature,
             retry: Retriable(0),
+            not_found: None,
+            invalid_reason: None,
+            invalidated_at: None,
         }
     }
+
+    pub fn update_invalidated_at(&mut self, timestamp: u64) {
+        self.invalidated_at = Some(timestamp);
+    }
+
+    pub fn get_invalidated_at(&self) -> Option<u64> {
+        self.invalidated_at
+    }
+
+    pub fn get_not_found_lookups(&self) -> u8 {
+        self.not_found.as_ref().map_or(0, Retriable::get_retries)
+    }
+
+    pub fn increment_not_found_lookups(&mut self) {
+        self.not_found
+            .get_or_insert(Retriable(0))
+            .increment_retries();
+    }
 }
 
 impl std::fmt::Display for SolanaSignature {
@@ -71,11 +181,159 @@ impl std::fmt::Display for SolanaSignature {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum DepositEventError {
+    #[error("deposit data is not valid base64")]
     InvalidBase64Data,
-    InvalidPrincipal,
-    // other variants if needed
+    #[error("deposit data has {0} bytes, expected at least 20")]
+    InvalidDataLength(usize),
+    #[error("deposit data does not hold a valid principal: {0}")]
+    InvalidPrincipal(BadPrincipal),
+    #[error("deposit data holds an amount of 0 lamports")]
+    ZeroAmount,
+}
+
+/// Why the recipient principal of a deposit was rejected.
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Encode, Decode)]
+pub enum BadPrincipal {
+    /// Not a principal in any of the accepted encodings.
+    #[n(0)]
+    Malformed,
+    /// Textual principal whose checksum does not match its bytes, usually a typo.
+    #[n(1)]
+    BadChecksum,
+    /// The anonymous principal cannot hold the minted gSOL.
+    #[n(2)]
+    Anonymous,
+    /// The management canister cannot hold the minted gSOL.
+    #[n(3)]
+    ManagementCanister,
+}
+
+impl std::fmt::Display for BadPrincipal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BadPrincipal::Malformed => write!(f, "malformed principal"),
+            BadPrincipal::BadChecksum => write!(f, "principal checksum does not match"),
+            BadPrincipal::Anonymous => write!(f, "anonymous principal"),
+            BadPrincipal::ManagementCanister => write!(f, "management canister"),
+        }
+    }
+}
+
+/// Why a Solana transaction was recorded as an invalid event instead of a deposit.
+#[derive(CandidType, Deserialize, Clone, PartialEq, Eq, Debug, Encode, Decode)]
+pub enum InvalidReason {
+    /// The transaction could not be parsed as a deposit.
+    #[n(0)]
+    ParseFailure,
+    /// The deposit names a recipient that cannot receive gSOL.
+    #[n(1)]
+    BadPrincipal(#[n(0)] BadPrincipal),
+    /// The transaction is not a deposit into the bridge program.
+    #[n(2)]
+    NotADeposit,
+    /// The deposit holds no lamports.
+    #[n(3)]
+    BelowMinimum,
+    /// The deposit was sent from a blocked Solana address. Such deposits are dead-lettered
+    /// since, the reason is only found on invalid events recorded before.
+    #[n(4)]
+    Blocked,
+    /// The deposit was made with an instruction of the bridge program the minter does
+    /// not parse.
+    #[n(5)]
+    UnsupportedVersion,
+}
+
+/// Encoding of the recipient principal in the data of a deposit. Wallets encode it
+/// differently, the parser tries the encodings in this order.
+#[derive(
+    CandidType,
+    Deserialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Debug,
+    Encode,
+    Decode,
+    Serialize,
+)]
+pub enum PrincipalEncoding {
+    /// Textual form, `aaaaa-aa`, surrounding whitespace is ignored.
+    #[n(0)]
+    Text,
+    /// Hex of the principal bytes, with or without a `0x` prefix.
+    #[n(1)]
+    Hex,
+    /// Raw principal bytes, at most 29, ending with the class of the principal.
+    #[n(2)]
+    Blob,
+}
+
+// last byte of opaque, self-authenticating, derived and anonymous principals
+const PRINCIPAL_CLASSES: [u8; 4] = [0x01, 0x02, 0x03, 0x04];
+
+// Decodes the recipient principal of a deposit from any of the encodings used by wallets.
+// Principals that cannot hold the minted gSOL are rejected with the reason.
+fn decode_principal(bytes: &[u8]) -> Result<(Principal, PrincipalEncoding), BadPrincipal> {
+    let (principal, encoding) = decode_any_principal(bytes)?;
+    if principal == Principal::anonymous() {
+        return Err(BadPrincipal::Anonymous);
+    }
+    if principal == Principal::management_canister() {
+        return Err(BadPrincipal::ManagementCanister);
+    }
+    Ok((principal, encoding))
+}
+
+fn decode_any_principal(bytes: &[u8]) -> Result<(Principal, PrincipalEncoding), BadPrincipal> {
+    let text = std::str::from_utf8(bytes).map(str::trim).ok();
+    if let Some(text) = text {
+        if let Ok(principal) = Principal::from_text(text) {
+            return Ok((principal, PrincipalEncoding::Text));
+        }
+        let digits = text.strip_prefix("0x").unwrap_or(text);
+        if !digits.is_empty() {
+            if let Ok(principal_bytes) = hex::decode(digits) {
+                if let Ok(principal) = Principal::try_from_slice(&principal_bytes) {
+                    return Ok((principal, PrincipalEncoding::Hex));
+                }
+            }
+        }
+    }
+    // any short byte string is a principal, the class byte tells blobs from garbage
+    if let Some(class) = bytes.last() {
+        if PRINCIPAL_CLASSES.contains(class) {
+            if let Ok(principal) = Principal::try_from_slice(bytes) {
+                return Ok((principal, PrincipalEncoding::Blob));
+            }
+        }
+    }
+    match text {
+        Some(text) if is_grouped_principal_text(text) => Err(BadPrincipal::BadChecksum),
+        _ => Err(BadPrincipal::Malformed),
+    }
+}
+
+// Whether the text has the shape of a textual principal, lowercase base32 in groups of
+// five separated by dashes, so that failing to parse it means its checksum is wrong.
+fn is_grouped_principal_text(text: &str) -> bool {
+    let groups: Vec<&str> = text.split('-').collect();
+    let (last, full) = groups
+        .split_last()
+        .expect("split yields at least one group");
+    groups.len() > 1
+        && full.iter().all(|group| group.len() == 5)
+        && (1..=5).contains(&last.len())
+        && groups.iter().all(|group| {
+            group
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || ('2'..='7').contains(&c))
+        })
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Encode, Decode, Serialize)]
@@ -94,6 +352,20 @@ pub struct DepositEvent {
     icp_mint_block_index: Option<u64>,
     #[n(6)]
     pub retry: Retriable,
+    // block of the deposit transaction, None for deposits recorded before it was tracked
+    #[n(7)]
+    pub solana_block: Option<SolanaBlock>,
+    // transfer fee recorded with the mint, only by minters that charged it on mints, which
+    // ICRC-1 ledgers do not
+    #[cbor(n(8), with = "crate::cbor::nat::option")]
+    pub ledger_fee: Option<Nat>,
+    // encoding of the recipient in the deposit data, None for deposits recorded before it was tracked
+    #[n(9)]
+    pub principal_encoding: Option<PrincipalEncoding>,
+    // time the deposit was minted, None until it is and for deposits minted before it was tracked
+    #[n(10)]
+    #[serde(skip_serializing)]
+    minted_at: Option<u64>,
 }
 
 impl DepositEvent {
@@ -108,45 +380,91 @@ impl DepositEvent {
         let bytes = BASE64_STANDARD
             .decode(encode_data)
             .map_err(|_| DepositEventError::InvalidBase64Data)?;
-        let amount_bytes = &bytes[bytes.len() - 8..];
-        let mut value: BigUint = BigUint::default(); // Initialize BigUint to 0
-        for i in 0..8 {
-            let byte_as_u64 = amount_bytes[i] as u64;
-            let shifted_value = BigUint::from(byte_as_u64) << (i * 8); // Shifted byte value as BigUint
-            value |= &shifted_value;
+        // 12 bytes of event header, the principal and the little endian amount of lamports
+        if bytes.len() < 12 + 8 {
+            return Err(DepositEventError::InvalidDataLength(bytes.len()));
+        }
+        let (address_bytes, amount_bytes) = bytes[12..].split_at(bytes.len() - 12 - 8);
+        let lamports = Lamports(u64::from_le_bytes(
+            amount_bytes.try_into().expect("the amount is 8 bytes long"),
+        ));
+        if lamports.get() < MINIMUM_DEPOSIT_LAMPORTS {
+            return Err(DepositEventError::ZeroAmount);
         }
 
-        let address_bytes = &bytes[12..bytes.len() - 8];
-        let address_hex = String::from_utf8_lossy(&address_bytes);
-        let principal = Principal::from_text(address_hex.trim())
-            .map_err(|_| DepositEventError::InvalidPrincipal)?;
+        let (principal, encoding) =
+            decode_principal(address_bytes).map_err(DepositEventError::InvalidPrincipal)?;
 
         Ok(DepositEvent {
             id: deposit_id,
             from_sol_address: from_address.to_string(),
             to_icp_address: principal,
-            amount: Nat::from(value),
+            amount: lamports.to_gsol(),
             sol_sig: sol_sig.to_string(),
             icp_mint_block_index: None,
             retry: Retriable(0),
+            solana_block: None,
+            ledger_fee: None,
+            principal_encoding: Some(encoding),
+            minted_at: None,
         })
     }
 
+    /// Mint of the burned amount of a cancelled withdrawal back to its sender. Its signature
+    /// is not a Solana signature, the NFT canister deduplicates the re-mint by it.
+    pub fn remint(withdrawal: &WithdrawalEvent) -> Self {
+        Self {
+            id: withdrawal.get_burn_id(),
+            from_sol_address: withdrawal.to_sol_address.to_string(),
+            to_icp_address: withdrawal.from_icp_address,
+            amount: withdrawal.amount.clone(),
+            sol_sig: format!("remint-{}", withdrawal.get_burn_id()),
+            icp_mint_block_index: None,
+            retry: Retriable(0),
+            solana_block: None,
+            ledger_fee: None,
+            principal_encoding: None,
+            minted_at: None,
+        }
+    }
+
+    pub fn with_solana_block(mut self, block: SolanaBlock) -> Self {
+        self.solana_block = Some(block);
+        self
+    }
+
     pub fn update_mint_block_index(&mut self, block_index: u64) {
         self.icp_mint_block_index = Some(block_index);
     }
 
+    pub fn update_minted_at(&mut self, timestamp: u64) {
+        self.minted_at = Some(timestamp);
+    }
+
+    pub fn get_minted_at(&self) -> Option<u64> {
+        self.minted_at
+    }
+
     pub fn get_mint_block_index(&self) -> Option<u64> {
         self.icp_mint_block_index
     }
 }
 
+/// Accepted deposit whose mint failed with an error that retries cannot resolve.
+#[derive(Clone, PartialEq, Eq, Debug, Encode, Decode)]
+pub struct DeadLetter {
+    #[n(0)]
+    pub deposit: DepositEvent,
+    #[n(1)]
+    pub error: String,
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Encode, Decode, Serialize)]
 pub struct WithdrawalEvent {
     #[cbor(n(1), with = "crate::cbor::principal")]
     pub from_icp_address: Principal,
     #[n(2)]
-    pub to_sol_address: String,
+    pub to_sol_address: SolanaAddress,
     #[cbor(n(3), with = "crate::cbor::nat")]
     pub amount: Nat,
     #[n(0)]
@@ -161,10 +479,41 @@ pub struct WithdrawalEvent {
     #[n(7)]
     #[serde(skip_serializing)]
     pub retry: Retriable,
+    // SPL mint of the withdrawn asset, None for native SOL withdrawals
+    #[n(8)]
+    pub spl_mint: Option<String>,
+    // associated token account of `to_sol_address` for `spl_mint`
+    #[n(9)]
+    pub to_token_account: Option<String>,
+    // fee of the bridged asset retained by the minter, None for native SOL withdrawals
+    #[cbor(n(10), with = "crate::cbor::nat::option")]
+    pub fee: Option<Nat>,
+    // latest Solana block observed by the minter when the coupon was issued
+    #[n(11)]
+    pub solana_block: Option<SolanaBlock>,
+    // time a controller cancelled the withdrawal, the created_at_time of its re-mint
+    #[n(12)]
+    #[serde(skip_serializing)]
+    cancelled_at: Option<u64>,
+    // time the first coupon was issued, None until it is and for withdrawals redeemed before
+    // it was tracked
+    #[n(13)]
+    #[serde(skip_serializing)]
+    redeemed_at: Option<u64>,
+    // created_at_time of the ledger re-mints of a cancelled withdrawal, the time of the first
+    // attempt until the ledger rejects it as too old
+    #[n(14)]
+    #[serde(skip_serializing)]
+    remint_created_at: Option<u64>,
+    // whether a re-mint attempt may have minted without its outcome being recorded, None
+    // until an attempt is recorded
+    #[n(15)]
+    #[serde(skip_serializing)]
+    remint_unresolved: Option<bool>,
 }
 
 impl WithdrawalEvent {
-    pub fn new(burn_id: u64, from: Principal, to_sol_address: String, amount: Nat) -> Self {
+    pub fn new(burn_id: u64, from: Principal, to_sol_address: SolanaAddress, amount: Nat) -> Self {
         WithdrawalEvent {
             from_icp_address: from,
             to_sol_address,
@@ -174,6 +523,34 @@ impl WithdrawalEvent {
             icp_burn_block_index: None,
             coupon: None,
             retry: Retriable(0),
+            spl_mint: None,
+            to_token_account: None,
+            fee: None,
+            solana_block: None,
+            cancelled_at: None,
+            redeemed_at: None,
+            remint_created_at: None,
+            remint_unresolved: None,
+        }
+    }
+
+    pub fn with_spl_destination(mut self, mint: String, token_account: String) -> Self {
+        self.spl_mint = Some(mint);
+        self.to_token_account = Some(token_account);
+        self
+    }
+
+    pub fn with_fee(mut self, fee: Nat) -> Self {
+        self.fee = Some(fee);
+        self
+    }
+
+    /// Amount paid out on Solana, the burned amount less the fee.
+    pub fn payout_amount(&self) -> Nat {
+        match &self.fee {
+            Some(fee) if fee.0 < self.amount.0 => Nat(&self.amount.0 - &fee.0),
+            Some(_) => Nat::from(0u8),
+            None => self.amount.clone(),
         }
     }
 
@@ -194,10 +571,54 @@ impl WithdrawalEvent {
         self.icp_burn_block_index = Some(block_index);
     }
 
+    pub fn update_after_cancel(&mut self, timestamp: u64) {
+        self.cancelled_at = Some(timestamp);
+    }
+
+    pub fn get_cancelled_at(&self) -> Option<u64> {
+        self.cancelled_at
+    }
+
+    pub fn update_after_remint_attempt(&mut self, created_at: Option<u64>) {
+        if created_at.is_some() {
+            self.remint_created_at = created_at;
+        }
+        self.remint_unresolved = Some(true);
+    }
+
+    pub fn update_after_remint_failure(&mut self) {
+        self.remint_unresolved = Some(false);
+    }
+
+    /// `created_at_time` of the next ledger re-mint, the one of the previous attempts so that
+    /// the ledger deduplicates them. Attempts made before they were recorded carried the
+    /// cancellation time.
+    pub fn get_remint_created_at(&self) -> Option<u64> {
+        self.remint_created_at.or(self.cancelled_at)
+    }
+
+    /// Whether an earlier re-mint attempt may have minted without its outcome being
+    /// recorded, which withdrawals cancelled before the attempts were recorded may have.
+    pub fn is_remint_unresolved(&self) -> bool {
+        self.remint_unresolved.unwrap_or(true)
+    }
+
+    pub fn update_redeemed_at(&mut self, timestamp: u64) {
+        self.redeemed_at = Some(timestamp);
+    }
+
+    pub fn get_redeemed_at(&self) -> Option<u64> {
+        self.redeemed_at
+    }
+
     pub fn update_after_redeem(&mut self, coupon: Coupon) {
         self.coupon = Some(coupon);
     }
 
+    pub fn update_solana_block(&mut self, block: Option<SolanaBlock>) {
+        self.solana_block = block;
+    }
+
     pub fn get_coupon(&self) -> Option<&Coupon> {
         self.coupon.as_ref()
     }
@@ -0,0 +1,70 @@
+use super::{BadPrincipal, DepositEvent, DepositEventError, PrincipalEncoding};
+
+use base64::prelude::*;
+use candid::Principal;
+
+const RECIPIENT: &str = "2chl6-4hpzw-vqaaa-aaaaa-c";
+
+// deposit data as emitted by the bridge contract: padding, encoded principal, le amount
+fn deposit(principal: &[u8]) -> Result<DepositEvent, DepositEventError> {
+    let mut data = vec![0u8; 12];
+    data.extend_from_slice(principal);
+    data.extend_from_slice(&1_000_000u64.to_le_bytes());
+    DepositEvent::new(0, "sig", "sender", &BASE64_STANDARD.encode(data))
+}
+
+fn recipient() -> Principal {
+    Principal::from_text(RECIPIENT).unwrap()
+}
+
+#[test]
+fn should_decode_every_principal_encoding() {
+    let hex = hex::encode(recipient().as_slice());
+    let encodings = [
+        (RECIPIENT.as_bytes().to_vec(), PrincipalEncoding::Text),
+        (
+            format!(" {RECIPIENT}\n").into_bytes(),
+            PrincipalEncoding::Text,
+        ),
+        (hex.clone().into_bytes(), PrincipalEncoding::Hex),
+        (format!("0x{hex}").into_bytes(), PrincipalEncoding::Hex),
+        (recipient().as_slice().to_vec(), PrincipalEncoding::Blob),
+    ];
+
+    for (bytes, encoding) in encodings {
+        let event = deposit(&bytes).expect("valid deposit");
+        assert_eq!(event.to_icp_address, recipient());
+        assert_eq!(event.principal_encoding, Some(encoding));
+    }
+}
+
+#[test]
+fn should_classify_rejected_principals() {
+    let rejections: [(&[u8], BadPrincipal); 6] = [
+        (&[0xff; 30], BadPrincipal::Malformed),
+        (b"not a principal", BadPrincipal::Malformed),
+        (b"2chl6-4hpzw-vqaaa-aaaab-c", BadPrincipal::BadChecksum),
+        (b"2vxsx-fae", BadPrincipal::Anonymous),
+        (b"04", BadPrincipal::Anonymous),
+        (b"aaaaa-aa", BadPrincipal::ManagementCanister),
+    ];
+
+    for (bytes, reason) in rejections {
+        assert_eq!(
+            deposit(bytes),
+            Err(DepositEventError::InvalidPrincipal(reason))
+        );
+    }
+}
+
+#[test]
+fn should_reject_deposit_without_lamports() {
+    let mut data = vec![0u8; 12];
+    data.extend_from_slice(RECIPIENT.as_bytes());
+    data.extend_from_slice(&0u64.to_le_bytes());
+
+    assert_eq!(
+        DepositEvent::new(0, "sig", "sender", &BASE64_STANDARD.encode(data)),
+        Err(DepositEventError::ZeroAmount)
+    );
+}
@@ -0,0 +1,150 @@
+use crate::{
+    lifecycle::SolanaRpcUrl,
+    logs::INFO,
+    spl::decode_pubkey,
+    state::{audit::process_event, event::EventType, mutate_state, read_state, rotate_ecdsa_key},
+};
+
+use candid::{CandidType, Deserialize, Principal};
+use minicbor::{Decode, Encode};
+
+/// Sensitive configuration change, applied once its timelock expired.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub enum ConfigChange {
+    #[n(0)]
+    SolanaContractAddress(#[n(0)] String),
+    #[n(1)]
+    SolanaRpcUrl(#[n(0)] SolanaRpcUrl),
+    /// Rotates the ECDSA key, see `state::rotate_ecdsa_key`.
+    #[n(2)]
+    EcdsaKeyName {
+        #[n(0)]
+        key_name: String,
+        #[n(1)]
+        grace_period_secs: u64,
+    },
+}
+
+impl ConfigChange {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ConfigChange::SolanaContractAddress(address) => decode_pubkey(address)
+                .map(|_| ())
+                .map_err(|e| format!("invalid contract address: {e}")),
+            ConfigChange::SolanaRpcUrl(url) => {
+                if url.get().starts_with("https://") {
+                    Ok(())
+                } else {
+                    Err("the Solana RPC url must use https".to_string())
+                }
+            }
+            ConfigChange::EcdsaKeyName { key_name, .. } => {
+                if key_name.trim().is_empty() {
+                    Err("ecdsa_key_name cannot be blank".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct ConfigProposal {
+    #[n(0)]
+    pub id: u64,
+    #[n(1)]
+    pub change: ConfigChange,
+    #[cbor(n(2), with = "crate::cbor::principal")]
+    pub proposer: Principal,
+    #[n(3)]
+    pub proposed_at: u64,
+    /// The change cannot be executed before this time.
+    #[n(4)]
+    pub executable_at: u64,
+}
+
+/// Records a proposal to apply the change once the configured timelock expired.
+pub fn propose(change: ConfigChange, proposer: Principal) -> Result<u64, String> {
+    change.validate()?;
+
+    let now = ic_cdk::api::time();
+    mutate_state(|s| {
+        let id = s.next_proposal_id;
+        let proposal = ConfigProposal {
+            id,
+            change,
+            proposer,
+            proposed_at: now,
+            executable_at: now.saturating_add(s.config_timelock_secs.saturating_mul(1_000_000_000)),
+        };
+        crate::logs::log!(
+            INFO,
+            "\n{proposer} proposed config change {id}: {proposal:?}"
+        );
+        process_event(s, EventType::ConfigChangeProposed(proposal));
+        Ok(id)
+    })
+}
+
+// Marks a proposal as executing until dropped, so that it is neither executed twice
+// nor cancelled while the key is rotated.
+struct ExecutingProposal(u64);
+
+impl ExecutingProposal {
+    fn new(id: u64) -> Result<Self, String> {
+        mutate_state(|s| {
+            if !s.executing_config_proposals.insert(id) {
+                return Err(format!("config proposal {id} is already executing"));
+            }
+            Ok(Self(id))
+        })
+    }
+}
+
+impl Drop for ExecutingProposal {
+    fn drop(&mut self) {
+        mutate_state(|s| s.executing_config_proposals.remove(&self.0));
+    }
+}
+
+/// Applies the change of a proposal whose timelock expired.
+pub async fn execute(id: u64, executor: Principal) -> Result<(), String> {
+    let proposal = read_state(|s| s.config_proposals.get(&id).cloned())
+        .ok_or_else(|| format!("unknown config proposal {id}"))?;
+    if ic_cdk::api::time() < proposal.executable_at {
+        return Err(format!(
+            "config proposal {id} cannot be executed before {}",
+            proposal.executable_at
+        ));
+    }
+    let _executing = ExecutingProposal::new(id)?;
+
+    // the key rotation records its own event once the new key was fetched
+    if let ConfigChange::EcdsaKeyName {
+        key_name,
+        grace_period_secs,
+    } = &proposal.change
+    {
+        rotate_ecdsa_key(key_name.clone(), *grace_period_secs)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    crate::logs::log!(INFO, "\n{executor} executed config change {id}");
+    mutate_state(|s| process_event(s, EventType::ConfigChangeExecuted { id, executor }));
+    Ok(())
+}
+
+pub fn cancel(id: u64, canceller: Principal) -> Result<(), String> {
+    mutate_state(|s| {
+        if !s.config_proposals.contains_key(&id) {
+            return Err(format!("unknown config proposal {id}"));
+        }
+        if s.executing_config_proposals.contains(&id) {
+            return Err(format!("config proposal {id} is executing"));
+        }
+        process_event(s, EventType::ConfigChangeCancelled { id, canceller });
+        Ok(())
+    })
+}
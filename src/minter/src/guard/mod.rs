@@ -1,7 +1,11 @@
-use crate::state::{mutate_state, State, TaskType};
-use candid::Principal;
-use std::collections::BTreeSet;
+use crate::constants::WITHDRAWAL_LOCK_TIMEOUT;
+use crate::logs::{DEBUG, INFO};
+use crate::state::{mutate_state, State, TaskRun, TaskType};
+use candid::{CandidType, Deserialize, Principal};
+use minicbor::{Decode, Encode};
+use std::collections::BTreeMap;
 use std::marker::PhantomData;
+use std::time::Duration;
 
 pub const MAX_CONCURRENT: usize = 100;
 
@@ -13,20 +17,31 @@ pub enum GuardError {
 }
 
 pub trait RequestsGuardedByPrincipal {
-    fn guarded_principals(state: &mut State) -> &mut BTreeSet<Principal>;
+    fn guarded_principals(state: &mut State) -> &mut BTreeMap<Principal, usize>;
+    /// Time the guard of each principal was last acquired.
+    fn locked_at(state: &mut State) -> &mut BTreeMap<Principal, u64>;
+    fn max_requests_per_principal(state: &State) -> usize;
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct PendingRetrieveSolRequests;
 
 impl RequestsGuardedByPrincipal for PendingRetrieveSolRequests {
-    fn guarded_principals(state: &mut State) -> &mut BTreeSet<Principal> {
+    fn guarded_principals(state: &mut State) -> &mut BTreeMap<Principal, usize> {
         &mut state.withdrawing_principals
     }
+
+    fn locked_at(state: &mut State) -> &mut BTreeMap<Principal, u64> {
+        &mut state.withdrawal_locked_at
+    }
+
+    fn max_requests_per_principal(state: &State) -> usize {
+        state.max_withdrawals_per_principal
+    }
 }
 
-/// Guards a block from executing twice when called by the same user and from being
-/// executed [MAX_CONCURRENT] or more times in parallel.
+/// Guards a block from executing more than the allowed number of times in parallel
+/// for the same user and from being executed [MAX_CONCURRENT] or more times in parallel.
 #[must_use]
 #[derive(Debug, PartialEq, Eq)]
 pub struct Guard<PR: RequestsGuardedByPrincipal> {
@@ -35,19 +50,25 @@ pub struct Guard<PR: RequestsGuardedByPrincipal> {
 }
 
 impl<PR: RequestsGuardedByPrincipal> Guard<PR> {
-    /// Attempts to create a new guard for the current code block. Fails if there is
-    /// already a pending request for the specified [principal] or if there
+    /// Attempts to create a new guard for the current code block. Fails if the
+    /// specified [principal] already reached its limit of pending requests or if there
     /// are at least [MAX_CONCURRENT] pending requests.
     fn new(principal: Principal) -> Result<Self, GuardError> {
         mutate_state(|s| {
+            let limit = PR::max_requests_per_principal(s);
             let principals = PR::guarded_principals(s);
-            if principals.contains(&principal) {
-                return Err(GuardError::AlreadyProcessing);
+            let pending = principals.get(&principal).copied().unwrap_or_default();
+            if pending >= limit {
+                return Err(match limit {
+                    1 => GuardError::AlreadyProcessing,
+                    _ => GuardError::TooManyPendingRequests,
+                });
             }
-            if principals.len() >= MAX_CONCURRENT {
+            if principals.values().sum::<usize>() >= MAX_CONCURRENT {
                 return Err(GuardError::TooManyConcurrentRequests);
             }
-            principals.insert(principal);
+            principals.insert(principal, pending + 1);
+            PR::locked_at(s).insert(principal, ic_cdk::api::time());
             Ok(Self {
                 principal,
                 _marker: PhantomData,
@@ -58,41 +79,242 @@ impl<PR: RequestsGuardedByPrincipal> Guard<PR> {
 
 impl<PR: RequestsGuardedByPrincipal> Drop for Guard<PR> {
     fn drop(&mut self) {
-        mutate_state(|s| PR::guarded_principals(s).remove(&self.principal));
+        mutate_state(|s| {
+            let principals = PR::guarded_principals(s);
+            match principals.get(&self.principal).copied() {
+                Some(pending) if pending > 1 => {
+                    principals.insert(self.principal, pending - 1);
+                }
+                _ => {
+                    principals.remove(&self.principal);
+                    PR::locked_at(s).remove(&self.principal);
+                }
+            }
+        });
     }
 }
 
+/// Releases the guards of principals that did not acquire one within `timeout`.
+/// Such guards were leaked by calls that trapped before dropping them.
+fn release_stale_guards<PR: RequestsGuardedByPrincipal>(now: u64, timeout: Duration) {
+    mutate_state(|s| {
+        let stale: Vec<Principal> = PR::locked_at(s)
+            .iter()
+            .filter(|(_, locked_at)| now.saturating_sub(**locked_at) > timeout.as_nanos() as u64)
+            .map(|(principal, _)| *principal)
+            .collect();
+        for principal in stale {
+            crate::logs::log!(INFO, "Releasing the stale guard of principal {principal}");
+            PR::guarded_principals(s).remove(&principal);
+            PR::locked_at(s).remove(&principal);
+        }
+    });
+}
+
+/// Janitor task releasing withdrawal locks leaked by failed withdrawals.
+pub fn release_stale_locks() {
+    let _guard = match TimerGuard::new(TaskType::ReleaseStaleLocks) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    let now = ic_cdk::api::time();
+    release_stale_guards::<PendingRetrieveSolRequests>(now, WITHDRAWAL_LOCK_TIMEOUT);
+    release_stale_task_locks(now);
+}
+
+/// Releases the locks of timer tasks that started more than the configured
+/// timeout ago. Such locks were leaked by tasks that trapped while holding them.
+fn release_stale_task_locks(now: u64) {
+    mutate_state(|s| {
+        let timeout = Duration::from_secs(s.task_lock_timeout_secs).as_nanos() as u64;
+        let stale: Vec<TaskType> = s
+            .active_tasks
+            .keys()
+            .filter(|task| {
+                s.task_runs
+                    .get(task)
+                    .is_some_and(|run| now.saturating_sub(run.started_at) > timeout)
+            })
+            .copied()
+            .collect();
+        for task in stale {
+            crate::logs::log!(
+                INFO,
+                "[{task:?}]: force-releasing the lock held for more than {}s",
+                s.task_lock_timeout_secs
+            );
+            s.active_tasks.remove(&task);
+            if let Some(run) = s.task_runs.get_mut(&task) {
+                run.last_error = Some("lock force-released by the watchdog".to_string());
+            }
+        }
+    });
+}
+
 pub fn retrieve_sol_guard(
     principal: Principal,
 ) -> Result<Guard<PendingRetrieveSolRequests>, GuardError> {
     Guard::new(principal)
 }
 
+/// Guards a burned withdrawal from having its coupon signed concurrently,
+/// which allows several withdrawals of the same principal to be in flight.
+#[must_use]
+#[derive(Debug, PartialEq, Eq)]
+pub struct BurnGuard {
+    burn_id: u64,
+}
+
+impl BurnGuard {
+    pub fn new(burn_id: u64) -> Result<Self, GuardError> {
+        mutate_state(|s| {
+            if !s.processing_burn_ids.insert(burn_id) {
+                return Err(GuardError::AlreadyProcessing);
+            }
+            Ok(Self { burn_id })
+        })
+    }
+}
+
+impl Drop for BurnGuard {
+    fn drop(&mut self) {
+        mutate_state(|s| {
+            s.processing_burn_ids.remove(&self.burn_id);
+        });
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum TimerGuardError {
     AlreadyProcessing,
+    Draining,
+    LowCycles,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct TimerGuard {
     task: TaskType,
+    /// Generation of the run, the lock may have been force-released and acquired by a
+    /// later run when it is dropped.
+    generation: u64,
 }
 
 impl TimerGuard {
     pub fn new(task: TaskType) -> Result<Self, TimerGuardError> {
         mutate_state(|s| {
-            if !s.active_tasks.insert(task) {
+            if s.draining_since.is_some() && !task.runs_while_draining() {
+                crate::logs::log!(DEBUG, "[{task:?}]: skipped, the minter is draining");
+                return Err(TimerGuardError::Draining);
+            }
+            if s.is_frozen(task) {
+                crate::logs::log!(DEBUG, "[{task:?}]: skipped, the cycles balance is low");
+                return Err(TimerGuardError::LowCycles);
+            }
+            if s.active_tasks.contains_key(&task) {
+                crate::logs::log!(DEBUG, "[{task:?}]: skipped, the task is already running");
                 return Err(TimerGuardError::AlreadyProcessing);
             }
-            Ok(Self { task })
+            s.task_generation += 1;
+            let generation = s.task_generation;
+            s.active_tasks.insert(task, generation);
+            s.task_runs.insert(
+                task,
+                TaskRun {
+                    started_at: ic_cdk::api::time(),
+                    ..Default::default()
+                },
+            );
+            Ok(Self { task, generation })
         })
     }
+
+    // whether the run still holds the lock, i.e. it was not force-released by the watchdog
+    fn holds_lock(&self, s: &State) -> bool {
+        s.active_tasks.get(&self.task) == Some(&self.generation)
+    }
+
+    /// Records the error as the outcome of the current run of the task.
+    pub fn record_error(&self, error: impl ToString) {
+        mutate_state(|s| {
+            if !self.holds_lock(s) {
+                return;
+            }
+            if let Some(run) = s.task_runs.get_mut(&self.task) {
+                run.last_error = Some(error.to_string());
+            }
+        });
+    }
+
+    /// Adds the number of items handled to the current run of the task.
+    pub fn record_items(&self, count: usize) {
+        mutate_state(|s| {
+            if !self.holds_lock(s) {
+                return;
+            }
+            if let Some(run) = s.task_runs.get_mut(&self.task) {
+                run.items_processed += count as u64;
+            }
+        });
+    }
+
+    /// Counts a call to another canister made by the current run of the task.
+    pub fn record_outcall(&self) {
+        mutate_state(|s| {
+            if self.holds_lock(s) {
+                s.record_task_outcall(self.task);
+            }
+        });
+    }
 }
 
 impl Drop for TimerGuard {
     fn drop(&mut self) {
         mutate_state(|s| {
+            // a force-released lock may be held by a later run of the task
+            if !self.holds_lock(s) {
+                return;
+            }
             s.active_tasks.remove(&self.task);
+            let now = ic_cdk::api::time();
+            if let Some(run) = s.task_runs.get_mut(&self.task) {
+                run.finished_at = Some(now);
+                if run.last_error.is_none() {
+                    s.task_last_success.insert(self.task, now);
+                }
+                s.last_finished_task_runs.insert(self.task, run.clone());
+            }
+            s.record_task_trace(self.task);
         });
     }
 }
+
+/// Number of calls a caller may make to the rate limited endpoints per window.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct RateLimit {
+    #[n(0)]
+    pub max_calls: u32,
+    #[n(1)]
+    pub window_secs: u64,
+}
+
+/// Calls of a caller in the current rate limiting window.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallWindow {
+    pub start: u64,
+    pub calls: u32,
+}
+
+/// Counts a call of the caller against the configured rate limit. Fails with the
+/// number of seconds until the caller's window ends if it is exhausted.
+/// Controllers are not rate limited.
+pub fn rate_limit(caller: Principal) -> Result<(), u64> {
+    if ic_cdk::api::is_controller(&caller) {
+        return Ok(());
+    }
+    let now = ic_cdk::api::time();
+    mutate_state(|s| s.consume_call(caller, now)).map_err(|retry_after| {
+        crate::logs::log!(DEBUG, "Rate limited caller {caller} for {retry_after}s");
+        retry_after
+    })
+}
@@ -0,0 +1,405 @@
+use crate::{
+    constants::MAX_LOGS_BODY_BYTES,
+    dashboard,
+    logs::{Log, Priority, Sort},
+    state::{read_state, RpcProviderStats, TaskType},
+    storage::{heap_memory_bytes, stable_memory_bytes, total_event_count},
+};
+
+use candid::{CandidType, Deserialize};
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use serde_bytes::ByteBuf;
+use std::fmt::Write;
+use std::str::FromStr;
+use strum::IntoEnumIterator;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: ByteBuf,
+}
+
+impl HttpResponse {
+    pub fn ok(content_type: &str, body: Vec<u8>) -> Self {
+        Self {
+            status_code: 200,
+            headers: vec![
+                ("Content-Type".to_string(), content_type.to_string()),
+                ("Content-Length".to_string(), body.len().to_string()),
+                ("Cache-Control".to_string(), "no-store".to_string()),
+            ],
+            body: ByteBuf::from(body),
+        }
+    }
+
+    pub fn bad_request(message: String) -> Self {
+        Self {
+            status_code: 400,
+            headers: vec![],
+            body: ByteBuf::from(message),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self {
+            status_code: 404,
+            headers: vec![],
+            body: ByteBuf::from("not found"),
+        }
+    }
+}
+
+/// Serves the minter's HTTP interface.
+pub fn serve(req: HttpRequest) -> HttpResponse {
+    match req.url.split('?').next().unwrap_or_default() {
+        "/metrics" => HttpResponse::ok("text/plain; version=0.0.4", encode_metrics().into_bytes()),
+        "/dashboard" => {
+            HttpResponse::ok("text/html; charset=utf-8", dashboard::render().into_bytes())
+        }
+        "/logs" => serve_logs(&req.url),
+        "/health" => serve_health(),
+        _ => HttpResponse::not_found(),
+    }
+}
+
+// Value of the query parameter `name` of the url.
+fn query_param<'a>(url: &'a str, name: &str) -> Option<&'a str> {
+    url.split_once('?')?
+        .1
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find_map(|(key, value)| (key == name).then_some(value))
+}
+
+// /logs?priority=info|debug|trace_http&sort=asc|desc
+fn serve_logs(url: &str) -> HttpResponse {
+    let mut log = Log::default();
+    match query_param(url, "priority").map(Priority::from_str) {
+        Some(Ok(priority)) => log.push_logs(priority),
+        Some(Err(error)) => return HttpResponse::bad_request(error),
+        None => log.push_all(),
+    }
+    match query_param(url, "sort").map(Sort::from_str) {
+        Some(Ok(sort)) => log.sort_logs(sort),
+        Some(Err(error)) => return HttpResponse::bad_request(error),
+        None => log.sort_desc(),
+    }
+
+    HttpResponse::ok(
+        "application/json; charset=utf-8",
+        log.serialize_logs(MAX_LOGS_BODY_BYTES).into_bytes(),
+    )
+}
+
+// Status of the minter for uptime monitors, answered with 503 unless the minter is healthy.
+fn serve_health() -> HttpResponse {
+    let cycles_balance = ic_cdk::api::canister_balance128();
+
+    let (status, body) = read_state(|s| {
+        let low_cycles = s
+            .last_cycles_report
+            .as_ref()
+            .is_some_and(|report| report.low_balance);
+        let status = if s.is_paused() {
+            "paused"
+        } else if s.draining_since.is_some() {
+            "draining"
+        } else if low_cycles {
+            "low_cycles"
+        } else {
+            "ok"
+        };
+
+        let body = serde_json::json!({
+            "status": status,
+            "paused": s.is_paused(),
+            "pause_reason": s.paused,
+            "draining": s.draining_since.is_some(),
+            "backlog": {
+                "signature_ranges": s.solana_signature_ranges.len(),
+                "signatures": s.solana_signatures.len(),
+                "accepted_deposits": s.accepted_events.len(),
+                "burned_withdrawals": s.withdrawal_burned_events.len(),
+            },
+            "last_successful_scrape": s.task_last_success.get(&TaskType::GetLatestSignature),
+            "cycles_balance": cycles_balance,
+            "low_cycles": low_cycles,
+        });
+        (status, body)
+    });
+
+    let mut response = HttpResponse::ok(
+        "application/json; charset=utf-8",
+        body.to_string().into_bytes(),
+    );
+    if status != "ok" {
+        response.status_code = 503;
+    }
+    response
+}
+
+// Prometheus text exposition format:
+// https://prometheus.io/docs/instrumenting/exposition_formats/
+struct MetricsEncoder {
+    out: String,
+    now_millis: u64,
+}
+
+impl MetricsEncoder {
+    fn new(now_nanos: u64) -> Self {
+        Self {
+            out: String::new(),
+            now_millis: now_nanos / 1_000_000,
+        }
+    }
+
+    fn gauge(&mut self, name: &str, value: f64, help: &str) {
+        self.header(name, help, "gauge");
+        _ = writeln!(self.out, "{name} {value} {}", self.now_millis);
+    }
+
+    fn counter(&mut self, name: &str, value: f64, help: &str) {
+        self.header(name, help, "counter");
+        _ = writeln!(self.out, "{name} {value} {}", self.now_millis);
+    }
+
+    fn gauge_vec(&mut self, name: &str, label: &str, values: &[(String, f64)], help: &str) {
+        self.header(name, help, "gauge");
+        self.samples(name, label, values);
+    }
+
+    fn counter_vec(&mut self, name: &str, label: &str, values: &[(String, f64)], help: &str) {
+        self.header(name, help, "counter");
+        self.samples(name, label, values);
+    }
+
+    fn samples(&mut self, name: &str, label: &str, values: &[(String, f64)]) {
+        for (label_value, value) in values {
+            _ = writeln!(
+                self.out,
+                "{name}{{{label}=\"{label_value}\"}} {value} {}",
+                self.now_millis
+            );
+        }
+    }
+
+    fn header(&mut self, name: &str, help: &str, metric_type: &str) {
+        _ = writeln!(self.out, "# HELP {name} {help}");
+        _ = writeln!(self.out, "# TYPE {name} {metric_type}");
+    }
+}
+
+fn biguint_to_f64(amount: &BigUint) -> f64 {
+    amount.to_f64().unwrap_or(f64::INFINITY)
+}
+
+pub fn encode_metrics() -> String {
+    let mut w = MetricsEncoder::new(ic_cdk::api::time());
+
+    w.gauge(
+        "minter_cycle_balance",
+        ic_cdk::api::canister_balance128() as f64,
+        "Cycle balance of the minter canister.",
+    );
+    w.gauge(
+        "minter_stable_memory_bytes",
+        stable_memory_bytes() as f64,
+        "Size of the stable memory allocated by the minter in bytes.",
+    );
+    w.gauge(
+        "minter_heap_memory_bytes",
+        heap_memory_bytes() as f64,
+        "Size of the heap memory allocated by the minter in bytes.",
+    );
+    w.gauge(
+        "minter_event_count",
+        total_event_count() as f64,
+        "Number of events in the minter's event log.",
+    );
+
+    read_state(|s| {
+        w.counter(
+            "minter_http_outcalls_total",
+            s.http_request_counter as f64,
+            "Number of HTTP outcalls since the last upgrade.",
+        );
+        w.counter(
+            "minter_http_outcall_cycles_total",
+            s.http_outcall_cycles as f64,
+            "Cycles attached to HTTP outcalls since the last upgrade.",
+        );
+
+        let per_provider = |value: fn(&RpcProviderStats) -> Option<f64>| -> Vec<(String, f64)> {
+            s.rpc_provider_stats
+                .iter()
+                .filter_map(|(provider, stats)| value(stats).map(|v| (provider.clone(), v)))
+                .collect()
+        };
+        w.counter_vec(
+            "minter_rpc_successes_total",
+            "provider",
+            &per_provider(|stats| Some(stats.successes as f64)),
+            "Successful JSON-RPC outcalls per provider since the last upgrade.",
+        );
+        w.counter_vec(
+            "minter_rpc_failures_total",
+            "provider",
+            &per_provider(|stats| Some(stats.failures as f64)),
+            "Failed JSON-RPC outcalls per provider since the last upgrade, timeouts excluded.",
+        );
+        w.counter_vec(
+            "minter_rpc_timeouts_total",
+            "provider",
+            &per_provider(|stats| Some(stats.timeouts as f64)),
+            "Timed out JSON-RPC outcalls per provider since the last upgrade.",
+        );
+        w.gauge_vec(
+            "minter_rpc_average_latency_seconds",
+            "provider",
+            &per_provider(|stats| {
+                stats
+                    .average_latency_nanos()
+                    .map(|latency| latency as f64 / 1_000_000_000.0)
+            }),
+            "Average latency of the JSON-RPC outcalls per provider since the last upgrade.",
+        );
+        w.counter(
+            "minter_ecdsa_sign_calls_total",
+            s.ecdsa_sign_calls as f64,
+            "Number of threshold ECDSA signatures requested since the last upgrade.",
+        );
+        w.counter(
+            "minter_ecdsa_sign_cycles_total",
+            s.ecdsa_sign_cycles as f64,
+            "Cycles attached to threshold ECDSA signature requests since the last upgrade.",
+        );
+        w.counter(
+            "minter_signature_cache_hits_total",
+            s.signature_cache_hits as f64,
+            "Coupon signatures reused from the cache since the last upgrade.",
+        );
+        w.gauge(
+            "minter_solana_signature_ranges",
+            s.solana_signature_ranges.len() as f64,
+            "Solana signature ranges waiting to be scraped.",
+        );
+        w.gauge(
+            "minter_solana_signatures",
+            s.solana_signatures.len() as f64,
+            "Solana signatures waiting for their transaction to be fetched.",
+        );
+        w.gauge(
+            "minter_invalid_events",
+            s.invalid_events.len() as f64,
+            "Solana transactions that do not hold a valid deposit.",
+        );
+        w.gauge(
+            "minter_accepted_events",
+            s.accepted_events.len() as f64,
+            "Deposits waiting to be minted.",
+        );
+        w.gauge(
+            "minter_minted_events",
+            s.minted_events.len() as f64,
+            "Deposits that were minted.",
+        );
+        w.counter(
+            "minter_minted_amount",
+            biguint_to_f64(&s.total_minted),
+            "Total amount of minted deposits.",
+        );
+        w.gauge(
+            "minter_withdrawal_burned_events",
+            s.withdrawal_burned_events.len() as f64,
+            "Burned withdrawals waiting for a coupon.",
+        );
+        w.gauge(
+            "minter_withdrawal_redeemed_events",
+            s.withdrawal_redeemed_events.len() as f64,
+            "Withdrawals with an issued coupon.",
+        );
+        w.counter(
+            "minter_burned_amount",
+            biguint_to_f64(&s.total_burned),
+            "Total amount of gSOL burned for withdrawals.",
+        );
+        w.gauge(
+            "minter_withdrawing_principals",
+            s.withdrawing_principals.len() as f64,
+            "Principals with a withdrawal in progress.",
+        );
+
+        if let Some(reconciliation) = &s.last_reconciliation {
+            w.gauge(
+                "minter_vault_balance",
+                biguint_to_f64(&reconciliation.vault_balance.0),
+                "SOL held by the vault at the last reconciliation.",
+            );
+            w.gauge(
+                "minter_ledger_total_supply",
+                biguint_to_f64(&reconciliation.total_supply.0),
+                "gSOL supply of the ledger at the last reconciliation.",
+            );
+            w.gauge(
+                "minter_supply_drift",
+                biguint_to_f64(&reconciliation.drift.0),
+                "Difference between the vault balance and the gSOL supply.",
+            );
+        }
+
+        if let Some(report) = &s.last_cycles_report {
+            w.gauge(
+                "minter_cycles_balance",
+                report.balance as f64,
+                "Cycles balance of the minter at the last monitoring run.",
+            );
+            w.gauge(
+                "minter_cycles_burn_rate_per_day",
+                report.burn_rate_per_day as f64,
+                "Cycles attached to outcalls per day at the last monitoring run.",
+            );
+        }
+
+        let last_runs: Vec<(String, f64)> = TaskType::iter()
+            .filter_map(|task| {
+                s.last_finished_task_runs
+                    .get(&task)
+                    .and_then(|run| run.finished_at)
+                    .map(|ts| (format!("{task:?}"), (ts / 1_000_000_000) as f64))
+            })
+            .collect();
+        w.gauge_vec(
+            "minter_task_last_run_seconds",
+            "task",
+            &last_runs,
+            "Unix timestamp of the last completed run of a periodic task.",
+        );
+
+        let durations: Vec<(String, f64)> = TaskType::iter()
+            .filter_map(|task| {
+                s.last_finished_task_runs
+                    .get(&task)
+                    .and_then(|run| run.duration())
+                    .map(|duration| (format!("{task:?}"), duration as f64 / 1_000_000_000.0))
+            })
+            .collect();
+        w.gauge_vec(
+            "minter_task_last_duration_seconds",
+            "task",
+            &durations,
+            "Duration of the last completed run of a periodic task.",
+        );
+    });
+
+    w.out
+}
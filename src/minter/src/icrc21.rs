@@ -0,0 +1,235 @@
+use crate::{constants::GSOL_DECIMALS, state::read_state};
+
+use candid::{CandidType, Decode, Deserialize, Nat};
+use serde_bytes::ByteBuf;
+
+// Consent messages of the user-facing updates, rendered by wallets before the call is signed:
+// https://github.com/dfinity/wg-identity-authentication/blob/main/topics/ICRC-21/icrc_21_consent_msg.md
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StandardRecord {
+    pub name: String,
+    pub url: String,
+}
+
+/// Standards implemented by the minter, as reported by icrc10_supported_standards.
+pub fn supported_standards() -> Vec<StandardRecord> {
+    vec![
+        StandardRecord {
+            name: "ICRC-10".to_string(),
+            url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-10/ICRC-10.md".to_string(),
+        },
+        StandardRecord {
+            name: "ICRC-3".to_string(),
+            url: "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-3".to_string(),
+        },
+        StandardRecord {
+            name: "ICRC-21".to_string(),
+            url: "https://github.com/dfinity/wg-identity-authentication/blob/main/topics/ICRC-21/icrc_21_consent_msg.md".to_string(),
+        },
+        StandardRecord {
+            name: "ICRC-28".to_string(),
+            url: "https://github.com/dfinity/wg-identity-authentication/blob/main/topics/icrc_28_trusted_origins.md".to_string(),
+        },
+    ]
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Icrc28TrustedOriginsResponse {
+    pub trusted_origins: Vec<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConsentMessageMetadata {
+    pub language: String,
+    pub utc_offset_minutes: Option<i16>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum DisplayMessageType {
+    GenericDisplay,
+    LineDisplay {
+        characters_per_line: u16,
+        lines_per_page: u16,
+    },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConsentMessageSpec {
+    pub metadata: ConsentMessageMetadata,
+    pub device_spec: Option<DisplayMessageType>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConsentMessageRequest {
+    pub method: String,
+    pub arg: ByteBuf,
+    pub user_preferences: ConsentMessageSpec,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LineDisplayPage {
+    pub lines: Vec<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ConsentMessage {
+    GenericDisplayMessage(String),
+    LineDisplayMessage { pages: Vec<LineDisplayPage> },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ConsentInfo {
+    pub consent_message: ConsentMessage,
+    pub metadata: ConsentMessageMetadata,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ErrorInfo {
+    pub description: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Icrc21Error {
+    UnsupportedCanisterCall(ErrorInfo),
+    ConsentMessageUnavailable(ErrorInfo),
+    InsufficientPayment(ErrorInfo),
+    GenericError {
+        error_code: Nat,
+        description: String,
+    },
+}
+
+impl Icrc21Error {
+    fn unsupported(description: String) -> Self {
+        Self::UnsupportedCanisterCall(ErrorInfo { description })
+    }
+}
+
+// formats an amount of gSOL base units as a decimal number of gSOL
+fn format_gsol(amount: &Nat) -> String {
+    let digits = format!(
+        "{:0>width$}",
+        amount.0.to_string(),
+        width = GSOL_DECIMALS as usize + 1
+    );
+    let (units, fraction) = digits.split_at(digits.len() - GSOL_DECIMALS as usize);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{units} gSOL")
+    } else {
+        format!("{units}.{fraction} gSOL")
+    }
+}
+
+// message of the call as a title followed by `label: value` lines
+fn describe_call(method: &str, arg: &[u8]) -> Result<(String, Vec<String>), Icrc21Error> {
+    let decode_error = |error: candid::Error| {
+        Icrc21Error::unsupported(format!(
+            "failed to decode the arguments of {method}: {error}"
+        ))
+    };
+
+    match method {
+        "withdraw" => {
+            let (solana_address, amount, spl_mint, burn_block_index) =
+                Decode!(arg, String, Nat, Option<String>, Option<u64>).map_err(decode_error)?;
+            let mut lines = vec![format!("Destination: {solana_address}")];
+            match spl_mint {
+                Some(mint) => {
+                    let fee = read_state(|s| s.asset(&mint).map(|asset| asset.fee.clone()))
+                        .ok_or_else(|| {
+                            Icrc21Error::unsupported(format!(
+                                "SPL mint {mint} is not bridged by the minter"
+                            ))
+                        })?;
+                    lines.push(format!("Amount: {amount}"));
+                    lines.push(format!("Asset: SPL token {mint}"));
+                    lines.push(format!("Fee: {fee}"));
+                }
+                None => {
+                    lines.push(format!("Amount: {}", format_gsol(&amount)));
+                    lines.push("Asset: native SOL".to_string());
+                    lines.push("Fee: none charged by the minter".to_string());
+                }
+            }
+            if let Some(index) = burn_block_index {
+                lines.push(format!("Burn block: {index}"));
+            }
+            Ok(("Withdraw gSOL to Solana".to_string(), lines))
+        }
+        "get_coupon" => {
+            let burn_id = Decode!(arg, u64).map_err(decode_error)?;
+            let destination = read_state(|s| {
+                s.withdrawal_burned_events
+                    .get(&burn_id)
+                    .cloned()
+                    .or_else(|| s.withdrawal_redeemed_events.get(&burn_id))
+                    .map(|event| event.to_sol_address)
+            });
+            let mut lines = vec![format!("Burn id: {burn_id}")];
+            if let Some(destination) = destination {
+                lines.push(format!("Destination: {destination}"));
+            }
+            Ok(("Retrieve the coupon of a withdrawal".to_string(), lines))
+        }
+        _ => Err(Icrc21Error::unsupported(format!(
+            "no consent message for method {method}"
+        ))),
+    }
+}
+
+// splits the lines to fit the device, long lines are wrapped by characters
+fn paginate(
+    lines: &[String],
+    characters_per_line: u16,
+    lines_per_page: u16,
+) -> Vec<LineDisplayPage> {
+    let width = characters_per_line.max(1) as usize;
+    let wrapped: Vec<String> = lines
+        .iter()
+        .flat_map(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            chars
+                .chunks(width)
+                .map(|chunk| chunk.iter().collect::<String>())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    wrapped
+        .chunks(lines_per_page.max(1) as usize)
+        .map(|lines| LineDisplayPage {
+            lines: lines.to_vec(),
+        })
+        .collect()
+}
+
+/// Builds the consent message of a call to one of the user-facing updates.
+pub fn consent_message(request: ConsentMessageRequest) -> Result<ConsentInfo, Icrc21Error> {
+    let (title, lines) = describe_call(&request.method, &request.arg)?;
+
+    let consent_message = match request.user_preferences.device_spec {
+        Some(DisplayMessageType::LineDisplay {
+            characters_per_line,
+            lines_per_page,
+        }) => {
+            let lines: Vec<String> = std::iter::once(title).chain(lines).collect();
+            ConsentMessage::LineDisplayMessage {
+                pages: paginate(&lines, characters_per_line, lines_per_page),
+            }
+        }
+        Some(DisplayMessageType::GenericDisplay) | None => {
+            let body: Vec<String> = lines.iter().map(|line| format!("- {line}")).collect();
+            ConsentMessage::GenericDisplayMessage(format!("# {title}\n\n{}", body.join("\n")))
+        }
+    };
+
+    Ok(ConsentInfo {
+        consent_message,
+        // messages are only available in English
+        metadata: ConsentMessageMetadata {
+            language: "en".to_string(),
+            utc_offset_minutes: request.user_preferences.metadata.utc_offset_minutes,
+        },
+    })
+}
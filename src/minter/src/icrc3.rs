@@ -0,0 +1,262 @@
+use crate::events::{DepositEvent, WithdrawalEvent};
+use crate::state::event::{Event, EventType};
+
+use candid::{CandidType, Deserialize, Int, Nat, Principal};
+use ic_cdk::api::call::RejectionCode;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+
+// Subset of the ICRC-3 interface used by the minter to inspect ledger blocks, and to
+// expose its own event log as blocks:
+// https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-3
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksArgs {
+    pub start: Nat,
+    pub length: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Blob(ByteBuf),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Representation-independent hash of the value, as defined by ICRC-3.
+    pub fn hash(&self) -> [u8; 32] {
+        match self {
+            Value::Blob(bytes) => Sha256::digest(bytes).into(),
+            Value::Text(text) => Sha256::digest(text.as_bytes()).into(),
+            Value::Nat(nat) => {
+                let mut buf = vec![];
+                nat.encode(&mut buf)
+                    .expect("encoding into a vector should succeed");
+                Sha256::digest(buf).into()
+            }
+            Value::Int(int) => {
+                let mut buf = vec![];
+                int.encode(&mut buf)
+                    .expect("encoding into a vector should succeed");
+                Sha256::digest(buf).into()
+            }
+            Value::Array(values) => {
+                let mut hasher = Sha256::new();
+                for value in values {
+                    hasher.update(value.hash());
+                }
+                hasher.finalize().into()
+            }
+            Value::Map(entries) => {
+                let mut pairs: Vec<Vec<u8>> = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        let key_hash: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+                        [key_hash, value.hash()].concat()
+                    })
+                    .collect();
+                pairs.sort();
+                let mut hasher = Sha256::new();
+                for pair in pairs {
+                    hasher.update(pair);
+                }
+                hasher.finalize().into()
+            }
+        }
+    }
+}
+
+fn nat(n: u64) -> Value {
+    Value::Nat(Nat::from(n))
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+fn principal(p: &Principal) -> Value {
+    Value::Blob(ByteBuf::from(p.as_slice().to_vec()))
+}
+
+// optional fields are left out of the map when they are not set
+fn map(entries: Vec<(&str, Option<Value>)>) -> Value {
+    Value::Map(
+        entries
+            .into_iter()
+            .filter_map(|(key, value)| value.map(|value| (key.to_string(), value)))
+            .collect(),
+    )
+}
+
+fn deposit_tx(deposit: &DepositEvent) -> Value {
+    map(vec![
+        ("id", Some(nat(deposit.id))),
+        ("sol_sig", Some(text(&deposit.sol_sig))),
+        ("from", Some(text(&deposit.from_sol_address))),
+        ("to", Some(principal(&deposit.to_icp_address))),
+        ("amt", Some(Value::Nat(deposit.amount.clone()))),
+        ("fee", deposit.ledger_fee.clone().map(Value::Nat)),
+        ("mint_block", deposit.get_mint_block_index().map(nat)),
+    ])
+}
+
+fn withdrawal_tx(withdrawal: &WithdrawalEvent) -> Value {
+    map(vec![
+        ("burn_id", Some(nat(withdrawal.get_burn_id()))),
+        ("from", Some(principal(&withdrawal.from_icp_address))),
+        ("to", Some(text(&withdrawal.to_sol_address.to_string()))),
+        ("amt", Some(Value::Nat(withdrawal.amount.clone()))),
+        ("fee", withdrawal.fee.clone().map(Value::Nat)),
+        ("spl_mint", withdrawal.spl_mint.as_deref().map(text)),
+        ("burn_block", withdrawal.get_icp_burn_block_index().map(nat)),
+    ])
+}
+
+/// Encodes an event of the log as an ICRC-3 block. The `btype` is the kind of the
+/// event. Deposits and withdrawals are encoded field by field, the payload of the other
+/// events as its CBOR encoding under `cbor`.
+pub fn event_block(event: &Event, phash: Option<[u8; 32]>) -> Value {
+    let tx = match &event.payload {
+        EventType::AcceptedEvent { event_source, .. } | EventType::MintedEvent { event_source } => {
+            deposit_tx(event_source)
+        }
+        EventType::WithdrawalBurnedEvent { event_source, .. }
+        | EventType::WithdrawalRedeemedEvent { event_source } => withdrawal_tx(event_source),
+        payload => map(vec![(
+            "cbor",
+            Some(Value::Blob(ByteBuf::from(
+                minicbor::to_vec(payload).expect("event encoding should always succeed"),
+            ))),
+        )]),
+    };
+
+    map(vec![
+        (
+            "phash",
+            phash.map(|hash| Value::Blob(ByteBuf::from(hash.to_vec()))),
+        ),
+        ("btype", Some(text(&format!("{:?}", event.payload.kind())))),
+        ("ts", Some(nat(event.timestamp))),
+        ("tx", Some(tx)),
+    ])
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BlockWithId {
+    pub id: Nat,
+    pub block: Value,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchivedBlocks {
+    pub args: Vec<GetBlocksArgs>,
+    pub callback: candid::Func,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksResult {
+    pub log_length: Nat,
+    pub blocks: Vec<BlockWithId>,
+    pub archived_blocks: Vec<ArchivedBlocks>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetArchivesArgs {
+    /// Only archives following this one are returned.
+    pub from: Option<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveInfo {
+    pub canister_id: Principal,
+    pub start: Nat,
+    pub end: Nat,
+}
+
+/// Certificate of the last block, see `icrc3_get_tip_certificate`.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DataCertificate {
+    pub certificate: ByteBuf,
+    /// CBOR encoded hash tree holding the `last_block_index` and `last_block_hash` labels.
+    pub hash_tree: ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SupportedBlockType {
+    pub block_type: String,
+    pub url: String,
+}
+
+/// Fetches a single block from the ledger. Returns None if the ledger does not
+/// hold the block, e.g. because it was moved to an archive.
+pub async fn get_block(
+    ledger_id: Principal,
+    index: u64,
+) -> Result<Option<Value>, (RejectionCode, String)> {
+    let args = vec![GetBlocksArgs {
+        start: Nat::from(index),
+        length: Nat::from(1u8),
+    }];
+
+    let (result,): (GetBlocksResult,) =
+        ic_cdk::call(ledger_id, "icrc3_get_blocks", (args,)).await?;
+
+    Ok(result
+        .blocks
+        .into_iter()
+        .find(|b| b.id == Nat::from(index))
+        .map(|b| b.block))
+}
+
+/// The fields of an ICRC-1 burn transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BurnBlock {
+    pub from: Principal,
+    pub amount: Nat,
+    pub memo: Option<Vec<u8>>,
+}
+
+impl TryFrom<&Value> for BurnBlock {
+    type Error = String;
+
+    fn try_from(block: &Value) -> Result<Self, Self::Error> {
+        let tx = block.get("tx").ok_or("block has no tx field")?;
+
+        let is_burn = matches!(block.get("btype"), Some(Value::Text(t)) if t == "1burn")
+            || matches!(tx.get("op"), Some(Value::Text(op)) if op == "burn");
+        if !is_burn {
+            return Err("block is not a burn".to_string());
+        }
+
+        let from = match tx.get("from") {
+            Some(Value::Array(account)) => match account.first() {
+                Some(Value::Blob(owner)) => Principal::try_from_slice(owner)
+                    .map_err(|e| format!("invalid burn owner: {e}"))?,
+                _ => return Err("burn account has no owner".to_string()),
+            },
+            _ => return Err("burn has no from account".to_string()),
+        };
+
+        let amount = match tx.get("amt") {
+            Some(Value::Nat(amount)) => amount.clone(),
+            _ => return Err("burn has no amount".to_string()),
+        };
+
+        let memo = match tx.get("memo") {
+            Some(Value::Blob(memo)) => Some(memo.to_vec()),
+            _ => None,
+        };
+
+        Ok(BurnBlock { from, amount, memo })
+    }
+}
@@ -0,0 +1,88 @@
+use crate::constants::{GSOL_DECIMALS, SOL_DECIMALS};
+
+use candid::Nat;
+use minicbor::{Decode, Encode};
+use num_bigint::BigUint;
+use num_traits::{ToPrimitive, Zero};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LamportsError {
+    /// The amount does not fit in the u64 used by Solana for lamports.
+    Overflow(Nat),
+    /// The amount is not a whole number of lamports.
+    PrecisionLoss(Nat),
+}
+
+impl std::fmt::Display for LamportsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LamportsError::Overflow(amount) => {
+                write!(f, "{amount} exceeds the largest amount of lamports")
+            }
+            LamportsError::PrecisionLoss(amount) => {
+                write!(f, "{amount} is not a whole number of lamports")
+            }
+        }
+    }
+}
+
+/// Amount of native SOL in lamports, the unit of amounts on Solana.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+#[cbor(transparent)]
+pub struct Lamports(#[n(0)] pub u64);
+
+impl Lamports {
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Converts the amount to units of a ledger using `decimals` decimals.
+    pub fn to_ledger_units(self, decimals: u32) -> Nat {
+        let amount = BigUint::from(self.0);
+        Nat(match decimals.cmp(&SOL_DECIMALS) {
+            std::cmp::Ordering::Equal => amount,
+            std::cmp::Ordering::Greater => amount * pow10(decimals - SOL_DECIMALS),
+            // rounded down, a ledger with fewer decimals cannot hold single lamports
+            std::cmp::Ordering::Less => amount / pow10(SOL_DECIMALS - decimals),
+        })
+    }
+
+    /// Converts units of a ledger using `decimals` decimals to lamports, amounts that
+    /// do not fit or are not whole lamports are rejected instead of being truncated.
+    pub fn from_ledger_units(amount: &Nat, decimals: u32) -> Result<Lamports, LamportsError> {
+        let lamports = match decimals.cmp(&SOL_DECIMALS) {
+            std::cmp::Ordering::Equal => amount.0.clone(),
+            std::cmp::Ordering::Greater => {
+                let factor = pow10(decimals - SOL_DECIMALS);
+                if !(&amount.0 % &factor).is_zero() {
+                    return Err(LamportsError::PrecisionLoss(amount.clone()));
+                }
+                &amount.0 / factor
+            }
+            std::cmp::Ordering::Less => &amount.0 * pow10(SOL_DECIMALS - decimals),
+        };
+        lamports
+            .to_u64()
+            .map(Lamports)
+            .ok_or_else(|| LamportsError::Overflow(amount.clone()))
+    }
+
+    /// Amount of gSOL minted for, or burned to withdraw, these lamports.
+    pub fn to_gsol(self) -> Nat {
+        self.to_ledger_units(GSOL_DECIMALS)
+    }
+
+    pub fn from_gsol(amount: &Nat) -> Result<Lamports, LamportsError> {
+        Self::from_ledger_units(amount, GSOL_DECIMALS)
+    }
+}
+
+impl std::fmt::Display for Lamports {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} lamports", self.0)
+    }
+}
+
+fn pow10(exponent: u32) -> BigUint {
+    BigUint::from(10u8).pow(exponent)
+}
@@ -1,349 +1,1713 @@
-mod cbor;
-pub mod constants;
-pub mod deposit;
-pub mod escda;
-pub mod events;
-pub mod guard;
-pub mod lifecycle;
-pub mod logs;
-pub mod sol_rpc_client;
-pub mod state;
-pub mod storage;
-pub mod utils;
-pub mod withdraw;
-
-use audit::*;
-pub use constants::*;
-use deposit::*;
-use escda::*;
-use lifecycle::post_upgrade as lifecycle_post_upgrade;
-use lifecycle::*;
-pub use logs::*;
-use serde_bytes::ByteBuf;
-use state::*;
-use withdraw::{
-    get_coupon as get_or_regen_coupon, get_withdraw_info as get_user_withdraw_info, withdraw_gsol,
-    Coupon, CouponError, UserWithdrawInfo, WithdrawError,
-};
-
-use candid::{candid_method, Principal};
-use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
-use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
-use num_bigint::BigUint;
-use state::lazy_call_ecdsa_public_key;
-use std::time::Duration;
-
-static BTOWN_CANISTER_LOCAL: Principal = Principal::from_slice(&[128, 0, 0, 0, 0, 16, 0, 12, 1, 1]);
-static _BTOWN_CANISTER_MAINNET: Principal =
-    Principal::from_slice(&[0, 0, 0, 0, 1, 16, 121, 223, 1, 1]);
-static BTOWN_CANISTER_STAGING: Principal =
-    Principal::from_slice(&[0, 0, 0, 0, 0, 224, 134, 65, 1, 1]);
-
-fn get_btown_nft_canister() -> Principal {
-    if option_env!("DFX_NETWORK").unwrap_or("local") == "ic" {
-        // BTOWN_CANISTER_MAINNET
-        BTOWN_CANISTER_STAGING
-    } else {
-        BTOWN_CANISTER_LOCAL
-    }
-}
-
-fn get_derivation_path() -> Vec<ByteBuf> {
-    if option_env!("DFX_NETWORK").unwrap_or("local") == "ic" {
-        vec![
-            ByteBuf::from(vec![0x80]),
-            ByteBuf::from(vec![44]),
-            ByteBuf::from(_BTOWN_CANISTER_MAINNET.as_slice()),
-        ]
-    } else {
-        DERIVATION_PATH
-    }
-}
-
-pub fn is_allowed_canister() -> Result<(), String> {
-    let caller = ic_cdk::caller();
-    if caller == get_btown_nft_canister() || ic_cdk::api::is_controller(&caller) {
-        Ok(())
-    } else {
-        Err("caller is not a valid canister".to_string())
-    }
-}
-
-/// Sets up timers for various tasks, such as fetching latest signatures and scraping logs.
-fn setup_timers() {
-    // Set timer to fetch ECDSA public key immediately after install.
-    ic_cdk_timers::set_timer(Duration::from_secs(0), || {
-        ic_cdk::spawn(async {
-            let _ = lazy_call_ecdsa_public_key().await;
-        });
-    });
-
-    // Set timers for scraping logs and other operations with specified intervals.
-    // These timers are started immediately after installation.
-    ic_cdk_timers::set_timer(Duration::from_secs(0), || {
-        ic_cdk::spawn(async {
-            get_latest_signature().await;
-            scrap_signature_range().await;
-            scrap_signatures().await;
-            mint_gsol().await;
-        });
-    });
-
-    // Set intervals for periodic tasks.
-    ic_cdk_timers::set_timer_interval(GET_LATEST_SOLANA_SIGNATURE, || {
-        ic_cdk::spawn(async {
-            get_latest_signature().await;
-        });
-    });
-
-    ic_cdk_timers::set_timer_interval(SCRAPPING_SOLANA_SIGNATURE_RANGES, || {
-        ic_cdk::spawn(async {
-            scrap_signature_range().await;
-        });
-    });
-
-    ic_cdk_timers::set_timer_interval(SCRAPPING_SOLANA_SIGNATURES, || {
-        ic_cdk::spawn(async {
-            scrap_signatures().await;
-        });
-    });
-
-    ic_cdk_timers::set_timer_interval(MINT_GSOL, || {
-        ic_cdk::spawn(async {
-            mint_gsol().await;
-        });
-    });
-}
-
-/// Initializes the Minter canister with the given arguments.
-///
-/// # Arguments
-///
-/// * `args` - Initialization arguments for the Minter canister.
-#[candid_method(init)]
-#[init]
-pub fn init(args: MinterArg) {
-    // Match on the initialization arguments.
-    match args {
-        // If the argument is an initialization argument, initialize the state.
-        MinterArg::Init(init_arg) => {
-            ic_canister_log::log!(INFO, "\ninitialized minter with arg:\n{init_arg:?}");
-            STATE.with(|cell| {
-                storage::record_event(EventType::Init(init_arg.clone()));
-                *cell.borrow_mut() =
-                    Some(State::try_from(init_arg).expect("failed to initialize minter"))
-            });
-        }
-        // If the argument is an upgrade argument, trap with an error message.
-        MinterArg::Upgrade(_) => {
-            ic_cdk::trap("cannot init canister state with upgrade args");
-        }
-    }
-
-    // Setup timers for periodic tasks.
-    setup_timers();
-}
-
-/// Performs actions before upgrading the canister state.
-#[pre_upgrade]
-fn pre_upgrade() {
-    read_state(|s| {
-        storage::record_event(EventType::LastKnownSolanaSignature(
-            s.get_solana_last_known_signature(),
-        ));
-        storage::record_event(EventType::LastDepositIdCounter(s.deposit_id_counter));
-        storage::record_event(EventType::LastBurnIdCounter(s.burn_id_counter));
-    });
-}
-
-/// Performs actions after upgrading the canister state.
-#[post_upgrade]
-fn post_upgrade(minter_arg: Option<MinterArg>) {
-    match minter_arg {
-        Some(MinterArg::Init(_)) => {
-            ic_cdk::trap("cannot upgrade canister state with init args");
-        }
-        Some(MinterArg::Upgrade(upgrade_args)) => lifecycle_post_upgrade(Some(upgrade_args)),
-        None => lifecycle_post_upgrade(None),
-    }
-
-    // Setup timers for periodic tasks after upgrade.
-    setup_timers();
-}
-
-/// Returns the compressed and uncompressed public keys.
-#[query]
-pub async fn get_address() -> (String, String, String) {
-    read_state(|s| {
-        (
-            s.compressed_public_key(),
-            s.uncompressed_public_key(),
-            s.ecdsa_proxy_public_key.clone().unwrap_or_default(),
-        )
-    })
-}
-
-/// Withdraws GSOL tokens to the specified Solana address.
-///
-/// # Arguments
-///
-/// * `solana_address` - The Solana address to withdraw GSOL tokens to.
-/// * `withdraw_amount` - The amount of GSOL tokens to withdraw.
-#[update(guard = "is_allowed_canister")]
-async fn withdraw(
-    solana_address: String,
-    withdraw_amount: candid::Nat,
-) -> Result<Coupon, WithdrawError> {
-    let caller = validate_caller_not_anonymous();
-    is_over_limit(&withdraw_amount.0);
-
-    withdraw_gsol(caller, solana_address, withdraw_amount).await
-}
-
-// can only be called by allowed canisters
-#[update(guard = "is_allowed_canister")]
-// #[update]
-async fn trigger_check() -> Result<(), String> {
-    if LAST_CHECKED
-        .with_borrow(|cell| ic_cdk::api::time() - *cell > LAST_CHECK_MIN.as_nanos() as u64)
-    {
-        ic_cdk_timers::set_timer(Duration::from_secs(0), || {
-            ic_cdk::spawn(async {
-                get_latest_signature().await;
-                scrap_signature_range().await;
-                scrap_signatures().await;
-                mint_gsol().await;
-            });
-        });
-    } else {
-        return Err("last check was too recent".to_string());
-    }
-
-    LAST_CHECKED.with_borrow_mut(|cell| *cell = ic_cdk::api::time());
-    Ok(())
-}
-
-/// Gets coupon or tries to regenerate coupon if it is not found.
-///
-/// # Arguments
-///
-/// * `burn_id` - Burn id of the coupon.
-#[update(guard = "is_allowed_canister")]
-async fn get_coupon(burn_id: u64) -> Result<Coupon, WithdrawError> {
-    let caller = validate_caller_not_anonymous();
-
-    get_or_regen_coupon(caller, burn_id).await
-}
-
-/// Returns ledger id.
-#[query]
-async fn get_withdraw_info() -> UserWithdrawInfo {
-    let caller = validate_caller_not_anonymous();
-
-    get_user_withdraw_info(caller).await
-}
-
-/// Verification method that validates coupon.
-#[query]
-async fn verify(coupon: Coupon) -> Result<bool, CouponError> {
-    coupon.verify()
-}
-
-/// Cleans up the HTTP response headers to make them deterministic.
-///
-/// # Arguments
-///
-/// * `args` - Transformation arguments containing the HTTP response.
-#[query(hidden = true)]
-fn cleanup_response(mut args: TransformArgs) -> HttpResponse {
-    // The response header contain non-deterministic fields that make it impossible to reach consensus!
-    // Errors seem deterministic and do not contain data that can break consensus.
-
-    // Clear non-deterministic fields from the response headers.
-    args.response.headers.clear();
-
-    args.response
-}
-
-/// Returns the current state of the Minter canister.
-#[query]
-fn get_state() -> String {
-    is_controller();
-
-    read_state(|s| {
-        ic_canister_log::log!(INFO, "state: {:?}", s);
-        s.to_string()
-    })
-}
-
-/// Returns the storage events recorded in the Minter canister.
-#[query]
-fn get_storage() -> String {
-    is_controller();
-
-    use std::fmt::Write;
-
-    let events = storage::get_storage_events();
-    let mut result = String::new();
-    for event in events {
-        write!(
-            &mut result,
-            "Event(timestamp: {}, payload: {:?})\n",
-            event.timestamp, event.payload
-        )
-        .unwrap();
-    }
-    result
-}
-
-/// Returns active tasks in the Minter canister.
-#[query]
-fn get_active_tasks() {
-    is_controller();
-
-    read_state(|s| ic_canister_log::log!(INFO, "active_tasks: {:?}", s.active_tasks));
-}
-
-ic_cdk_macros::export_candid!();
-
-fn validate_caller_not_anonymous() -> candid::Principal {
-    let principal = ic_cdk::caller();
-    if principal == candid::Principal::anonymous() {
-        ic_cdk::trap("anonymous principal is not allowed");
-    }
-    principal
-}
-
-fn is_controller() -> candid::Principal {
-    let principal = ic_cdk::caller();
-    if !ic_cdk::api::is_controller(&principal) {
-        ic_cdk::trap("only controller can call this method");
-    }
-
-    principal
-}
-
-fn is_over_limit(withdraw_amount: &BigUint) {
-    let minimum = read_state(|s| s.minimum_withdrawal_amount.clone());
-
-    match minimum.cmp(&withdraw_amount) {
-        std::cmp::Ordering::Greater => {
-            ic_cdk::trap("withdraw amount is less than minimum withdrawal amount");
-        }
-        _ => {}
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use libsecp256k1::{PublicKey, PublicKeyFormat};
-
-    #[test]
-    fn test_to_bytearray() {
-        let hex = "023848ffecda8dccb6960db7d42c4accac0fa6dfc4419f1eef7b59991e979498f7";
-        let array = hex::decode(hex).unwrap();
-        let pk = PublicKey::parse_slice(&array, Some(PublicKeyFormat::Compressed)).unwrap();
-        println!("{:?}", pk.serialize());
-        println!("{:?}", pk.serialize().len());
-    }
-}
+pub mod archive;
+pub mod assets;
+mod cbor;
+pub mod certification;
+pub mod constants;
+pub mod coupon_payload;
+pub mod cycles;
+pub mod dashboard;
+pub mod deposit;
+pub mod endpoints;
+pub mod escda;
+pub mod events;
+pub mod governance;
+pub mod guard;
+pub mod http;
+pub mod icrc21;
+pub mod icrc3;
+pub mod lamports;
+pub mod lifecycle;
+pub mod logs;
+pub mod reconciliation;
+pub mod recovery;
+pub mod sol_rpc_client;
+pub mod spl;
+pub mod state;
+pub mod storage;
+pub mod subscriptions;
+pub mod utils;
+pub mod withdraw;
+
+use audit::*;
+use certification::{certify_coupon, update_certified_coupons_of, CertifiedCoupon};
+pub use constants::*;
+use deposit::*;
+use endpoints::{
+    CandidDeadLetter, CandidEvent, CandidSolanaSignature, CandidWithdrawalEvent, CollectionSize,
+    EventChunk, GetArchivedInvalidEventsResult, GetEventsArg, GetEventsResult,
+    GetFilteredEventsResult, GetInvalidEventsResult, MemoryUsage, MintedDeposit,
+    MintedDepositsPage, MinterAddress, MinterInfo, MinterState, RedeemedWithdrawal,
+    RedeemedWithdrawalsPage, SimulatedParse, SupplyStats, TaskStatus, TimeLockedWithdrawal,
+};
+use escda::*;
+use guard::rate_limit;
+use lamports::Lamports;
+use lifecycle::post_upgrade as lifecycle_post_upgrade;
+use lifecycle::*;
+pub use logs::*;
+use serde_bytes::ByteBuf;
+use state::*;
+use withdraw::{
+    add_destination_address as add_address_book_entry, get_coupon as get_or_regen_coupon,
+    get_withdraw_info as get_user_withdraw_info, regenerate_coupons as regenerate_burned_coupons,
+    remove_destination_address as remove_address_book_entry, withdraw_gsol, Coupon, CouponError,
+    UserWithdrawInfo, WithdrawError,
+};
+
+use candid::{candid_method, Principal};
+use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
+use ic_cdk_macros::{init, inspect_message, post_upgrade, pre_upgrade, query, update};
+use num_bigint::BigUint;
+use state::lazy_call_ecdsa_public_key;
+use std::time::Duration;
+
+static BTOWN_CANISTER_LOCAL: Principal = Principal::from_slice(&[128, 0, 0, 0, 0, 16, 0, 12, 1, 1]);
+static _BTOWN_CANISTER_MAINNET: Principal =
+    Principal::from_slice(&[0, 0, 0, 0, 1, 16, 121, 223, 1, 1]);
+static BTOWN_CANISTER_STAGING: Principal =
+    Principal::from_slice(&[0, 0, 0, 0, 0, 224, 134, 65, 1, 1]);
+
+pub fn get_network() -> &'static str {
+    option_env!("DFX_NETWORK").unwrap_or("local")
+}
+
+fn get_btown_nft_canister() -> Principal {
+    if get_network() == "ic" {
+        // BTOWN_CANISTER_MAINNET
+        BTOWN_CANISTER_STAGING
+    } else {
+        BTOWN_CANISTER_LOCAL
+    }
+}
+
+fn get_derivation_path() -> Vec<ByteBuf> {
+    if get_network() == "ic" {
+        vec![
+            ByteBuf::from(vec![0x80]),
+            ByteBuf::from(vec![44]),
+            ByteBuf::from(_BTOWN_CANISTER_MAINNET.as_slice()),
+        ]
+    } else {
+        DERIVATION_PATH
+    }
+}
+
+// Derivation path of the key cosigning coupons above the dual signature threshold.
+fn get_cosigner_derivation_path() -> Vec<Vec<u8>> {
+    get_derivation_path()
+        .into_iter()
+        .map(|x| x.to_vec())
+        .chain(std::iter::once(COSIGNER_DERIVATION_PATH_SUFFIX.to_vec()))
+        .collect()
+}
+
+pub fn is_allowed_canister() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if caller == get_btown_nft_canister() || ic_cdk::api::is_controller(&caller) {
+        Ok(())
+    } else {
+        Err("caller is not a valid canister".to_string())
+    }
+}
+
+/// Sets up timers for various tasks, such as fetching latest signatures and scraping logs.
+fn setup_timers() {
+    // Set timer to fetch ECDSA public key immediately after install.
+    ic_cdk_timers::set_timer(Duration::from_secs(0), || {
+        ic_cdk::spawn(async {
+            let _ = lazy_call_ecdsa_public_key().await;
+        });
+    });
+
+    // Set timers for scraping logs and other operations with specified intervals.
+    // These timers are started immediately after installation.
+    ic_cdk_timers::set_timer(Duration::from_secs(0), || {
+        ic_cdk::spawn(async {
+            get_latest_signature().await;
+            scrap_signature_range().await;
+            scrap_signatures().await;
+            mint_gsol().await;
+        });
+    });
+
+    // Set intervals for periodic tasks.
+    for task in [
+        TaskType::GetLatestSignature,
+        TaskType::ScrapSignatureRanges,
+        TaskType::ScrapSignatures,
+        TaskType::MintGSol,
+        TaskType::ReconcileSupply,
+    ] {
+        arm_task_timer(task);
+    }
+
+    ic_cdk_timers::set_timer_interval(CHECK_INVARIANTS, invariants::check_state_invariants);
+
+    ic_cdk_timers::set_timer_interval(PRUNE_TERMINAL_EVENTS, prune_terminal_events);
+
+    ic_cdk_timers::set_timer_interval(CHECKPOINT_STATE, || {
+        ic_cdk::spawn(async {
+            checkpoint_if_due().await;
+        });
+    });
+
+    ic_cdk_timers::set_timer_interval(RELEASE_STALE_LOCKS, guard::release_stale_locks);
+
+    ic_cdk_timers::set_timer_interval(MONITOR_CYCLES, || {
+        ic_cdk::spawn(async {
+            cycles::monitor_cycles().await;
+        });
+    });
+
+    ic_cdk_timers::set_timer_interval(PUSH_EVENTS, subscriptions::push_events);
+
+    ic_cdk_timers::set_timer_interval(SIGN_PENDING_COUPONS, || {
+        ic_cdk::spawn(async {
+            withdraw::sign_pending_coupons().await;
+        });
+    });
+}
+
+/// Sets the interval timer of a task with a configurable interval, replacing the
+/// timer set up before. The first run is delayed by a random jitter so that minters
+/// installed at the same time do not synchronize their outcalls.
+fn arm_task_timer(task: TaskType) {
+    let interval = match read_state(|s| s.timer_intervals.interval(task)) {
+        Some(secs) => Duration::from_secs(secs),
+        None => return,
+    };
+
+    let timer_id = ic_cdk_timers::set_timer(interval + timer_jitter(task, interval), move || {
+        run_task(task);
+        let timer_id = ic_cdk_timers::set_timer_interval(interval, move || run_task(task));
+        TASK_TIMERS.with(|timers| timers.borrow_mut().insert(task, timer_id));
+    });
+
+    if let Some(previous) = TASK_TIMERS.with(|timers| timers.borrow_mut().insert(task, timer_id)) {
+        ic_cdk_timers::clear_timer(previous);
+    }
+}
+
+fn run_task(task: TaskType) {
+    ic_cdk::spawn(async move {
+        match task {
+            TaskType::GetLatestSignature => get_latest_signature().await,
+            TaskType::ScrapSignatureRanges => scrap_signature_range().await,
+            TaskType::ScrapSignatures => scrap_signatures().await,
+            TaskType::MintGSol => mint_gsol().await,
+            TaskType::ReconcileSupply => reconciliation::reconcile_supply().await,
+            _ => {}
+        }
+    });
+}
+
+/// Pseudo-random delay of up to 1/TIMER_JITTER_RATIO of the interval, seeded by the
+/// canister id and the current time.
+fn timer_jitter(task: TaskType, interval: Duration) -> Duration {
+    let max_jitter = interval.as_secs() / TIMER_JITTER_RATIO;
+    if max_jitter == 0 {
+        return Duration::ZERO;
+    }
+
+    let seed = sha3_256(
+        &[
+            ic_cdk::id().as_slice(),
+            &ic_cdk::api::time().to_le_bytes(),
+            format!("{task:?}").as_bytes(),
+        ]
+        .concat(),
+    );
+    let random = u64::from_le_bytes(seed[..8].try_into().unwrap());
+
+    Duration::from_secs(random % (max_jitter + 1))
+}
+
+/// Initializes the Minter canister with the given arguments.
+///
+/// # Arguments
+///
+/// * `args` - Initialization arguments for the Minter canister.
+#[candid_method(init)]
+#[init]
+pub fn init(args: MinterArg) {
+    // Match on the initialization arguments.
+    match args {
+        // If the argument is an initialization argument, initialize the state.
+        MinterArg::Init(init_arg) => {
+            crate::logs::log!(INFO, "\ninitialized minter with arg:\n{init_arg:?}");
+            STATE.with(|cell| {
+                storage::record_event(EventType::Init(init_arg.clone()));
+                *cell.borrow_mut() =
+                    Some(State::try_from(init_arg).expect("failed to initialize minter"))
+            });
+        }
+        // If the argument is an upgrade argument, trap with an error message.
+        MinterArg::Upgrade(_) => {
+            ic_cdk::trap("cannot init canister state with upgrade args");
+        }
+    }
+
+    // Setup timers for periodic tasks.
+    setup_timers();
+}
+
+/// Performs actions before upgrading the canister state.
+#[pre_upgrade]
+fn pre_upgrade() {
+    read_state(|s| {
+        storage::record_event(EventType::LastKnownSolanaSignature(
+            s.get_solana_last_known_signature(),
+        ));
+        storage::record_event(EventType::LastDepositIdCounter(s.deposit_id_counter));
+        storage::record_event(EventType::LastBurnIdCounter(s.burn_id_counter));
+    });
+    lifecycle::record_state_digest();
+}
+
+/// Performs actions after upgrading the canister state.
+#[post_upgrade]
+fn post_upgrade(minter_arg: Option<MinterArg>) {
+    match minter_arg {
+        Some(MinterArg::Init(_)) => {
+            ic_cdk::trap("cannot upgrade canister state with init args");
+        }
+        Some(MinterArg::Upgrade(upgrade_args)) => lifecycle_post_upgrade(Some(upgrade_args)),
+        None => lifecycle_post_upgrade(None),
+    }
+
+    // Setup timers for periodic tasks after upgrade.
+    setup_timers();
+}
+
+/// Returns the compressed and uncompressed public keys, an error until the ECDSA key
+/// is fetched.
+#[query]
+fn get_address() -> Result<MinterAddress, EcdsaKeyError> {
+    read_state(MinterAddress::try_from)
+}
+
+/// Withdraws GSOL tokens to the specified Solana address.
+///
+/// # Arguments
+///
+/// * `solana_address` - The Solana address to withdraw GSOL tokens to.
+/// * `withdraw_amount` - The amount of GSOL tokens to withdraw.
+/// * `spl_mint` - The SPL mint to withdraw, tokens are sent to the associated token account
+///   of `solana_address`. The mint must be registered with `register_asset`, whose minimum
+///   and fee apply instead of the native SOL limits. Native SOL is withdrawn when omitted.
+/// * `burn_block_index` - The gSOL ledger block of the burn backing the withdrawal. The burn
+///   memo must hold the 32 byte public key of `solana_address`. Required once a ledger is configured.
+#[update(guard = "is_allowed_canister")]
+async fn withdraw(
+    solana_address: String,
+    withdraw_amount: candid::Nat,
+    spl_mint: Option<String>,
+    burn_block_index: Option<u64>,
+) -> Result<Coupon, WithdrawError> {
+    let caller = validate_caller_not_anonymous()?;
+    rate_limit(caller).map_err(|retry_after| WithdrawError::RateLimited { retry_after })?;
+    if spl_mint.is_none() {
+        // native SOL is paid out in lamports
+        Lamports::from_gsol(&withdraw_amount)
+            .map_err(|e| WithdrawError::InvalidAmount(e.to_string()))?;
+        is_over_limit(&withdraw_amount.0)?;
+    }
+
+    withdraw_gsol(
+        caller,
+        solana_address,
+        withdraw_amount,
+        spl_mint,
+        burn_block_index,
+    )
+    .await
+}
+
+// can only be called by allowed canisters
+#[update(guard = "is_allowed_canister")]
+// #[update]
+async fn trigger_check() -> Result<(), String> {
+    rate_limit(ic_cdk::caller())
+        .map_err(|retry_after| format!("too many calls, retry after {retry_after}s"))?;
+
+    if LAST_CHECKED
+        .with_borrow(|cell| ic_cdk::api::time() - *cell > LAST_CHECK_MIN.as_nanos() as u64)
+    {
+        ic_cdk_timers::set_timer(Duration::from_secs(0), || {
+            ic_cdk::spawn(async {
+                get_latest_signature().await;
+                scrap_signature_range().await;
+                scrap_signatures().await;
+                mint_gsol().await;
+            });
+        });
+    } else {
+        return Err("last check was too recent".to_string());
+    }
+
+    LAST_CHECKED.with_borrow_mut(|cell| *cell = ic_cdk::api::time());
+    Ok(())
+}
+
+/// Gets coupon or tries to regenerate coupon if it is not found.
+///
+/// # Arguments
+///
+/// * `burn_id` - Burn id of the coupon.
+#[update(guard = "is_allowed_canister")]
+async fn get_coupon(burn_id: u64) -> Result<Coupon, WithdrawError> {
+    let caller = validate_caller_not_anonymous()?;
+    rate_limit(caller).map_err(|retry_after| WithdrawError::RateLimited { retry_after })?;
+
+    get_or_regen_coupon(caller, burn_id).await
+}
+
+/// Re-signs coupons for burned withdrawals that have not been redeemed yet.
+///
+/// # Arguments
+///
+/// * `burn_ids` - Burn ids of the coupons to regenerate.
+#[update]
+async fn regenerate_coupons(burn_ids: Vec<u64>) -> Vec<(u64, Result<Coupon, WithdrawError>)> {
+    require_role(Role::Operator);
+
+    regenerate_burned_coupons(burn_ids).await
+}
+
+/// Returns ledger id.
+#[query]
+async fn get_withdraw_info() -> UserWithdrawInfo {
+    let caller = validate_caller_not_anonymous().unwrap_or_else(|e| ic_cdk::trap(&e.to_string()));
+
+    get_user_withdraw_info(caller).await
+}
+
+/// Returns the unredeemed coupons issued for withdrawals to a Solana address, so that
+/// relayers can redeem them on behalf of the recipients. The minter does not observe
+/// Solana, relayers report the coupons they redeemed with `report_redeemed_coupons` and
+/// those are no longer returned. At most `MAX_EVENTS_PER_RESPONSE` coupons are returned, the
+/// oldest first, the next ones come once these are reported. Coupons signed before a key
+/// rotation are re-signed by `get_coupon`.
+///
+/// # Arguments
+///
+/// * `solana_address` - Destination address of the withdrawals.
+#[query]
+fn get_coupons_for_address(solana_address: String) -> Vec<Coupon> {
+    read_state(|s| s.coupons_for_address(&solana_address, MAX_EVENTS_PER_RESPONSE as usize))
+}
+
+/// Reports coupons as redeemed on Solana, `get_coupons_for_address` no longer returns them.
+/// Burn ids without an issued coupon are ignored.
+///
+/// # Arguments
+///
+/// * `burn_ids` - Burn ids of the redeemed coupons.
+#[update]
+fn report_redeemed_coupons(burn_ids: Vec<u64>) {
+    let caller = require_role(Role::Operator);
+
+    mutate_state(|s| {
+        for burn_id in burn_ids {
+            if s.withdrawal_redeemed_events.contains_key(&burn_id)
+                && !s.is_solana_redeemed_coupon(burn_id)
+            {
+                process_event(s, EventType::CouponRedemptionReported { burn_id, caller });
+            }
+        }
+    });
+}
+
+/// Adds a Solana address to the caller's address book. Once a principal has an address
+/// book, it can only withdraw to the addresses in it, and only after the activation delay
+/// elapsed since they were added.
+///
+/// # Arguments
+///
+/// * `solana_address` - The Solana address the caller may withdraw to.
+#[update(guard = "is_allowed_canister")]
+fn add_destination_address(solana_address: String) -> Result<DestinationAddress, WithdrawError> {
+    let caller = validate_caller_not_anonymous()?;
+
+    add_address_book_entry(caller, solana_address)
+}
+
+/// Removes a Solana address from the caller's address book, withdrawals to it are rejected
+/// right away. The caller stays bound to its address book even once it is empty.
+///
+/// # Arguments
+///
+/// * `solana_address` - The Solana address to remove.
+#[update(guard = "is_allowed_canister")]
+fn remove_destination_address(solana_address: String) {
+    remove_address_book_entry(ic_cdk::caller(), solana_address)
+}
+
+/// Returns the caller's address book.
+#[query]
+fn get_destination_addresses() -> Vec<DestinationAddress> {
+    read_state(|s| s.destination_addresses(&ic_cdk::caller()))
+}
+
+/// Verification method that validates coupon.
+#[query]
+async fn verify(coupon: Coupon) -> Result<bool, CouponError> {
+    coupon.verify()
+}
+
+/// Encodes the coupon in the compact form accepted by `verify_compact`, a single
+/// base64 string that fits in a QR code.
+///
+/// # Arguments
+///
+/// * `coupon` - Coupon issued by the minter.
+#[query]
+fn to_compact_coupon(coupon: Coupon) -> Result<String, CouponError> {
+    coupon.to_compact()
+}
+
+/// Verification method that validates a coupon in its compact form.
+///
+/// # Arguments
+///
+/// * `compact` - Coupon encoded by `to_compact_coupon`.
+#[query]
+fn verify_compact(compact: String) -> Result<bool, CouponError> {
+    Coupon::from_compact(&compact)?.verify()
+}
+
+/// Recovers the public key that signed the coupon from its signature and recovery id.
+#[query]
+fn recover_public_key(coupon: Coupon) -> Result<String, CouponError> {
+    coupon.recover_public_key()
+}
+
+/// Returns a Merkle proof that the coupon was issued by the minter, certified by the
+/// subnet through the `coupons` label of the canister's certified hash tree. Must be
+/// called as a query.
+#[query]
+fn get_certified_coupon(coupon: Coupon) -> Option<CertifiedCoupon> {
+    certify_coupon(&coupon)
+}
+
+/// Returns a human-readable description of a call to `withdraw` or `get_coupon`,
+/// shown by wallets before the user signs the call.
+#[update]
+fn icrc21_canister_call_consent_message(
+    request: icrc21::ConsentMessageRequest,
+) -> Result<icrc21::ConsentInfo, icrc21::Icrc21Error> {
+    icrc21::consent_message(request)
+}
+
+/// Returns the ICRC standards implemented by the minter.
+#[query]
+fn icrc10_supported_standards() -> Vec<icrc21::StandardRecord> {
+    icrc21::supported_standards()
+}
+
+/// Returns the events of the log as ICRC-3 blocks, the id of a block is the index of its
+/// event in the log, counting the compacted events. Compacted blocks are no longer
+/// returned, those pushed to the event archive are pointed to by `archived_blocks`.
+///
+/// # Arguments
+///
+/// * `args` - Ranges of block ids, at most `MAX_EVENTS_PER_RESPONSE` blocks are returned
+///   in total.
+#[query]
+fn icrc3_get_blocks(args: Vec<icrc3::GetBlocksArgs>) -> icrc3::GetBlocksResult {
+    let mut blocks = vec![];
+    let mut archived_blocks = vec![];
+    for arg in args {
+        let (Ok(start), Ok(length)) = (u64::try_from(arg.start.0), u64::try_from(arg.length.0))
+        else {
+            continue;
+        };
+        let remaining = MAX_EVENTS_PER_RESPONSE.saturating_sub(blocks.len() as u64);
+        for id in start..start.saturating_add(length.min(remaining)) {
+            if let Some((event, phash)) = storage::get_block(id) {
+                blocks.push(icrc3::BlockWithId {
+                    id: candid::Nat::from(id),
+                    block: icrc3::event_block(&event, phash),
+                });
+            }
+        }
+        archived_blocks.extend(archive::archived_block_pointers(
+            start,
+            start.saturating_add(length),
+        ));
+    }
+
+    icrc3::GetBlocksResult {
+        log_length: candid::Nat::from(storage::block_count()),
+        blocks,
+        archived_blocks,
+    }
+}
+
+/// Returns the ranges of blocks pushed to the event archive, `end` included.
+///
+/// # Arguments
+///
+/// * `args` - Only the ranges following the last range of `from` are returned.
+#[query]
+fn icrc3_get_archives(args: icrc3::GetArchivesArgs) -> Vec<icrc3::ArchiveInfo> {
+    archive::archives(args.from)
+}
+
+/// Returns the certificate of the last hashed block, None until a block is hashed.
+#[query]
+fn icrc3_get_tip_certificate() -> Option<icrc3::DataCertificate> {
+    certification::tip_certificate()
+}
+
+/// Returns the block types of the minter, one per kind of event.
+#[query]
+fn icrc3_supported_block_types() -> Vec<icrc3::SupportedBlockType> {
+    state::event::EventKind::all()
+        .iter()
+        .map(|kind| icrc3::SupportedBlockType {
+            block_type: format!("{kind:?}"),
+            url: "https://github.com/lordrio/galactic-bridge-icp#icrc-3-blocks".to_string(),
+        })
+        .collect()
+}
+
+/// Returns the front-end origins from which wallets may accept calls to the minter.
+#[update]
+fn icrc28_trusted_origins() -> icrc21::Icrc28TrustedOriginsResponse {
+    icrc21::Icrc28TrustedOriginsResponse {
+        trusted_origins: read_state(|s| s.trusted_origins.clone()),
+    }
+}
+
+/// Returns the gSOL ledger whose burns back withdrawals, None until it is configured.
+#[query]
+fn get_ledger_id() -> Option<Principal> {
+    read_state(|s| s.ledger_id)
+}
+
+/// Returns the public configuration of the minter.
+#[query]
+fn get_minter_info() -> MinterInfo {
+    read_state(MinterInfo::from)
+}
+
+/// Returns a page of the minter's log entries, newest first.
+///
+/// # Arguments
+///
+/// * `priority` - Priority of the returned entries, all priorities if None.
+/// * `offset` - Number of newest entries to skip.
+/// * `limit` - Maximum number of entries returned, capped at [MAX_LOGS_PER_RESPONSE].
+#[query]
+fn get_logs(priority: Option<logs::Priority>, offset: u64, limit: u64) -> Vec<logs::LogEntry> {
+    require_role(Role::ReadOnly);
+
+    logs::get_logs(priority, offset, limit)
+}
+
+/// Returns the cumulative minted, burned and fee amounts of gSOL.
+#[query]
+fn get_supply_stats() -> SupplyStats {
+    read_state(SupplyStats::from)
+}
+
+/// Serves the minter's HTTP endpoints: Prometheus metrics at `/metrics`, a
+/// status page at `/dashboard`, the log entries at `/logs` and a JSON health
+/// check at `/health`.
+#[query(hidden = true)]
+fn http_request(req: http::HttpRequest) -> http::HttpResponse {
+    http::serve(req)
+}
+
+/// Cleans up the HTTP response headers to make them deterministic.
+///
+/// # Arguments
+///
+/// * `args` - Transformation arguments containing the HTTP response.
+#[query(hidden = true)]
+fn cleanup_response(mut args: TransformArgs) -> HttpResponse {
+    // The response header contain non-deterministic fields that make it impossible to reach consensus!
+    // Errors seem deterministic and do not contain data that can break consensus.
+
+    // Clear non-deterministic fields from the response headers.
+    args.response.headers.clear();
+
+    args.response
+}
+
+/// Returns the current state of the Minter canister.
+#[query]
+fn get_state() -> String {
+    require_role(Role::ReadOnly);
+
+    read_state(|s| {
+        crate::logs::log!(INFO, "state: {:?}", s);
+        s.to_string()
+    })
+}
+
+/// Returns the current state of the Minter canister as a candid record.
+#[query]
+fn get_minter_state() -> MinterState {
+    require_role(Role::ReadOnly);
+
+    read_state(MinterState::from)
+}
+
+/// Exports the event log as CBOR in chunks that fit into a single reply.
+///
+/// # Arguments
+///
+/// * `start` - Index of the first event of the chunk.
+/// * `limit` - Maximum number of events of the chunk, only bounded by the reply size if None.
+/// * `prev_hash` - `running_hash` of the previous chunk, 32 zero bytes if None.
+#[query]
+fn export_events(start: u64, limit: Option<u64>, prev_hash: Option<ByteBuf>) -> EventChunk {
+    require_role(Role::ReadOnly);
+
+    let prev_hash: [u8; 32] = match prev_hash {
+        Some(hash) => hash
+            .as_slice()
+            .try_into()
+            .unwrap_or_else(|_| ic_cdk::trap("prev_hash must be 32 bytes long")),
+        None => [0; 32],
+    };
+    let total_event_count = storage::total_event_count();
+    let (cbor, count, running_hash) = storage::export_events_cbor(
+        start,
+        limit.unwrap_or(u64::MAX),
+        MAX_EXPORT_CHUNK_BYTES,
+        prev_hash,
+    );
+    let next = start.saturating_add(count);
+
+    EventChunk {
+        cbor: ByteBuf::from(cbor),
+        start,
+        count,
+        next_start: (next < total_event_count).then_some(next),
+        total_event_count,
+        running_hash: ByteBuf::from(running_hash.to_vec()),
+    }
+}
+
+/// Exports a chunk of the CBOR encoded state snapshot for disaster recovery.
+///
+/// # Arguments
+///
+/// * `index` - Index of the chunk, starting at 0.
+#[query]
+fn export_state_snapshot(index: u64) -> recovery::StateSnapshotChunk {
+    is_controller();
+
+    recovery::export_chunk(index)
+}
+
+/// Imports a chunk of a state snapshot exported by `export_state_snapshot`.
+/// Only allowed on a freshly installed minter; the state is replaced once the
+/// last chunk is imported and the snapshot matches its checksum.
+#[update]
+fn import_state_snapshot(
+    chunk: recovery::StateSnapshotChunk,
+) -> Result<(), recovery::ImportSnapshotError> {
+    is_controller();
+
+    recovery::import_chunk(chunk)
+}
+
+/// Returns the heap and stable memory used by the Minter canister.
+#[query]
+fn get_memory_usage() -> MemoryUsage {
+    require_role(Role::ReadOnly);
+
+    let (event_log_index_bytes, event_log_data_bytes) = storage::event_log_memory_bytes();
+
+    MemoryUsage {
+        heap_bytes: storage::heap_memory_bytes(),
+        stable_bytes: storage::stable_memory_bytes(),
+        event_log_index_bytes,
+        event_log_data_bytes,
+        event_count: storage::total_event_count(),
+        collections: read_state(CollectionSize::of_state),
+    }
+}
+
+/// Returns a page of the events recorded in the Minter canister.
+///
+/// # Arguments
+///
+/// * `arg` - Index of the first event and the number of events to return,
+///   capped at `MAX_EVENTS_PER_RESPONSE`.
+#[query]
+fn get_events(arg: GetEventsArg) -> GetEventsResult {
+    require_role(Role::ReadOnly);
+
+    let length = arg.length.min(MAX_EVENTS_PER_RESPONSE);
+
+    GetEventsResult {
+        events: storage::get_events(arg.start, length)
+            .iter()
+            .map(CandidEvent::from)
+            .collect(),
+        total_event_count: storage::total_event_count(),
+    }
+}
+
+/// Returns a page of the events of the given kinds recorded within a time range. A page
+/// examines at most `MAX_EXAMINED_EVENTS_PER_QUERY` events, so it may hold fewer matching
+/// events than `limit` even though more follow: `next_start` resumes the scan.
+///
+/// # Arguments
+///
+/// * `types` - Kinds of the events to return, all kinds if empty.
+/// * `from_ts` - Earliest timestamp of the returned events, in nanoseconds since the epoch.
+/// * `to_ts` - Timestamp before which the returned events were recorded.
+/// * `start` - Block id to resume the scan from, the `next_start` of the previous page.
+/// * `limit` - Number of events to return, capped at `MAX_EVENTS_PER_RESPONSE`.
+#[query]
+fn get_events_filtered(
+    types: Vec<state::event::EventKind>,
+    from_ts: Option<u64>,
+    to_ts: Option<u64>,
+    start: Option<u64>,
+    limit: u64,
+) -> GetFilteredEventsResult {
+    require_role(Role::ReadOnly);
+
+    let limit = limit.min(MAX_EVENTS_PER_RESPONSE) as usize;
+    let to_ts = to_ts.unwrap_or(u64::MAX);
+    let compacted = storage::compacted_event_count();
+    let block_count = storage::block_count();
+    // events are recorded in the order of their timestamps
+    let first = match from_ts.and_then(|from_ts| from_ts.checked_sub(1)) {
+        Some(before) => storage::first_block_after(before),
+        None => compacted,
+    };
+    let start = start.unwrap_or(0).max(first).min(block_count);
+    let end = block_count.min(start.saturating_add(MAX_EXAMINED_EVENTS_PER_QUERY));
+
+    let mut events = vec![];
+    let mut next = start;
+    for event in storage::get_events(start - compacted, end - start) {
+        if event.timestamp >= to_ts {
+            return GetFilteredEventsResult {
+                events,
+                next_start: None,
+            };
+        }
+        next += 1;
+        if types.is_empty() || types.contains(&event.payload.kind()) {
+            events.push(CandidEvent::from(&event));
+            if events.len() >= limit {
+                break;
+            }
+        }
+    }
+    GetFilteredEventsResult {
+        events,
+        next_start: (next < block_count).then_some(next),
+    }
+}
+
+/// Returns a page of the invalid events archived by the retention policy.
+///
+/// # Arguments
+///
+/// * `arg` - Archival index of the first event and the number of events to return,
+///   capped at `MAX_EVENTS_PER_RESPONSE`.
+#[query]
+fn get_archived_invalid_events(arg: GetEventsArg) -> GetArchivedInvalidEventsResult {
+    require_role(Role::ReadOnly);
+
+    let length = arg.length.min(MAX_EVENTS_PER_RESPONSE);
+
+    read_state(|s| GetArchivedInvalidEventsResult {
+        signatures: s
+            .archived_invalid_events
+            .values_from(arg.start, length as usize)
+            .iter()
+            .map(CandidSolanaSignature::from)
+            .collect(),
+        total_count: s.archived_invalid_events.len(),
+    })
+}
+
+/// Returns a page of the invalid events held in memory, ordered by Solana signature.
+///
+/// # Arguments
+///
+/// * `offset` - Number of matching events to skip.
+/// * `limit` - Number of events to return, capped at `MAX_EVENTS_PER_RESPONSE`.
+/// * `reason_filter` - Only return the events recorded for this kind of reason, the
+///   details of a `BadPrincipal` reason are ignored.
+#[query]
+fn get_invalid_events(
+    offset: u64,
+    limit: u64,
+    reason_filter: Option<events::InvalidReason>,
+) -> GetInvalidEventsResult {
+    require_role(Role::ReadOnly);
+
+    let limit = limit.min(MAX_EVENTS_PER_RESPONSE);
+
+    read_state(|s| {
+        let mut signatures: Vec<_> = s
+            .invalid_events
+            .values()
+            .filter(|sig| match &reason_filter {
+                Some(filter) => sig.invalid_reason.as_ref().is_some_and(|reason| {
+                    std::mem::discriminant(reason) == std::mem::discriminant(filter)
+                }),
+                None => true,
+            })
+            .collect();
+        signatures.sort_by(|a, b| a.sol_sig.cmp(&b.sol_sig));
+
+        GetInvalidEventsResult {
+            total_count: signatures.len() as u64,
+            signatures: signatures
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .map(CandidSolanaSignature::from)
+                .collect(),
+        }
+    })
+}
+
+/// Subscribes the calling canister to new deposit and withdrawal events, pushed in
+/// batches to its `on_minter_events : (vec CandidEvent) -> ()` method. Subscribing
+/// again changes the filter without replaying delivered events.
+///
+/// # Arguments
+///
+/// * `filter` - Kinds of events pushed to the caller.
+#[update]
+fn subscribe(filter: subscriptions::SubscriptionFilter) -> Result<(), String> {
+    let subscriber = require_role(Role::ReadOnly);
+
+    mutate_state(|s| {
+        let cursor = match s.subscriptions.get(&subscriber) {
+            Some(subscription) => subscription.cursor,
+            None if s.subscriptions.len() >= MAX_SUBSCRIBERS => {
+                return Err(format!(
+                    "the minter accepts at most {MAX_SUBSCRIBERS} subscribers"
+                ));
+            }
+            // only events of the current and later rounds are pushed
+            None => {
+                storage::set_delivery_cursor(&subscriber, storage::block_count());
+                ic_cdk::api::time().saturating_sub(1)
+            }
+        };
+        process_event(
+            s,
+            EventType::Subscribed(subscriptions::Subscription {
+                subscriber,
+                filter,
+                cursor,
+            }),
+        );
+        Ok(())
+    })
+}
+
+/// Stops pushing events to the calling canister.
+#[update]
+fn unsubscribe() {
+    let subscriber = ic_cdk::caller();
+
+    mutate_state(|s| {
+        if s.subscriptions.contains_key(&subscriber) {
+            process_event(
+                s,
+                EventType::Unsubscribed {
+                    subscriber,
+                    reason: None,
+                },
+            );
+            storage::remove_delivery_cursor(&subscriber);
+        }
+    });
+}
+
+/// Returns the canisters subscribed to the minter's events.
+#[query]
+fn get_subscriptions() -> Vec<subscriptions::Subscription> {
+    require_role(Role::ReadOnly);
+
+    read_state(|s| s.subscriptions.values().cloned().collect())
+}
+
+/// Adds Solana addresses to the blocklist.
+///
+/// # Arguments
+///
+/// * `addresses` - Solana addresses that withdrawals cannot be sent to and whose deposits
+///   are recorded as invalid events.
+#[update]
+fn add_blocked_addresses(addresses: Vec<String>) {
+    require_role(Role::Operator);
+
+    mutate_state(|s| {
+        for address in addresses {
+            if !s.is_blocked_address(&address) {
+                process_event(s, EventType::AddBlockedAddress(address));
+            }
+        }
+    });
+}
+
+/// Removes Solana addresses from the withdrawal blocklist.
+///
+/// # Arguments
+///
+/// * `addresses` - Solana addresses to unblock.
+#[update]
+fn remove_blocked_addresses(addresses: Vec<String>) {
+    require_role(Role::Operator);
+
+    mutate_state(|s| {
+        for address in addresses {
+            if s.is_blocked_address(&address) {
+                process_event(s, EventType::RemoveBlockedAddress(address));
+            }
+        }
+    });
+}
+
+/// Returns the blocklist.
+#[query]
+fn get_blocked_addresses() -> Vec<String> {
+    require_role(Role::ReadOnly);
+
+    read_state(|s| s.blocked_addresses.iter().cloned().collect())
+}
+
+/// Revokes issued coupons, `verify` returns false for them, they are no longer certified
+/// and `get_coupon` and `regenerate_coupons` refuse to return them.
+///
+/// # Arguments
+///
+/// * `burn_ids` - Burn ids of the coupons to revoke.
+#[update]
+fn revoke_coupons(burn_ids: Vec<u64>) {
+    let caller = is_controller();
+
+    mutate_state(|s| {
+        for burn_id in burn_ids.iter().copied() {
+            if !s.is_revoked_coupon(burn_id) {
+                process_event(s, EventType::CouponRevoked { burn_id, caller });
+            }
+        }
+    });
+    update_certified_coupons_of(&burn_ids);
+}
+
+/// Lifts the revocation of coupons.
+///
+/// # Arguments
+///
+/// * `burn_ids` - Burn ids of the revoked coupons.
+#[update]
+fn unrevoke_coupons(burn_ids: Vec<u64>) {
+    let caller = is_controller();
+
+    mutate_state(|s| {
+        for burn_id in burn_ids.iter().copied() {
+            if s.is_revoked_coupon(burn_id) {
+                process_event(s, EventType::CouponRevocationLifted { burn_id, caller });
+            }
+        }
+    });
+    update_certified_coupons_of(&burn_ids);
+}
+
+/// Returns the burn ids of the revoked coupons, relayers refuse to submit them to Solana.
+#[query]
+fn get_revoked_coupons() -> Vec<u64> {
+    read_state(|s| s.revoked_coupons.iter().copied().collect())
+}
+
+/// Returns a page of the minted deposits, for indexers. Deposits pruned by the retention
+/// policy are no longer listed.
+///
+/// # Arguments
+///
+/// * `cursor` - `next_cursor` of the previous page, None for the first page.
+/// * `limit` - Number of deposits to return, capped at `MAX_EVENTS_PER_RESPONSE`.
+#[query]
+fn list_minted_deposits(cursor: Option<u64>, limit: u64) -> MintedDepositsPage {
+    let limit = limit.min(MAX_EVENTS_PER_RESPONSE) as usize;
+
+    let deposits = read_state(|s| s.minted_deposits_after(cursor, limit));
+    MintedDepositsPage {
+        next_cursor: match deposits.last() {
+            Some(event) if deposits.len() == limit => Some(event.id),
+            _ => None,
+        },
+        deposits: deposits.iter().map(MintedDeposit::from).collect(),
+    }
+}
+
+/// Returns a page of the redeemed withdrawals, for indexers. Withdrawals pruned by the
+/// retention policy are no longer listed.
+///
+/// # Arguments
+///
+/// * `cursor` - `next_cursor` of the previous page, None for the first page.
+/// * `limit` - Number of withdrawals to return, capped at `MAX_EVENTS_PER_RESPONSE`.
+#[query]
+fn list_redeemed_withdrawals(cursor: Option<u64>, limit: u64) -> RedeemedWithdrawalsPage {
+    let limit = limit.min(MAX_EVENTS_PER_RESPONSE) as usize;
+
+    let entries = read_state(|s| s.withdrawal_redeemed_events.entries_after(cursor, limit));
+    RedeemedWithdrawalsPage {
+        next_cursor: match entries.last() {
+            Some((burn_id, _)) if entries.len() == limit => Some(*burn_id),
+            _ => None,
+        },
+        withdrawals: entries
+            .iter()
+            .map(|(_, event)| RedeemedWithdrawal::from(event))
+            .collect(),
+    }
+}
+
+/// Returns the status and last run of every timer task in the Minter canister.
+#[query]
+fn get_active_tasks() -> Vec<TaskStatus> {
+    require_role(Role::ReadOnly);
+
+    read_state(endpoints::task_statuses)
+}
+
+/// Returns the most recent completed runs of the timer tasks, newest first.
+///
+/// # Arguments
+///
+/// * `task` - Task whose runs are returned, all tasks if None.
+#[query]
+fn get_task_traces(task: Option<TaskType>) -> Vec<TaskTrace> {
+    require_role(Role::ReadOnly);
+
+    read_state(|s| {
+        s.task_traces
+            .iter()
+            .rev()
+            .filter(|trace| task.is_none_or(|task| trace.task == task))
+            .cloned()
+            .collect()
+    })
+}
+
+/// Stops starting timer tasks and withdrawals and waits for the work in flight to
+/// finish, so the minter can be upgraded without interrupting it.
+#[update]
+async fn prepare_upgrade() -> UpgradeReadiness {
+    is_controller();
+
+    lifecycle::prepare_upgrade().await
+}
+
+/// Restarts timer tasks and withdrawals after `prepare_upgrade` without upgrading.
+#[update]
+fn cancel_upgrade() {
+    is_controller();
+
+    lifecycle::cancel_upgrade();
+}
+
+/// Proposes a change of the contract address, the Solana RPC url or the ECDSA key.
+/// The change can be executed once the configured timelock expired. Returns the id
+/// of the proposal.
+///
+/// # Arguments
+///
+/// * `change` - Configuration change to apply.
+#[update]
+fn propose_config_change(change: governance::ConfigChange) -> Result<u64, String> {
+    let proposer = is_controller();
+
+    governance::propose(change, proposer)
+}
+
+/// Applies a proposed configuration change whose timelock expired.
+///
+/// # Arguments
+///
+/// * `id` - Id of the proposal.
+#[update]
+async fn execute_config_change(id: u64) -> Result<(), String> {
+    let executor = is_controller();
+
+    governance::execute(id, executor).await
+}
+
+/// Drops a proposed configuration change.
+///
+/// # Arguments
+///
+/// * `id` - Id of the proposal.
+#[update]
+fn cancel_config_change(id: u64) -> Result<(), String> {
+    let canceller = is_controller();
+
+    governance::cancel(id, canceller)
+}
+
+/// Returns the configuration changes waiting to be executed.
+#[query]
+fn get_config_proposals() -> Vec<governance::ConfigProposal> {
+    require_role(Role::ReadOnly);
+
+    read_state(|s| s.config_proposals.values().cloned().collect())
+}
+
+/// Runs the deposit parser on a transaction without changing the state.
+///
+/// # Arguments
+///
+/// * `raw_transaction_json` - getTransaction response, with or without its JSON-RPC envelope.
+#[query]
+fn simulate_parse(raw_transaction_json: String) -> Result<SimulatedParse, SimulateParseError> {
+    is_controller();
+
+    dry_run_parse(&raw_transaction_json)
+}
+
+/// Changes the interval of a periodic task and re-arms its timer.
+///
+/// # Arguments
+///
+/// * `task` - Task whose interval is changed.
+/// * `secs` - Seconds between two runs of the task.
+#[update]
+fn set_task_interval(task: TaskType, secs: u64) -> Result<(), String> {
+    require_role(Role::Admin);
+
+    if secs == 0 {
+        return Err("interval must be positive".to_string());
+    }
+    let mut intervals = read_state(|s| s.timer_intervals.clone());
+    match intervals.interval_mut(task) {
+        Some(interval) => *interval = secs,
+        None => return Err(format!("{task:?} does not have a configurable interval")),
+    }
+
+    mutate_state(|s| process_event(s, EventType::TimerIntervalsChanged { intervals }));
+    arm_task_timer(task);
+
+    Ok(())
+}
+
+/// Moves the scraping anchor to `signature`, so that the scraper skips a signature it
+/// cannot get past. Signatures between the current and the new anchor are not scanned,
+/// the ranges already recorded are scanned as before.
+///
+/// # Arguments
+///
+/// * `signature` - Solana signature the next scan stops at.
+#[update]
+fn set_last_known_signature(signature: String) -> Result<(), String> {
+    is_controller();
+
+    if !spl::is_valid_signature(&signature) {
+        return Err(format!("{signature} is not a valid Solana signature"));
+    }
+    // the running scan would record a range ending at the previous anchor
+    if read_state(|s| s.active_tasks.contains_key(&TaskType::GetLatestSignature)) {
+        return Err("the scraper is running, retry later".to_string());
+    }
+    let previous = read_state(|s| s.get_solana_last_known_signature());
+    if previous == signature {
+        return Err(format!("{signature} is already the last known signature"));
+    }
+
+    crate::logs::log!(
+        INFO,
+        "\nLast known signature overridden from {previous} to {signature}"
+    );
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::LastKnownSolanaSignatureOverridden {
+                signature,
+                previous,
+                caller: ic_cdk::caller(),
+            },
+        )
+    });
+
+    Ok(())
+}
+
+/// Queues a signature range for scanning, so that deposits missed by the scraper or
+/// skipped by `set_last_known_signature` are recovered. Deposits of the range that
+/// were already accepted, minted or rejected are not processed again.
+///
+/// # Arguments
+///
+/// * `before_sig` - Newest signature of the range, scanned as well.
+/// * `until_sig` - Oldest signature of the range, not scanned.
+#[update]
+fn rescan_range(before_sig: String, until_sig: String) -> Result<(), String> {
+    require_role(Role::Operator);
+
+    if let Some(signature) = [&before_sig, &until_sig]
+        .into_iter()
+        .find(|signature| !spl::is_valid_signature(signature))
+    {
+        return Err(format!("{signature} is not a valid Solana signature"));
+    }
+    if before_sig == until_sig {
+        return Err("the range is empty".to_string());
+    }
+    let range = events::SolanaSignatureRange::new(before_sig, until_sig);
+    if read_state(|s| s.has_solana_signature_range(&range)) {
+        return Err("the range is already queued".to_string());
+    }
+
+    crate::logs::log!(
+        INFO,
+        "\nRescanning range before {} until {}",
+        range.before_sol_sig,
+        range.until_sol_sig
+    );
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::SolanaSignatureRangeRescanRequested {
+                range,
+                caller: ic_cdk::caller(),
+            },
+        )
+    });
+
+    Ok(())
+}
+
+/// Returns the deposits whose mint failed with an error that retries cannot resolve,
+/// together with that error.
+#[query]
+fn get_dead_letters() -> Vec<CandidDeadLetter> {
+    require_role(Role::ReadOnly);
+
+    read_state(|s| {
+        s.dead_letters
+            .values()
+            .map(CandidDeadLetter::from)
+            .collect()
+    })
+}
+
+/// Moves a dead-lettered deposit back to the deposits to mint, with its retries reset.
+/// The cause of the failure, e.g. the fee of an upgraded ledger or a blocked sender,
+/// should be fixed first.
+///
+/// # Arguments
+///
+/// * `sol_sig` - Solana signature of the deposit.
+#[update]
+fn requeue_dead_letter(sol_sig: String) -> Result<(), String> {
+    require_role(Role::Operator);
+
+    let sender = match read_state(|s| s.dead_letters.get(&sol_sig).cloned()) {
+        Some(dead_letter) => dead_letter.deposit.from_sol_address,
+        None => return Err(format!("{sol_sig} is not a dead-lettered deposit")),
+    };
+    if read_state(|s| s.is_blocked_address(&sender)) {
+        return Err(format!(
+            "{sol_sig} was sent from the blocked address {sender}"
+        ));
+    }
+
+    crate::logs::log!(INFO, "\nRequeued dead-lettered deposit {sol_sig}");
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::DeadLetterRequeued {
+                sol_sig,
+                caller: ic_cdk::caller(),
+            },
+        )
+    });
+
+    Ok(())
+}
+
+/// Returns the burned withdrawals whose coupon waits for an operator's approval, oldest first.
+#[query]
+fn get_pending_approvals() -> Vec<CandidWithdrawalEvent> {
+    require_role(Role::ReadOnly);
+
+    read_state(|s| {
+        s.withdrawals_awaiting_approval()
+            .iter()
+            .map(CandidWithdrawalEvent::from)
+            .collect()
+    })
+}
+
+/// Approves a withdrawal held because of its amount, its coupon is then signed like the
+/// coupon of any other burned withdrawal.
+///
+/// # Arguments
+///
+/// * `burn_id` - Burn id of the held withdrawal.
+#[update]
+fn approve_withdrawal(burn_id: u64) -> Result<(), String> {
+    let caller = require_role(Role::Operator);
+
+    if read_state(|s| s.withdrawal_approvals.get(&burn_id) != Some(&ApprovalStatus::Pending)) {
+        return Err(format!("withdrawal {burn_id} is not awaiting approval"));
+    }
+
+    crate::logs::log!(INFO, "\nWithdrawal {burn_id} approved by {caller}");
+    mutate_state(|s| process_event(s, EventType::WithdrawalApproved { burn_id, caller }));
+
+    Ok(())
+}
+
+/// Rejects a withdrawal held because of its amount, its coupon is never signed and the
+/// burned amount is re-minted to the sender like for `cancel_withdrawal`. If the re-mint
+/// fails, calling it again retries the re-mint.
+///
+/// # Arguments
+///
+/// * `burn_id` - Burn id of the held withdrawal.
+/// * `reason` - Why the withdrawal was rejected, recorded in the event log.
+#[update]
+async fn reject_withdrawal(burn_id: u64, reason: String) -> Result<(), String> {
+    let caller = require_role(Role::Operator);
+
+    // a rejected withdrawal whose re-mint failed is cancelled, or re-minted, again
+    let retry = read_state(|s| {
+        s.is_rejected_withdrawal(burn_id) || s.cancelled_withdrawals.contains_key(&burn_id)
+    });
+    if !retry {
+        if read_state(|s| s.withdrawal_approvals.get(&burn_id) != Some(&ApprovalStatus::Pending)) {
+            return Err(format!("withdrawal {burn_id} is not awaiting approval"));
+        }
+
+        crate::logs::log!(
+            INFO,
+            "\nWithdrawal {burn_id} rejected by {caller}: {reason}"
+        );
+        mutate_state(|s| {
+            process_event(
+                s,
+                EventType::WithdrawalApprovalRejected {
+                    burn_id,
+                    reason: reason.clone(),
+                    caller,
+                },
+            )
+        });
+    }
+
+    withdraw::cancel_withdrawal(burn_id, reason, caller).await
+}
+
+/// Returns the burned withdrawals whose coupon is not released yet, oldest first.
+#[query]
+fn get_time_locked_withdrawals() -> Vec<TimeLockedWithdrawal> {
+    require_role(Role::ReadOnly);
+
+    read_state(|s| {
+        s.time_locked_withdrawals(ic_cdk::api::time())
+            .iter()
+            .map(|(event, release_at)| TimeLockedWithdrawal {
+                withdrawal: CandidWithdrawalEvent::from(event),
+                release_at: *release_at,
+            })
+            .collect()
+    })
+}
+
+/// Cancels a withdrawal whose coupon is not released yet, its coupon is never issued and
+/// the burned amount is re-minted to the sender. If the re-mint fails, calling it again
+/// retries the re-mint.
+///
+/// # Arguments
+///
+/// * `burn_id` - Burn id of the time-locked withdrawal.
+/// * `reason` - Why the withdrawal was cancelled, recorded in the event log.
+#[update]
+async fn cancel_withdrawal(burn_id: u64, reason: String) -> Result<(), String> {
+    let caller = is_controller();
+
+    withdraw::cancel_withdrawal(burn_id, reason, caller).await
+}
+
+/// Resolves the re-mint of a cancelled withdrawal whose outcome is unknown: an attempt
+/// through the NFT canister, or an attempt the ledger no longer deduplicates, that may
+/// have minted without the minter recording it. A minted withdrawal counts as re-minted,
+/// otherwise `cancel_withdrawal` retries the re-mint.
+///
+/// # Arguments
+///
+/// * `burn_id` - Burn id of the cancelled withdrawal.
+/// * `minted` - Whether the controller found the burned amount re-minted to the sender.
+#[update]
+fn resolve_remint(burn_id: u64, minted: bool) -> Result<(), String> {
+    let caller = is_controller();
+
+    withdraw::resolve_remint(burn_id, minted, caller)
+}
+
+/// Changes the verbosity of the minter's logs.
+///
+/// # Arguments
+///
+/// * `level` - Most verbose level that is logged.
+#[update]
+fn set_log_level(level: logs::LogLevel) {
+    is_controller();
+
+    mutate_state(|s| process_event(s, EventType::LogLevelChanged { level }));
+}
+
+/// Registers an SPL token bridged to an ICRC ledger, or updates its registration.
+///
+/// # Arguments
+///
+/// * `asset` - Mint, ledger, minimum withdrawal amount and fee of the token.
+#[update]
+fn register_asset(asset: assets::BridgedAsset) -> Result<(), String> {
+    is_controller();
+
+    asset.validate()?;
+    mutate_state(|s| process_event(s, EventType::AssetRegistered(asset)));
+    Ok(())
+}
+
+/// Removes an SPL token from the registry, its burned withdrawals are still signed.
+///
+/// # Arguments
+///
+/// * `spl_mint` - Mint of the token.
+#[update]
+fn remove_asset(spl_mint: String) -> Result<(), String> {
+    is_controller();
+
+    mutate_state(|s| {
+        if s.asset(&spl_mint).is_none() {
+            return Err(format!("SPL mint {spl_mint} is not registered"));
+        }
+        process_event(s, EventType::AssetRemoved { spl_mint });
+        Ok(())
+    })
+}
+
+/// Returns the SPL tokens bridged by the minter.
+#[query]
+fn get_assets() -> Vec<assets::BridgedAsset> {
+    read_state(|s| s.assets.values().cloned().collect())
+}
+
+/// Pauses minting and withdrawals.
+///
+/// # Arguments
+///
+/// * `reason` - Why the bridge is paused, returned to callers of `withdraw`.
+#[update]
+fn pause(reason: String) {
+    require_role(Role::Operator);
+
+    mutate_state(|s| {
+        if !s.is_paused() {
+            process_event(s, EventType::Paused { reason });
+        }
+    });
+}
+
+/// Resumes minting and withdrawals, acknowledging the reason the bridge was paused for.
+#[update]
+fn resume() {
+    let controller = is_controller();
+
+    mutate_state(|s| {
+        if s.is_paused() {
+            process_event(s, EventType::Resumed { by: controller });
+        }
+    });
+}
+
+/// Grants a role to a principal, replacing its previous role.
+///
+/// # Arguments
+///
+/// * `principal` - Principal receiving the role.
+/// * `role` - Role to grant.
+#[update]
+fn grant_role(principal: Principal, role: Role) {
+    require_role(Role::Admin);
+
+    mutate_state(|s| process_event(s, EventType::GrantRole(RoleGrant { principal, role })));
+}
+
+/// Revokes the role of a principal.
+///
+/// # Arguments
+///
+/// * `principal` - Principal losing its role.
+#[update]
+fn revoke_role(principal: Principal) {
+    require_role(Role::Admin);
+
+    mutate_state(|s| {
+        if s.roles.contains_key(&principal) {
+            process_event(s, EventType::RevokeRole(principal));
+        }
+    });
+}
+
+/// Returns the roles granted to principals that are not controllers.
+#[query]
+fn get_roles() -> Vec<RoleGrant> {
+    require_role(Role::ReadOnly);
+
+    read_state(|s| s.role_grants())
+}
+
+/// Rejects ingress messages to endpoints the caller is not allowed to call, before
+/// they are executed and charged to the minter.
+#[inspect_message]
+fn inspect_message() {
+    let caller = ic_cdk::caller();
+    let method = ic_cdk::api::call::method_name();
+    let allowed = match method.as_str() {
+        "withdraw"
+        | "get_coupon"
+        | "trigger_check"
+        | "add_destination_address"
+        | "remove_destination_address" => is_allowed_canister().is_ok(),
+        "export_state_snapshot"
+        | "import_state_snapshot"
+        | "resume"
+        | "prepare_upgrade"
+        | "cancel_upgrade"
+        | "set_last_known_signature"
+        | "set_log_level"
+        | "register_asset"
+        | "remove_asset"
+        | "propose_config_change"
+        | "execute_config_change"
+        | "cancel_config_change"
+        | "cancel_withdrawal"
+        | "resolve_remint"
+        | "revoke_coupons"
+        | "unrevoke_coupons"
+        | "simulate_parse" => ic_cdk::api::is_controller(&caller),
+        "grant_role" | "revoke_role" | "set_task_interval" => {
+            is_controller_or_has_role(&caller, Role::Admin)
+        }
+        "regenerate_coupons"
+        | "add_blocked_addresses"
+        | "remove_blocked_addresses"
+        | "pause"
+        | "approve_withdrawal"
+        | "reject_withdrawal"
+        | "rescan_range"
+        | "report_redeemed_coupons"
+        | "requeue_dead_letter" => is_controller_or_has_role(&caller, Role::Operator),
+        "get_state"
+        | "get_minter_state"
+        | "export_events"
+        | "get_memory_usage"
+        | "get_events"
+        | "get_events_filtered"
+        | "get_blocked_addresses"
+        | "get_active_tasks"
+        | "get_logs"
+        | "get_task_traces"
+        | "get_subscriptions"
+        | "get_config_proposals"
+        | "get_archived_invalid_events"
+        | "get_invalid_events"
+        | "get_pending_approvals"
+        | "get_time_locked_withdrawals"
+        | "get_dead_letters"
+        | "subscribe"
+        | "get_roles" => is_controller_or_has_role(&caller, Role::ReadOnly),
+        _ => true,
+    };
+
+    if !allowed {
+        ic_cdk::trap(&format!("caller {caller} is not allowed to call {method}"));
+    }
+    ic_cdk::api::call::accept_message();
+}
+
+ic_cdk_macros::export_candid!();
+
+fn validate_caller_not_anonymous() -> Result<candid::Principal, WithdrawError> {
+    let principal = ic_cdk::caller();
+    if principal == candid::Principal::anonymous() {
+        return Err(WithdrawError::AnonymousCaller);
+    }
+    Ok(principal)
+}
+
+fn is_controller() -> candid::Principal {
+    let principal = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&principal) {
+        ic_cdk::trap("only controller can call this method");
+    }
+
+    principal
+}
+
+fn is_controller_or_has_role(principal: &Principal, role: Role) -> bool {
+    ic_cdk::api::is_controller(principal) || read_state(|s| s.has_role(principal, role))
+}
+
+/// Traps unless the caller is a controller or was granted `role` or a role including it.
+fn require_role(role: Role) -> candid::Principal {
+    let principal = ic_cdk::caller();
+    if !is_controller_or_has_role(&principal, role) {
+        ic_cdk::trap(&format!("caller is missing the {role:?} role"));
+    }
+
+    principal
+}
+
+fn is_over_limit(withdraw_amount: &BigUint) -> Result<(), WithdrawError> {
+    let (minimum, maximum) = read_state(|s| {
+        (
+            s.minimum_withdrawal_amount.clone(),
+            s.max_withdrawal_amount.clone(),
+        )
+    });
+
+    if let Some(maximum) = maximum {
+        if *withdraw_amount > maximum {
+            return Err(WithdrawError::AmountTooHigh {
+                maximum: candid::Nat(maximum),
+            });
+        }
+    }
+
+    match minimum.cmp(&withdraw_amount) {
+        std::cmp::Ordering::Greater => Err(WithdrawError::AmountTooLow {
+            minimum: candid::Nat(minimum),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libsecp256k1::{PublicKey, PublicKeyFormat};
+
+    #[test]
+    fn test_to_bytearray() {
+        let hex = "023848ffecda8dccb6960db7d42c4accac0fa6dfc4419f1eef7b59991e979498f7";
+        let array = hex::decode(hex).unwrap();
+        let pk = PublicKey::parse_slice(&array, Some(PublicKeyFormat::Compressed)).unwrap();
+        println!("{:?}", pk.serialize());
+        println!("{:?}", pk.serialize().len());
+    }
+}
@@ -1,13 +1,25 @@
+use crate::certification::update_certified_coupons;
+use crate::constants::{
+    DEFAULT_CHECKPOINT_INTERVAL, DEFAULT_CHECKPOINT_INTERVAL_EVENTS, DEFAULT_CONFIG_TIMELOCK,
+    DEFAULT_DESTINATION_ACTIVATION_DELAY, DEFAULT_INVALID_EVENTS_RETENTION,
+    DEFAULT_MAX_WITHDRAWALS_PER_PRINCIPAL, DEFAULT_MINT_PARALLELISM, DEFAULT_RELEASE_DELAY,
+    DEFAULT_TASK_LOCK_TIMEOUT, DRAIN_TIMEOUT,
+};
+use crate::cycles::{CyclesMonitorConfig, CyclesTopUpConfig};
+use crate::events::RetryPolicy;
+use crate::guard::RateLimit;
 use crate::logs::INFO;
+use crate::sol_rpc_client::{types::ConfirmationStatus, ProxyTokenConfig};
 use crate::state::{
-    audit::{process_event, replay_events, EventType},
-    mutate_state, InvalidStateError, State, STATE,
+    audit::{process_event, replay_events, Event, EventType},
+    mutate_state, read_state, InvalidStateError, State, TimerIntervals, STATE,
 };
-use crate::storage::total_event_count;
+use crate::storage::{self, total_event_count};
 
-use candid::{CandidType, Deserialize, Nat};
+use candid::{CandidType, Deserialize, Nat, Principal};
 use minicbor::{Decode, Encode};
 use num_bigint::ToBigUint;
+use sha2::{Digest, Sha256};
 use std::fmt::{Display, Formatter};
 
 #[derive(CandidType, Deserialize, Clone, Debug, Encode, Decode, PartialEq, Eq)]
@@ -22,6 +34,8 @@ pub struct InitArg {
     pub ecdsa_key_name: String,
     #[cbor(n(4), with = "crate::cbor::nat")]
     pub minimum_withdrawal_amount: Nat,
+    #[cbor(n(5), with = "crate::cbor::principal::option")]
+    pub ledger_id: Option<Principal>,
 }
 
 impl TryFrom<InitArg> for State {
@@ -33,6 +47,7 @@ impl TryFrom<InitArg> for State {
             solana_initial_signature,
             ecdsa_key_name,
             minimum_withdrawal_amount,
+            ledger_id,
         }: InitArg,
     ) -> Result<Self, Self::Error> {
         let minimum_withdrawal_amount = minimum_withdrawal_amount.0.to_biguint().ok_or(
@@ -48,20 +63,104 @@ impl TryFrom<InitArg> for State {
             ecdsa_key_name,
             ecdsa_public_key: None,
             ecdsa_proxy_public_key: None,
+            cosigner_public_key_hex: None,
+            previous_ecdsa_key: None,
             minimum_withdrawal_amount,
+            ledger_id,
             solana_last_known_signature: None,
             solana_signature_ranges: Default::default(),
             solana_signatures: Default::default(),
             invalid_events: Default::default(),
+            archived_invalid_events: storage::archived_invalid_events().cleared(),
+            invalid_events_retention_secs: DEFAULT_INVALID_EVENTS_RETENTION.as_secs(),
             accepted_events: Default::default(),
-            minted_events: Default::default(),
+            dead_letters: Default::default(),
+            withdrawal_approvals: Default::default(),
+            address_books: Default::default(),
+            destination_activation_delay_secs: DEFAULT_DESTINATION_ACTIVATION_DELAY.as_secs(),
+            time_locked_withdrawals: Default::default(),
+            release_delay_secs: DEFAULT_RELEASE_DELAY.as_secs(),
+            cancelled_withdrawals: Default::default(),
+            minted_events: storage::minted_events().cleared(),
             withdrawal_burned_events: Default::default(),
-            withdrawal_redeemed_events: Default::default(),
+            withdrawal_redeemed_events: storage::redeemed_events().cleared(),
+            deposits_by_principal: Default::default(),
+            minted_deposit_ids: Default::default(),
+            withdrawals_by_principal: Default::default(),
+            withdrawals_by_sol_address: Default::default(),
             withdrawing_principals: Default::default(),
+            withdrawal_locked_at: Default::default(),
+            max_withdrawals_per_principal: DEFAULT_MAX_WITHDRAWALS_PER_PRINCIPAL,
+            mint_parallelism: DEFAULT_MINT_PARALLELISM,
+            processing_burn_ids: Default::default(),
+            executing_config_proposals: Default::default(),
+            blocked_addresses: Default::default(),
+            revoked_coupons: Default::default(),
+            solana_redeemed_coupons: Default::default(),
+            roles: Default::default(),
+            paused: None,
+            terminal_events_retention: None,
+            terminal_events_max_age_secs: None,
+            used_burn_block_indices: Default::default(),
+            used_asset_burn_blocks: Default::default(),
+            pruned_signatures: Default::default(),
+            total_minted: Default::default(),
+            total_burned: Default::default(),
+            total_fees: Default::default(),
+            total_ledger_fees: Default::default(),
+            solana_vault_address: None,
+            reconciliation_tolerance: Default::default(),
+            last_reconciliation: None,
+            cycles_monitor: Default::default(),
+            last_cycles_report: None,
+            cycles_top_up: None,
+            upgrade_audit: Default::default(),
+            log_level: Default::default(),
+            trusted_origins: Default::default(),
+            assets: Default::default(),
+            subscriptions: Default::default(),
+            delivery_failures: Default::default(),
+            pending_deliveries: Default::default(),
+            config_proposals: Default::default(),
+            next_proposal_id: 0,
+            config_timelock_secs: DEFAULT_CONFIG_TIMELOCK.as_secs(),
+            retry_policy: Default::default(),
+            last_attempts: Default::default(),
+            rate_limit: None,
+            call_windows: Default::default(),
+            task_lock_timeout_secs: DEFAULT_TASK_LOCK_TIMEOUT.as_secs(),
+            proxy_token: Default::default(),
+            timer_intervals: Default::default(),
+            checkpoint_interval_events: DEFAULT_CHECKPOINT_INTERVAL_EVENTS,
+            checkpoint_interval_secs: DEFAULT_CHECKPOINT_INTERVAL.as_secs(),
+            event_log_max_bytes: None,
+            event_archive: None,
+            archived_blocks: Default::default(),
+            commitment: Default::default(),
+            max_deposit_amount: None,
+            max_withdrawal_amount: None,
+            dual_signature_threshold: None,
+            approval_threshold: None,
+            release_delay_threshold: None,
+            reported_invariant_violations: Default::default(),
             burn_id_counter: 0,
             deposit_id_counter: 0,
             http_request_counter: 0,
+            http_outcall_cycles: 0,
+            rpc_provider_stats: Default::default(),
+            ecdsa_sign_calls: 0,
+            ecdsa_sign_cycles: 0,
+            signature_cache_hits: 0,
+            signature_cache: Default::default(),
             active_tasks: Default::default(),
+            task_generation: 0,
+            latest_solana_block: None,
+            ledger_fee: None,
+            task_runs: Default::default(),
+            last_finished_task_runs: Default::default(),
+            task_traces: Default::default(),
+            task_last_success: Default::default(),
+            draining_since: None,
         };
 
         state.validate_config()?;
@@ -81,6 +180,81 @@ pub struct UpgradeArg {
     pub ecdsa_key_name: Option<String>,
     #[cbor(n(4), with = "crate::cbor::nat::option")]
     pub minimum_withdrawal_amount: Option<Nat>,
+    #[cbor(n(5), with = "crate::cbor::principal::option")]
+    pub ledger_id: Option<Principal>,
+    #[n(6)]
+    pub max_withdrawals_per_principal: Option<u64>,
+    #[n(7)]
+    pub terminal_events_retention: Option<u64>,
+    #[n(8)]
+    pub solana_vault_address: Option<String>,
+    #[cbor(n(9), with = "crate::cbor::nat::option")]
+    pub reconciliation_tolerance: Option<Nat>,
+    #[n(10)]
+    pub retry_policy: Option<RetryPolicy>,
+    #[n(11)]
+    pub rate_limit: Option<RateLimit>,
+    #[n(12)]
+    pub task_lock_timeout_secs: Option<u64>,
+    #[n(13)]
+    pub proxy_token: Option<ProxyTokenConfig>,
+    #[n(14)]
+    pub timer_intervals: Option<TimerIntervals>,
+    #[n(15)]
+    pub commitment: Option<ConfirmationStatus>,
+    #[cbor(n(16), with = "crate::cbor::nat::option")]
+    pub max_deposit_amount: Option<Nat>,
+    #[cbor(n(17), with = "crate::cbor::nat::option")]
+    pub max_withdrawal_amount: Option<Nat>,
+    #[n(18)]
+    pub cycles_monitor: Option<CyclesMonitorConfig>,
+    #[n(19)]
+    pub cycles_top_up: Option<CyclesTopUpConfig>,
+    #[n(20)]
+    pub upgrade_audit: Option<UpgradeAudit>,
+    #[n(21)]
+    pub trusted_origins: Option<Vec<String>>,
+    #[n(22)]
+    pub config_timelock_secs: Option<u64>,
+    #[n(23)]
+    pub mint_parallelism: Option<u64>,
+    #[n(24)]
+    pub invalid_events_retention_secs: Option<u64>,
+    #[cbor(n(25), with = "crate::cbor::nat::option")]
+    pub dual_signature_threshold: Option<Nat>,
+    #[cbor(n(26), with = "crate::cbor::nat::option")]
+    pub approval_threshold: Option<Nat>,
+    #[n(27)]
+    pub destination_activation_delay_secs: Option<u64>,
+    #[cbor(n(28), with = "crate::cbor::nat::option")]
+    pub release_delay_threshold: Option<Nat>,
+    #[n(29)]
+    pub release_delay_secs: Option<u64>,
+    #[n(30)]
+    pub checkpoint_interval_events: Option<u64>,
+    #[n(31)]
+    pub checkpoint_interval_secs: Option<u64>,
+    #[cbor(n(32), with = "crate::cbor::principal::option")]
+    pub event_archive: Option<Principal>,
+    #[n(33)]
+    pub terminal_events_max_age_secs: Option<u64>,
+    #[n(34)]
+    pub event_log_max_bytes: Option<u64>,
+}
+
+/// Check of the state replayed at upgrade against a digest of the state recorded
+/// before the upgrade.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub enum UpgradeAudit {
+    #[n(0)]
+    #[default]
+    Off,
+    /// Records an invariant violation when the states diverge.
+    #[n(1)]
+    Alert,
+    /// Aborts the upgrade when the states diverge.
+    #[n(2)]
+    Trap,
 }
 
 pub fn post_upgrade(upgrade_args: Option<UpgradeArg>) {
@@ -89,22 +263,227 @@ pub fn post_upgrade(upgrade_args: Option<UpgradeArg>) {
     STATE.with(|cell| {
         *cell.borrow_mut() = Some(replay_events());
     });
+    crate::logs::set_log_level(read_state(|s| s.log_level));
+    audit_replayed_state();
+    // events recorded before the ICRC-3 block hashes were introduced
+    storage::hash_missing_blocks_in_batches();
     if let Some(args) = upgrade_args {
+        // changed through a timelocked config proposal only
+        if args.solana_rpc_url.is_some()
+            || args.solana_contract_address.is_some()
+            || args.ecdsa_key_name.is_some()
+        {
+            ic_cdk::trap(
+                "solana_rpc_url, solana_contract_address and ecdsa_key_name can only be changed by propose_config_change",
+            );
+        }
         mutate_state(|s| process_event(s, EventType::Upgrade(args)))
     }
+    // certified data is cleared by the upgrade
+    update_certified_coupons();
 
     let end = ic_cdk::api::instruction_counter();
 
     let event_count = total_event_count();
     let instructions_consumed = end - start;
 
-    ic_canister_log::log!(
+    crate::logs::log!(
         INFO,
         "[upgrade]: replaying {event_count} events consumed {instructions_consumed} instructions ({} instructions per event on average)",
         instructions_consumed / event_count
     );
 }
 
+// bumped whenever the fields digested by `state_digest` change, digests of another
+// version are not checked
+const STATE_DIGEST_VERSION: u32 = 2;
+
+/// Records a digest of the state, checked against the replayed state after the upgrade.
+pub fn record_state_digest() {
+    read_state(|s| {
+        if s.upgrade_audit != UpgradeAudit::Off {
+            storage::record_event(EventType::StateDigest {
+                sha256: state_digest(s).to_vec(),
+                version: Some(STATE_DIGEST_VERSION),
+            });
+        }
+    });
+}
+
+// SHA-256 of the totals, counters and key sets of the state. Unlike the encoding of the
+// whole state, they do not change when a new version adds a field, and digesting them
+// does not copy the events.
+fn state_digest(state: &State) -> [u8; 32] {
+    fn digest_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(bytes);
+    }
+
+    fn digest_keys<K: Encode<()>>(hasher: &mut Sha256, keys: impl Iterator<Item = K>) {
+        let mut keys: Vec<Vec<u8>> = keys
+            .map(|key| minicbor::to_vec(key).expect("encoding should succeed"))
+            .collect();
+        keys.sort_unstable();
+        hasher.update((keys.len() as u64).to_be_bytes());
+        for key in keys {
+            digest_bytes(hasher, &key);
+        }
+    }
+
+    let mut hasher = Sha256::new();
+    for total in [
+        &state.total_minted,
+        &state.total_burned,
+        &state.total_fees,
+        &state.total_ledger_fees,
+    ] {
+        digest_bytes(&mut hasher, &total.to_bytes_be());
+    }
+    for counter in [
+        state.deposit_id_counter,
+        state.burn_id_counter,
+        state.next_proposal_id,
+    ] {
+        hasher.update(counter.to_be_bytes());
+    }
+    digest_keys(&mut hasher, state.solana_signature_ranges.keys());
+    digest_keys(&mut hasher, state.solana_signatures.keys());
+    digest_keys(&mut hasher, state.invalid_events.keys());
+    digest_keys(&mut hasher, state.accepted_events.keys());
+    state
+        .minted_events
+        .with_iter(|iter| digest_keys(&mut hasher, iter.map(|(sig, _)| sig)));
+    digest_keys(&mut hasher, state.withdrawal_burned_events.keys());
+    state
+        .withdrawal_redeemed_events
+        .with_iter(|iter| digest_keys(&mut hasher, iter.map(|(burn_id, _)| burn_id)));
+    digest_keys(&mut hasher, state.dead_letters.keys());
+    digest_keys(&mut hasher, state.withdrawal_approvals.keys());
+    digest_keys(&mut hasher, state.time_locked_withdrawals.keys());
+    digest_keys(&mut hasher, state.cancelled_withdrawals.keys());
+    digest_keys(&mut hasher, state.config_proposals.keys());
+    digest_keys(&mut hasher, state.blocked_addresses.iter());
+    digest_keys(&mut hasher, state.revoked_coupons.iter());
+    digest_keys(&mut hasher, state.solana_redeemed_coupons.iter());
+    digest_keys(&mut hasher, state.used_burn_block_indices.iter());
+    digest_keys(&mut hasher, state.pruned_signatures.iter());
+    hasher.finalize().into()
+}
+
+// compare the replayed state with the digest recorded before the upgrade
+fn audit_replayed_state() {
+    let total = total_event_count();
+    let expected = match storage::get_events(total.saturating_sub(1), 1).pop() {
+        Some(Event {
+            payload: EventType::StateDigest { sha256, version },
+            ..
+        }) if version == Some(STATE_DIGEST_VERSION) => sha256,
+        Some(Event {
+            payload: EventType::StateDigest { .. },
+            ..
+        }) => {
+            crate::logs::log!(
+                INFO,
+                "[upgrade]: the recorded state digest has another version, skipping the check"
+            );
+            return;
+        }
+        _ => return,
+    };
+
+    let (mode, actual) = read_state(|s| (s.upgrade_audit, state_digest(s)));
+    if expected == actual {
+        crate::logs::log!(
+            INFO,
+            "[upgrade]: replayed state matches the recorded digest"
+        );
+        return;
+    }
+
+    let details = format!(
+        "replayed state digest {} differs from the pre-upgrade digest {}",
+        hex::encode(actual),
+        hex::encode(&expected)
+    );
+    match mode {
+        UpgradeAudit::Trap => ic_cdk::trap(&format!("[upgrade]: {details}")),
+        UpgradeAudit::Alert | UpgradeAudit::Off => {
+            crate::logs::log!(INFO, "[upgrade]: {details}");
+            mutate_state(|s| {
+                process_event(
+                    s,
+                    EventType::InvariantViolated {
+                        invariant: "replayed state".to_string(),
+                        details,
+                    },
+                )
+            });
+        }
+    }
+}
+
+/// Work still in flight while the minter drains for an upgrade.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UpgradeReadiness {
+    /// Whether nothing is in flight and the minter can be upgraded.
+    pub ready: bool,
+    pub draining_since: Option<u64>,
+    pub active_tasks: Vec<String>,
+    pub withdrawing_principals: u64,
+    pub processing_burn_ids: Vec<u64>,
+}
+
+impl From<&State> for UpgradeReadiness {
+    fn from(state: &State) -> Self {
+        Self {
+            ready: state.is_idle(),
+            draining_since: state.draining_since,
+            active_tasks: state
+                .active_tasks
+                .keys()
+                .map(|task| format!("{task:?}"))
+                .collect(),
+            withdrawing_principals: state.withdrawing_principals.len() as u64,
+            processing_burn_ids: state.processing_burn_ids.iter().copied().collect(),
+        }
+    }
+}
+
+/// Stops starting timer tasks and withdrawals, then waits up to [DRAIN_TIMEOUT]
+/// for the work in flight to finish.
+pub async fn prepare_upgrade() -> UpgradeReadiness {
+    let start = ic_cdk::api::time();
+    mutate_state(|s| {
+        if s.draining_since.is_none() {
+            crate::logs::log!(INFO, "[upgrade]: draining the minter");
+            s.draining_since = Some(start);
+        }
+    });
+
+    while !read_state(State::is_idle)
+        && ic_cdk::api::time().saturating_sub(start) < DRAIN_TIMEOUT.as_nanos() as u64
+    {
+        // yield so that the tasks in flight can process their replies
+        if ic_cdk::api::management_canister::main::raw_rand()
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    read_state(UpgradeReadiness::from)
+}
+
+/// Lets timer tasks and withdrawals start again after an aborted upgrade.
+pub fn cancel_upgrade() {
+    mutate_state(|s| {
+        if s.draining_since.take().is_some() {
+            crate::logs::log!(INFO, "[upgrade]: stopped draining the minter");
+        }
+    });
+}
+
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub enum MinterArg {
     Init(InitArg),
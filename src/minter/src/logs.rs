@@ -1,5 +1,10 @@
+use crate::constants::MAX_LOGS_PER_RESPONSE;
+
+use candid::CandidType;
 use ic_canister_log::{declare_log_buffer, export as export_logs, GlobalBuffer, Sink};
+use minicbor::{Decode, Encode};
 use serde::Deserialize;
+use std::cell::Cell;
 use std::str::FromStr;
 
 // High-priority messages.
@@ -11,20 +16,79 @@ declare_log_buffer!(name = DEBUG_BUF, capacity = 1000);
 // Trace of HTTP requests and responses.
 declare_log_buffer!(name = TRACE_HTTP_BUF, capacity = 1000);
 
-pub const INFO: PrintProxySink = PrintProxySink("INFO", &INFO_BUF);
-pub const DEBUG: PrintProxySink = PrintProxySink("DEBUG", &DEBUG_BUF);
-pub const TRACE_HTTP: PrintProxySink = PrintProxySink("TRACE_HTTP", &TRACE_HTTP_BUF);
+pub const INFO: PrintProxySink = PrintProxySink("INFO", &INFO_BUF, LogLevel::Info);
+pub const DEBUG: PrintProxySink = PrintProxySink("DEBUG", &DEBUG_BUF, LogLevel::Debug);
+// Traces are redacted and only carry payload digests below the debug level.
+pub const TRACE_HTTP: PrintProxySink =
+    PrintProxySink("TRACE_HTTP", &TRACE_HTTP_BUF, LogLevel::Info);
+
+/// Verbosity of the minter's logs, each level includes the levels declared before it.
+#[derive(
+    CandidType,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Encode,
+    Decode,
+)]
+pub enum LogLevel {
+    #[n(0)]
+    #[default]
+    Info,
+    #[n(1)]
+    Debug,
+}
+
+thread_local! {
+    // mirror of the state's log level, read by the sinks without borrowing the state
+    static LOG_LEVEL: Cell<LogLevel> = Cell::new(LogLevel::default());
+}
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.with(|cell| cell.set(level));
+}
 
-pub struct PrintProxySink(&'static str, &'static GlobalBuffer);
+/// Whether debug output, such as raw payload dumps, should be produced.
+pub fn is_debug_enabled() -> bool {
+    LOG_LEVEL.with(|cell| cell.get()) >= LogLevel::Debug
+}
+
+/// Appends a message to a sink like `ic_canister_log::log!`, without formatting it when
+/// the log level filters the sink out.
+macro_rules! log {
+    ($sink:expr, $($message:tt)*) => {
+        if $sink.is_enabled() {
+            ic_canister_log::log!($sink, $($message)*);
+        }
+    };
+}
+pub(crate) use log;
+
+pub struct PrintProxySink(&'static str, &'static GlobalBuffer, LogLevel);
+
+impl PrintProxySink {
+    pub fn is_enabled(&self) -> bool {
+        self.2 <= LOG_LEVEL.with(|cell| cell.get())
+    }
+}
 
 impl Sink for PrintProxySink {
     fn append(&self, entry: ic_canister_log::LogEntry) {
+        if !self.is_enabled() {
+            return;
+        }
         ic_cdk::println!("{} {}:{} {}", self.0, entry.file, entry.line, entry.message);
         self.1.append(entry)
     }
 }
 
-#[derive(Clone, serde::Serialize, Deserialize, Debug, Copy)]
+#[derive(CandidType, Clone, serde::Serialize, Deserialize, Debug, Copy)]
 pub enum Priority {
     Info,
     TraceHttp,
@@ -62,7 +126,7 @@ impl FromStr for Sort {
     }
 }
 
-#[derive(Clone, serde::Serialize, Deserialize, Debug)]
+#[derive(CandidType, Clone, serde::Serialize, Deserialize, Debug)]
 pub struct LogEntry {
     pub timestamp: u64,
     pub priority: Priority,
@@ -141,3 +205,19 @@ impl Log {
         self.entries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
     }
 }
+
+/// Returns a page of the entries of `priority`, or of all priorities if None, newest first.
+pub fn get_logs(priority: Option<Priority>, offset: u64, limit: u64) -> Vec<LogEntry> {
+    let mut log = Log::default();
+    match priority {
+        Some(priority) => log.push_logs(priority),
+        None => log.push_all(),
+    }
+    log.sort_desc();
+
+    log.entries
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit.min(MAX_LOGS_PER_RESPONSE) as usize)
+        .collect()
+}
@@ -0,0 +1,131 @@
+use crate::{
+    guard::TimerGuard,
+    logs::{DEBUG, INFO},
+    sol_rpc_client::SolRpcClient,
+    state::{
+        audit::{process_event, trip_circuit_breaker},
+        event::EventType,
+        mutate_state, read_state, TaskType,
+    },
+};
+
+use candid::{CandidType, Deserialize, Nat, Principal};
+use num_bigint::BigUint;
+
+/// Vault balance and gSOL supply observed by a reconciliation run.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SupplyReconciliation {
+    pub timestamp: u64,
+    pub vault_balance: Nat,
+    pub total_supply: Nat,
+    /// Absolute difference between the vault balance and the gSOL supply.
+    pub drift: Nat,
+    pub exceeds_tolerance: bool,
+}
+
+impl SupplyReconciliation {
+    pub fn new(
+        timestamp: u64,
+        vault_balance: BigUint,
+        total_supply: BigUint,
+        tolerance: &BigUint,
+    ) -> Self {
+        let drift = if vault_balance >= total_supply {
+            &vault_balance - &total_supply
+        } else {
+            &total_supply - &vault_balance
+        };
+
+        Self {
+            timestamp,
+            exceeds_tolerance: &drift > tolerance,
+            vault_balance: Nat(vault_balance),
+            total_supply: Nat(total_supply),
+            drift: Nat(drift),
+        }
+    }
+}
+
+async fn get_total_supply(ledger_id: Principal) -> Result<Nat, String> {
+    let (supply,): (Nat,) = ic_cdk::call(ledger_id, "icrc1_total_supply", ())
+        .await
+        .map_err(|(code, msg)| format!("{code:?}: {msg}"))?;
+    Ok(supply)
+}
+
+// compare the SOL held by the vault with the gSOL supply of the ledger
+pub async fn reconcile_supply() {
+    let guard = match TimerGuard::new(TaskType::ReconcileSupply) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    let (vault_address, ledger_id) =
+        match read_state(|s| (s.solana_vault_address.clone(), s.ledger_id)) {
+            (Some(vault_address), Some(ledger_id)) => (vault_address, ledger_id),
+            _ => {
+                crate::logs::log!(DEBUG, "\nReconciliation is not configured");
+                return;
+            }
+        };
+
+    let vault_balance = match read_state(SolRpcClient::from_state)
+        .get_balance(&vault_address)
+        .await
+    {
+        Ok(balance) => balance,
+        Err(error) => {
+            crate::logs::log!(INFO, "\nFailed to get the vault balance: {error:?}");
+            guard.record_error(error);
+            return;
+        }
+    };
+
+    let total_supply = match get_total_supply(ledger_id).await {
+        Ok(supply) => supply,
+        Err(error) => {
+            crate::logs::log!(INFO, "\nFailed to get the gSOL total supply: {error}");
+            guard.record_error(error);
+            return;
+        }
+    };
+
+    let tolerance = read_state(|s| s.reconciliation_tolerance.clone());
+    let reconciliation = SupplyReconciliation::new(
+        ic_cdk::api::time(),
+        vault_balance.to_gsol().0,
+        total_supply.0,
+        &tolerance,
+    );
+
+    if reconciliation.exceeds_tolerance {
+        crate::logs::log!(
+            INFO,
+            "\nSupply drift of {} exceeds the tolerance of {tolerance}: vault balance {}, gSOL supply {}",
+            reconciliation.drift,
+            reconciliation.vault_balance,
+            reconciliation.total_supply
+        );
+
+        mutate_state(|s| {
+            process_event(
+                s,
+                EventType::SupplyDriftDetected {
+                    vault_balance: reconciliation.vault_balance.clone(),
+                    total_supply: reconciliation.total_supply.clone(),
+                    tolerance: Nat(tolerance.clone()),
+                },
+            )
+        });
+
+        // more gSOL in circulation than SOL backing it
+        if reconciliation.total_supply > reconciliation.vault_balance {
+            trip_circuit_breaker(format!(
+                "gSOL supply {} exceeds the vault balance {} by more than the tolerance of {tolerance}",
+                reconciliation.total_supply, reconciliation.vault_balance
+            ));
+        }
+    }
+
+    mutate_state(|s| s.last_reconciliation = Some(reconciliation));
+}
@@ -0,0 +1,166 @@
+//! Disaster recovery of the minter state.
+//!
+//! Controllers export the persistent state as a CBOR encoded [StateSnapshot] along
+//! with the minted and redeemed events held in stable memory, split into chunks that
+//! fit into a single reply, and import it chunk by chunk into a freshly installed
+//! minter. The imported snapshot is recorded as a checkpoint, which becomes the
+//! first event of the new minter's log.
+
+use crate::{
+    certification::update_certified_coupons,
+    constants::MAX_EXPORT_CHUNK_BYTES,
+    events::{DepositEvent, WithdrawalEvent},
+    logs::INFO,
+    state::{
+        audit::{process_event, EventType},
+        mutate_state, read_state,
+        snapshot::StateSnapshot,
+    },
+    storage::{self, compact_events, total_event_count},
+};
+
+use candid::{CandidType, Deserialize};
+use minicbor::{Decode, Encode};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StateSnapshotChunk {
+    pub index: u64,
+    pub total_chunks: u64,
+    pub data: ByteBuf,
+    /// SHA-256 of the whole encoded snapshot.
+    pub sha256: ByteBuf,
+    /// Number of events in the log when the snapshot was taken. Chunks taken at
+    /// different event counts do not belong to the same snapshot.
+    pub event_count: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ImportSnapshotError {
+    NotFreshCanister { event_count: u64 },
+    UnexpectedChunk { expected: u64, found: u64 },
+    ChecksumMismatch,
+    DecodingFailed(String),
+}
+
+impl std::fmt::Display for ImportSnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportSnapshotError::NotFreshCanister { event_count } => {
+                write!(
+                    f,
+                    "Snapshots can only be imported into a fresh minter, found {event_count} events"
+                )
+            }
+            ImportSnapshotError::UnexpectedChunk { expected, found } => {
+                write!(f, "Expected chunk {expected}, found chunk {found}")
+            }
+            ImportSnapshotError::ChecksumMismatch => {
+                write!(f, "Imported snapshot does not match its checksum")
+            }
+            ImportSnapshotError::DecodingFailed(err) => {
+                write!(f, "Failed to decode the imported snapshot: {err}")
+            }
+        }
+    }
+}
+
+/// Exported state, the terminal events are not part of the checkpoint snapshot.
+#[derive(Encode, Decode)]
+struct RecoverySnapshot {
+    #[n(0)]
+    state: StateSnapshot,
+    #[n(1)]
+    minted_events: Vec<DepositEvent>,
+    #[n(2)]
+    redeemed_events: Vec<WithdrawalEvent>,
+}
+
+thread_local! {
+    /// Chunks of the snapshot being imported.
+    static IMPORT_BUFFER: RefCell<Vec<u8>> = RefCell::default();
+    static IMPORTED_CHUNKS: RefCell<u64> = RefCell::default();
+}
+
+fn encode_snapshot() -> Vec<u8> {
+    let snapshot = read_state(|s| RecoverySnapshot {
+        state: StateSnapshot::from(s),
+        minted_events: s.minted_events.values(),
+        redeemed_events: s.withdrawal_redeemed_events.values(),
+    });
+    let mut buf = vec![];
+    minicbor::encode(&snapshot, &mut buf).expect("snapshot encoding should always succeed");
+    buf
+}
+
+/// Returns the chunk at `index` of the encoded state snapshot.
+pub fn export_chunk(index: u64) -> StateSnapshotChunk {
+    let bytes = encode_snapshot();
+    let chunks: Vec<&[u8]> = bytes.chunks(MAX_EXPORT_CHUNK_BYTES).collect();
+
+    StateSnapshotChunk {
+        index,
+        total_chunks: chunks.len() as u64,
+        data: ByteBuf::from(chunks.get(index as usize).copied().unwrap_or_default()),
+        sha256: ByteBuf::from(Sha256::digest(&bytes).to_vec()),
+        event_count: total_event_count(),
+    }
+}
+
+/// Buffers the chunk and, once the last chunk is received, verifies the
+/// snapshot and replaces the minter state with it.
+pub fn import_chunk(chunk: StateSnapshotChunk) -> Result<(), ImportSnapshotError> {
+    // only the Init event of the fresh installation may precede the snapshot
+    let event_count = total_event_count();
+    if event_count > 1 {
+        return Err(ImportSnapshotError::NotFreshCanister { event_count });
+    }
+
+    let expected = IMPORTED_CHUNKS.with(|c| *c.borrow());
+    if chunk.index != expected {
+        return Err(ImportSnapshotError::UnexpectedChunk {
+            expected,
+            found: chunk.index,
+        });
+    }
+
+    IMPORT_BUFFER.with(|b| b.borrow_mut().extend_from_slice(&chunk.data));
+    IMPORTED_CHUNKS.with(|c| *c.borrow_mut() += 1);
+
+    if chunk.index + 1 < chunk.total_chunks {
+        return Ok(());
+    }
+
+    let bytes = IMPORT_BUFFER.with(|b| std::mem::take(&mut *b.borrow_mut()));
+    IMPORTED_CHUNKS.with(|c| *c.borrow_mut() = 0);
+
+    if Sha256::digest(&bytes).as_slice() != chunk.sha256.as_slice() {
+        return Err(ImportSnapshotError::ChecksumMismatch);
+    }
+
+    let snapshot: RecoverySnapshot =
+        minicbor::decode(&bytes).map_err(|e| ImportSnapshotError::DecodingFailed(e.to_string()))?;
+
+    // filled before the checkpoint, whose state indexes the events of the stable maps
+    let minted_events = storage::minted_events().cleared();
+    for event in snapshot.minted_events {
+        minted_events.insert(event.sol_sig.to_string(), event);
+    }
+    let redeemed_events = storage::redeemed_events().cleared();
+    for event in snapshot.redeemed_events {
+        redeemed_events.insert(event.get_burn_id(), event);
+    }
+    mutate_state(|s| process_event(s, EventType::Checkpoint(snapshot.state)));
+    compact_events();
+    update_certified_coupons();
+
+    crate::logs::log!(
+        INFO,
+        "[recovery]: imported a state snapshot of {} bytes",
+        bytes.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! Canned Solana RPC responses served instead of HTTPS outcalls when the minter is built
+//! with the `mock-rpc` feature, so that the deposit flow runs locally without a Solana
+//! validator or the idempotent proxy.
+//!
+//! The fixtures hold a few transactions of the bridge program, `{contract_address}` is
+//! replaced by the configured contract address.
+
+use crate::{
+    lamports::Lamports,
+    sol_rpc_client::{types::RpcMethod, SolRpcError},
+    state::read_state,
+};
+
+use candid::Nat;
+use serde_json::{json, Value};
+
+// newest first, as returned by getSignaturesForAddress
+const SIGNATURES: &str = include_str!("fixtures/signatures.json");
+// getTransaction results by signature
+const TRANSACTIONS: &str = include_str!("fixtures/transactions.json");
+
+fn fixture(raw: &str) -> Value {
+    let contract_address = read_state(|s| s.solana_contract_address.clone());
+    serde_json::from_str(&raw.replace("{contract_address}", &contract_address))
+        .expect("mock RPC fixtures should be valid JSON")
+}
+
+/// Answers a JSON-RPC request, or a batch of them, from the fixtures.
+pub fn respond(payload: &str) -> Result<String, SolRpcError> {
+    let request: Value = serde_json::from_str(payload)
+        .map_err(|error| SolRpcError::FromStringOfJsonFailed(error.to_string()))?;
+
+    let response = match request {
+        Value::Array(requests) => Value::Array(requests.iter().map(respond_one).collect()),
+        request => respond_one(&request),
+    };
+    Ok(response.to_string())
+}
+
+fn respond_one(request: &Value) -> Value {
+    let id = request["id"].clone();
+    let params = &request["params"];
+
+    let result = match request["method"].as_str() {
+        Some(method) if method == RpcMethod::GetSignaturesForAddress.as_str() => {
+            signatures_for_address(&params[1])
+        }
+        Some(method) if method == RpcMethod::GetTransaction.as_str() => {
+            fixture(TRANSACTIONS)[params[0].as_str().unwrap_or_default()].clone()
+        }
+        Some(method) if method == RpcMethod::GetBalance.as_str() => {
+            json!({ "context": { "slot": 0 }, "value": vault_balance() })
+        }
+        // the fixtures do not hold any deposit records
+        Some(method) if method == RpcMethod::GetProgramAccounts.as_str() => json!([]),
+        // no SPL tokens are bridged by the fixtures
+        Some(method) if method == RpcMethod::GetTokenAccountBalance.as_str() => json!({
+            "context": { "slot": 0 },
+            "value": { "amount": "0", "decimals": 9, "uiAmount": 0.0, "uiAmountString": "0" },
+        }),
+        method => {
+            return json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": format!("Method not found: {method:?}") },
+            })
+        }
+    };
+
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+// signatures older than `before` and newer than `until`, an unknown `until` is
+// treated as older than all fixtures
+fn signatures_for_address(options: &Value) -> Value {
+    let signatures = match fixture(SIGNATURES) {
+        Value::Array(signatures) => signatures,
+        _ => vec![],
+    };
+    let position = |signature: &Value| {
+        signatures
+            .iter()
+            .position(|s| !signature.is_null() && s["signature"] == *signature)
+    };
+
+    let start = position(&options["before"]).map_or(0, |index| index + 1);
+    let end = position(&options["until"]).unwrap_or(signatures.len());
+    let limit = options["limit"].as_u64().unwrap_or(1_000) as usize;
+
+    Value::Array(
+        signatures[start.min(end)..end]
+            .iter()
+            .take(limit)
+            .cloned()
+            .collect(),
+    )
+}
+
+// the vault holds what was minted and not burned, keeping the supply reconciliation even
+fn vault_balance() -> u64 {
+    read_state(|s| {
+        if s.total_minted > s.total_burned {
+            Lamports::from_gsol(&Nat(&s.total_minted - &s.total_burned))
+                .map_or(u64::MAX, Lamports::get)
+        } else {
+            0
+        }
+    })
+}
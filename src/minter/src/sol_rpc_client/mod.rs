@@ -1,20 +1,35 @@
 use crate::{
     escda,
+    lamports::Lamports,
     lifecycle::SolanaRpcUrl,
+    logs::{self, TRACE_HTTP},
     sha3_256,
     sol_rpc_client::{
-        requests::{GetSignaturesForAddressRequestOptions, GetTransactionRequestOptions},
-        responses::{GetTransactionResponse, JsonRpcResponse, SignatureResponse},
+        requests::{
+            GetBalanceRequestOptions, GetProgramAccountsRequestOptions,
+            GetSignaturesForAddressRequestOptions, GetTransactionRequestOptions,
+            ProgramAccountsFilter,
+        },
+        responses::{
+            GetBalanceResponse, GetTokenAccountBalanceResponse, GetTransactionResponse,
+            JsonRpcResponse, ProgramAccountResponse, SignatureResponse,
+        },
         types::{
-            ConfirmationStatus, RpcMethod, HEADER_SIZE_LIMIT, SIGNATURE_RESPONSE_SIZE_ESTIMATE,
+            ConfirmationStatus, RpcMethod, BALANCE_RESPONSE_SIZE_ESTIMATE, HEADER_SIZE_LIMIT,
+            MAX_PAYLOAD_SIZE, MAX_TRANSACTION_RESPONSE_SIZE_ESTIMATE,
+            SIGNATURE_RESPONSE_SIZE_ESTIMATE, TOKEN_ACCOUNT_BALANCE_RESPONSE_SIZE_ESTIMATE,
             TRANSACTION_RESPONSE_SIZE_ESTIMATE,
         },
     },
-    state::{mutate_state, read_state, State},
+    state::{mutate_state, read_state, RpcOutcome, State, TaskType},
     AGENT_TOKEN_N_EXPIRY, CHAIN_ID,
 };
 
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD as base64_url, Engine};
+use base64::{
+    engine::general_purpose::{STANDARD as base64_standard, URL_SAFE_NO_PAD as base64_url},
+    Engine,
+};
+use candid::{CandidType, Deserialize};
 use ic_cdk::api::{
     call::RejectionCode,
     management_canister::http_request::{
@@ -22,20 +37,103 @@ use ic_cdk::api::{
     },
 };
 use icrc_ledger_types::icrc1::transfer::Memo;
+use minicbor::{Decode, Encode};
 use serde_json::json;
 use std::collections::HashMap;
 
+// Headers whose values are never written to the logs.
+const REDACTED_HEADERS: [&str; 2] = ["authorization", "proxy-authorization"];
+
+fn redact_headers(headers: &[HttpHeader]) -> String {
+    headers
+        .iter()
+        .map(|header| {
+            if REDACTED_HEADERS.contains(&header.name.to_lowercase().as_str()) {
+                format!("{}: <redacted>", header.name)
+            } else {
+                format!("{}: {}", header.name, header.value)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// log an outgoing request without its credentials, the payload is only logged
+// in full when debug output is enabled and otherwise as a digest
+fn trace_request(url: &str, headers: &[HttpHeader], payload: &str) {
+    let body = if logs::is_debug_enabled() {
+        payload.to_string()
+    } else {
+        format!(
+            "<{} bytes, sha3 {}>",
+            payload.len(),
+            hex::encode(sha3_256(payload.as_bytes()))
+        )
+    };
+    crate::logs::log!(
+        TRACE_HTTP,
+        "POST {url} [{}] {body}",
+        redact_headers(headers)
+    );
+}
+
+#[cfg(feature = "mock-rpc")]
+mod mock;
 pub mod requests;
 pub mod responses;
 pub mod types;
 
 pub const SECONDS: u64 = 1_000_000_000;
-pub const REFRESH_PROXY_TOKEN_INTERVAL: u64 = 60 * 60; // 60 minutes
-const AGENT_NAME: &str = "Pipans";
+pub const DEFAULT_PROXY_TOKEN_TTL: u64 = 60 * 60; // 60 minutes
+const DEFAULT_AGENT_NAME: &str = "Pipans";
+
+/// Claims of the tokens authenticating the minter to the idempotent proxy.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct ProxyTokenConfig {
+    /// Lifetime of a token in seconds.
+    #[n(0)]
+    pub ttl_secs: u64,
+    #[n(1)]
+    pub agent_name: String,
+    /// Proxy the tokens are issued for, not restricted if None.
+    #[n(2)]
+    pub audience: Option<String>,
+    /// Proxy routes the tokens may be used for, not restricted if empty.
+    #[n(3)]
+    pub scopes: Vec<String>,
+}
+
+impl Default for ProxyTokenConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: DEFAULT_PROXY_TOKEN_TTL,
+            agent_name: DEFAULT_AGENT_NAME.to_string(),
+            audience: None,
+            scopes: vec![],
+        }
+    }
+}
+
+impl ProxyTokenConfig {
+    /// Subject signed into the token: the agent name, followed by the audience and
+    /// scope claims when they are configured.
+    pub fn subject(&self) -> String {
+        let mut subject = self.agent_name.clone();
+        if let Some(audience) = &self.audience {
+            subject.push_str(&format!("|aud={audience}"));
+        }
+        if !self.scopes.is_empty() {
+            subject.push_str(&format!("|scope={}", self.scopes.join(",")));
+        }
+        subject
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SolRpcClient {
     rpc_url: SolanaRpcUrl,
+    // timer task whose run is charged with the outcalls
+    task: Option<TaskType>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -69,9 +167,82 @@ impl std::fmt::Display for SolRpcError {
     }
 }
 
+/// Account owned by a program, as returned by getProgramAccounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramAccount {
+    pub pubkey: String,
+    pub owner: String,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+}
+
+impl TryFrom<ProgramAccountResponse> for ProgramAccount {
+    type Error = SolRpcError;
+
+    fn try_from(response: ProgramAccountResponse) -> Result<Self, Self::Error> {
+        let (data, encoding) = response.account.data;
+        if encoding != "base64" {
+            return Err(SolRpcError::FromStringOfJsonFailed(format!(
+                "account {} is {encoding} encoded, expected base64",
+                response.pubkey
+            )));
+        }
+        let data = base64_standard.decode(data).map_err(|error| {
+            SolRpcError::FromStringOfJsonFailed(format!(
+                "invalid data of account {}: {error}",
+                response.pubkey
+            ))
+        })?;
+
+        Ok(Self {
+            pubkey: response.pubkey,
+            owner: response.account.owner,
+            lamports: response.account.lamports,
+            data,
+        })
+    }
+}
+
+/// SPL tokens held by a token account, in the smallest unit of the mint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplTokenBalance {
+    pub amount: u64,
+    pub decimals: u8,
+}
+
+impl SolRpcError {
+    /// Whether the response was larger than the `max_response_bytes` of the outcall.
+    pub fn is_response_too_large(&self) -> bool {
+        matches!(
+            self,
+            SolRpcError::RequestFailed { code: RejectionCode::SysFatal, msg }
+                if msg.contains("size limit")
+        )
+    }
+
+    /// Whether the outcall timed out before the provider answered.
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            SolRpcError::RequestFailed { code: RejectionCode::SysTransient, msg }
+                if msg.to_lowercase().contains("timeout")
+                    || msg.to_lowercase().contains("timed out")
+        )
+    }
+}
+
 impl SolRpcClient {
     const fn new(rpc_url: SolanaRpcUrl) -> Self {
-        Self { rpc_url }
+        Self {
+            rpc_url,
+            task: None,
+        }
+    }
+
+    /// Counts the outcalls of the client in the current run of the task.
+    pub fn for_task(mut self, task: TaskType) -> Self {
+        self.task = Some(task);
+        self
     }
 
     pub fn from_state(state: &State) -> Self {
@@ -82,11 +253,13 @@ impl SolRpcClient {
         let (token, expire_at) = AGENT_TOKEN_N_EXPIRY.with(|t| t.borrow().clone());
         if expire_at < (ic_cdk::api::time() / SECONDS) {
             // expired
-            let expire_at = (ic_cdk::api::time() / SECONDS) + REFRESH_PROXY_TOKEN_INTERVAL;
-            let ecdsa_key_name = read_state(|s| s.ecdsa_key_name.clone());
-            let token = escda::sign_proxy_token(&ecdsa_key_name, expire_at + 120, AGENT_NAME)
-                .await
-                .unwrap();
+            let (ecdsa_key_name, config) =
+                read_state(|s| (s.ecdsa_key_name.clone(), s.proxy_token.clone()));
+            let expire_at = (ic_cdk::api::time() / SECONDS) + config.ttl_secs;
+            let token =
+                escda::sign_proxy_token(&ecdsa_key_name, expire_at + 120, &config.subject())
+                    .await
+                    .unwrap();
             AGENT_TOKEN_N_EXPIRY.with(|t| *t.borrow_mut() = (token.clone(), expire_at));
             return token;
         }
@@ -94,18 +267,25 @@ impl SolRpcClient {
         token
     }
 
+    // the mock responses return early, leaving the outcall unreachable
+    #[cfg_attr(feature = "mock-rpc", allow(unreachable_code, unused_variables))]
     async fn rpc_call(
         &self,
         payload: &String,
         effective_size_estimate: u64,
     ) -> Result<String, SolRpcError> {
+        // answered from canned fixtures in local development builds
+        #[cfg(feature = "mock-rpc")]
+        {
+            trace_request("mock-rpc", &[], payload);
+            return mock::respond(payload);
+        }
+
         //https://idempotent-proxy-cf-worker.rio-lee.workers.dev
         let token = Self::get_agent_token().await;
         let host = "idempotent-proxy-cf-worker.rio-lee.workers.dev";
         let url = format!("https://{}/URL_SOLANA_DEVNET", host);
 
-        ic_cdk::println!("url: {}", url);
-
         let chain_id = CHAIN_ID.with(|t| *t.borrow());
         let next_chain =
             sha3_256(format!("{}-{}", hex::encode(chain_id), ic_cdk::api::time()).as_bytes());
@@ -113,8 +293,6 @@ impl SolRpcClient {
         CHAIN_ID.with_borrow_mut(|i| *i = next_chain);
         let idempotent_key = format!("{}", base64_url.encode(next_chain));
 
-        ic_cdk::println!("idempotent_key: {}", idempotent_key);
-
         let request_headers = vec![
             HttpHeader {
                 name: "Host".to_string(),
@@ -134,7 +312,7 @@ impl SolRpcClient {
             },
         ];
 
-        ic_cdk::println!("body: {}", payload);
+        trace_request(&url, &request_headers, payload);
 
         let request = CanisterHttpRequestArgument {
             url: url.to_string(),
@@ -153,8 +331,15 @@ impl SolRpcClient {
         const BASE_SUBNET_SIZE: u128 = 13;
         const SUBNET_SIZE: u128 = 34;
         let cycles = base_cycles * SUBNET_SIZE / BASE_SUBNET_SIZE;
+        mutate_state(|s| {
+            s.record_http_outcall(cycles);
+            if let Some(task) = self.task {
+                s.record_task_outcall(task);
+            }
+        });
 
-        match http_request(request, cycles).await {
+        let started_at = ic_cdk::api::time();
+        let result = match http_request(request, cycles).await {
             Ok((response,)) => {
                 let str_body = String::from_utf8(response.body);
                 // ic_cdk::println!("response: {:?}", str_body);
@@ -171,7 +356,17 @@ impl SolRpcClient {
                 ic_cdk::println!("error 01 : {:?}, {:?}", r, m);
                 Err(SolRpcError::RequestFailed { code: r, msg: m })
             }
-        }
+        };
+
+        let outcome = match &result {
+            Ok(_) => RpcOutcome::Success,
+            Err(error) if error.is_timeout() => RpcOutcome::Timeout,
+            Err(_) => RpcOutcome::Failure,
+        };
+        let latency = ic_cdk::api::time().saturating_sub(started_at);
+        mutate_state(|s| s.record_rpc_outcome(host, outcome, latency));
+
+        result
     }
 
     async fn _rpc_call_unused(
@@ -221,15 +416,17 @@ impl SolRpcClient {
         &self,
         limit: u8,
         before: Option<&String>,
-        until: &String,
+        until: Option<&String>,
+        min_context_slot: Option<u64>,
     ) -> Result<Vec<SignatureResponse>, SolRpcError> {
         let params: [&dyn erased_serde::Serialize; 2] = [
             &read_state(|s| s.solana_contract_address.clone()),
             &GetSignaturesForAddressRequestOptions {
                 limit: Some(limit),
-                commitment: Some(ConfirmationStatus::Confirmed.as_str().to_string()),
+                commitment: Some(read_state(|s| s.commitment).as_str().to_string()),
                 before: before.map(|s| s.to_string()),
-                until: Some(until.to_string()),
+                until: until.map(|s| s.to_string()),
+                min_context_slot,
             },
         ];
 
@@ -278,6 +475,141 @@ impl SolRpcClient {
         }
     }
 
+    // Method relies on the getBalance RPC call to get the lamports held by the account:
+    // https://solana.com/docs/rpc/http/getbalance
+    pub async fn get_balance(&self, address: &String) -> Result<Lamports, SolRpcError> {
+        let params: [&dyn erased_serde::Serialize; 2] = [
+            address,
+            &GetBalanceRequestOptions {
+                commitment: Some(ConfirmationStatus::Finalized.as_str().to_string()),
+            },
+        ];
+
+        let payload = serde_json::to_string(&json!({
+            "jsonrpc": "2.0",
+            "id": mutate_state(State::next_request_id),
+            "method": RpcMethod::GetBalance.as_str(),
+            "params": params
+        }))
+        .map_err(|error| SolRpcError::ToStringOfJsonFailed(error.to_string()))?;
+
+        let effective_size_estimate = BALANCE_RESPONSE_SIZE_ESTIMATE + HEADER_SIZE_LIMIT;
+
+        let response = self.rpc_call(&payload, effective_size_estimate).await?;
+        let json_response = serde_json::from_str::<JsonRpcResponse<GetBalanceResponse>>(&response)
+            .map_err(|error| SolRpcError::FromStringOfJsonFailed(error.to_string()))?;
+
+        match (json_response.error, json_response.result) {
+            (Some(error), _) => Err(SolRpcError::JsonRpcFailed {
+                code: error.code,
+                msg: error.message,
+            }),
+            (None, Some(result)) => Ok(Lamports(result.value)),
+            (None, None) => Err(SolRpcError::FromStringOfJsonFailed(
+                "getBalance returned no result".to_string(),
+            )),
+        }
+    }
+
+    // Method relies on the getProgramAccounts RPC call to list the accounts owned by a
+    // program, e.g. the deposit record PDAs of the bridge program:
+    // https://solana.com/docs/rpc/http/getprogramaccounts
+    // The number of returned accounts is not bounded, the response may use the whole
+    // payload of the outcall and the filters should be as narrow as possible.
+    pub async fn get_program_accounts(
+        &self,
+        program_id: &String,
+        filters: Vec<ProgramAccountsFilter>,
+    ) -> Result<Vec<ProgramAccount>, SolRpcError> {
+        let params: [&dyn erased_serde::Serialize; 2] = [
+            program_id,
+            &GetProgramAccountsRequestOptions {
+                commitment: Some(ConfirmationStatus::Finalized.as_str().to_string()),
+                encoding: Some("base64".to_string()),
+                filters,
+            },
+        ];
+
+        let payload = serde_json::to_string(&json!({
+            "jsonrpc": "2.0",
+            "id": mutate_state(State::next_request_id),
+            "method": RpcMethod::GetProgramAccounts.as_str(),
+            "params": params
+        }))
+        .map_err(|error| SolRpcError::ToStringOfJsonFailed(error.to_string()))?;
+
+        let response = self
+            .rpc_call(&payload, MAX_PAYLOAD_SIZE + HEADER_SIZE_LIMIT)
+            .await?;
+        let json_response =
+            serde_json::from_str::<JsonRpcResponse<Vec<ProgramAccountResponse>>>(&response)
+                .map_err(|error| SolRpcError::FromStringOfJsonFailed(error.to_string()))?;
+
+        match (json_response.error, json_response.result) {
+            (Some(error), _) => Err(SolRpcError::JsonRpcFailed {
+                code: error.code,
+                msg: error.message,
+            }),
+            (None, Some(accounts)) => accounts.into_iter().map(ProgramAccount::try_from).collect(),
+            (None, None) => Err(SolRpcError::FromStringOfJsonFailed(
+                "getProgramAccounts returned no result".to_string(),
+            )),
+        }
+    }
+
+    // Method relies on the getTokenAccountBalance RPC call to get the raw amount of SPL
+    // tokens held by a token account, and the decimals of its mint:
+    // https://solana.com/docs/rpc/http/gettokenaccountbalance
+    pub async fn get_token_account_balance(
+        &self,
+        token_account: &String,
+    ) -> Result<SplTokenBalance, SolRpcError> {
+        let params: [&dyn erased_serde::Serialize; 2] = [
+            token_account,
+            &GetBalanceRequestOptions {
+                commitment: Some(ConfirmationStatus::Finalized.as_str().to_string()),
+            },
+        ];
+
+        let payload = serde_json::to_string(&json!({
+            "jsonrpc": "2.0",
+            "id": mutate_state(State::next_request_id),
+            "method": RpcMethod::GetTokenAccountBalance.as_str(),
+            "params": params
+        }))
+        .map_err(|error| SolRpcError::ToStringOfJsonFailed(error.to_string()))?;
+
+        let effective_size_estimate =
+            TOKEN_ACCOUNT_BALANCE_RESPONSE_SIZE_ESTIMATE + HEADER_SIZE_LIMIT;
+
+        let response = self.rpc_call(&payload, effective_size_estimate).await?;
+        let json_response =
+            serde_json::from_str::<JsonRpcResponse<GetTokenAccountBalanceResponse>>(&response)
+                .map_err(|error| SolRpcError::FromStringOfJsonFailed(error.to_string()))?;
+
+        match (json_response.error, json_response.result) {
+            (Some(error), _) => Err(SolRpcError::JsonRpcFailed {
+                code: error.code,
+                msg: error.message,
+            }),
+            (None, Some(result)) => {
+                let amount = result.value.amount.parse::<u64>().map_err(|error| {
+                    SolRpcError::FromStringOfJsonFailed(format!(
+                        "invalid token amount {}: {error}",
+                        result.value.amount
+                    ))
+                })?;
+                Ok(SplTokenBalance {
+                    amount,
+                    decimals: result.value.decimals,
+                })
+            }
+            (None, None) => Err(SolRpcError::FromStringOfJsonFailed(
+                "getTokenAccountBalance returned no result".to_string(),
+            )),
+        }
+    }
+
     // Method relies on the gettransaction RPC call to get the transaction data:
     // https://solana.com/docs/rpc/http/gettransaction
     // It is using a batch request to get multiple transactions at once.
@@ -297,11 +629,13 @@ impl SolRpcClient {
         // But still need to increment it to count the call.
         mutate_state(State::next_request_id);
 
+        let commitment = read_state(|s| s.commitment);
         for (position, signature) in signatures.iter().enumerate() {
             let params: [&dyn erased_serde::Serialize; 2] = [
                 &signature,
                 &GetTransactionRequestOptions {
-                    commitment: Some(ConfirmationStatus::Confirmed.as_str().to_string()),
+                    commitment: Some(commitment.as_str().to_string()),
+                    encoding: Some("json".to_string()),
                 },
             ];
 
@@ -325,7 +659,18 @@ impl SolRpcClient {
         let effective_size_estimate: u64 =
             (signatures.len() as u64) * TRANSACTION_RESPONSE_SIZE_ESTIMATE + HEADER_SIZE_LIMIT;
 
-        match self.rpc_call(&payload, effective_size_estimate).await {
+        // retry once with room for unusually large transactions
+        let response = match self.rpc_call(&payload, effective_size_estimate).await {
+            Err(error) if error.is_response_too_large() => {
+                let max_size_estimate = (signatures.len() as u64)
+                    * MAX_TRANSACTION_RESPONSE_SIZE_ESTIMATE
+                    + HEADER_SIZE_LIMIT;
+                self.rpc_call(&payload, max_size_estimate).await
+            }
+            response => response,
+        };
+
+        match response {
             Ok(response) => {
                 let json_responses =
                     serde_json::from_str::<Vec<JsonRpcResponse<GetTransactionResponse>>>(&response);
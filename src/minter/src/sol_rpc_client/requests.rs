@@ -6,9 +6,36 @@ pub struct GetSignaturesForAddressRequestOptions {
     pub commitment: Option<String>,
     pub until: Option<String>,
     pub before: Option<String>,
+    // the request fails instead of being answered by a node behind this slot
+    #[serde(rename = "minContextSlot", skip_serializing_if = "Option::is_none")]
+    pub min_context_slot: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct GetTransactionRequestOptions {
     pub commitment: Option<String>,
+    // "json", the default, keeps the account keys readable without decoding the wire format
+    pub encoding: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetBalanceRequestOptions {
+    pub commitment: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetProgramAccountsRequestOptions {
+    pub commitment: Option<String>,
+    pub encoding: Option<String>,
+    pub filters: Vec<ProgramAccountsFilter>,
+}
+
+/// Filter on the accounts returned by getProgramAccounts, all filters of a request must match.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ProgramAccountsFilter {
+    /// Accounts whose data has the given length in bytes.
+    DataSize(u64),
+    /// Accounts whose data holds the base58 encoded `bytes` at `offset`.
+    Memcmp { offset: u64, bytes: String },
 }
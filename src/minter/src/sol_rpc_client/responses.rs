@@ -15,6 +15,38 @@ pub struct JsonRpcResponse<T> {
     pub id: u64,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct GetBalanceResponse {
+    pub value: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProgramAccountResponse {
+    pub pubkey: String,
+    pub account: AccountResponse,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AccountResponse {
+    // the encoded data and its encoding
+    pub data: (String, String),
+    pub lamports: u64,
+    pub owner: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GetTokenAccountBalanceResponse {
+    pub value: TokenAmount,
+}
+
+// The raw amount is a string as it may not fit in a JSON number, the ui amounts
+// are not deserialized.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TokenAmount {
+    pub amount: String,
+    pub decimals: u8,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct SignatureResponse {
     #[serde(rename = "blockTime")]
@@ -27,70 +59,19 @@ pub struct SignatureResponse {
     pub slot: u64,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct Header {
-    #[serde(rename = "numReadonlySignedAccounts")]
-    pub num_readonly_signed_accounts: u64,
-    #[serde(rename = "numReadonlyUnsignedAccounts")]
-    pub num_readonly_unsigned_accounts: u64,
-    #[serde(rename = "numRequiredSignatures")]
-    pub num_required_signatures: u64,
-}
-
-#[derive(Debug, Deserialize, Clone)]
-pub struct Instruction {
-    pub accounts: Vec<u64>,
-    pub data: String,
-    #[serde(rename = "programIdIndex")]
-    pub program_id_index: u64,
-    #[serde(rename = "stackHeight")]
-    pub stack_height: Option<u64>,
-}
-
+// Only the fields read by the deposit parser are deserialized, the rest of the
+// getTransaction response is skipped.
 #[derive(Debug, Deserialize, Clone)]
 pub struct Message {
     #[serde(rename = "accountKeys")]
     pub account_keys: Vec<String>,
-    pub header: Header,
-    pub instructions: Vec<Instruction>,
-    #[serde(rename = "recentBlockhash")]
-    pub recent_blockhash: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Meta {
-    #[serde(rename = "computeUnitsConsumed")]
-    pub compute_units_consumed: u64,
     pub err: Option<serde_json::Value>,
-    pub fee: u64,
-    #[serde(rename = "innerInstructions")]
-    pub inner_instructions: Vec<serde_json::Value>,
-    #[serde(rename = "loadedAddresses")]
-    pub loaded_addresses: LoadedAddresses,
     #[serde(rename = "logMessages")]
     pub log_messages: Vec<String>,
-    #[serde(rename = "postBalances")]
-    pub post_balances: Vec<u64>,
-    #[serde(rename = "postTokenBalances")]
-    pub post_token_balances: Vec<serde_json::Value>,
-    #[serde(rename = "preBalances")]
-    pub pre_balances: Vec<u64>,
-    #[serde(rename = "preTokenBalances")]
-    pub pre_token_balances: Vec<serde_json::Value>,
-    pub rewards: Vec<serde_json::Value>,
-    pub status: Status,
-}
-
-#[derive(Debug, Deserialize, Clone)]
-pub struct Status {
-    #[serde(rename = "Ok")]
-    pub ok: Option<serde_json::Value>,
-}
-
-#[derive(Debug, Deserialize, Clone)]
-pub struct LoadedAddresses {
-    pub readonly: Vec<serde_json::Value>,
-    pub writable: Vec<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
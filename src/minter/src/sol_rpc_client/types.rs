@@ -1,3 +1,6 @@
+use candid::{CandidType, Deserialize};
+use minicbor::{Decode, Encode};
+
 // This constant is our approximation of the expected header size.
 // The HTTP standard doesn't define any limit, and many implementations limit
 // the headers size to 8 KiB. We chose a lower limit because headers observed on most providers
@@ -17,25 +20,45 @@ pub const SIGNATURE_RESPONSE_SIZE_ESTIMATE: u64 = 500;
 // In case no memo is set transaction object should be around 1100 bytes long.
 pub const TRANSACTION_RESPONSE_SIZE_ESTIMATE: u64 = 2200 * 3;
 
+// Used for a batch whose response exceeded TRANSACTION_RESPONSE_SIZE_ESTIMATE.
+pub const MAX_TRANSACTION_RESPONSE_SIZE_ESTIMATE: u64 = 2200 * 5;
+
+// Balance object should be around 100 bytes long.
+pub const BALANCE_RESPONSE_SIZE_ESTIMATE: u64 = 200;
+
+// Token amount object should be around 150 bytes long.
+pub const TOKEN_ACCOUNT_BALANCE_RESPONSE_SIZE_ESTIMATE: u64 = 300;
+
 #[derive(Debug, Clone, Copy)]
 pub enum RpcMethod {
+    GetBalance,
+    GetProgramAccounts,
     GetSignaturesForAddress,
+    GetTokenAccountBalance,
     GetTransaction,
 }
 
 impl RpcMethod {
     pub fn as_str(&self) -> &str {
         match self {
+            RpcMethod::GetBalance => "getBalance",
+            RpcMethod::GetProgramAccounts => "getProgramAccounts",
             RpcMethod::GetSignaturesForAddress => "getSignaturesForAddress",
+            RpcMethod::GetTokenAccountBalance => "getTokenAccountBalance",
             RpcMethod::GetTransaction => "getTransaction",
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Commitment level of the data returned by the RPC.
+#[derive(CandidType, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
 pub enum ConfirmationStatus {
+    #[n(0)]
     Finalized,
+    #[n(1)]
+    #[default]
     Confirmed,
+    #[n(2)]
     Processed,
 }
 
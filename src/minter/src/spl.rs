@@ -0,0 +1,218 @@
+use minicbor::{Decode, Encode};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: &str = "ATokenGPvbdGVxr1b2hvZbsiqW7xWH25efTNsLJA8knL";
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const PDA_MARKER: &[u8] = b"ProgramDerivedAddress";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplError {
+    InvalidBase58(String),
+    InvalidPubkeyLength { address: String, length: usize },
+    NoViableBumpSeed,
+}
+
+impl std::fmt::Display for SplError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplError::InvalidBase58(address) => {
+                write!(f, "{address} is not a valid base58 string")
+            }
+            SplError::InvalidPubkeyLength { address, length } => {
+                write!(f, "{address} decodes to {length} bytes, expected 32")
+            }
+            SplError::NoViableBumpSeed => {
+                write!(f, "Unable to find a viable program address bump seed")
+            }
+        }
+    }
+}
+
+pub fn decode_base58(input: &str) -> Result<Vec<u8>, SplError> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(input.len());
+
+    for c in input.bytes() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or_else(|| SplError::InvalidBase58(input.to_string()))?
+            as u32;
+
+        for byte in bytes.iter_mut() {
+            carry += (*byte as u32) * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+
+    // leading '1's encode leading zero bytes
+    bytes.extend(input.bytes().take_while(|&c| c == b'1').map(|_| 0u8));
+    bytes.reverse();
+    Ok(bytes)
+}
+
+pub fn encode_base58(input: &[u8]) -> String {
+    let mut digits: Vec<u8> = Vec::with_capacity(input.len() * 138 / 100 + 1);
+
+    for &byte in input {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let leading_zeros = input.iter().take_while(|&&b| b == 0).count();
+    std::iter::repeat(b'1')
+        .take(leading_zeros)
+        .chain(digits.iter().rev().map(|&d| BASE58_ALPHABET[d as usize]))
+        .map(char::from)
+        .collect()
+}
+
+/// Decodes a base58 Solana address into its 32 byte public key.
+pub fn decode_pubkey(address: &str) -> Result<[u8; 32], SplError> {
+    let bytes = decode_base58(address)?;
+    bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| SplError::InvalidPubkeyLength {
+            address: address.to_string(),
+            length: bytes.len(),
+        })
+}
+
+/// Whether `signature` is a base58 encoded 64 byte Solana transaction signature.
+pub fn is_valid_signature(signature: &str) -> bool {
+    decode_base58(signature).is_ok_and(|bytes| bytes.len() == 64)
+}
+
+/// Base58 encoded 32 byte Solana account address. Encoded as a plain string, recorded
+/// addresses are not parsed again when replaying events.
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Encode, Decode,
+)]
+#[serde(try_from = "String", into = "String")]
+#[cbor(transparent)]
+pub struct SolanaAddress(#[n(0)] String);
+
+impl SolanaAddress {
+    pub fn parse(address: &str) -> Result<Self, SplError> {
+        decode_pubkey(address)?;
+        Ok(Self(address.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Fails for addresses decoded from events, which are not parsed again.
+    pub fn to_bytes(&self) -> Result<[u8; 32], SplError> {
+        decode_pubkey(&self.0)
+    }
+
+    /// Whether the address is an ed25519 public key, i.e. an account with a private key.
+    /// Program derived addresses, such as token accounts, are off the curve.
+    pub fn is_on_curve(&self) -> bool {
+        self.to_bytes().is_ok_and(|bytes| is_on_curve(&bytes))
+    }
+}
+
+impl std::str::FromStr for SolanaAddress {
+    type Err = SplError;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        Self::parse(address)
+    }
+}
+
+impl TryFrom<String> for SolanaAddress {
+    type Error = SplError;
+
+    fn try_from(address: String) -> Result<Self, Self::Error> {
+        Self::parse(&address)
+    }
+}
+
+impl From<SolanaAddress> for String {
+    fn from(address: SolanaAddress) -> Self {
+        address.0
+    }
+}
+
+impl std::fmt::Display for SolanaAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Derives the associated token account of `owner` for the given SPL `mint`.
+pub fn get_associated_token_address(owner: &str, mint: &str) -> Result<String, SplError> {
+    let owner = decode_pubkey(owner)?;
+    let mint = decode_pubkey(mint)?;
+    let token_program = decode_pubkey(TOKEN_PROGRAM_ID)?;
+    let program_id = decode_pubkey(ASSOCIATED_TOKEN_PROGRAM_ID)?;
+
+    let address = find_program_address(&[&owner, &token_program, &mint], &program_id)?;
+    Ok(encode_base58(&address))
+}
+
+// Same search as solana_program::pubkey::Pubkey::find_program_address.
+fn find_program_address(seeds: &[&[u8]], program_id: &[u8; 32]) -> Result<[u8; 32], SplError> {
+    for bump in (0..=u8::MAX).rev() {
+        let mut hasher = Sha256::new();
+        for seed in seeds {
+            hasher.update(seed);
+        }
+        hasher.update([bump]);
+        hasher.update(program_id);
+        hasher.update(PDA_MARKER);
+        let hash: [u8; 32] = hasher.finalize().into();
+
+        if !is_on_curve(&hash) {
+            return Ok(hash);
+        }
+    }
+
+    Err(SplError::NoViableBumpSeed)
+}
+
+// Checks whether the bytes are a valid compressed ed25519 point, i.e. whether
+// x^2 = (y^2 - 1) / (d * y^2 + 1) has a solution modulo p = 2^255 - 19.
+fn is_on_curve(bytes: &[u8; 32]) -> bool {
+    let p = (BigUint::from(1u8) << 255) - BigUint::from(19u8);
+    let one = BigUint::from(1u8);
+
+    let mut y_bytes = *bytes;
+    y_bytes[31] &= 0x7f;
+    let y = BigUint::from_bytes_le(&y_bytes) % &p;
+
+    // d = -121665 / 121666
+    let inv_121666 = BigUint::from(121666u32).modpow(&(&p - 2u8), &p);
+    let d = (&p - BigUint::from(121665u32)) * inv_121666 % &p;
+
+    let y2 = &y * &y % &p;
+    let u = (&y2 + &p - &one) % &p;
+    let v = (&d * &y2 + &one) % &p;
+
+    let x2 = &u * v.modpow(&(&p - 2u8), &p) % &p;
+    if x2 == BigUint::from(0u8) {
+        return true;
+    }
+
+    // Euler's criterion
+    x2.modpow(&((&p - &one) >> 1), &p) == one
+}
@@ -1,43 +1,313 @@
-use crate::events::{DepositEvent, SolanaSignature, SolanaSignatureRange, WithdrawalEvent};
-use crate::lifecycle::{SolanaRpcUrl, UpgradeArg};
-use crate::{escda, get_derivation_path};
-
-use candid::Principal;
+use crate::assets::BridgedAsset;
+use crate::constants::{
+    GET_LATEST_SOLANA_SIGNATURE, MAX_TASK_TRACES, MINT_GSOL, RECONCILE_SUPPLY,
+    SCRAPPING_SOLANA_SIGNATURES, SCRAPPING_SOLANA_SIGNATURE_RANGES,
+};
+use crate::cycles::{CyclesMonitorConfig, CyclesReport, CyclesTopUpConfig};
+use crate::events::{
+    DeadLetter, DepositEvent, InvalidReason, Retriable, RetryPolicy, SolanaBlock, SolanaSignature,
+    SolanaSignatureRange, WithdrawalEvent,
+};
+use crate::governance::{ConfigChange, ConfigProposal};
+use crate::guard::{CallWindow, RateLimit};
+use crate::lifecycle::{SolanaRpcUrl, UpgradeArg, UpgradeAudit};
+use crate::logs::LogLevel;
+use crate::reconciliation::SupplyReconciliation;
+use crate::sol_rpc_client::{types::ConfirmationStatus, ProxyTokenConfig};
+use crate::storage::StableEventMap;
+use crate::subscriptions::Subscription;
+use crate::withdraw::Coupon;
+use crate::{escda, get_cosigner_derivation_path, get_derivation_path};
+use invariants::InvariantViolation;
+
+use candid::{CandidType, Deserialize, Nat, Principal};
 use ic_cdk::api::management_canister::ecdsa::EcdsaPublicKeyResponse;
+use ic_cdk_timers::TimerId;
+use minicbor::{Decode, Encode};
 use num_bigint::BigUint;
 use num_bigint::ToBigUint;
 use std::{
     cell::RefCell,
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    ops::Bound,
 };
 use strum_macros::EnumIter;
 
 pub mod audit;
 pub mod event;
+pub mod invariants;
+pub mod snapshot;
+#[cfg(test)]
+mod tests;
 
 thread_local! {
   pub static STATE: RefCell<Option<State>> = RefCell::default();
 
   pub static LAST_CHECKED: RefCell<u64> = RefCell::default();
   pub static AGENT_TOKEN_N_EXPIRY: RefCell<(String, u64)> = RefCell::default();
+  pub static TASK_TIMERS: RefCell<HashMap<TaskType, TimerId>> = RefCell::default();
   pub static CHAIN_ID: RefCell<[u8; 32]> = RefCell::default();
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, thiserror::Error)]
 pub enum InvalidStateError {
+    #[error("{0}")]
     InvalidEcdsaKeyName(String),
+    #[error("{0}")]
     InvalidLedgerId(String),
+    #[error("{0}")]
     InvalidSolanaContractAddress(String),
+    #[error("{0}")]
     InvalidMinimumWithdrawalAmount(String),
+    #[error("{0}")]
     InvalidSolanaInitialSignature(String),
+    #[error("{0}")]
+    InvalidMaxWithdrawalsPerPrincipal(String),
+    #[error("{0}")]
+    InvalidSolanaVaultAddress(String),
+    #[error("{0}")]
+    InvalidRetryPolicy(String),
+    #[error("{0}")]
+    InvalidRateLimit(String),
+    #[error("{0}")]
+    InvalidTaskLockTimeout(String),
+    #[error("{0}")]
+    InvalidProxyToken(String),
+    #[error("{0}")]
+    InvalidTimerIntervals(String),
+    #[error("{0}")]
+    InvalidCommitment(String),
+    #[error("{0}")]
+    InvalidMaximumAmount(String),
+    #[error("{0}")]
+    InvalidCyclesTopUp(String),
+    #[error("{0}")]
+    InvalidTrustedOrigin(String),
+    #[error("{0}")]
+    InvalidMintParallelism(String),
+}
+
+/// State transition that does not match the current state. The state is left
+/// unchanged when a transition is rejected.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum StateTransitionError {
+    #[error("Attempted to record existing range: {0} .")]
+    DuplicateSignatureRange(String),
+    #[error("Attempted to update NON existing range: {0} .")]
+    UnknownSignatureRange(String),
+    #[error("Attempted to remove NON existing solana signature {0} .")]
+    UnknownSolanaSignature(String),
+    #[error("Attempted to record existing invalid event: {0} .")]
+    DuplicateInvalidEvent(String),
+    #[error("Attempted to remove NON existing accepted event: {0} .")]
+    UnknownAcceptedEvent(String),
+    #[error("Attempted to record existing minted event: {0} .")]
+    DuplicateMintedEvent(String),
+    #[error("Attempted to requeue NON existing dead letter: {0} .")]
+    UnknownDeadLetter(String),
+    #[error("Attempted to remove NON existing withdrawal burned event: {0} .")]
+    UnknownBurnedWithdrawal(u64),
+    #[error("Attempted to re-sign NON existing withdrawal redeemed event: {0} .")]
+    UnknownRedeemedWithdrawal(u64),
+    #[error("Attempted to hold an already held withdrawal: {0} .")]
+    DuplicateApproval(u64),
+    #[error("Attempted to approve or reject a withdrawal NOT awaiting approval: {0} .")]
+    NotAwaitingApproval(u64),
+    #[error("Attempted to time-lock an already time-locked withdrawal: {0} .")]
+    DuplicateTimeLock(u64),
+    #[error("Attempted to cancel a withdrawal that is NEITHER time-locked NOR rejected: {0} .")]
+    NotCancellable(u64),
+    #[error("Attempted to re-mint NON existing cancelled withdrawal: {0} .")]
+    UnknownCancelledWithdrawal(u64),
+    #[error("Attempted to archive blocks {start}..{end} that were already archived.")]
+    OverlappingArchivedBlocks { start: u64, end: u64 },
+}
+
+/// Failure to use or rotate the ECDSA key signing the coupons.
+#[derive(CandidType, Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EcdsaKeyError {
+    #[error("ecdsa_key_name cannot be blank")]
+    BlankKeyName,
+    #[error("{0} is already the current key")]
+    AlreadyCurrentKey(String),
+    #[error("Public key is not initialized")]
+    NotInitialized,
+    #[error("Failed to deserialize sec1 encoding into public key")]
+    InvalidPublicKey,
+    #[error("Failed to fetch the public key: {0}")]
+    FetchFailed(String),
 }
 
-#[derive(Debug, Hash, Copy, Clone, PartialEq, Eq, EnumIter)]
+#[derive(CandidType, Deserialize, Debug, Hash, Copy, Clone, PartialEq, Eq, EnumIter)]
 pub enum TaskType {
     GetLatestSignature,
     ScrapSignatureRanges,
     ScrapSignatures,
     MintGSol,
+    ReconcileSupply,
+    CheckInvariants,
+    PruneTerminalEvents,
+    CheckpointState,
+    ReleaseStaleLocks,
+    SignPendingCoupons,
+    MonitorCycles,
+    PushEvents,
+}
+
+impl TaskType {
+    /// Whether the task keeps running while a low cycles balance freezes the other tasks.
+    pub fn is_essential(&self) -> bool {
+        !matches!(
+            self,
+            TaskType::ReconcileSupply
+                | TaskType::CheckInvariants
+                | TaskType::PruneTerminalEvents
+                | TaskType::CheckpointState
+                | TaskType::PushEvents
+        )
+    }
+
+    /// Whether the task keeps running while the minter drains for an upgrade. The janitor
+    /// releases leaked locks, which the draining waits for.
+    pub fn runs_while_draining(&self) -> bool {
+        matches!(self, TaskType::ReleaseStaleLocks)
+    }
+}
+
+/// Seconds between two runs of the scraping, minting and reconciliation timers.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct TimerIntervals {
+    #[n(0)]
+    pub get_latest_signature_secs: u64,
+    #[n(1)]
+    pub scrap_signature_ranges_secs: u64,
+    #[n(2)]
+    pub scrap_signatures_secs: u64,
+    #[n(3)]
+    pub mint_gsol_secs: u64,
+    #[n(4)]
+    pub reconcile_supply_secs: u64,
+}
+
+impl Default for TimerIntervals {
+    fn default() -> Self {
+        Self {
+            get_latest_signature_secs: GET_LATEST_SOLANA_SIGNATURE.as_secs(),
+            scrap_signature_ranges_secs: SCRAPPING_SOLANA_SIGNATURE_RANGES.as_secs(),
+            scrap_signatures_secs: SCRAPPING_SOLANA_SIGNATURES.as_secs(),
+            mint_gsol_secs: MINT_GSOL.as_secs(),
+            reconcile_supply_secs: RECONCILE_SUPPLY.as_secs(),
+        }
+    }
+}
+
+impl TimerIntervals {
+    /// Seconds between two runs of the task, None if its interval is not configurable.
+    pub fn interval(&self, task: TaskType) -> Option<u64> {
+        match task {
+            TaskType::GetLatestSignature => Some(self.get_latest_signature_secs),
+            TaskType::ScrapSignatureRanges => Some(self.scrap_signature_ranges_secs),
+            TaskType::ScrapSignatures => Some(self.scrap_signatures_secs),
+            TaskType::MintGSol => Some(self.mint_gsol_secs),
+            TaskType::ReconcileSupply => Some(self.reconcile_supply_secs),
+            _ => None,
+        }
+    }
+
+    pub fn interval_mut(&mut self, task: TaskType) -> Option<&mut u64> {
+        match task {
+            TaskType::GetLatestSignature => Some(&mut self.get_latest_signature_secs),
+            TaskType::ScrapSignatureRanges => Some(&mut self.scrap_signature_ranges_secs),
+            TaskType::ScrapSignatures => Some(&mut self.scrap_signatures_secs),
+            TaskType::MintGSol => Some(&mut self.mint_gsol_secs),
+            TaskType::ReconcileSupply => Some(&mut self.reconcile_supply_secs),
+            _ => None,
+        }
+    }
+}
+
+/// Role granted to a principal, each role includes the permissions of the roles
+/// declared before it. Controllers hold the Admin role implicitly.
+#[derive(
+    CandidType, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode,
+)]
+pub enum Role {
+    /// Reads the minter's internal state and logs.
+    #[n(0)]
+    ReadOnly,
+    /// Performs day-to-day operations such as managing the blocklist.
+    #[n(1)]
+    Operator,
+    /// Manages the roles of other principals.
+    #[n(2)]
+    Admin,
+}
+
+/// Solana address registered in the address book of a principal.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct DestinationAddress {
+    #[n(0)]
+    pub address: String,
+    /// Withdrawals to the address are accepted from this time on.
+    #[n(1)]
+    pub active_at: u64,
+}
+
+/// Blocks of the event log pushed to an archive canister, `end` excluded.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ArchivedBlockRange {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub archive: Principal,
+    #[n(1)]
+    pub start: u64,
+    #[n(2)]
+    pub end: u64,
+}
+
+/// Address book of a principal, as stored in the state snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct AddressBook {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub owner: Principal,
+    #[n(1)]
+    pub destinations: Vec<DestinationAddress>,
+}
+
+/// Approval of a burned withdrawal held for an operator because of its amount.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum ApprovalStatus {
+    #[n(0)]
+    Pending,
+    /// The coupon is never signed.
+    #[n(1)]
+    Rejected {
+        #[n(0)]
+        reason: String,
+    },
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct RoleGrant {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub principal: Principal,
+    #[n(1)]
+    pub role: Role,
+}
+
+/// Entry of the scraping and minting backlogs that is retried on failure.
+#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+pub enum RetryTarget {
+    SignatureRange(String),
+    Signature(String),
+    Deposit(String),
+    /// Burned withdrawal whose coupon is signed by the signing queue, by burn id.
+    Withdrawal(u64),
+}
+
+impl RetryTarget {
+    pub fn range(range: &SolanaSignatureRange) -> Self {
+        Self::SignatureRange(range_key(&range.before_sol_sig, &range.until_sol_sig))
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -52,7 +322,13 @@ pub struct State {
     // raw format of the public key
     pub ecdsa_public_key: Option<EcdsaPublicKeyResponse>,
     pub ecdsa_proxy_public_key: Option<String>,
+    // uncompressed public key of the cosigner derivation path, in hex format
+    pub cosigner_public_key_hex: Option<String>,
+    // Key replaced by the last rotation, its coupons stay valid during the grace window
+    pub previous_ecdsa_key: Option<PreviousEcdsaKey>,
     pub minimum_withdrawal_amount: BigUint,
+    // gSOL ledger whose burn blocks back the issued coupons
+    pub ledger_id: Option<Principal>,
 
     // scrapper config
     pub solana_last_known_signature: Option<String>,
@@ -62,18 +338,184 @@ pub struct State {
 
     // invalid transactions - cannot be parsed, does not hold deposit event, blocked user, etc.
     pub invalid_events: HashMap<String, SolanaSignature>,
+    // invalid events moved out of the heap, see ArchiveInvalidEvents
+    pub archived_invalid_events: StableEventMap<u64, SolanaSignature>,
+    // seconds an invalid event stays in invalid_events before being archived
+    pub invalid_events_retention_secs: u64,
     // valid transaction events
     pub accepted_events: HashMap<String, DepositEvent>,
-    // minted events
-    pub minted_events: HashMap<String, DepositEvent>,
+    // minted events, kept in stable memory
+    pub minted_events: StableEventMap<String, DepositEvent>,
 
     // withdrawal with burned gSol
     pub withdrawal_burned_events: HashMap<u64, WithdrawalEvent>,
-    // withdrawal with generated coupon
-    pub withdrawal_redeemed_events: HashMap<u64, WithdrawalEvent>,
-
-    // Withdrawal requests that are currently being processed
-    pub withdrawing_principals: BTreeSet<Principal>,
+    // withdrawal with generated coupon, kept in stable memory
+    pub withdrawal_redeemed_events: StableEventMap<u64, WithdrawalEvent>,
+
+    // Accepted deposits whose mint failed permanently or whose sender is blocked, kept until
+    // an operator requeues them
+    pub dead_letters: BTreeMap<String, DeadLetter>,
+
+    // Burned withdrawals whose coupon waits for an operator, approved ones are removed
+    pub withdrawal_approvals: BTreeMap<u64, ApprovalStatus>,
+
+    // Solana addresses each principal may withdraw to and the time they become active.
+    // Principals without an address book may withdraw to any address, an emptied book
+    // still binds its principal.
+    pub address_books: BTreeMap<Principal, BTreeMap<String, u64>>,
+    pub destination_activation_delay_secs: u64,
+
+    // Release time of the coupons of burned withdrawals above the release delay threshold,
+    // removed once the coupon is issued
+    pub time_locked_withdrawals: BTreeMap<u64, u64>,
+    pub release_delay_secs: u64,
+    // Withdrawals cancelled by a controller whose burned amount is not re-minted yet
+    pub cancelled_withdrawals: BTreeMap<u64, WithdrawalEvent>,
+
+    // Solana signatures of the accepted, dead-lettered and minted deposits per recipient
+    pub deposits_by_principal: BTreeMap<Principal, BTreeSet<String>>,
+    // Solana signatures of the minted deposits per deposit id, in minting order
+    pub minted_deposit_ids: BTreeMap<u64, String>,
+    // Burn ids of the burned and redeemed withdrawals per sender
+    pub withdrawals_by_principal: BTreeMap<Principal, BTreeSet<u64>>,
+    // Burn ids of the burned and redeemed withdrawals per destination Solana address
+    pub withdrawals_by_sol_address: BTreeMap<String, BTreeSet<u64>>,
+
+    // Number of withdrawal requests that are currently being processed per principal
+    pub withdrawing_principals: BTreeMap<Principal, usize>,
+    // Time a withdrawal lock of each principal was last acquired
+    pub withdrawal_locked_at: BTreeMap<Principal, u64>,
+    pub max_withdrawals_per_principal: usize,
+    // Mint calls issued at the same time by the minting task
+    pub mint_parallelism: usize,
+    // Burns whose coupon is currently being signed
+    pub processing_burn_ids: BTreeSet<u64>,
+    // Config proposals whose execution awaits the rotation of the ECDSA key
+    pub executing_config_proposals: BTreeSet<u64>,
+
+    // Solana addresses that withdrawals cannot be sent to and deposits are not minted from
+    pub blocked_addresses: BTreeSet<String>,
+
+    // Burn ids of the coupons revoked by a controller, they no longer verify
+    pub revoked_coupons: BTreeSet<u64>,
+
+    // Burn ids of the coupons relayers reported as redeemed on Solana
+    pub solana_redeemed_coupons: BTreeSet<u64>,
+
+    // Canister receiving the blocks of the event log before they are compacted
+    pub event_archive: Option<Principal>,
+    // Blocks pushed to archive canisters, in the order of their ids
+    pub archived_blocks: Vec<ArchivedBlockRange>,
+
+    // Roles granted to principals that are not controllers
+    pub roles: BTreeMap<Principal, Role>,
+
+    // Reason the bridge was paused for, minting and withdrawals are halted while set
+    pub paused: Option<String>,
+
+    // Number of minted and redeemed events kept in the state, None keeps all of them
+    pub terminal_events_retention: Option<u64>,
+    // Seconds a minted or redeemed event stays in the state, None keeps all of them
+    pub terminal_events_max_age_secs: Option<u64>,
+    // Burn block indices of every burned withdrawal, they cannot back a new coupon
+    pub used_burn_block_indices: BTreeSet<u64>,
+    // Same for withdrawals of bridged assets, whose burns live on the asset's ledger
+    pub used_asset_burn_blocks: BTreeSet<(String, u64)>,
+    // Solana signatures of the pruned minted deposits and of the archived invalid events,
+    // their transactions are never parsed again
+    pub pruned_signatures: BTreeSet<String>,
+
+    // Cumulative amounts, kept independently of the (prunable) event maps
+    pub total_minted: BigUint,
+    pub total_burned: BigUint,
+    // Fees retained by the minter from withdrawals of bridged assets
+    pub total_fees: BigUint,
+    // Transfer fees charged by the gSOL ledger on mints, backed by the vault but not in circulation
+    pub total_ledger_fees: BigUint,
+
+    // Solana account holding the deposited SOL, reconciled against the gSOL supply
+    pub solana_vault_address: Option<String>,
+    // Largest accepted difference between the vault balance and the gSOL supply
+    pub reconciliation_tolerance: BigUint,
+    /// Outcome of the last reconciliation since the last upgrade.
+    pub last_reconciliation: Option<SupplyReconciliation>,
+    // Thresholds of the cycles balance monitoring
+    pub cycles_monitor: CyclesMonitorConfig,
+    /// Outcome of the last cycles monitoring run since the last upgrade.
+    pub last_cycles_report: Option<CyclesReport>,
+    // Source of the automatic cycles top-ups, None disables them
+    pub cycles_top_up: Option<CyclesTopUpConfig>,
+
+    // Check of the state replayed at upgrade against the state before the upgrade
+    pub upgrade_audit: UpgradeAudit,
+
+    // Verbosity of the logs, mirrored by crate::logs
+    pub log_level: LogLevel,
+
+    // Front-end origins wallets may accept calls to the minter from (ICRC-28)
+    pub trusted_origins: Vec<String>,
+
+    // SPL tokens bridged to ICRC ledgers, by mint
+    pub assets: BTreeMap<String, BridgedAsset>,
+
+    // Sensitive config changes waiting for their timelock, by id
+    pub config_proposals: BTreeMap<u64, ConfigProposal>,
+    pub next_proposal_id: u64,
+    // Seconds between proposing and executing a config change
+    pub config_timelock_secs: u64,
+
+    // Canisters receiving pushed events, and their consecutive failed deliveries
+    pub subscriptions: BTreeMap<Principal, Subscription>,
+    pub delivery_failures: BTreeMap<Principal, u32>,
+    // Time the delivery awaiting the reply of each subscriber was sent
+    pub pending_deliveries: BTreeMap<Principal, u64>,
+    // Retry policy of the scraping and minting tasks
+    pub retry_policy: RetryPolicy,
+    /// Time of the last attempt of each retried entry since the last upgrade.
+    pub last_attempts: HashMap<RetryTarget, u64>,
+
+    // Calls allowed per caller on the withdraw, get_coupon and trigger_check endpoints,
+    // None disables rate limiting
+    pub rate_limit: Option<RateLimit>,
+    /// Calls of each caller in its current window since the last upgrade.
+    pub call_windows: BTreeMap<Principal, CallWindow>,
+
+    // Claims of the tokens authenticating the minter to the RPC proxy
+    pub proxy_token: ProxyTokenConfig,
+
+    // Seconds after which the lock of a timer task is considered leaked and released
+    pub task_lock_timeout_secs: u64,
+
+    // Intervals of the periodic timers, applied when the timers are set up
+    pub timer_intervals: TimerIntervals,
+
+    // Events and seconds after the previous checkpoint after which the next one is recorded
+    pub checkpoint_interval_events: u64,
+    pub checkpoint_interval_secs: u64,
+    // Bytes used by the event log after which the next checkpoint is recorded, None only
+    // checks the intervals
+    pub event_log_max_bytes: Option<u64>,
+
+    // Commitment level of the signatures and transactions fetched from Solana
+    pub commitment: ConfirmationStatus,
+
+    // Largest deposit minted, larger deposits wait until the cap is raised
+    pub max_deposit_amount: Option<BigUint>,
+
+    // Largest amount a single withdrawal may burn
+    pub max_withdrawal_amount: Option<BigUint>,
+
+    // Coupons paying out at least this amount are also signed by the cosigner key
+    pub dual_signature_threshold: Option<BigUint>,
+
+    // Withdrawals paying out at least this amount wait for an operator's approval
+    pub approval_threshold: Option<BigUint>,
+
+    // Coupons paying out at least this amount are only issued once the release delay elapsed
+    pub release_delay_threshold: Option<BigUint>,
+
+    /// Invariant violations already recorded in the event log since the last upgrade.
+    pub reported_invariant_violations: BTreeSet<InvariantViolation>,
 
     // Unique identifier for each deposit -> used during mint process for unique memo
     pub deposit_id_counter: u64,
@@ -84,9 +526,146 @@ pub struct State {
 
     /// Number of HTTP outcalls since the last upgrade.
     pub http_request_counter: u64,
+    /// Cycles attached to HTTP outcalls since the last upgrade.
+    pub http_outcall_cycles: u128,
+    /// Outcomes of the JSON-RPC outcalls per provider host since the last upgrade, keyed by
+    /// host so that API keys in the path or query of the URL are not exposed.
+    pub rpc_provider_stats: BTreeMap<String, RpcProviderStats>,
+
+    /// Number of threshold ECDSA signatures requested since the last upgrade.
+    pub ecdsa_sign_calls: u64,
+    /// Cycles attached to threshold ECDSA signature requests since the last upgrade.
+    pub ecdsa_sign_cycles: u128,
+    /// Coupon signatures served from the signature cache since the last upgrade.
+    pub signature_cache_hits: u64,
+    /// Coupon signatures of burned withdrawals, reused when the coupon is generated again.
+    pub signature_cache: BTreeMap<u64, CachedSignature>,
+
+    /// Locks preventing concurrent execution timer tasks, with the generation of the run
+    /// holding each of them
+    pub active_tasks: HashMap<TaskType, u64>,
+    /// Generation of the last timer task run that acquired a lock.
+    pub task_generation: u64,
+
+    /// Newest Solana block seen by the scraper since the last upgrade.
+    pub latest_solana_block: Option<SolanaBlock>,
+    /// Transfer fee of the gSOL ledger and the time it was queried at, since the last upgrade.
+    pub ledger_fee: Option<(Nat, u64)>,
+
+    /// Last run of each timer task since the last upgrade, possibly still running.
+    pub task_runs: HashMap<TaskType, TaskRun>,
+    /// Last completed run of each timer task since the last upgrade, kept while the
+    /// next run is in progress.
+    pub last_finished_task_runs: HashMap<TaskType, TaskRun>,
+    /// Completed runs of the timer tasks since the last upgrade, oldest first.
+    pub task_traces: VecDeque<TaskTrace>,
+    /// Time the last run of each timer task without an error finished.
+    pub task_last_success: HashMap<TaskType, u64>,
+
+    /// Time the minter started draining for an upgrade, no new task runs or
+    /// withdrawals are started while set.
+    pub draining_since: Option<u64>,
+}
+
+/// Signature of a coupon message, valid as long as the message and the key are unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedSignature {
+    pub key_name: String,
+    pub message_hash: Vec<u8>,
+    pub signature: Vec<u8>,
+    /// Signature of the cosigner derivation path, for coupons above the dual signature threshold.
+    pub cosignature: Option<Vec<u8>>,
+}
 
-    /// Locks preventing concurrent execution timer tasks
-    pub active_tasks: HashSet<TaskType>,
+/// Public keys of the ECDSA key, recorded once fetched so that they survive upgrades.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct FetchedEcdsaKey {
+    #[cbor(n(0), with = "minicbor::bytes")]
+    pub public_key: Vec<u8>,
+    #[cbor(n(1), with = "minicbor::bytes")]
+    pub chain_code: Vec<u8>,
+    #[n(2)]
+    pub proxy_public_key: String,
+    /// Uncompressed key of the cosigner derivation path in hex format, None for keys
+    /// fetched before coupons were cosigned.
+    #[n(3)]
+    pub cosigner_public_key_hex: Option<String>,
+}
+
+/// ECDSA key replaced by a key rotation.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct PreviousEcdsaKey {
+    #[n(0)]
+    pub key_name: String,
+    /// Uncompressed public key in hex format, as found in the coupons it signed.
+    #[n(1)]
+    pub public_key_hex: String,
+    /// Coupons signed by the key are no longer accepted after this time.
+    #[n(2)]
+    pub valid_until: u64,
+    /// Cosigner key of the replaced key, trusted for the same grace window.
+    #[n(3)]
+    pub cosigner_public_key_hex: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct TaskRun {
+    pub started_at: u64,
+    /// None while the task is running.
+    pub finished_at: Option<u64>,
+    pub last_error: Option<String>,
+    /// Items, such as signatures or deposits, handled by the run.
+    pub items_processed: u64,
+    /// HTTP outcalls and inter-canister calls made by the run.
+    pub outcalls: u64,
+}
+
+/// Outcome of a JSON-RPC outcall.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RpcOutcome {
+    Success,
+    /// The outcall failed for another reason than a timeout.
+    Failure,
+    Timeout,
+}
+
+/// Outcomes of the JSON-RPC outcalls to a provider.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RpcProviderStats {
+    pub successes: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    /// Sum of the latencies of the outcalls in nanoseconds.
+    pub total_latency_nanos: u64,
+}
+
+impl RpcProviderStats {
+    pub fn outcalls(&self) -> u64 {
+        self.successes + self.failures + self.timeouts
+    }
+
+    /// None before the first outcall.
+    pub fn average_latency_nanos(&self) -> Option<u64> {
+        self.total_latency_nanos.checked_div(self.outcalls())
+    }
+}
+
+impl TaskRun {
+    pub fn duration(&self) -> Option<u64> {
+        self.finished_at
+            .map(|finished_at| finished_at.saturating_sub(self.started_at))
+    }
+}
+
+/// Completed run of a timer task.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TaskTrace {
+    pub task: TaskType,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub items_processed: u64,
+    pub outcalls: u64,
+    pub error: Option<String>,
 }
 
 impl State {
@@ -111,6 +690,133 @@ impl State {
                 "minimum_withdrawal_amount must be positive".to_string(),
             ));
         }
+        if self.max_withdrawals_per_principal == 0 {
+            return Err(InvalidStateError::InvalidMaxWithdrawalsPerPrincipal(
+                "max_withdrawals_per_principal must be positive".to_string(),
+            ));
+        }
+        if self.mint_parallelism == 0 {
+            return Err(InvalidStateError::InvalidMintParallelism(
+                "mint_parallelism must be positive".to_string(),
+            ));
+        }
+        if self.retry_policy.max_attempts == 0 {
+            return Err(InvalidStateError::InvalidRetryPolicy(
+                "retry_policy.max_attempts must be positive".to_string(),
+            ));
+        }
+        if let Some(limit) = &self.rate_limit {
+            if limit.max_calls == 0 || limit.window_secs == 0 {
+                return Err(InvalidStateError::InvalidRateLimit(
+                    "rate_limit.max_calls and rate_limit.window_secs must be positive".to_string(),
+                ));
+            }
+        }
+        if self.proxy_token.ttl_secs == 0 || self.proxy_token.agent_name.trim().is_empty() {
+            return Err(InvalidStateError::InvalidProxyToken(
+                "proxy_token.ttl_secs must be positive and proxy_token.agent_name cannot be blank"
+                    .to_string(),
+            ));
+        }
+        if self.task_lock_timeout_secs == 0 {
+            return Err(InvalidStateError::InvalidTaskLockTimeout(
+                "task_lock_timeout_secs must be positive".to_string(),
+            ));
+        }
+        let TimerIntervals {
+            get_latest_signature_secs,
+            scrap_signature_ranges_secs,
+            scrap_signatures_secs,
+            mint_gsol_secs,
+            reconcile_supply_secs,
+        } = &self.timer_intervals;
+        if [
+            get_latest_signature_secs,
+            scrap_signature_ranges_secs,
+            scrap_signatures_secs,
+            mint_gsol_secs,
+            reconcile_supply_secs,
+        ]
+        .contains(&&0)
+        {
+            return Err(InvalidStateError::InvalidTimerIntervals(
+                "timer intervals must be positive".to_string(),
+            ));
+        }
+        if self.checkpoint_interval_events == 0
+            || self.checkpoint_interval_secs == 0
+            || self.event_log_max_bytes == Some(0)
+        {
+            return Err(InvalidStateError::InvalidTimerIntervals(
+                "checkpoint intervals must be positive".to_string(),
+            ));
+        }
+        if let Some(config) = &self.cycles_top_up {
+            if !config.is_valid() {
+                return Err(InvalidStateError::InvalidCyclesTopUp(
+                    "cycles_top_up must transfer a positive amount".to_string(),
+                ));
+            }
+        }
+        // origins are compared verbatim by the wallets
+        if let Some(origin) = self
+            .trusted_origins
+            .iter()
+            .find(|origin| !origin.starts_with("https://") || origin.ends_with('/'))
+        {
+            return Err(InvalidStateError::InvalidTrustedOrigin(format!(
+                "trusted origin {origin} must be an https origin without a trailing slash"
+            )));
+        }
+        // getTransaction does not accept the processed commitment
+        if self.commitment == ConfirmationStatus::Processed {
+            return Err(InvalidStateError::InvalidCommitment(
+                "commitment must be Confirmed or Finalized".to_string(),
+            ));
+        }
+        if let Some(maximum) = &self.max_deposit_amount {
+            if *maximum == BigUint::from(0u8) {
+                return Err(InvalidStateError::InvalidMaximumAmount(
+                    "max_deposit_amount must be positive".to_string(),
+                ));
+            }
+        }
+        if let Some(maximum) = &self.max_withdrawal_amount {
+            if *maximum < self.minimum_withdrawal_amount {
+                return Err(InvalidStateError::InvalidMaximumAmount(
+                    "max_withdrawal_amount cannot be less than minimum_withdrawal_amount"
+                        .to_string(),
+                ));
+            }
+        }
+        if let Some(threshold) = &self.dual_signature_threshold {
+            if *threshold == BigUint::from(0u8) {
+                return Err(InvalidStateError::InvalidMaximumAmount(
+                    "dual_signature_threshold must be positive".to_string(),
+                ));
+            }
+        }
+        if let Some(threshold) = &self.approval_threshold {
+            if *threshold == BigUint::from(0u8) {
+                return Err(InvalidStateError::InvalidMaximumAmount(
+                    "approval_threshold must be positive".to_string(),
+                ));
+            }
+        }
+        if let Some(threshold) = &self.release_delay_threshold {
+            if *threshold == BigUint::from(0u8) {
+                return Err(InvalidStateError::InvalidMaximumAmount(
+                    "release_delay_threshold must be positive".to_string(),
+                ));
+            }
+        }
+        if let Some(address) = &self.solana_vault_address {
+            if crate::spl::decode_pubkey(address).is_err() {
+                return Err(InvalidStateError::InvalidSolanaVaultAddress(format!(
+                    "solana_vault_address {address} is not a valid Solana address"
+                )));
+            }
+        }
         Ok(())
     }
 
@@ -121,6 +827,36 @@ impl State {
             solana_initial_signature,
             ecdsa_key_name,
             minimum_withdrawal_amount,
+            ledger_id,
+            max_withdrawals_per_principal,
+            terminal_events_retention,
+            solana_vault_address,
+            reconciliation_tolerance,
+            retry_policy,
+            rate_limit,
+            task_lock_timeout_secs,
+            proxy_token,
+            timer_intervals,
+            commitment,
+            max_deposit_amount,
+            max_withdrawal_amount,
+            cycles_monitor,
+            cycles_top_up,
+            upgrade_audit,
+            trusted_origins,
+            config_timelock_secs,
+            mint_parallelism,
+            invalid_events_retention_secs,
+            dual_signature_threshold,
+            approval_threshold,
+            destination_activation_delay_secs,
+            release_delay_threshold,
+            release_delay_secs,
+            checkpoint_interval_events,
+            checkpoint_interval_secs,
+            event_archive,
+            terminal_events_max_age_secs,
+            event_log_max_bytes,
         } = upgrade_args;
         if let Some(url) = solana_rpc_url {
             self.solana_rpc_url = url;
@@ -144,34 +880,215 @@ impl State {
                     ))?;
             self.minimum_withdrawal_amount = amount;
         }
+        if let Some(ledger_id) = ledger_id {
+            self.ledger_id = Some(ledger_id);
+        }
+        if let Some(max) = max_withdrawals_per_principal {
+            self.max_withdrawals_per_principal = max as usize;
+        }
+        if let Some(retention) = terminal_events_retention {
+            self.terminal_events_retention = Some(retention);
+        }
+        if let Some(max_age) = terminal_events_max_age_secs {
+            self.terminal_events_max_age_secs = Some(max_age);
+        }
+        if let Some(address) = solana_vault_address {
+            self.solana_vault_address = Some(address);
+        }
+        if let Some(tolerance) = reconciliation_tolerance {
+            self.reconciliation_tolerance = tolerance.0;
+        }
+        if let Some(policy) = retry_policy {
+            self.retry_policy = policy;
+        }
+        if let Some(limit) = rate_limit {
+            self.rate_limit = Some(limit);
+        }
+        if let Some(timeout) = task_lock_timeout_secs {
+            self.task_lock_timeout_secs = timeout;
+        }
+        if let Some(config) = proxy_token {
+            self.proxy_token = config;
+        }
+        if let Some(intervals) = timer_intervals {
+            self.timer_intervals = intervals;
+        }
+        if let Some(interval) = checkpoint_interval_events {
+            self.checkpoint_interval_events = interval;
+        }
+        if let Some(interval) = checkpoint_interval_secs {
+            self.checkpoint_interval_secs = interval;
+        }
+        if let Some(max_bytes) = event_log_max_bytes {
+            self.event_log_max_bytes = Some(max_bytes);
+        }
+        if let Some(archive) = event_archive {
+            self.event_archive = Some(archive);
+        }
+        if let Some(config) = cycles_monitor {
+            self.cycles_monitor = config;
+        }
+        if let Some(config) = cycles_top_up {
+            self.cycles_top_up = Some(config);
+        }
+        if let Some(mode) = upgrade_audit {
+            self.upgrade_audit = mode;
+        }
+        if let Some(origins) = trusted_origins {
+            self.trusted_origins = origins;
+        }
+        if let Some(timelock) = config_timelock_secs {
+            self.config_timelock_secs = timelock;
+        }
+        if let Some(parallelism) = mint_parallelism {
+            self.mint_parallelism = parallelism as usize;
+        }
+        if let Some(retention) = invalid_events_retention_secs {
+            self.invalid_events_retention_secs = retention;
+        }
+        if let Some(delay) = destination_activation_delay_secs {
+            self.destination_activation_delay_secs = delay;
+        }
+        if let Some(delay) = release_delay_secs {
+            self.release_delay_secs = delay;
+        }
+        if let Some(commitment) = commitment {
+            self.commitment = commitment;
+        }
+        if let Some(amount) = max_deposit_amount {
+            let amount = amount
+                .0
+                .to_biguint()
+                .ok_or(InvalidStateError::InvalidMaximumAmount(
+                    "ERROR: max_deposit_amount is not a valid u256".to_string(),
+                ))?;
+            self.max_deposit_amount = Some(amount);
+        }
+        if let Some(amount) = max_withdrawal_amount {
+            let amount = amount
+                .0
+                .to_biguint()
+                .ok_or(InvalidStateError::InvalidMaximumAmount(
+                    "ERROR: max_withdrawal_amount is not a valid u256".to_string(),
+                ))?;
+            self.max_withdrawal_amount = Some(amount);
+        }
+        if let Some(amount) = dual_signature_threshold {
+            let amount = amount
+                .0
+                .to_biguint()
+                .ok_or(InvalidStateError::InvalidMaximumAmount(
+                    "ERROR: dual_signature_threshold is not a valid u256".to_string(),
+                ))?;
+            self.dual_signature_threshold = Some(amount);
+        }
+        if let Some(amount) = approval_threshold {
+            let amount = amount
+                .0
+                .to_biguint()
+                .ok_or(InvalidStateError::InvalidMaximumAmount(
+                    "ERROR: approval_threshold is not a valid u256".to_string(),
+                ))?;
+            self.approval_threshold = Some(amount);
+        }
+        if let Some(amount) = release_delay_threshold {
+            let amount = amount
+                .0
+                .to_biguint()
+                .ok_or(InvalidStateError::InvalidMaximumAmount(
+                    "ERROR: release_delay_threshold is not a valid u256".to_string(),
+                ))?;
+            self.release_delay_threshold = Some(amount);
+        }
         self.validate_config()
     }
 
     // compressed public key in hex format - 33 bytes
-    pub fn compressed_public_key(&self) -> String {
+    pub fn compressed_public_key(&self) -> Result<String, EcdsaKeyError> {
         let public_key = match &self.ecdsa_public_key {
             Some(response) => &response.public_key,
-            None => ic_cdk::trap("Public key is not initialized"),
+            None => return Err(EcdsaKeyError::NotInitialized),
         };
 
-        hex::encode(&public_key)
+        Ok(hex::encode(public_key))
     }
 
     // uncompressed public key in hex format - 65 bytes
-    pub fn uncompressed_public_key(&self) -> String {
-        use libsecp256k1::{PublicKey, PublicKeyFormat};
+    pub fn uncompressed_public_key(&self) -> Result<String, EcdsaKeyError> {
+        match &self.ecdsa_public_key {
+            Some(response) => uncompressed_hex(&response.public_key),
+            None => Err(EcdsaKeyError::NotInitialized),
+        }
+    }
 
-        let public_key = match &self.ecdsa_public_key {
-            Some(response) => &response.public_key,
-            None => ic_cdk::trap("Public key is not initialized"),
-        };
+    // uncompressed public key in hex format, None until the key is fetched
+    pub fn current_public_key_hex(&self) -> Option<String> {
+        self.uncompressed_public_key().ok()
+    }
+
+    /// Whether coupons signed by the given uncompressed public key are accepted at `now`,
+    /// which holds for the current key and for the previous key during its grace window.
+    pub fn is_trusted_public_key(&self, public_key_hex: &str, now: u64) -> bool {
+        if self.current_public_key_hex().as_deref() == Some(public_key_hex) {
+            return true;
+        }
+        self.previous_ecdsa_key
+            .as_ref()
+            .is_some_and(|key| key.public_key_hex == public_key_hex && now < key.valid_until)
+    }
+
+    /// Whether cosignatures by the given uncompressed public key are accepted at `now`, as
+    /// with [State::is_trusted_public_key] for the cosigner derivation path.
+    pub fn is_trusted_cosigner_key(&self, public_key_hex: &str, now: u64) -> bool {
+        if self.cosigner_public_key_hex.as_deref() == Some(public_key_hex) {
+            return true;
+        }
+        self.previous_ecdsa_key.as_ref().is_some_and(|key| {
+            key.cosigner_public_key_hex.as_deref() == Some(public_key_hex) && now < key.valid_until
+        })
+    }
+
+    /// Whether the coupon of a withdrawal paying out `amount` must carry a cosignature.
+    pub fn requires_cosignature(&self, amount: &BigUint) -> bool {
+        self.dual_signature_threshold
+            .as_ref()
+            .is_some_and(|threshold| amount >= threshold)
+    }
+
+    pub fn record_fetched_ecdsa_key(&mut self, key_name: &str, key: &FetchedEcdsaKey) {
+        // keys fetched before a rotation are stale
+        if key_name != self.ecdsa_key_name {
+            return;
+        }
+        self.ecdsa_public_key = Some(EcdsaPublicKeyResponse {
+            public_key: key.public_key.clone(),
+            chain_code: key.chain_code.clone(),
+        });
+        self.ecdsa_proxy_public_key = Some(key.proxy_public_key.clone());
+        self.cosigner_public_key_hex = key.cosigner_public_key_hex.clone();
+    }
 
-        match PublicKey::parse_slice(&public_key, Some(PublicKeyFormat::Compressed)) {
-            Ok(pk) => hex::encode(pk.serialize()),
-            Err(_) => ic_cdk::trap("Failed to deserialize sec1 encoding into public key"),
+    pub fn fetched_ecdsa_key(&self) -> Option<FetchedEcdsaKey> {
+        match (&self.ecdsa_public_key, &self.ecdsa_proxy_public_key) {
+            (Some(response), Some(proxy_public_key)) => Some(FetchedEcdsaKey {
+                public_key: response.public_key.clone(),
+                chain_code: response.chain_code.clone(),
+                proxy_public_key: proxy_public_key.clone(),
+                cosigner_public_key_hex: self.cosigner_public_key_hex.clone(),
+            }),
+            _ => None,
         }
     }
 
+    pub fn rotate_ecdsa_key(&mut self, key_name: &str, previous: PreviousEcdsaKey) {
+        self.ecdsa_key_name = key_name.to_string();
+        // fetched again for the new key
+        self.ecdsa_public_key = None;
+        self.ecdsa_proxy_public_key = None;
+        self.cosigner_public_key_hex = None;
+        self.previous_ecdsa_key = Some(previous);
+    }
+
     pub fn solana_rpc_url(&self) -> SolanaRpcUrl {
         self.solana_rpc_url.clone()
     }
@@ -181,6 +1098,15 @@ impl State {
         self.solana_last_known_signature = Some(sig.to_string());
     }
 
+    pub fn observe_solana_block(&mut self, block: SolanaBlock) {
+        if self
+            .latest_solana_block
+            .is_none_or(|latest| latest.slot < block.slot)
+        {
+            self.latest_solana_block = Some(block);
+        }
+    }
+
     pub fn get_solana_last_known_signature(&self) -> String {
         match &self.solana_last_known_signature {
             Some(sig) => sig.to_string(),
@@ -188,163 +1114,1009 @@ impl State {
         }
     }
 
-    pub fn record_solana_signature_range(&mut self, range: SolanaSignatureRange) {
+    pub fn has_solana_signature_range(&self, range: &SolanaSignatureRange) -> bool {
+        self.solana_signature_ranges
+            .contains_key(&range_key(&range.before_sol_sig, &range.until_sol_sig))
+    }
+
+    /// Whether the transaction of the signature was already parsed into an accepted,
+    /// dead-lettered, minted or invalid event, including pruned and archived ones.
+    pub fn is_processed_signature(&self, sig: &String) -> bool {
+        self.accepted_events.contains_key(sig)
+            || self.dead_letters.contains_key(sig)
+            || self.invalid_events.contains_key(sig)
+            || self.minted_events.contains_key(sig)
+            || self.pruned_signatures.contains(sig)
+    }
+
+    pub fn record_solana_signature_range(
+        &mut self,
+        range: SolanaSignatureRange,
+    ) -> Result<(), StateTransitionError> {
         let key = range_key(&range.before_sol_sig, &range.until_sol_sig);
 
-        match self.solana_signature_ranges.contains_key(&key) {
-            true => {
-                panic!("Attempted to record existing range: {key} .");
-            }
-            false => {
-                self.solana_signature_ranges.insert(key, range);
-            }
+        if self.solana_signature_ranges.contains_key(&key) {
+            return Err(StateTransitionError::DuplicateSignatureRange(key));
         }
+        self.solana_signature_ranges.insert(key, range);
+        Ok(())
     }
 
     pub fn retry_solana_signature_range(
         &mut self,
         old_range: SolanaSignatureRange,
         new_range: Option<SolanaSignatureRange>,
-    ) {
+    ) -> Result<(), StateTransitionError> {
         let old_key = range_key(&old_range.before_sol_sig, &old_range.until_sol_sig);
 
-        match self.solana_signature_ranges.remove(&old_key) {
-            Some(mut old_range) => {
-                match new_range {
-                    // if it is a sub range of previously failed range failed, remove the old range and add the new range
-                    Some(new_range) => {
-                        self.record_solana_signature_range(new_range);
-                    }
-                    None => {
-                        // in case range exists, increment the retries
-                        old_range.retry.increment_retries();
-                        self.solana_signature_ranges
-                            .insert(old_key.to_string(), old_range);
-                    }
+        if !self.solana_signature_ranges.contains_key(&old_key) {
+            return Err(StateTransitionError::UnknownSignatureRange(old_key));
+        }
+
+        match new_range {
+            // a sub range of the range failed, it replaces the range
+            Some(new_range) => {
+                let new_key = range_key(&new_range.before_sol_sig, &new_range.until_sol_sig);
+                if new_key != old_key && self.solana_signature_ranges.contains_key(&new_key) {
+                    return Err(StateTransitionError::DuplicateSignatureRange(new_key));
+                }
+                self.solana_signature_ranges.remove(&old_key);
+                self.solana_signature_ranges.insert(new_key, new_range);
+            }
+            None => {
+                // in case range exists, increment the retries
+                if let Some(range) = self.solana_signature_ranges.get_mut(&old_key) {
+                    range.retry.increment_retries();
                 }
             }
-            None => panic!("Attempted to re-record NON existing range: {old_key} ."),
         }
+        Ok(())
     }
 
-    pub fn remove_solana_signature_range(&mut self, range: &SolanaSignatureRange) {
+    /// Moves the start of the range to `before`, keeping its retries.
+    pub fn advance_solana_signature_range(
+        &mut self,
+        range: &SolanaSignatureRange,
+        before: &str,
+    ) -> Result<(), StateTransitionError> {
+        let key = range_key(&range.before_sol_sig, &range.until_sol_sig);
+        let new_key = range_key(&before.to_string(), &range.until_sol_sig);
+
+        if !self.solana_signature_ranges.contains_key(&key) {
+            return Err(StateTransitionError::UnknownSignatureRange(key));
+        }
+        if new_key != key && self.solana_signature_ranges.contains_key(&new_key) {
+            return Err(StateTransitionError::DuplicateSignatureRange(new_key));
+        }
+
+        if let Some(mut range) = self.solana_signature_ranges.remove(&key) {
+            range.before_sol_sig = before.to_string();
+            self.solana_signature_ranges.insert(new_key, range);
+        }
+        Ok(())
+    }
+
+    pub fn remove_solana_signature_range(
+        &mut self,
+        range: &SolanaSignatureRange,
+    ) -> Result<(), StateTransitionError> {
         let key = range_key(&range.before_sol_sig, &range.until_sol_sig);
 
         match self.solana_signature_ranges.remove(&key) {
-            Some(_) => {}
-            None => panic!("Attempted to remove NON existing range: {key} ."),
-        };
+            Some(_) => Ok(()),
+            None => Err(StateTransitionError::UnknownSignatureRange(key)),
+        }
     }
 
-    pub fn record_or_retry_solana_signature(&mut self, sig: SolanaSignature) {
-        match self.solana_signatures.contains_key(&sig.sol_sig) {
-            true => {
-                // if it exists - increment the retries
-                let mut existing_signature = self.solana_signatures.remove(&sig.sol_sig).unwrap();
-
-                existing_signature.retry.increment_retries();
-                self.solana_signatures
-                    .insert(sig.sol_sig.to_string(), existing_signature);
+    pub fn record_transaction_not_found(
+        &mut self,
+        sig: &String,
+    ) -> Result<(), StateTransitionError> {
+        match self.solana_signatures.get_mut(sig) {
+            Some(signature) => {
+                signature.increment_not_found_lookups();
+                Ok(())
             }
-            false => {
-                // if it does not exist - add it
+            None => Err(StateTransitionError::UnknownSolanaSignature(
+                sig.to_string(),
+            )),
+        }
+    }
+
+    pub fn record_or_retry_solana_signature(&mut self, sig: SolanaSignature) {
+        match self.solana_signatures.get_mut(&sig.sol_sig) {
+            // if it exists - increment the retries
+            Some(existing_signature) => existing_signature.retry.increment_retries(),
+            // if it does not exist - add it
+            None => {
                 self.solana_signatures.insert(sig.sol_sig.to_string(), sig);
             }
         }
     }
 
-    pub fn record_invalid_event(&mut self, mut sig: SolanaSignature) {
-        let key = &sig.sol_sig;
-
-        match self.solana_signatures.remove(key) {
-            Some(event) => event,
-            None => panic!("Attempted to remove NON existing solana signature {key} ."),
-        };
+    pub fn record_invalid_event(
+        &mut self,
+        mut sig: SolanaSignature,
+        reason: Option<InvalidReason>,
+    ) -> Result<(), StateTransitionError> {
+        let key = sig.sol_sig.to_string();
 
-        assert!(
-            !self.invalid_events.contains_key(key),
-            "Attempted to record existing invalid event: {key} ."
-        );
+        if !self.solana_signatures.contains_key(&key) {
+            return Err(StateTransitionError::UnknownSolanaSignature(key));
+        }
+        if self.invalid_events.contains_key(&key) {
+            return Err(StateTransitionError::DuplicateInvalidEvent(key));
+        }
 
+        self.solana_signatures.remove(&key);
         sig.retry.reset_retries();
-        self.invalid_events.insert(key.to_string(), sig);
-    }
-
-    pub fn record_or_retry_accepted_event(&mut self, deposit: DepositEvent) {
-        let key = &deposit.sol_sig;
-
-        match self.accepted_events.contains_key(key) {
-            // new event
-            false => {
-                // remove signature
-                match self.solana_signatures.remove(key) {
-                    // if signature exists
-                    Some(_) => {
-                        // add accepted event
-                        self.accepted_events.insert(key.to_string(), deposit);
-                    }
-                    // if signature doesn't exist -> something whet wrong
-                    None => panic!("Attempted to remove NON existing solana signature {key} ."),
-                };
-            }
-            // retrying accepted event
-            true => {
-                let mut existing_event = self.accepted_events.remove(key).unwrap();
-                // increment retries
-                existing_event.retry.increment_retries();
-                self.accepted_events.insert(key.to_string(), existing_event);
-            }
-        };
+        sig.invalid_reason = reason;
+        self.invalid_events.insert(key, sig);
+        Ok(())
     }
 
-    pub fn record_minted_event(&mut self, mut deposit: DepositEvent) {
-        let key = &deposit.sol_sig;
+    pub fn record_or_retry_accepted_event(
+        &mut self,
+        deposit: DepositEvent,
+    ) -> Result<(), StateTransitionError> {
+        let key = deposit.sol_sig.to_string();
+
+        // retrying accepted event
+        if let Some(existing_event) = self.accepted_events.get_mut(&key) {
+            existing_event.retry.increment_retries();
+            return Ok(());
+        }
 
-        _ = match self.accepted_events.remove(key) {
-            Some(event) => event,
-            None => panic!("Attempted to remove NON existing accepted event: {key} ."),
-        };
+        // new event, the signature was scraped before
+        if self.solana_signatures.remove(&key).is_none() {
+            return Err(StateTransitionError::UnknownSolanaSignature(key));
+        }
+        self.deposits_by_principal
+            .entry(deposit.to_icp_address)
+            .or_default()
+            .insert(key.to_string());
+        self.accepted_events.insert(key, deposit);
+        Ok(())
+    }
 
-        assert!(
-            !self.minted_events.contains_key(key),
-            "Attempted to record existing minted event: {key}.",
+    pub fn record_dead_letter(
+        &mut self,
+        sol_sig: &String,
+        error: &str,
+    ) -> Result<(), StateTransitionError> {
+        let deposit = self
+            .accepted_events
+            .remove(sol_sig)
+            .ok_or_else(|| StateTransitionError::UnknownAcceptedEvent(sol_sig.to_string()))?;
+        self.dead_letters.insert(
+            sol_sig.to_string(),
+            DeadLetter {
+                deposit,
+                error: error.to_string(),
+            },
         );
+        Ok(())
+    }
+
+    /// Moves a dead-lettered deposit back to the accepted ones, with its retries reset.
+    pub fn requeue_dead_letter(&mut self, sol_sig: &String) -> Result<(), StateTransitionError> {
+        let mut dead_letter = self
+            .dead_letters
+            .remove(sol_sig)
+            .ok_or_else(|| StateTransitionError::UnknownDeadLetter(sol_sig.to_string()))?;
+        dead_letter.deposit.retry.reset_retries();
+        self.accepted_events
+            .insert(sol_sig.to_string(), dead_letter.deposit);
+        Ok(())
+    }
 
+    pub fn record_minted_event(
+        &mut self,
+        mut deposit: DepositEvent,
+    ) -> Result<(), StateTransitionError> {
+        let key = deposit.sol_sig.to_string();
+
+        if !self.accepted_events.contains_key(&key) {
+            return Err(StateTransitionError::UnknownAcceptedEvent(key));
+        }
         deposit.retry.reset_retries();
-        _ = self.minted_events.insert(key.to_string(), deposit);
+        // replaying a mint recorded after the last checkpoint finds the same event in
+        // stable memory
+        if self
+            .minted_events
+            .get(&key)
+            .is_some_and(|minted| minted != deposit)
+        {
+            return Err(StateTransitionError::DuplicateMintedEvent(key));
+        }
+
+        self.accepted_events.remove(&key);
+        self.total_minted += &deposit.amount.0;
+        if let Some(fee) = &deposit.ledger_fee {
+            self.total_ledger_fees += &fee.0;
+        }
+        self.minted_deposit_ids.insert(deposit.id, key.clone());
+        _ = self.minted_events.insert(key, deposit);
+        Ok(())
     }
 
     pub fn record_or_retry_withdrawal_burned_event(&mut self, withdrawal: WithdrawalEvent) {
         let key = withdrawal.get_burn_id();
 
-        match self.withdrawal_burned_events.contains_key(&key) {
+        match self.withdrawal_burned_events.get_mut(&key) {
+            // if it exists - increment the retries
+            Some(event) => event.retry.increment_retries(),
             // if it does not exist - add it
-            false => {
+            None => {
+                self.total_burned += &withdrawal.amount.0;
+                if let Some(fee) = &withdrawal.fee {
+                    self.total_fees += &fee.0;
+                }
+                self.withdrawals_by_principal
+                    .entry(withdrawal.from_icp_address)
+                    .or_default()
+                    .insert(key);
+                self.withdrawals_by_sol_address
+                    .entry(withdrawal.to_sol_address.to_string())
+                    .or_default()
+                    .insert(key);
+                self.record_used_burn_block(&withdrawal);
                 self.withdrawal_burned_events.insert(key, withdrawal);
             }
-            // if it exists - increment the retries
-            true => {
-                let mut event: WithdrawalEvent =
-                    self.withdrawal_burned_events.remove(&key).unwrap();
+        }
+    }
+
+    pub fn record_withdrawal_redeemed_event(
+        &mut self,
+        mut withdrawal: WithdrawalEvent,
+    ) -> Result<(), StateTransitionError> {
+        let key = withdrawal.get_burn_id();
+
+        if self.withdrawal_burned_events.remove(&key).is_none() {
+            return Err(StateTransitionError::UnknownBurnedWithdrawal(key));
+        }
+        // the coupon is stored with the redeemed event
+        self.signature_cache.remove(&key);
+        self.time_locked_withdrawals.remove(&key);
+        withdrawal.retry.reset_retries();
+        self.withdrawal_redeemed_events.insert(key, withdrawal);
+        Ok(())
+    }
 
-                event.retry.increment_retries();
-                self.withdrawal_burned_events.insert(key, event);
+    pub fn record_destination_address(
+        &mut self,
+        owner: Principal,
+        destination: &DestinationAddress,
+    ) {
+        self.address_books
+            .entry(owner)
+            .or_default()
+            .insert(destination.address.clone(), destination.active_at);
+    }
+
+    pub fn remove_destination_address(&mut self, owner: &Principal, address: &str) {
+        if let Some(book) = self.address_books.get_mut(owner) {
+            book.remove(address);
+        }
+    }
+
+    pub fn destination_addresses(&self, owner: &Principal) -> Vec<DestinationAddress> {
+        self.address_books
+            .get(owner)
+            .into_iter()
+            .flatten()
+            .map(|(address, active_at)| DestinationAddress {
+                address: address.clone(),
+                active_at: *active_at,
+            })
+            .collect()
+    }
+
+    /// Holds the coupon of a burned withdrawal until an operator approves it.
+    pub fn hold_withdrawal_for_approval(
+        &mut self,
+        burn_id: u64,
+    ) -> Result<(), StateTransitionError> {
+        if !self.withdrawal_burned_events.contains_key(&burn_id) {
+            return Err(StateTransitionError::UnknownBurnedWithdrawal(burn_id));
+        }
+        if self.withdrawal_approvals.contains_key(&burn_id) {
+            return Err(StateTransitionError::DuplicateApproval(burn_id));
+        }
+        self.withdrawal_approvals
+            .insert(burn_id, ApprovalStatus::Pending);
+        Ok(())
+    }
+
+    /// Releases a held withdrawal, its coupon is signed like any other burned withdrawal.
+    pub fn approve_withdrawal(&mut self, burn_id: u64) -> Result<(), StateTransitionError> {
+        match self.withdrawal_approvals.get(&burn_id) {
+            Some(ApprovalStatus::Pending) => {
+                self.withdrawal_approvals.remove(&burn_id);
+                Ok(())
             }
+            _ => Err(StateTransitionError::NotAwaitingApproval(burn_id)),
         }
     }
 
-    pub fn record_withdrawal_redeemed_event(&mut self, mut withdrawal: WithdrawalEvent) {
+    pub fn reject_withdrawal(
+        &mut self,
+        burn_id: u64,
+        reason: &str,
+    ) -> Result<(), StateTransitionError> {
+        match self.withdrawal_approvals.get_mut(&burn_id) {
+            Some(status @ ApprovalStatus::Pending) => {
+                *status = ApprovalStatus::Rejected {
+                    reason: reason.to_string(),
+                };
+                Ok(())
+            }
+            _ => Err(StateTransitionError::NotAwaitingApproval(burn_id)),
+        }
+    }
+
+    /// Whether an operator rejected the held withdrawal, which waits to be cancelled.
+    pub fn is_rejected_withdrawal(&self, burn_id: u64) -> bool {
+        matches!(
+            self.withdrawal_approvals.get(&burn_id),
+            Some(ApprovalStatus::Rejected { .. })
+        )
+    }
+
+    /// Whether a withdrawal paying out `amount` waits for an operator's approval.
+    pub fn requires_approval(&self, amount: &BigUint) -> bool {
+        self.approval_threshold
+            .as_ref()
+            .is_some_and(|threshold| amount >= threshold)
+    }
+
+    /// Burned withdrawals waiting for an operator's approval, oldest first.
+    pub fn withdrawals_awaiting_approval(&self) -> Vec<WithdrawalEvent> {
+        self.withdrawal_approvals
+            .iter()
+            .filter(|(_, status)| **status == ApprovalStatus::Pending)
+            .filter_map(|(burn_id, _)| self.withdrawal_burned_events.get(burn_id).cloned())
+            .collect()
+    }
+
+    /// Holds the coupon of a burned withdrawal until `release_at`.
+    pub fn time_lock_withdrawal(
+        &mut self,
+        burn_id: u64,
+        release_at: u64,
+    ) -> Result<(), StateTransitionError> {
+        if !self.withdrawal_burned_events.contains_key(&burn_id) {
+            return Err(StateTransitionError::UnknownBurnedWithdrawal(burn_id));
+        }
+        if self.time_locked_withdrawals.contains_key(&burn_id) {
+            return Err(StateTransitionError::DuplicateTimeLock(burn_id));
+        }
+        self.time_locked_withdrawals.insert(burn_id, release_at);
+        Ok(())
+    }
+
+    /// Removes a time-locked or rejected withdrawal, its coupon is never issued and the
+    /// burned amount waits to be re-minted to the sender.
+    pub fn cancel_withdrawal(
+        &mut self,
+        burn_id: u64,
+        cancelled_at: Option<u64>,
+    ) -> Result<(), StateTransitionError> {
+        if !self.time_locked_withdrawals.contains_key(&burn_id)
+            && !self.is_rejected_withdrawal(burn_id)
+        {
+            return Err(StateTransitionError::NotCancellable(burn_id));
+        }
+        // the last check, the state is only modified once the transition is accepted
+        let mut event = self
+            .withdrawal_burned_events
+            .remove(&burn_id)
+            .ok_or(StateTransitionError::UnknownBurnedWithdrawal(burn_id))?;
+        self.time_locked_withdrawals.remove(&burn_id);
+        self.withdrawal_approvals.remove(&burn_id);
+        self.signature_cache.remove(&burn_id);
+        // the burn block stays used, it cannot fund another withdrawal
+        self.remove_withdrawal_from_indices(&event);
+        if let Some(timestamp) = cancelled_at {
+            event.update_after_cancel(timestamp);
+        }
+        self.cancelled_withdrawals.insert(burn_id, event);
+        Ok(())
+    }
+
+    pub fn record_withdrawal_reminted(&mut self, burn_id: u64) -> Result<(), StateTransitionError> {
+        let event = self
+            .cancelled_withdrawals
+            .remove(&burn_id)
+            .ok_or(StateTransitionError::UnknownCancelledWithdrawal(burn_id))?;
+        self.total_burned -= &event.amount.0;
+        if let Some(fee) = &event.fee {
+            self.total_fees -= &fee.0;
+        }
+        Ok(())
+    }
+
+    pub fn record_remint_attempt(
+        &mut self,
+        burn_id: u64,
+        created_at: Option<u64>,
+    ) -> Result<(), StateTransitionError> {
+        self.cancelled_withdrawals
+            .get_mut(&burn_id)
+            .ok_or(StateTransitionError::UnknownCancelledWithdrawal(burn_id))?
+            .update_after_remint_attempt(created_at);
+        Ok(())
+    }
+
+    pub fn record_remint_failure(&mut self, burn_id: u64) -> Result<(), StateTransitionError> {
+        self.cancelled_withdrawals
+            .get_mut(&burn_id)
+            .ok_or(StateTransitionError::UnknownCancelledWithdrawal(burn_id))?
+            .update_after_remint_failure();
+        Ok(())
+    }
+
+    /// Whether a withdrawal paying out `amount` waits for the release delay.
+    pub fn requires_release_delay(&self, amount: &BigUint) -> bool {
+        self.release_delay_threshold
+            .as_ref()
+            .is_some_and(|threshold| amount >= threshold)
+    }
+
+    /// Whether the coupon of the withdrawal is not released at `now` yet.
+    pub fn is_time_locked(&self, burn_id: u64, now: u64) -> bool {
+        self.time_locked_withdrawals
+            .get(&burn_id)
+            .is_some_and(|release_at| now < *release_at)
+    }
+
+    /// Burned withdrawals whose coupon is not released yet and their release times,
+    /// oldest first.
+    pub fn time_locked_withdrawals(&self, now: u64) -> Vec<(WithdrawalEvent, u64)> {
+        self.time_locked_withdrawals
+            .iter()
+            .filter(|(_, release_at)| now < **release_at)
+            .filter_map(|(burn_id, release_at)| {
+                self.withdrawal_burned_events
+                    .get(burn_id)
+                    .map(|event| (event.clone(), *release_at))
+            })
+            .collect()
+    }
+
+    pub fn record_resigned_coupon(
+        &mut self,
+        withdrawal: WithdrawalEvent,
+    ) -> Result<(), StateTransitionError> {
         let key = withdrawal.get_burn_id();
 
-        match self.withdrawal_burned_events.remove(&key) {
-            Some(_) => {
-                withdrawal.retry.reset_retries();
-                self.withdrawal_redeemed_events.insert(key, withdrawal);
+        if !self.withdrawal_redeemed_events.contains_key(&key) {
+            return Err(StateTransitionError::UnknownRedeemedWithdrawal(key));
+        }
+        self.withdrawal_redeemed_events.insert(key, withdrawal);
+        Ok(())
+    }
+
+    pub fn record_blocked_address(&mut self, address: &String) {
+        self.blocked_addresses.insert(address.to_string());
+    }
+
+    pub fn remove_blocked_address(&mut self, address: &String) {
+        self.blocked_addresses.remove(address);
+    }
+
+    pub fn is_blocked_address(&self, address: &str) -> bool {
+        self.blocked_addresses.contains(address)
+    }
+
+    pub fn is_revoked_coupon(&self, burn_id: u64) -> bool {
+        self.revoked_coupons.contains(&burn_id)
+    }
+
+    pub fn is_solana_redeemed_coupon(&self, burn_id: u64) -> bool {
+        self.solana_redeemed_coupons.contains(&burn_id)
+    }
+
+    /// Id of the first block not pushed to an archive yet.
+    pub fn archived_blocks_end(&self) -> u64 {
+        self.archived_blocks.last().map_or(0, |range| range.end)
+    }
+
+    pub fn record_archived_blocks(
+        &mut self,
+        archive: Principal,
+        start: u64,
+        end: u64,
+    ) -> Result<(), StateTransitionError> {
+        if start < self.archived_blocks_end() || end <= start {
+            return Err(StateTransitionError::OverlappingArchivedBlocks { start, end });
+        }
+        match self.archived_blocks.last_mut() {
+            Some(last) if last.archive == archive && last.end == start => last.end = end,
+            _ => self.archived_blocks.push(ArchivedBlockRange {
+                archive,
+                start,
+                end,
+            }),
+        }
+        Ok(())
+    }
+
+    pub fn register_asset(&mut self, asset: BridgedAsset) {
+        self.assets.insert(asset.spl_mint.clone(), asset);
+    }
+
+    pub fn remove_asset(&mut self, spl_mint: &str) {
+        self.assets.remove(spl_mint);
+    }
+
+    pub fn asset(&self, spl_mint: &str) -> Option<&BridgedAsset> {
+        self.assets.get(spl_mint)
+    }
+
+    pub fn record_config_proposal(&mut self, proposal: ConfigProposal) {
+        self.next_proposal_id = self.next_proposal_id.max(proposal.id + 1);
+        self.config_proposals.insert(proposal.id, proposal);
+    }
+
+    /// Applies the change of the proposal and drops it. ECDSA key changes are
+    /// applied by their own rotation event.
+    pub fn execute_config_proposal(&mut self, id: u64) {
+        let proposal = match self.config_proposals.remove(&id) {
+            Some(proposal) => proposal,
+            None => return,
+        };
+        match proposal.change {
+            ConfigChange::SolanaContractAddress(address) => {
+                self.solana_contract_address = address;
+            }
+            ConfigChange::SolanaRpcUrl(url) => {
+                self.solana_rpc_url = url;
+            }
+            ConfigChange::EcdsaKeyName { .. } => {}
+        }
+    }
+
+    pub fn subscribe(&mut self, subscription: Subscription) {
+        self.subscriptions
+            .insert(subscription.subscriber, subscription);
+    }
+
+    pub fn unsubscribe(&mut self, subscriber: &Principal) {
+        self.subscriptions.remove(subscriber);
+        self.delivery_failures.remove(subscriber);
+        self.pending_deliveries.remove(subscriber);
+    }
+
+    pub fn record_delivery(&mut self, subscriber: &Principal, cursor: u64) {
+        if let Some(subscription) = self.subscriptions.get_mut(subscriber) {
+            subscription.cursor = cursor;
+        }
+    }
+
+    pub fn pause(&mut self, reason: &str) {
+        self.paused = Some(reason.to_string());
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = None;
+    }
+
+    /// Whether no timer task, withdrawal or coupon signing is in flight.
+    pub fn is_idle(&self) -> bool {
+        self.active_tasks.is_empty()
+            && self.withdrawing_principals.is_empty()
+            && self.processing_burn_ids.is_empty()
+            && self.pending_deliveries.is_empty()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    pub fn is_within_deposit_cap(&self, amount: &Nat) -> bool {
+        match &self.max_deposit_amount {
+            Some(maximum) => amount.0 <= *maximum,
+            None => true,
+        }
+    }
+
+    pub fn grant_role(&mut self, principal: Principal, role: Role) {
+        self.roles.insert(principal, role);
+    }
+
+    pub fn revoke_role(&mut self, principal: &Principal) {
+        self.roles.remove(principal);
+    }
+
+    pub fn role_grants(&self) -> Vec<RoleGrant> {
+        self.roles
+            .iter()
+            .map(|(principal, role)| RoleGrant {
+                principal: *principal,
+                role: *role,
+            })
+            .collect()
+    }
+
+    /// Whether the principal was granted `role` or a role including it.
+    pub fn has_role(&self, principal: &Principal, role: Role) -> bool {
+        self.roles
+            .get(principal)
+            .is_some_and(|granted| *granted >= role)
+    }
+
+    /// Whether a withdrawal of the asset, native SOL if `spl_mint` is None, was
+    /// already issued for the burn block.
+    pub fn is_burn_block_used(&self, spl_mint: Option<&str>, block_index: u64) -> bool {
+        match spl_mint {
+            Some(mint) => self
+                .used_asset_burn_blocks
+                .contains(&(mint.to_string(), block_index)),
+            None => self.used_burn_block_indices.contains(&block_index),
+        }
+    }
+
+    pub fn archive_invalid_events(&mut self, signatures: &[String]) {
+        for key in signatures {
+            if let Some(sig) = self.invalid_events.remove(key) {
+                self.pruned_signatures.insert(key.clone());
+                self.archived_invalid_events
+                    .insert(self.archived_invalid_events.len(), sig);
             }
-            None => panic!("Attempted to remove NON existing withdrawal burned event."),
         }
     }
 
+    /// Returns the oldest minted and redeemed events exceeding the configured retention.
+    pub fn terminal_events_to_prune(&self) -> (Vec<String>, Vec<u64>) {
+        let retention = match self.terminal_events_retention {
+            Some(retention) => retention as usize,
+            None => return (vec![], vec![]),
+        };
+
+        // the events stay in stable memory, the index of the minted deposit ids orders them
+        let minted = self
+            .minted_deposit_ids
+            .values()
+            .take(self.minted_deposit_ids.len().saturating_sub(retention))
+            .cloned()
+            .collect();
+
+        // keys are iterated in ascending order
+        let excess = (self.withdrawal_redeemed_events.len() as usize).saturating_sub(retention);
+        let redeemed = self
+            .withdrawal_redeemed_events
+            .with_iter(|iter| iter.take(excess).map(|(burn_id, _)| burn_id).collect());
+
+        (minted, redeemed)
+    }
+
+    pub fn prune_terminal_events(&mut self, minted: &[String], redeemed: &[u64]) {
+        for key in minted {
+            // events minted before the last checkpoint are already gone when replayed
+            self.pruned_signatures.insert(key.clone());
+            if let Some(event) = self.minted_events.remove(key) {
+                remove_from_index(&mut self.deposits_by_principal, &event.to_icp_address, key);
+                self.minted_deposit_ids.remove(&event.id);
+            }
+        }
+        for burn_id in redeemed {
+            if let Some(event) = self.withdrawal_redeemed_events.remove(burn_id) {
+                self.remove_withdrawal_from_indices(&event);
+            }
+            self.solana_redeemed_coupons.remove(burn_id);
+        }
+    }
+
+    // keeps the burn block of a withdrawal from backing another one
+    fn record_used_burn_block(&mut self, event: &WithdrawalEvent) {
+        match (event.get_icp_burn_block_index(), &event.spl_mint) {
+            (Some(block_index), Some(mint)) => {
+                self.used_asset_burn_blocks
+                    .insert((mint.clone(), block_index));
+            }
+            (Some(block_index), None) => {
+                self.used_burn_block_indices.insert(block_index);
+            }
+            (None, _) => {}
+        }
+    }
+
+    fn remove_withdrawal_from_indices(&mut self, event: &WithdrawalEvent) {
+        let burn_id = event.get_burn_id();
+        remove_from_index(
+            &mut self.withdrawals_by_principal,
+            &event.from_icp_address,
+            &burn_id,
+        );
+        remove_from_index(
+            &mut self.withdrawals_by_sol_address,
+            &event.to_sol_address.to_string(),
+            &burn_id,
+        );
+    }
+
+    /// Burned withdrawals whose coupon the timer signs at `now`, at most `capacity` of them in
+    /// burn id order. Withdrawals being signed, held for approval, time-locked or revoked are
+    /// left out, and so are those not due for a retry.
+    pub fn coupons_to_sign(&self, now: u64, capacity: usize) -> Vec<WithdrawalEvent> {
+        let mut pending: Vec<_> = self
+            .withdrawal_burned_events
+            .values()
+            .filter(|e| {
+                !self.processing_burn_ids.contains(&e.get_burn_id())
+                    && !self.withdrawal_approvals.contains_key(&e.get_burn_id())
+                    && !self.is_time_locked(e.get_burn_id(), now)
+                    && !self.is_revoked_coupon(e.get_burn_id())
+                    && self.is_retry_due(&RetryTarget::Withdrawal(e.get_burn_id()), &e.retry, now)
+            })
+            .cloned()
+            .collect();
+        pending.sort_by_key(|e| e.get_burn_id());
+        pending.truncate(capacity);
+        pending
+    }
+
+    /// Whether the retry policy allows attempting the entry at `now`.
+    pub fn is_retry_due(&self, target: &RetryTarget, retry: &Retriable, now: u64) -> bool {
+        self.retry_policy
+            .should_retry(retry, self.last_attempts.get(target).copied(), now)
+    }
+
+    /// Whether the transaction of a scraped signature may be looked up at `now`, a lookup
+    /// answered with null backs off on its own counter.
+    pub fn is_signature_due(&self, sig: &SolanaSignature, now: u64) -> bool {
+        let target = RetryTarget::Signature(sig.sol_sig.clone());
+        let last_attempt = self.last_attempts.get(&target).copied();
+        self.retry_policy
+            .should_retry(&sig.retry, last_attempt, now)
+            && self
+                .retry_policy
+                .is_backoff_elapsed(sig.get_not_found_lookups(), last_attempt, now)
+    }
+
+    pub fn record_attempt(&mut self, target: RetryTarget, now: u64) {
+        self.last_attempts.insert(target, now);
+    }
+
+    /// Counts a call of the principal in its current window, returning the seconds
+    /// left in the window if the rate limit is exhausted.
+    pub fn consume_call(&mut self, principal: Principal, now: u64) -> Result<(), u64> {
+        let Some(limit) = &self.rate_limit else {
+            return Ok(());
+        };
+        let window = limit.window_secs.saturating_mul(1_000_000_000);
+        // windows that ended cannot limit anymore
+        self.call_windows
+            .retain(|_, w| now < w.start.saturating_add(window));
+
+        let entry = self.call_windows.entry(principal).or_insert(CallWindow {
+            start: now,
+            calls: 0,
+        });
+        if entry.calls >= limit.max_calls {
+            let end = entry.start.saturating_add(window);
+            return Err((end - now).div_ceil(1_000_000_000));
+        }
+        entry.calls += 1;
+        Ok(())
+    }
+
+    /// Drops the attempt times of entries that left their backlog.
+    pub fn prune_attempts(&mut self) {
+        let Self {
+            last_attempts,
+            solana_signature_ranges,
+            solana_signatures,
+            accepted_events,
+            withdrawal_burned_events,
+            ..
+        } = self;
+        last_attempts.retain(|target, _| match target {
+            RetryTarget::SignatureRange(key) => solana_signature_ranges.contains_key(key),
+            RetryTarget::Signature(key) => solana_signatures.contains_key(key),
+            RetryTarget::Deposit(key) => accepted_events.contains_key(key),
+            RetryTarget::Withdrawal(burn_id) => withdrawal_burned_events.contains_key(burn_id),
+        });
+    }
+
+    /// Rebuilds the per-principal indexes from the deposit and withdrawal maps. The burn
+    /// blocks of the withdrawals are marked as used, checkpoints taken before every burn
+    /// block was tracked only hold those of the pruned withdrawals.
+    pub fn rebuild_principal_indexes(&mut self) {
+        self.deposits_by_principal.clear();
+        for event in self
+            .accepted_events
+            .values()
+            .cloned()
+            .chain(self.dead_letters.values().map(|d| d.deposit.clone()))
+            .chain(self.minted_events.values())
+        {
+            self.deposits_by_principal
+                .entry(event.to_icp_address)
+                .or_default()
+                .insert(event.sol_sig);
+        }
+        self.minted_deposit_ids = self
+            .minted_events
+            .with_iter(|iter| iter.map(|(sol_sig, event)| (event.id, sol_sig)).collect());
+
+        self.withdrawals_by_principal.clear();
+        self.withdrawals_by_sol_address.clear();
+        let withdrawals: Vec<WithdrawalEvent> = self
+            .withdrawal_burned_events
+            .values()
+            .cloned()
+            .chain(self.withdrawal_redeemed_events.values())
+            .collect();
+        for event in withdrawals {
+            self.record_used_burn_block(&event);
+            self.withdrawals_by_principal
+                .entry(event.from_icp_address)
+                .or_default()
+                .insert(event.get_burn_id());
+            self.withdrawals_by_sol_address
+                .entry(event.to_sol_address.to_string())
+                .or_default()
+                .insert(event.get_burn_id());
+        }
+    }
+
+    /// Returns at most `limit` minted deposits whose id follows `cursor`, in deposit id order.
+    pub fn minted_deposits_after(&self, cursor: Option<u64>, limit: usize) -> Vec<DepositEvent> {
+        let start = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+        self.minted_deposit_ids
+            .range((start, Bound::Unbounded))
+            .take(limit)
+            .filter_map(|(_, sol_sig)| self.minted_events.get(sol_sig))
+            .collect()
+    }
+
+    /// Returns the accepted, dead-lettered and minted deposits of the principal.
+    pub fn deposits_of(&self, principal: &Principal) -> Vec<DepositEvent> {
+        self.deposits_by_principal
+            .get(principal)
+            .into_iter()
+            .flatten()
+            .filter_map(|sig| {
+                self.accepted_events
+                    .get(sig)
+                    .or_else(|| self.dead_letters.get(sig).map(|d| &d.deposit))
+                    .cloned()
+                    .or_else(|| self.minted_events.get(sig))
+            })
+            .collect()
+    }
+
+    /// Returns the first `limit` unredeemed coupons issued for withdrawals to the Solana
+    /// address, in burn id order. Withdrawals whose coupon was not generated yet, was revoked
+    /// or was reported as redeemed on Solana are skipped.
+    pub fn coupons_for_address(&self, address: &str, limit: usize) -> Vec<Coupon> {
+        self.withdrawals_by_sol_address
+            .get(address)
+            .into_iter()
+            .flatten()
+            .filter(|burn_id| {
+                !self.is_revoked_coupon(**burn_id) && !self.is_solana_redeemed_coupon(**burn_id)
+            })
+            .filter_map(|burn_id| self.withdrawal_redeemed_events.get(burn_id))
+            .filter_map(|event| event.get_coupon().cloned())
+            .take(limit)
+            .collect()
+    }
+
+    /// Returns the burned and redeemed withdrawals of the principal.
+    pub fn withdrawals_of(&self, principal: &Principal) -> Vec<WithdrawalEvent> {
+        self.withdrawals_by_principal
+            .get(principal)
+            .into_iter()
+            .flatten()
+            .filter_map(|burn_id| {
+                self.withdrawal_burned_events
+                    .get(burn_id)
+                    .cloned()
+                    .or_else(|| self.withdrawal_redeemed_events.get(burn_id))
+            })
+            .collect()
+    }
+
+    pub fn record_ecdsa_sign_call(&mut self, cycles: u128) {
+        self.ecdsa_sign_calls += 1;
+        self.ecdsa_sign_cycles += cycles;
+    }
+
+    pub fn record_http_outcall(&mut self, cycles: u128) {
+        self.http_outcall_cycles += cycles;
+    }
+
+    pub fn record_rpc_outcome(&mut self, provider: &str, outcome: RpcOutcome, latency_nanos: u64) {
+        let stats = self
+            .rpc_provider_stats
+            .entry(provider.to_string())
+            .or_default();
+        match outcome {
+            RpcOutcome::Success => stats.successes += 1,
+            RpcOutcome::Failure => stats.failures += 1,
+            RpcOutcome::Timeout => stats.timeouts += 1,
+        }
+        stats.total_latency_nanos = stats.total_latency_nanos.saturating_add(latency_nanos);
+    }
+
+    pub fn record_task_outcall(&mut self, task: TaskType) {
+        if let Some(run) = self.task_runs.get_mut(&task) {
+            run.outcalls += 1;
+        }
+    }
+
+    /// Moves the current run of the task to the bounded trace buffer.
+    pub fn record_task_trace(&mut self, task: TaskType) {
+        let run = match self.task_runs.get(&task) {
+            Some(run) => run,
+            None => return,
+        };
+        if self.task_traces.len() >= MAX_TASK_TRACES {
+            self.task_traces.pop_front();
+        }
+        self.task_traces.push_back(TaskTrace {
+            task,
+            started_at: run.started_at,
+            finished_at: run.finished_at.unwrap_or(run.started_at),
+            items_processed: run.items_processed,
+            outcalls: run.outcalls,
+            error: run.last_error.clone(),
+        });
+    }
+
+    /// Cycles attached to HTTP outcalls and signature requests since the last upgrade.
+    pub fn outcall_cycles(&self) -> u128 {
+        self.http_outcall_cycles + self.ecdsa_sign_cycles
+    }
+
+    /// Whether the task is skipped because the cycles balance is low.
+    pub fn is_frozen(&self, task: TaskType) -> bool {
+        self.cycles_monitor.freeze_non_essential_tasks
+            && !task.is_essential()
+            && self
+                .last_cycles_report
+                .as_ref()
+                .is_some_and(|report| report.low_balance)
+    }
+
+    /// Returns the cached signature of the burn's coupon if it signed `message_hash` with `key_name`.
+    pub fn cached_signature(
+        &mut self,
+        burn_id: u64,
+        key_name: &str,
+        message_hash: &[u8],
+    ) -> Option<Vec<u8>> {
+        let cached = self
+            .signature_cache
+            .get(&burn_id)
+            .filter(|c| c.key_name == key_name && c.message_hash == message_hash)?
+            .signature
+            .clone();
+        self.signature_cache_hits += 1;
+        Some(cached)
+    }
+
+    /// Returns the cached cosignature of the burn's coupon if it signed `message_hash` with
+    /// `key_name`.
+    pub fn cached_cosignature(
+        &mut self,
+        burn_id: u64,
+        key_name: &str,
+        message_hash: &[u8],
+    ) -> Option<Vec<u8>> {
+        let cached = self
+            .signature_cache
+            .get(&burn_id)
+            .filter(|c| c.key_name == key_name && c.message_hash == message_hash)?
+            .cosignature
+            .clone()?;
+        self.signature_cache_hits += 1;
+        Some(cached)
+    }
+
     pub fn next_request_id(&mut self) -> u64 {
         let current_request_id = self.http_request_counter;
         // overflow is not an issue here because we only use `next_request_id` to correlate
@@ -376,6 +2148,15 @@ impl State {
     }
 }
 
+fn remove_from_index<I: Ord, K: Ord>(index: &mut BTreeMap<I, BTreeSet<K>>, owner: &I, key: &K) {
+    if let Some(keys) = index.get_mut(owner) {
+        keys.remove(key);
+        if keys.is_empty() {
+            index.remove(owner);
+        }
+    }
+}
+
 impl std::fmt::Display for State {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Format Solana config
@@ -393,6 +2174,7 @@ impl std::fmt::Display for State {
 
         // Format ICP config
         writeln!(f, "ECDSA Key Name: {}", self.ecdsa_key_name)?;
+        writeln!(f, "Previous ECDSA Key: {:?}", self.previous_ecdsa_key)?;
         if let Some(ecdsa_public_key) = &self.ecdsa_public_key {
             writeln!(f, "ECDSA Public Key: {:?}", ecdsa_public_key)?;
         }
@@ -402,6 +2184,10 @@ impl std::fmt::Display for State {
             self.minimum_withdrawal_amount
         )?;
 
+        if let Some(ledger_id) = &self.ledger_id {
+            writeln!(f, "Ledger ID: {}", ledger_id)?;
+        }
+
         // Format Scrapper config
         if let Some(solana_last_known_signature) = &self.solana_last_known_signature {
             writeln!(
@@ -419,6 +2205,32 @@ impl std::fmt::Display for State {
 
         // Format invalid events
         writeln!(f, "Invalid Events: {:?}", self.invalid_events)?;
+        writeln!(
+            f,
+            "Archived Invalid Events: {}",
+            self.archived_invalid_events.len()
+        )?;
+        writeln!(
+            f,
+            "Invalid Events Retention: {}s",
+            self.invalid_events_retention_secs
+        )?;
+        writeln!(
+            f,
+            "Destination Activation Delay: {}s",
+            self.destination_activation_delay_secs
+        )?;
+        writeln!(f, "Release Delay: {}s", self.release_delay_secs)?;
+        writeln!(
+            f,
+            "Time-Locked Withdrawals: {:?}",
+            self.time_locked_withdrawals
+        )?;
+        writeln!(
+            f,
+            "Cancelled Withdrawals: {:?}",
+            self.cancelled_withdrawals.keys()
+        )?;
         writeln!(f, "Accepted Events: {:?}", self.accepted_events)?;
         writeln!(f, "Minted Events: {:?}", self.minted_events)?;
 
@@ -440,14 +2252,88 @@ impl std::fmt::Display for State {
             "Withdrawing Principals: {:?}",
             self.withdrawing_principals
         )?;
+        writeln!(
+            f,
+            "Max Withdrawals Per Principal: {}",
+            self.max_withdrawals_per_principal
+        )?;
+        writeln!(f, "Mint Parallelism: {}", self.mint_parallelism)?;
+
+        writeln!(f, "Blocked Addresses: {:?}", self.blocked_addresses)?;
+        writeln!(f, "Revoked Coupons: {:?}", self.revoked_coupons)?;
+        writeln!(
+            f,
+            "Coupons Redeemed On Solana: {:?}",
+            self.solana_redeemed_coupons
+        )?;
+        writeln!(f, "Roles: {:?}", self.roles)?;
+        writeln!(f, "Paused: {:?}", self.paused)?;
+        writeln!(
+            f,
+            "Terminal Events Retention: {:?}",
+            self.terminal_events_retention
+        )?;
+        writeln!(
+            f,
+            "Terminal Events Max Age: {:?}",
+            self.terminal_events_max_age_secs
+        )?;
+
+        writeln!(f, "Total Minted: {}", self.total_minted)?;
+        writeln!(f, "Total Burned: {}", self.total_burned)?;
+        writeln!(f, "Total Fees: {}", self.total_fees)?;
+        writeln!(f, "Total Ledger Fees: {}", self.total_ledger_fees)?;
+        writeln!(f, "Solana Vault Address: {:?}", self.solana_vault_address)?;
+        writeln!(f, "Retry Policy: {:?}", self.retry_policy)?;
+        writeln!(f, "Rate Limit: {:?}", self.rate_limit)?;
+        writeln!(f, "Task Lock Timeout: {}s", self.task_lock_timeout_secs)?;
+        writeln!(f, "Proxy Token: {:?}", self.proxy_token)?;
+        writeln!(f, "Timer Intervals: {:?}", self.timer_intervals)?;
+        writeln!(
+            f,
+            "Checkpoint Interval: {} events or {}s",
+            self.checkpoint_interval_events, self.checkpoint_interval_secs
+        )?;
+        writeln!(f, "Event Log Max Bytes: {:?}", self.event_log_max_bytes)?;
+        writeln!(f, "Event Archive: {:?}", self.event_archive)?;
+        writeln!(f, "Archived Blocks: {:?}", self.archived_blocks)?;
+        writeln!(f, "Cycles Monitor: {:?}", self.cycles_monitor)?;
+        writeln!(f, "Cycles Top-up: {:?}", self.cycles_top_up)?;
+        writeln!(f, "Upgrade Audit: {:?}", self.upgrade_audit)?;
+        writeln!(f, "Log Level: {:?}", self.log_level)?;
+        writeln!(f, "Trusted Origins: {:?}", self.trusted_origins)?;
+        writeln!(f, "Bridged Assets: {:?}", self.assets.keys())?;
+        writeln!(f, "Subscribers: {:?}", self.subscriptions.keys())?;
+        writeln!(f, "Config Timelock: {}s", self.config_timelock_secs)?;
+        writeln!(f, "Config Proposals: {:?}", self.config_proposals.keys())?;
+        writeln!(f, "Commitment: {:?}", self.commitment)?;
+        writeln!(f, "Max Deposit Amount: {:?}", self.max_deposit_amount)?;
+        writeln!(f, "Max Withdrawal Amount: {:?}", self.max_withdrawal_amount)?;
+        writeln!(
+            f,
+            "Dual Signature Threshold: {:?}",
+            self.dual_signature_threshold
+        )?;
+        writeln!(f, "Approval Threshold: {:?}", self.approval_threshold)?;
+        writeln!(
+            f,
+            "Release Delay Threshold: {:?}",
+            self.release_delay_threshold
+        )?;
+        writeln!(
+            f,
+            "Reconciliation Tolerance: {}",
+            self.reconciliation_tolerance
+        )?;
 
         // Format counters
         writeln!(f, "Deposit ID Counter: {}", self.deposit_id_counter)?;
         writeln!(f, "Burn ID Counter: {}", self.burn_id_counter)?;
         writeln!(f, "HTTP Request Counter: {}", self.http_request_counter)?;
+        writeln!(f, "RPC Provider Stats: {:?}", self.rpc_provider_stats)?;
 
         // Format active tasks
-        writeln!(f, "Active Tasks: {:?}", self.active_tasks)
+        writeln!(f, "Active Tasks: {:?}", self.active_tasks.keys())
     }
 }
 
@@ -480,13 +2366,18 @@ pub async fn lazy_call_ecdsa_public_key() -> ic_crypto_ecdsa_secp256k1::PublicKe
         )
     }
 
-    if let Some(ecdsa_pk_response) = read_state(|s| s.ecdsa_public_key.clone()) {
+    // keys fetched before coupons were cosigned lack the cosigner key
+    if let Some(ecdsa_pk_response) = read_state(|s| {
+        s.ecdsa_public_key
+            .clone()
+            .filter(|_| s.cosigner_public_key_hex.is_some())
+    }) {
         return to_public_key(&ecdsa_pk_response);
     }
 
     let key_name = read_state(|s| s.ecdsa_key_name.clone());
 
-    ic_canister_log::log!(
+    crate::logs::log!(
         crate::logs::DEBUG,
         "\nFetching the ECDSA public key {key_name}"
     );
@@ -510,14 +2401,122 @@ pub async fn lazy_call_ecdsa_public_key() -> ic_crypto_ecdsa_secp256k1::PublicKe
         ))
     });
 
-    mutate_state(|s| s.ecdsa_public_key = Some(response.clone()));
-
-    let proxy_public_key = escda::get_proxy_token_public_key(&key_name).await.unwrap();
-    mutate_state(|s| s.ecdsa_proxy_public_key = Some(proxy_public_key));
+    let proxy_public_key = escda::get_proxy_token_public_key(&key_name)
+        .await
+        .unwrap_or_else(|e| ic_cdk::trap(&EcdsaKeyError::FetchFailed(e).to_string()));
+    let cosigner_public_key_hex = fetch_cosigner_public_key(&key_name)
+        .await
+        .unwrap_or_else(|e| ic_cdk::trap(&e.to_string()));
+    record_fetched_ecdsa_key(
+        key_name,
+        &response,
+        proxy_public_key,
+        cosigner_public_key_hex,
+    );
 
     to_public_key(&response)
 }
 
+async fn fetch_cosigner_public_key(key_name: &str) -> Result<String, EcdsaKeyError> {
+    let response = escda::public_key_with(key_name, get_cosigner_derivation_path())
+        .await
+        .map_err(EcdsaKeyError::FetchFailed)?;
+    uncompressed_hex(&response.public_key)
+}
+
+fn uncompressed_hex(compressed_public_key: &[u8]) -> Result<String, EcdsaKeyError> {
+    use libsecp256k1::{PublicKey, PublicKeyFormat};
+
+    PublicKey::parse_slice(compressed_public_key, Some(PublicKeyFormat::Compressed))
+        .map(|pk| hex::encode(pk.serialize()))
+        .map_err(|_| EcdsaKeyError::InvalidPublicKey)
+}
+
+/// Switches coupon signing to the ECDSA key `key_name`. Coupons signed by the current
+/// key remain valid for `grace_period_secs` and are re-signed when requested again.
+pub async fn rotate_ecdsa_key(
+    key_name: String,
+    grace_period_secs: u64,
+) -> Result<(), EcdsaKeyError> {
+    if key_name.trim().is_empty() {
+        return Err(EcdsaKeyError::BlankKeyName);
+    }
+    let previous_key_name = read_state(|s| s.ecdsa_key_name.clone());
+    if key_name == previous_key_name {
+        return Err(EcdsaKeyError::AlreadyCurrentKey(key_name));
+    }
+
+    lazy_call_ecdsa_public_key().await;
+    let previous = PreviousEcdsaKey {
+        key_name: previous_key_name,
+        public_key_hex: read_state(|s| s.uncompressed_public_key())?,
+        valid_until: ic_cdk::api::time()
+            .saturating_add(grace_period_secs.saturating_mul(1_000_000_000)),
+        cosigner_public_key_hex: read_state(|s| s.cosigner_public_key_hex.clone()),
+    };
+
+    // fails if the key does not exist, before anything is recorded
+    let response = escda::public_key_with(
+        &key_name,
+        get_derivation_path()
+            .into_iter()
+            .map(|x| x.to_vec())
+            .collect(),
+    )
+    .await
+    .map_err(EcdsaKeyError::FetchFailed)?;
+    let proxy_public_key = escda::get_proxy_token_public_key(&key_name)
+        .await
+        .map_err(EcdsaKeyError::FetchFailed)?;
+    let cosigner_public_key_hex = fetch_cosigner_public_key(&key_name).await?;
+
+    crate::logs::log!(
+        crate::logs::INFO,
+        "\nRotating the ECDSA key from {} to {key_name}",
+        previous.key_name
+    );
+
+    mutate_state(|s| {
+        audit::process_event(
+            s,
+            event::EventType::EcdsaKeyRotated {
+                key_name: key_name.clone(),
+                previous,
+            },
+        )
+    });
+    record_fetched_ecdsa_key(
+        key_name,
+        &response,
+        proxy_public_key,
+        cosigner_public_key_hex,
+    );
+
+    Ok(())
+}
+
+fn record_fetched_ecdsa_key(
+    key_name: String,
+    response: &EcdsaPublicKeyResponse,
+    proxy_public_key: String,
+    cosigner_public_key_hex: String,
+) {
+    mutate_state(|s| {
+        audit::process_event(
+            s,
+            event::EventType::EcdsaPublicKeyFetched {
+                key_name,
+                key: FetchedEcdsaKey {
+                    public_key: response.public_key.clone(),
+                    chain_code: response.chain_code.clone(),
+                    proxy_public_key,
+                    cosigner_public_key_hex: Some(cosigner_public_key_hex),
+                },
+            },
+        )
+    });
+}
+
 fn range_key(start: &String, end: &String) -> String {
     return format!("{}-{}", start, end);
 }
@@ -1,6 +1,17 @@
 pub use super::event::{Event, EventType};
-use super::State;
-use crate::storage::{record_event, with_event_iter};
+use super::{
+    mutate_state, read_state, snapshot::StateSnapshot, State, StateTransitionError, TaskType,
+};
+use crate::certification::update_certified_coupons_of;
+use crate::constants::{MAX_INVALID_EVENTS_IN_MEMORY, MAX_TERMINAL_EVENTS_PER_PRUNE};
+use crate::guard::TimerGuard;
+use crate::logs::INFO;
+use crate::storage::{
+    compact_events, event_log_size_bytes, is_compacting, last_checkpoint_block, record_event,
+    total_event_count, with_event_iter,
+};
+use std::collections::BTreeSet;
+use std::time::Duration;
 
 /// Updates the state to reflect the given state transition.
 // public because it's used in tests since process_event
@@ -19,7 +30,7 @@ pub fn apply_state_transition(state: &mut State, payload: &EventType) {
             state.record_solana_last_known_signature(signature);
         }
         EventType::NewSolanaSignatureRange(range) => {
-            state.record_solana_signature_range(range.clone());
+            expect_transition(state.record_solana_signature_range(range.clone()));
         }
         EventType::LastDepositIdCounter(id) => {
             state.set_deposit_id_counter(id);
@@ -28,14 +39,16 @@ pub fn apply_state_transition(state: &mut State, payload: &EventType) {
             state.set_burn_id_counter(id);
         }
         EventType::RemoveSolanaSignatureRange(range) => {
-            state.remove_solana_signature_range(range);
+            expect_transition(state.remove_solana_signature_range(range));
         }
         EventType::RetrySolanaSignatureRange {
             range,
             failed_sub_range,
             fail_reason: _,
         } => {
-            state.retry_solana_signature_range(range.clone(), failed_sub_range.clone());
+            expect_transition(
+                state.retry_solana_signature_range(range.clone(), failed_sub_range.clone()),
+            );
         }
         EventType::SolanaSignature {
             signature,
@@ -44,19 +57,18 @@ pub fn apply_state_transition(state: &mut State, payload: &EventType) {
             state.record_or_retry_solana_signature(signature.clone());
         }
         EventType::InvalidEvent {
-            signature,
-            fail_reason: _,
+            signature, reason, ..
         } => {
-            state.record_invalid_event(signature.clone());
+            expect_transition(state.record_invalid_event(signature.clone(), reason.clone()));
         }
         EventType::AcceptedEvent {
             event_source,
             fail_reason: _,
         } => {
-            state.record_or_retry_accepted_event(event_source.clone());
+            expect_transition(state.record_or_retry_accepted_event(event_source.clone()));
         }
         EventType::MintedEvent { event_source } => {
-            state.record_minted_event(event_source.clone());
+            expect_transition(state.record_minted_event(event_source.clone()));
         }
         EventType::WithdrawalBurnedEvent {
             event_source,
@@ -65,8 +77,180 @@ pub fn apply_state_transition(state: &mut State, payload: &EventType) {
             state.record_or_retry_withdrawal_burned_event(event_source.clone());
         }
         EventType::WithdrawalRedeemedEvent { event_source } => {
-            state.record_withdrawal_redeemed_event(event_source.clone());
+            expect_transition(state.record_withdrawal_redeemed_event(event_source.clone()));
+        }
+        EventType::AddBlockedAddress(address) => {
+            state.record_blocked_address(address);
+        }
+        EventType::RemoveBlockedAddress(address) => {
+            state.remove_blocked_address(address);
+        }
+        EventType::WithdrawalRejected { .. } => {
+            // audit only, rejected withdrawals do not change the state
+        }
+        EventType::Checkpoint(snapshot) => {
+            *state = State::from(snapshot.clone());
+            crate::logs::set_log_level(state.log_level);
+        }
+        EventType::PruneTerminalEvents { minted, redeemed } => {
+            state.prune_terminal_events(minted, redeemed);
+        }
+        EventType::SupplyDriftDetected { .. } => {
+            // audit only, the drift is resolved by the controllers
+        }
+        EventType::InvariantViolated { .. } => {
+            // audit only, the violation is resolved by the controllers
+        }
+        EventType::SolanaTransactionNotFound { sol_sig } => {
+            expect_transition(state.record_transaction_not_found(sol_sig));
+        }
+        EventType::DepositDeadLettered { sol_sig, error } => {
+            expect_transition(state.record_dead_letter(sol_sig, error));
+        }
+        EventType::DeadLetterRequeued { sol_sig, .. } => {
+            expect_transition(state.requeue_dead_letter(sol_sig));
+        }
+        EventType::WithdrawalHeldForApproval { burn_id } => {
+            expect_transition(state.hold_withdrawal_for_approval(*burn_id));
+        }
+        EventType::WithdrawalApproved { burn_id, .. } => {
+            expect_transition(state.approve_withdrawal(*burn_id));
+        }
+        EventType::WithdrawalApprovalRejected {
+            burn_id, reason, ..
+        } => {
+            expect_transition(state.reject_withdrawal(*burn_id, reason));
+        }
+        EventType::DestinationAddressAdded { owner, destination } => {
+            state.record_destination_address(*owner, destination);
+        }
+        EventType::DestinationAddressRemoved { owner, address } => {
+            state.remove_destination_address(owner, address);
+        }
+        EventType::WithdrawalTimeLocked {
+            burn_id,
+            release_at,
+        } => {
+            expect_transition(state.time_lock_withdrawal(*burn_id, *release_at));
+        }
+        EventType::WithdrawalCancelled {
+            burn_id,
+            cancelled_at,
+            ..
+        } => {
+            expect_transition(state.cancel_withdrawal(*burn_id, *cancelled_at));
+        }
+        EventType::CancelledWithdrawalReminted { burn_id, .. } => {
+            expect_transition(state.record_withdrawal_reminted(*burn_id));
+        }
+        EventType::RemintAttempted {
+            burn_id,
+            created_at,
+        } => {
+            expect_transition(state.record_remint_attempt(*burn_id, *created_at));
+        }
+        EventType::RemintFailed { burn_id, .. } => {
+            expect_transition(state.record_remint_failure(*burn_id));
+        }
+        EventType::CouponRevoked { burn_id, .. } => {
+            state.revoked_coupons.insert(*burn_id);
+        }
+        EventType::CouponRevocationLifted { burn_id, .. } => {
+            state.revoked_coupons.remove(burn_id);
+        }
+        EventType::CouponRedemptionReported { burn_id, .. } => {
+            state.solana_redeemed_coupons.insert(*burn_id);
+        }
+        EventType::EventsArchived {
+            archive,
+            start,
+            end,
+        } => {
+            expect_transition(state.record_archived_blocks(*archive, *start, *end));
+        }
+        EventType::SignatureGapDetected { .. } => {
+            // audit only, the gap is rescanned by the controllers
+        }
+        EventType::LowCyclesBalance { .. }
+        | EventType::CyclesToppedUp { .. }
+        | EventType::StateDigest { .. } => {
+            // audit only
+        }
+        EventType::LogLevelChanged { level } => {
+            state.log_level = *level;
+            crate::logs::set_log_level(*level);
+        }
+        EventType::AssetRegistered(asset) => {
+            state.register_asset(asset.clone());
+        }
+        EventType::AssetRemoved { spl_mint } => {
+            state.remove_asset(spl_mint);
+        }
+        EventType::Subscribed(subscription) => {
+            state.subscribe(subscription.clone());
+        }
+        EventType::Unsubscribed { subscriber, .. } => {
+            state.unsubscribe(subscriber);
+        }
+        EventType::EventsDelivered { subscriber, cursor } => {
+            state.record_delivery(subscriber, *cursor);
+        }
+        EventType::ConfigChangeProposed(proposal) => {
+            state.record_config_proposal(proposal.clone());
+        }
+        EventType::ConfigChangeExecuted { id, .. } => {
+            state.execute_config_proposal(*id);
+        }
+        EventType::ConfigChangeCancelled { id, .. } => {
+            state.config_proposals.remove(id);
+        }
+        EventType::SolanaSignatureRangeAdvanced {
+            range,
+            before_sol_sig,
+        } => {
+            expect_transition(state.advance_solana_signature_range(range, before_sol_sig));
+        }
+        EventType::ArchiveInvalidEvents { signatures } => {
+            state.archive_invalid_events(signatures);
+        }
+        EventType::LastKnownSolanaSignatureOverridden { signature, .. } => {
+            state.record_solana_last_known_signature(signature);
+        }
+        EventType::SolanaSignatureRangeRescanRequested { range, .. } => {
+            expect_transition(state.record_solana_signature_range(range.clone()));
         }
+        EventType::GrantRole(grant) => {
+            state.grant_role(grant.principal, grant.role);
+        }
+        EventType::RevokeRole(principal) => {
+            state.revoke_role(principal);
+        }
+        EventType::Paused { reason } => {
+            state.pause(reason);
+        }
+        EventType::Resumed { .. } => {
+            state.resume();
+        }
+        EventType::EcdsaKeyRotated { key_name, previous } => {
+            state.rotate_ecdsa_key(key_name, previous.clone());
+        }
+        EventType::CouponResigned { event_source } => {
+            expect_transition(state.record_resigned_coupon(event_source.clone()));
+        }
+        EventType::EcdsaPublicKeyFetched { key_name, key } => {
+            state.record_fetched_ecdsa_key(key_name, key);
+        }
+        EventType::TimerIntervalsChanged { intervals } => {
+            state.timer_intervals = intervals.clone();
+        }
+    }
+}
+
+// A rejected transition means that the event does not match the state, the call
+// traps so that the event is never recorded.
+fn expect_transition(result: Result<(), StateTransitionError>) {
+    if let Err(e) = result {
+        panic!("{e}");
     }
 }
 
@@ -76,13 +260,224 @@ pub fn process_event(state: &mut State, payload: EventType) {
     record_event(payload);
 }
 
+/// Records a snapshot of the current state. Compacting the log then drops all events
+/// preceding it, bounding the number of events replayed at upgrade.
+fn record_checkpoint() {
+    let snapshot = read_state(StateSnapshot::from);
+    record_event(EventType::Checkpoint(snapshot));
+}
+
+// the copy of a large log is spread over several messages, each resuming it right away
+fn compact() {
+    match compact_events() {
+        Some(dropped) => crate::logs::log!(
+            INFO,
+            "[checkpoint]: compacted the event log, dropped {dropped} events"
+        ),
+        None => {
+            ic_cdk_timers::set_timer(
+                Duration::from_secs(0),
+                || ic_cdk::spawn(checkpoint_if_due()),
+            );
+        }
+    }
+}
+
+// true once the configured number of events or seconds followed the first event of the
+// log, which is the previous checkpoint once the log was compacted, or once the log
+// uses the configured number of bytes
+fn is_checkpoint_due() -> bool {
+    let (interval_events, interval_secs, max_bytes) = read_state(|s| {
+        (
+            s.checkpoint_interval_events,
+            s.checkpoint_interval_secs,
+            s.event_log_max_bytes,
+        )
+    });
+    let first_timestamp =
+        with_event_iter(|mut iter| iter.next().map(|event| event.timestamp)).unwrap_or_default();
+    let elapsed = ic_cdk::api::time().saturating_sub(first_timestamp);
+
+    total_event_count().saturating_sub(1) >= interval_events
+        || elapsed >= interval_secs.saturating_mul(1_000_000_000)
+        // a log holding only the checkpoint cannot shrink
+        || (total_event_count() > 1
+            && max_bytes.is_some_and(|max_bytes| event_log_size_bytes() >= max_bytes))
+}
+
+/// Records a checkpoint when one is due and compacts the log. With an event archive,
+/// the events preceding the checkpoint are pushed to the archive first, and no new
+/// checkpoint is recorded until they are. No checkpoint is recorded either while a
+/// compaction is in progress, it is resumed instead.
+pub async fn checkpoint_if_due() {
+    let _guard = match TimerGuard::new(TaskType::CheckpointState) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    if is_compacting() {
+        compact();
+        return;
+    }
+
+    match read_state(|s| s.event_archive) {
+        None => {
+            if is_checkpoint_due() {
+                record_checkpoint();
+                compact();
+            }
+        }
+        Some(archive) => {
+            if last_checkpoint_block().is_none() && is_checkpoint_due() {
+                record_checkpoint();
+            }
+            if crate::archive::archive_blocks(archive).await {
+                compact();
+            }
+        }
+    }
+}
+
+/// Drops the minted and redeemed events exceeding the configured retention, or older than
+/// the configured max age, from the state, at most `MAX_TERMINAL_EVENTS_PER_PRUNE` per run
+/// so that a large backlog is pruned over several runs. The events remain available in the
+/// event log until it is compacted, and in the event archive once pushed to it. Invalid events
+/// exceeding their retention are moved to stable memory.
+pub fn prune_terminal_events() {
+    let _guard = match TimerGuard::new(TaskType::PruneTerminalEvents) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    let signatures = invalid_events_to_archive(ic_cdk::api::time());
+    if !signatures.is_empty() {
+        crate::logs::log!(
+            INFO,
+            "[retention]: archiving {} invalid events",
+            signatures.len()
+        );
+        mutate_state(|s| process_event(s, EventType::ArchiveInvalidEvents { signatures }));
+    }
+
+    let (minted, redeemed) = read_state(State::terminal_events_to_prune);
+    let (expired_minted, expired_redeemed) = expired_terminal_events(ic_cdk::api::time());
+    let minted: Vec<String> = minted
+        .into_iter()
+        .chain(expired_minted)
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .take(MAX_TERMINAL_EVENTS_PER_PRUNE)
+        .collect();
+    let redeemed: Vec<u64> = redeemed
+        .into_iter()
+        .chain(expired_redeemed)
+        .collect::<BTreeSet<u64>>()
+        .into_iter()
+        .take(MAX_TERMINAL_EVENTS_PER_PRUNE - minted.len())
+        .collect();
+    if minted.is_empty() && redeemed.is_empty() {
+        return;
+    }
+
+    crate::logs::log!(
+        INFO,
+        "[retention]: pruning {} minted and {} redeemed events",
+        minted.len(),
+        redeemed.len()
+    );
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::PruneTerminalEvents {
+                minted,
+                redeemed: redeemed.clone(),
+            },
+        )
+    });
+
+    update_certified_coupons_of(&redeemed);
+}
+
+// minted and redeemed events recorded before the configured max age, at most
+// MAX_TERMINAL_EVENTS_PER_PRUNE of each. Events recorded before their time was tracked count
+// as recorded at the first event of the log.
+fn expired_terminal_events(now: u64) -> (Vec<String>, Vec<u64>) {
+    let Some(max_age_secs) = read_state(|s| s.terminal_events_max_age_secs) else {
+        return (vec![], vec![]);
+    };
+    let cutoff = now.saturating_sub(max_age_secs.saturating_mul(1_000_000_000));
+    let first_timestamp =
+        with_event_iter(|mut iter| iter.next().map(|event| event.timestamp)).unwrap_or_default();
+    let is_expired = |recorded_at: Option<u64>| recorded_at.unwrap_or(first_timestamp) < cutoff;
+
+    read_state(|s| {
+        (
+            s.minted_events.with_iter(|iter| {
+                iter.filter(|(_, event)| is_expired(event.get_minted_at()))
+                    .map(|(sig, _)| sig)
+                    .take(MAX_TERMINAL_EVENTS_PER_PRUNE)
+                    .collect()
+            }),
+            s.withdrawal_redeemed_events.with_iter(|iter| {
+                iter.filter(|(_, event)| is_expired(event.get_redeemed_at()))
+                    .map(|(burn_id, _)| burn_id)
+                    .take(MAX_TERMINAL_EVENTS_PER_PRUNE)
+                    .collect()
+            }),
+        )
+    })
+}
+
+/// Returns the invalid events recorded before the retention window, and the oldest ones
+/// exceeding `MAX_INVALID_EVENTS_IN_MEMORY`, oldest first. Events invalidated before their
+/// time was tracked count as recorded at the first event of the log.
+fn invalid_events_to_archive(now: u64) -> Vec<String> {
+    let retention_secs = read_state(|s| s.invalid_events_retention_secs);
+    let cutoff = now.saturating_sub(retention_secs.saturating_mul(1_000_000_000));
+    let first_timestamp =
+        with_event_iter(|mut iter| iter.next().map(|event| event.timestamp)).unwrap_or_default();
+
+    let mut by_age: Vec<(u64, String)> = read_state(|s| {
+        s.invalid_events
+            .values()
+            .map(|sig| {
+                (
+                    sig.get_invalidated_at().unwrap_or(first_timestamp),
+                    sig.sol_sig.clone(),
+                )
+            })
+            .collect()
+    });
+    by_age.sort();
+
+    let excess = by_age.len().saturating_sub(MAX_INVALID_EVENTS_IN_MEMORY);
+    by_age
+        .into_iter()
+        .enumerate()
+        .filter(|(index, (timestamp, _))| *index < excess || *timestamp < cutoff)
+        .map(|(_, (_, sig))| sig)
+        .collect()
+}
+
+/// Pauses minting and withdrawals until a controller resumes the bridge. Does
+/// nothing if the bridge is already paused.
+pub fn trip_circuit_breaker(reason: String) {
+    mutate_state(|s| {
+        if s.is_paused() {
+            return;
+        }
+        crate::logs::log!(INFO, "[circuit breaker]: pausing the bridge: {reason}");
+        process_event(s, EventType::Paused { reason });
+    });
+}
+
 /// Recomputes the minter state from the event log.
 ///
 /// # Panics
 ///
 /// This function panics if:
 ///   * The event log is empty.
-///   * The first event in the log is not an Init event.
+///   * The first event in the log is neither an Init nor a Checkpoint event.
 ///   * One of the events in the log invalidates the minter's state invariants.
 pub fn replay_events() -> State {
     with_event_iter(|mut iter| {
@@ -91,7 +486,11 @@ pub fn replay_events() -> State {
                 payload: EventType::Init(init_arg),
                 ..
             } => State::try_from(init_arg).expect("state initialization should succeed"),
-            other => panic!("the first event must be an Init event, got: {other:?}"),
+            Event {
+                payload: EventType::Checkpoint(snapshot),
+                ..
+            } => State::from(snapshot),
+            other => panic!("the first event must be an Init or Checkpoint event, got: {other:?}"),
         };
         for event in iter {
             apply_state_transition(&mut state, &event.payload);
@@ -1,13 +1,23 @@
+use crate::assets::BridgedAsset;
+use crate::events::InvalidReason;
+use crate::governance::ConfigProposal;
 use crate::lifecycle::{InitArg, UpgradeArg};
-use crate::state::{DepositEvent, SolanaSignature, SolanaSignatureRange, WithdrawalEvent};
+use crate::logs::LogLevel;
+use crate::state::snapshot::StateSnapshot;
+use crate::state::{
+    DepositEvent, DestinationAddress, FetchedEcdsaKey, PreviousEcdsaKey, RoleGrant,
+    SolanaSignature, SolanaSignatureRange, TimerIntervals, WithdrawalEvent,
+};
+use crate::subscriptions::Subscription;
 
+use candid::{CandidType, Deserialize, Nat, Principal};
 use minicbor::{Decode, Encode};
 
 /// The event describing the gSol minter state transition.
 #[derive(Clone, Debug, Encode, Decode, PartialEq, Eq)]
 pub enum EventType {
     /// The minter initialization event.
-    /// Must be the first event in the log.
+    /// Must be the first event in the log, unless the log was compacted.
     #[n(0)]
     Init(#[n(0)] InitArg),
     /// The minter upgraded with the specified arguments.
@@ -54,6 +64,9 @@ pub enum EventType {
         /// The reason for invalidating the transaction in solana.
         #[n(1)]
         fail_reason: String,
+        /// Classification of the reason, None for events recorded before it was tracked.
+        #[n(2)]
+        reason: Option<InvalidReason>,
     },
     #[n(10)]
     AcceptedEvent {
@@ -84,8 +97,601 @@ pub enum EventType {
         #[n(0)]
         event_source: WithdrawalEvent,
     },
+    /// Solana address added to the withdrawal blocklist.
+    #[n(14)]
+    AddBlockedAddress(#[n(0)] String),
+    /// Solana address removed from the withdrawal blocklist.
+    #[n(15)]
+    RemoveBlockedAddress(#[n(0)] String),
+    #[n(16)]
+    WithdrawalRejected {
+        /// The principal requesting the withdrawal.
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        from: Principal,
+        /// The rejected Solana destination.
+        #[n(1)]
+        to_sol_address: String,
+        /// The reason for rejecting the withdrawal.
+        #[n(2)]
+        reason: String,
+    },
+    /// Snapshot of the minter state. Events preceding the latest checkpoint
+    /// are dropped when the event log is compacted.
+    #[n(17)]
+    Checkpoint(#[n(0)] StateSnapshot),
+    /// Minted and redeemed events dropped from the state by the retention policy.
+    #[n(18)]
+    PruneTerminalEvents {
+        /// Solana signatures of the pruned minted events.
+        #[n(0)]
+        minted: Vec<String>,
+        /// Burn ids of the pruned redeemed withdrawals.
+        #[n(1)]
+        redeemed: Vec<u64>,
+    },
+    /// The SOL held by the vault and the gSOL supply differ by more than the tolerance.
+    #[n(19)]
+    SupplyDriftDetected {
+        #[cbor(n(0), with = "crate::cbor::nat")]
+        vault_balance: Nat,
+        #[cbor(n(1), with = "crate::cbor::nat")]
+        total_supply: Nat,
+        #[cbor(n(2), with = "crate::cbor::nat")]
+        tolerance: Nat,
+    },
+    /// The periodic check found the state breaking one of its invariants.
+    #[n(20)]
+    InvariantViolated {
+        #[n(0)]
+        invariant: String,
+        #[n(1)]
+        details: String,
+    },
+    /// Role granted to a principal, replacing its previous role.
+    #[n(21)]
+    GrantRole(#[n(0)] RoleGrant),
+    /// Role of a principal revoked.
+    #[n(22)]
+    RevokeRole(#[cbor(n(0), with = "crate::cbor::principal")] Principal),
+    /// Minting and withdrawals halted, either by an operator or by the circuit breaker.
+    #[n(23)]
+    Paused {
+        #[n(0)]
+        reason: String,
+    },
+    /// Minting and withdrawals resumed after a controller acknowledged the pause.
+    #[n(24)]
+    Resumed {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        by: Principal,
+    },
+    /// Coupons are signed with a new ECDSA key.
+    #[n(25)]
+    EcdsaKeyRotated {
+        #[n(0)]
+        key_name: String,
+        /// The replaced key, trusted until the end of its grace window.
+        #[n(1)]
+        previous: PreviousEcdsaKey,
+    },
+    /// Coupon of a redeemed withdrawal signed again with the current key.
+    #[n(26)]
+    CouponResigned {
+        #[n(0)]
+        event_source: WithdrawalEvent,
+    },
+    /// Public keys of the ECDSA key, fetched from the management canister.
+    #[n(27)]
+    EcdsaPublicKeyFetched {
+        #[n(0)]
+        key_name: String,
+        #[n(1)]
+        key: FetchedEcdsaKey,
+    },
+    /// Intervals of the periodic timers changed at runtime.
+    #[n(28)]
+    TimerIntervalsChanged {
+        #[n(0)]
+        intervals: TimerIntervals,
+    },
+    /// The projected runway of the cycles balance dropped below the threshold.
+    #[n(29)]
+    LowCyclesBalance {
+        #[cbor(n(0), with = "crate::cbor::nat")]
+        balance: Nat,
+        #[n(1)]
+        runway_secs: u64,
+    },
+    /// The minter topped up its cycles balance from the configured funding source.
+    #[n(30)]
+    CyclesToppedUp {
+        #[cbor(n(0), with = "crate::cbor::nat")]
+        cycles: Nat,
+        /// Balance before the top-up.
+        #[cbor(n(1), with = "crate::cbor::nat")]
+        balance: Nat,
+    },
+    /// Digest of the state recorded before an upgrade, see [crate::lifecycle::UpgradeAudit].
+    #[n(31)]
+    StateDigest {
+        #[cbor(n(0), with = "minicbor::bytes")]
+        sha256: Vec<u8>,
+        /// Version of the digested fields, None for digests of the whole state snapshot.
+        #[n(1)]
+        version: Option<u32>,
+    },
+    /// A controller changed the verbosity of the logs.
+    #[n(32)]
+    LogLevelChanged {
+        #[n(0)]
+        level: LogLevel,
+    },
+    /// A controller registered an SPL token, or updated its registration.
+    #[n(33)]
+    AssetRegistered(#[n(0)] BridgedAsset),
+    /// A controller removed an SPL token from the registry.
+    #[n(34)]
+    AssetRemoved {
+        #[n(0)]
+        spl_mint: String,
+    },
+    /// A canister subscribed to pushed events, or changed its filter.
+    #[n(35)]
+    Subscribed(#[n(0)] Subscription),
+    /// A canister stopped receiving pushed events, the reason is set when it was
+    /// unsubscribed by the minter.
+    #[n(36)]
+    Unsubscribed {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        subscriber: Principal,
+        #[n(1)]
+        reason: Option<String>,
+    },
+    /// The subscriber acknowledged the events up to the cursor. Recorded by earlier
+    /// versions, deliveries are no longer part of the event log.
+    #[n(37)]
+    EventsDelivered {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        subscriber: Principal,
+        #[n(1)]
+        cursor: u64,
+    },
+    /// A controller proposed a sensitive config change.
+    #[n(38)]
+    ConfigChangeProposed(#[n(0)] ConfigProposal),
+    /// A controller applied a proposed config change after its timelock.
+    #[n(39)]
+    ConfigChangeExecuted {
+        #[n(0)]
+        id: u64,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        executor: Principal,
+    },
+    /// A controller dropped a proposed config change.
+    #[n(40)]
+    ConfigChangeCancelled {
+        #[n(0)]
+        id: u64,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        canceller: Principal,
+    },
+    /// Part of a large range was scanned, the remainder starts at `before_sol_sig`.
+    #[n(41)]
+    SolanaSignatureRangeAdvanced {
+        #[n(0)]
+        range: SolanaSignatureRange,
+        #[n(1)]
+        before_sol_sig: String,
+    },
+    /// Invalid events moved from the heap to the stable memory archive.
+    #[n(42)]
+    ArchiveInvalidEvents {
+        /// Solana signatures of the archived invalid events, oldest first.
+        #[n(0)]
+        signatures: Vec<String>,
+    },
+    /// A controller moved the scraping anchor, signatures between the previous and the
+    /// new anchor are not scanned.
+    #[n(43)]
+    LastKnownSolanaSignatureOverridden {
+        #[n(0)]
+        signature: String,
+        #[n(1)]
+        previous: String,
+        #[cbor(n(2), with = "crate::cbor::principal")]
+        caller: Principal,
+    },
+    /// A controller queued a signature range for scanning.
+    #[n(44)]
+    SolanaSignatureRangeRescanRequested {
+        #[n(0)]
+        range: SolanaSignatureRange,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        caller: Principal,
+    },
+    /// The scan of a range ended before reaching `until_sol_sig`, the signatures in
+    /// between, starting at `next_sol_sig`, may hold skipped deposits.
+    #[n(45)]
+    SignatureGapDetected {
+        #[n(0)]
+        before_sol_sig: String,
+        #[n(1)]
+        until_sol_sig: String,
+        #[n(2)]
+        next_sol_sig: String,
+    },
+    /// getTransaction answered null for the signature, its transaction was not indexed yet.
+    #[n(46)]
+    SolanaTransactionNotFound {
+        #[n(0)]
+        sol_sig: String,
+    },
+    /// The mint of an accepted deposit failed with an error that retries cannot resolve,
+    /// the deposit waits for a controller to requeue it.
+    #[n(47)]
+    DepositDeadLettered {
+        #[n(0)]
+        sol_sig: String,
+        #[n(1)]
+        error: String,
+    },
+    /// A controller moved a dead-lettered deposit back to the accepted ones.
+    #[n(48)]
+    DeadLetterRequeued {
+        #[n(0)]
+        sol_sig: String,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        caller: Principal,
+    },
+    /// The burned withdrawal pays out at least the approval threshold, its coupon is not
+    /// signed until an operator approves it.
+    #[n(49)]
+    WithdrawalHeldForApproval {
+        #[n(0)]
+        burn_id: u64,
+    },
+    /// An operator approved a held withdrawal.
+    #[n(50)]
+    WithdrawalApproved {
+        #[n(0)]
+        burn_id: u64,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        caller: Principal,
+    },
+    /// An operator rejected a held withdrawal, its coupon is never signed.
+    #[n(51)]
+    WithdrawalApprovalRejected {
+        #[n(0)]
+        burn_id: u64,
+        #[n(1)]
+        reason: String,
+        #[cbor(n(2), with = "crate::cbor::principal")]
+        caller: Principal,
+    },
+    /// A principal registered a Solana address it may withdraw to.
+    #[n(52)]
+    DestinationAddressAdded {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        owner: Principal,
+        #[n(1)]
+        destination: DestinationAddress,
+    },
+    /// A principal removed a Solana address from its address book.
+    #[n(53)]
+    DestinationAddressRemoved {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        owner: Principal,
+        #[n(1)]
+        address: String,
+    },
+    /// The coupon of a large withdrawal is not issued before `release_at`, a controller
+    /// may cancel the withdrawal until then.
+    #[n(54)]
+    WithdrawalTimeLocked {
+        #[n(0)]
+        burn_id: u64,
+        #[n(1)]
+        release_at: u64,
+    },
+    /// A controller cancelled a time-locked withdrawal, its burned amount is re-minted
+    /// to the sender.
+    #[n(55)]
+    WithdrawalCancelled {
+        #[n(0)]
+        burn_id: u64,
+        #[n(1)]
+        reason: String,
+        #[cbor(n(2), with = "crate::cbor::principal")]
+        caller: Principal,
+        /// Time of the cancellation, the `created_at_time` of the re-mint so that the ledger
+        /// deduplicates its retries. None for withdrawals cancelled before it was recorded.
+        #[n(3)]
+        cancelled_at: Option<u64>,
+    },
+    /// The burned amount of a cancelled withdrawal was re-minted to the sender.
+    #[n(56)]
+    CancelledWithdrawalReminted {
+        #[n(0)]
+        burn_id: u64,
+        /// Block of the mint on the ledger, None if minted by the NFT canister or if a
+        /// controller resolved an attempt whose outcome was unknown as minted.
+        #[n(1)]
+        block_index: Option<u64>,
+    },
+    /// A controller revoked the coupon of a withdrawal, it no longer verifies.
+    #[n(57)]
+    CouponRevoked {
+        #[n(0)]
+        burn_id: u64,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        caller: Principal,
+    },
+    /// A controller lifted the revocation of a coupon.
+    #[n(58)]
+    CouponRevocationLifted {
+        #[n(0)]
+        burn_id: u64,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        caller: Principal,
+    },
+    /// Blocks `start..end` of the event log were pushed to an archive canister.
+    #[n(59)]
+    EventsArchived {
+        #[cbor(n(0), with = "crate::cbor::principal")]
+        archive: Principal,
+        #[n(1)]
+        start: u64,
+        #[n(2)]
+        end: u64,
+    },
+    /// The minter is about to re-mint the burned amount of a cancelled withdrawal.
+    #[n(60)]
+    RemintAttempted {
+        #[n(0)]
+        burn_id: u64,
+        /// `created_at_time` of the ledger mint, None for a mint through the NFT canister.
+        #[n(1)]
+        created_at: Option<u64>,
+    },
+    /// A re-mint attempt failed without minting, or a controller resolved an attempt whose
+    /// outcome was unknown as such.
+    #[n(61)]
+    RemintFailed {
+        #[n(0)]
+        burn_id: u64,
+        #[n(1)]
+        reason: String,
+    },
+    /// A relayer reported the coupon of a withdrawal as redeemed on Solana, it is no longer
+    /// listed by `get_coupons_for_address`.
+    #[n(62)]
+    CouponRedemptionReported {
+        #[n(0)]
+        burn_id: u64,
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        caller: Principal,
+    },
+}
+
+/// Kind of an event, used to select events without matching on their payload.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    Init,
+    Upgrade,
+    LastKnownSolanaSignature,
+    LastDepositIdCounter,
+    LastBurnIdCounter,
+    NewSolanaSignatureRange,
+    RemoveSolanaSignatureRange,
+    RetrySolanaSignatureRange,
+    SolanaSignature,
+    InvalidEvent,
+    AcceptedEvent,
+    MintedEvent,
+    WithdrawalBurnedEvent,
+    WithdrawalRedeemedEvent,
+    AddBlockedAddress,
+    RemoveBlockedAddress,
+    WithdrawalRejected,
+    Checkpoint,
+    PruneTerminalEvents,
+    SupplyDriftDetected,
+    InvariantViolated,
+    GrantRole,
+    RevokeRole,
+    Paused,
+    Resumed,
+    EcdsaKeyRotated,
+    CouponResigned,
+    EcdsaPublicKeyFetched,
+    TimerIntervalsChanged,
+    LowCyclesBalance,
+    CyclesToppedUp,
+    StateDigest,
+    LogLevelChanged,
+    AssetRegistered,
+    AssetRemoved,
+    Subscribed,
+    Unsubscribed,
+    EventsDelivered,
+    ConfigChangeProposed,
+    ConfigChangeExecuted,
+    ConfigChangeCancelled,
+    SolanaSignatureRangeAdvanced,
+    ArchiveInvalidEvents,
+    LastKnownSolanaSignatureOverridden,
+    SolanaSignatureRangeRescanRequested,
+    SignatureGapDetected,
+    SolanaTransactionNotFound,
+    DepositDeadLettered,
+    DeadLetterRequeued,
+    WithdrawalHeldForApproval,
+    WithdrawalApproved,
+    WithdrawalApprovalRejected,
+    DestinationAddressAdded,
+    DestinationAddressRemoved,
+    WithdrawalTimeLocked,
+    WithdrawalCancelled,
+    CancelledWithdrawalReminted,
+    CouponRevoked,
+    CouponRevocationLifted,
+    EventsArchived,
+    RemintAttempted,
+    RemintFailed,
+    CouponRedemptionReported,
 }
 
+impl EventKind {
+    pub fn all() -> &'static [EventKind] {
+        &[
+            EventKind::Init,
+            EventKind::Upgrade,
+            EventKind::LastKnownSolanaSignature,
+            EventKind::LastDepositIdCounter,
+            EventKind::LastBurnIdCounter,
+            EventKind::NewSolanaSignatureRange,
+            EventKind::RemoveSolanaSignatureRange,
+            EventKind::RetrySolanaSignatureRange,
+            EventKind::SolanaSignature,
+            EventKind::InvalidEvent,
+            EventKind::AcceptedEvent,
+            EventKind::MintedEvent,
+            EventKind::WithdrawalBurnedEvent,
+            EventKind::WithdrawalRedeemedEvent,
+            EventKind::AddBlockedAddress,
+            EventKind::RemoveBlockedAddress,
+            EventKind::WithdrawalRejected,
+            EventKind::Checkpoint,
+            EventKind::PruneTerminalEvents,
+            EventKind::SupplyDriftDetected,
+            EventKind::InvariantViolated,
+            EventKind::GrantRole,
+            EventKind::RevokeRole,
+            EventKind::Paused,
+            EventKind::Resumed,
+            EventKind::EcdsaKeyRotated,
+            EventKind::CouponResigned,
+            EventKind::EcdsaPublicKeyFetched,
+            EventKind::TimerIntervalsChanged,
+            EventKind::LowCyclesBalance,
+            EventKind::CyclesToppedUp,
+            EventKind::StateDigest,
+            EventKind::LogLevelChanged,
+            EventKind::AssetRegistered,
+            EventKind::AssetRemoved,
+            EventKind::Subscribed,
+            EventKind::Unsubscribed,
+            EventKind::EventsDelivered,
+            EventKind::ConfigChangeProposed,
+            EventKind::ConfigChangeExecuted,
+            EventKind::ConfigChangeCancelled,
+            EventKind::SolanaSignatureRangeAdvanced,
+            EventKind::ArchiveInvalidEvents,
+            EventKind::LastKnownSolanaSignatureOverridden,
+            EventKind::SolanaSignatureRangeRescanRequested,
+            EventKind::SignatureGapDetected,
+            EventKind::SolanaTransactionNotFound,
+            EventKind::DepositDeadLettered,
+            EventKind::DeadLetterRequeued,
+            EventKind::WithdrawalHeldForApproval,
+            EventKind::WithdrawalApproved,
+            EventKind::WithdrawalApprovalRejected,
+            EventKind::DestinationAddressAdded,
+            EventKind::DestinationAddressRemoved,
+            EventKind::WithdrawalTimeLocked,
+            EventKind::WithdrawalCancelled,
+            EventKind::CancelledWithdrawalReminted,
+            EventKind::CouponRevoked,
+            EventKind::CouponRevocationLifted,
+            EventKind::EventsArchived,
+            EventKind::RemintAttempted,
+            EventKind::RemintFailed,
+            EventKind::CouponRedemptionReported,
+        ]
+    }
+}
+
+impl EventType {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            EventType::Init { .. } => EventKind::Init,
+            EventType::Upgrade { .. } => EventKind::Upgrade,
+            EventType::LastKnownSolanaSignature { .. } => EventKind::LastKnownSolanaSignature,
+            EventType::LastDepositIdCounter { .. } => EventKind::LastDepositIdCounter,
+            EventType::LastBurnIdCounter { .. } => EventKind::LastBurnIdCounter,
+            EventType::NewSolanaSignatureRange { .. } => EventKind::NewSolanaSignatureRange,
+            EventType::RemoveSolanaSignatureRange { .. } => EventKind::RemoveSolanaSignatureRange,
+            EventType::RetrySolanaSignatureRange { .. } => EventKind::RetrySolanaSignatureRange,
+            EventType::SolanaSignature { .. } => EventKind::SolanaSignature,
+            EventType::InvalidEvent { .. } => EventKind::InvalidEvent,
+            EventType::AcceptedEvent { .. } => EventKind::AcceptedEvent,
+            EventType::MintedEvent { .. } => EventKind::MintedEvent,
+            EventType::WithdrawalBurnedEvent { .. } => EventKind::WithdrawalBurnedEvent,
+            EventType::WithdrawalRedeemedEvent { .. } => EventKind::WithdrawalRedeemedEvent,
+            EventType::AddBlockedAddress { .. } => EventKind::AddBlockedAddress,
+            EventType::RemoveBlockedAddress { .. } => EventKind::RemoveBlockedAddress,
+            EventType::WithdrawalRejected { .. } => EventKind::WithdrawalRejected,
+            EventType::Checkpoint { .. } => EventKind::Checkpoint,
+            EventType::PruneTerminalEvents { .. } => EventKind::PruneTerminalEvents,
+            EventType::SupplyDriftDetected { .. } => EventKind::SupplyDriftDetected,
+            EventType::InvariantViolated { .. } => EventKind::InvariantViolated,
+            EventType::GrantRole { .. } => EventKind::GrantRole,
+            EventType::RevokeRole { .. } => EventKind::RevokeRole,
+            EventType::Paused { .. } => EventKind::Paused,
+            EventType::Resumed { .. } => EventKind::Resumed,
+            EventType::EcdsaKeyRotated { .. } => EventKind::EcdsaKeyRotated,
+            EventType::CouponResigned { .. } => EventKind::CouponResigned,
+            EventType::EcdsaPublicKeyFetched { .. } => EventKind::EcdsaPublicKeyFetched,
+            EventType::TimerIntervalsChanged { .. } => EventKind::TimerIntervalsChanged,
+            EventType::LowCyclesBalance { .. } => EventKind::LowCyclesBalance,
+            EventType::CyclesToppedUp { .. } => EventKind::CyclesToppedUp,
+            EventType::StateDigest { .. } => EventKind::StateDigest,
+            EventType::LogLevelChanged { .. } => EventKind::LogLevelChanged,
+            EventType::AssetRegistered { .. } => EventKind::AssetRegistered,
+            EventType::AssetRemoved { .. } => EventKind::AssetRemoved,
+            EventType::Subscribed { .. } => EventKind::Subscribed,
+            EventType::Unsubscribed { .. } => EventKind::Unsubscribed,
+            EventType::EventsDelivered { .. } => EventKind::EventsDelivered,
+            EventType::ConfigChangeProposed { .. } => EventKind::ConfigChangeProposed,
+            EventType::ConfigChangeExecuted { .. } => EventKind::ConfigChangeExecuted,
+            EventType::ConfigChangeCancelled { .. } => EventKind::ConfigChangeCancelled,
+            EventType::SolanaSignatureRangeAdvanced { .. } => {
+                EventKind::SolanaSignatureRangeAdvanced
+            }
+            EventType::ArchiveInvalidEvents { .. } => EventKind::ArchiveInvalidEvents,
+            EventType::LastKnownSolanaSignatureOverridden { .. } => {
+                EventKind::LastKnownSolanaSignatureOverridden
+            }
+            EventType::SolanaSignatureRangeRescanRequested { .. } => {
+                EventKind::SolanaSignatureRangeRescanRequested
+            }
+            EventType::SignatureGapDetected { .. } => EventKind::SignatureGapDetected,
+            EventType::SolanaTransactionNotFound { .. } => EventKind::SolanaTransactionNotFound,
+            EventType::DepositDeadLettered { .. } => EventKind::DepositDeadLettered,
+            EventType::DeadLetterRequeued { .. } => EventKind::DeadLetterRequeued,
+            EventType::WithdrawalHeldForApproval { .. } => EventKind::WithdrawalHeldForApproval,
+            EventType::WithdrawalApproved { .. } => EventKind::WithdrawalApproved,
+            EventType::WithdrawalApprovalRejected { .. } => EventKind::WithdrawalApprovalRejected,
+            EventType::DestinationAddressAdded { .. } => EventKind::DestinationAddressAdded,
+            EventType::DestinationAddressRemoved { .. } => EventKind::DestinationAddressRemoved,
+            EventType::WithdrawalTimeLocked { .. } => EventKind::WithdrawalTimeLocked,
+            EventType::WithdrawalCancelled { .. } => EventKind::WithdrawalCancelled,
+            EventType::CancelledWithdrawalReminted { .. } => EventKind::CancelledWithdrawalReminted,
+            EventType::CouponRevoked { .. } => EventKind::CouponRevoked,
+            EventType::CouponRevocationLifted { .. } => EventKind::CouponRevocationLifted,
+            EventType::EventsArchived { .. } => EventKind::EventsArchived,
+            EventType::RemintAttempted { .. } => EventKind::RemintAttempted,
+            EventType::RemintFailed { .. } => EventKind::RemintFailed,
+            EventType::CouponRedemptionReported { .. } => EventKind::CouponRedemptionReported,
+        }
+    }
+}
+
+/// Version of the event schema written by this minter. Must be bumped, together
+/// with a migration in [crate::storage::migration], whenever the encoding of an
+/// existing event changes incompatibly.
+pub const CURRENT_EVENT_VERSION: u32 = 1;
+
 #[derive(Encode, Decode, Debug, PartialEq, Eq, Clone)]
 pub struct Event {
     /// The canister time at which the minter generated this event.
@@ -94,4 +700,8 @@ pub struct Event {
     /// The event type.
     #[n(1)]
     pub payload: EventType,
+    /// The schema version the event was encoded with, None for events recorded
+    /// before the schema was versioned.
+    #[n(2)]
+    pub version: Option<u32>,
 }
@@ -0,0 +1,195 @@
+use super::{
+    audit::{process_event, trip_circuit_breaker},
+    event::EventType,
+    mutate_state, read_state, State, TaskType,
+};
+use crate::{guard::TimerGuard, logs::INFO};
+
+use std::collections::HashMap;
+
+/// A broken assumption about the minter state, identified by the invariant name
+/// and a description of the offending entries.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InvariantViolation {
+    pub invariant: String,
+    pub details: String,
+}
+
+impl InvariantViolation {
+    fn new(invariant: &str, details: String) -> Self {
+        Self {
+            invariant: invariant.to_string(),
+            details,
+        }
+    }
+}
+
+// every solana signature is tracked by at most one of the signature and deposit maps
+fn check_unique_signatures(state: &State, violations: &mut Vec<InvariantViolation>) {
+    let minted_events = state.minted_events.keys();
+    let mut owners: HashMap<&String, Vec<&str>> = HashMap::new();
+    let maps = [
+        (
+            "solana_signatures",
+            state.solana_signatures.keys().collect::<Vec<_>>(),
+        ),
+        ("invalid_events", state.invalid_events.keys().collect()),
+        ("accepted_events", state.accepted_events.keys().collect()),
+        ("dead_letters", state.dead_letters.keys().collect()),
+        ("minted_events", minted_events.iter().collect()),
+    ];
+    for (name, keys) in maps {
+        for key in keys {
+            owners.entry(key).or_default().push(name);
+        }
+    }
+
+    let mut duplicates: Vec<String> = owners
+        .into_iter()
+        .filter(|(_, maps)| maps.len() > 1)
+        .map(|(sig, maps)| format!("{sig} in {}", maps.join(", ")))
+        .collect();
+    duplicates.sort();
+    for duplicate in duplicates {
+        violations.push(InvariantViolation::new("unique_signatures", duplicate));
+    }
+}
+
+// ids handed out by the counters are never reused
+fn check_monotonic_counters(state: &State, violations: &mut Vec<InvariantViolation>) {
+    let max_deposit_id = state
+        .accepted_events
+        .values()
+        .map(|e| e.id)
+        .chain(state.dead_letters.values().map(|d| d.deposit.id))
+        .chain(state.minted_events.values().into_iter().map(|e| e.id))
+        .max();
+    if let Some(id) = max_deposit_id.filter(|id| *id >= state.deposit_id_counter) {
+        violations.push(InvariantViolation::new(
+            "monotonic_deposit_id_counter",
+            format!(
+                "deposit id {id} is not below the counter {}",
+                state.deposit_id_counter
+            ),
+        ));
+    }
+
+    let max_burn_id = state
+        .withdrawal_burned_events
+        .keys()
+        .copied()
+        .chain(state.withdrawal_redeemed_events.keys())
+        .max();
+    if let Some(id) = max_burn_id.filter(|id| *id >= state.burn_id_counter) {
+        violations.push(InvariantViolation::new(
+            "monotonic_burn_id_counter",
+            format!(
+                "burn id {id} is not below the counter {}",
+                state.burn_id_counter
+            ),
+        ));
+    }
+}
+
+// a redeemed withdrawal went through a recorded burn and left the burned map
+fn check_redeemed_withdrawals(state: &State, violations: &mut Vec<InvariantViolation>) {
+    // redeemed events are iterated in burn id order
+    for (burn_id, event) in state
+        .withdrawal_redeemed_events
+        .with_iter(|iter| iter.collect::<Vec<_>>())
+    {
+        if state.withdrawal_burned_events.contains_key(&burn_id) {
+            violations.push(InvariantViolation::new(
+                "redeemed_withdrawal_has_burn",
+                format!("burn id {burn_id} is both burned and redeemed"),
+            ));
+        }
+        if event.get_burn_timestamp().is_none() || event.get_icp_burn_block_index().is_none() {
+            violations.push(InvariantViolation::new(
+                "redeemed_withdrawal_has_burn",
+                format!("burn id {burn_id} was redeemed without a recorded burn"),
+            ));
+        }
+        if event.get_coupon().is_none() {
+            violations.push(InvariantViolation::new(
+                "redeemed_withdrawal_has_burn",
+                format!("burn id {burn_id} was redeemed without a coupon"),
+            ));
+        }
+    }
+}
+
+// a held withdrawal is burned and its coupon was not signed
+fn check_held_withdrawals(state: &State, violations: &mut Vec<InvariantViolation>) {
+    for burn_id in state.withdrawal_approvals.keys() {
+        if !state.withdrawal_burned_events.contains_key(burn_id) {
+            violations.push(InvariantViolation::new(
+                "held_withdrawal_is_burned",
+                format!("burn id {burn_id} is held for approval without a pending burn"),
+            ));
+        }
+    }
+}
+
+// a time-locked withdrawal is burned and its coupon was not signed
+fn check_time_locked_withdrawals(state: &State, violations: &mut Vec<InvariantViolation>) {
+    for burn_id in state.time_locked_withdrawals.keys() {
+        if !state.withdrawal_burned_events.contains_key(burn_id) {
+            violations.push(InvariantViolation::new(
+                "time_locked_withdrawal_is_burned",
+                format!("burn id {burn_id} is time-locked without a pending burn"),
+            ));
+        }
+    }
+}
+
+/// Returns all the invariants violated by the given state.
+pub fn check_invariants(state: &State) -> Vec<InvariantViolation> {
+    let mut violations = vec![];
+    check_unique_signatures(state, &mut violations);
+    check_monotonic_counters(state, &mut violations);
+    check_redeemed_withdrawals(state, &mut violations);
+    check_held_withdrawals(state, &mut violations);
+    check_time_locked_withdrawals(state, &mut violations);
+    violations
+}
+
+// record the violations that were not reported since the last upgrade
+pub fn check_state_invariants() {
+    let _guard = match TimerGuard::new(TaskType::CheckInvariants) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    let violations = read_state(|s| {
+        check_invariants(s)
+            .into_iter()
+            .filter(|v| !s.reported_invariant_violations.contains(v))
+            .collect::<Vec<_>>()
+    });
+
+    for violation in violations {
+        crate::logs::log!(
+            INFO,
+            "\nInvariant {} violated: {}",
+            violation.invariant,
+            violation.details
+        );
+
+        mutate_state(|s| {
+            process_event(
+                s,
+                EventType::InvariantViolated {
+                    invariant: violation.invariant.clone(),
+                    details: violation.details.clone(),
+                },
+            );
+            s.reported_invariant_violations.insert(violation.clone());
+        });
+
+        trip_circuit_breaker(format!(
+            "invariant {} violated: {}",
+            violation.invariant, violation.details
+        ));
+    }
+}
@@ -0,0 +1,444 @@
+use super::{
+    range_key, AddressBook, ApprovalStatus, ArchivedBlockRange, FetchedEcdsaKey, PreviousEcdsaKey,
+    RoleGrant, State, TimerIntervals,
+};
+use crate::assets::BridgedAsset;
+use crate::cycles::{CyclesMonitorConfig, CyclesTopUpConfig};
+use crate::events::{
+    DeadLetter, DepositEvent, RetryPolicy, SolanaSignature, SolanaSignatureRange, WithdrawalEvent,
+};
+use crate::governance::ConfigProposal;
+use crate::guard::RateLimit;
+use crate::lifecycle::{SolanaRpcUrl, UpgradeAudit};
+use crate::logs::LogLevel;
+use crate::sol_rpc_client::{types::ConfirmationStatus, ProxyTokenConfig};
+use crate::storage;
+use crate::subscriptions::Subscription;
+
+use candid::{Nat, Principal};
+use minicbor::{Decode, Encode};
+use std::collections::BTreeSet;
+
+/// Snapshot of the persistent part of the minter state, recorded as a checkpoint
+/// in the event log. Transient data such as locks or the HTTP request counter is
+/// not part of the snapshot, nor are the minted and redeemed events, which stay in
+/// stable memory across upgrades.
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq)]
+pub struct StateSnapshot {
+    #[n(0)]
+    pub solana_rpc_url: SolanaRpcUrl,
+    #[n(1)]
+    pub solana_contract_address: String,
+    #[n(2)]
+    pub solana_initial_signature: String,
+    #[n(3)]
+    pub ecdsa_key_name: String,
+    #[cbor(n(4), with = "crate::cbor::nat")]
+    pub minimum_withdrawal_amount: Nat,
+    #[cbor(n(5), with = "crate::cbor::principal::option")]
+    pub ledger_id: Option<Principal>,
+    #[n(6)]
+    pub solana_last_known_signature: Option<String>,
+    #[n(7)]
+    pub solana_signature_ranges: Vec<SolanaSignatureRange>,
+    #[n(8)]
+    pub solana_signatures: Vec<SolanaSignature>,
+    #[n(9)]
+    pub invalid_events: Vec<SolanaSignature>,
+    #[n(10)]
+    pub accepted_events: Vec<DepositEvent>,
+    // n(11) and n(13) held the minted and redeemed events, ignored when decoding
+    #[n(12)]
+    pub withdrawal_burned_events: Vec<WithdrawalEvent>,
+    #[n(14)]
+    pub max_withdrawals_per_principal: u64,
+    #[n(15)]
+    pub blocked_addresses: Vec<String>,
+    #[n(16)]
+    pub deposit_id_counter: u64,
+    #[n(17)]
+    pub burn_id_counter: u64,
+    #[n(18)]
+    pub terminal_events_retention: Option<u64>,
+    #[n(19)]
+    pub used_burn_block_indices: Vec<u64>,
+    #[cbor(n(20), with = "crate::cbor::nat")]
+    pub total_minted: Nat,
+    #[cbor(n(21), with = "crate::cbor::nat")]
+    pub total_burned: Nat,
+    #[cbor(n(22), with = "crate::cbor::nat")]
+    pub total_fees: Nat,
+    #[n(23)]
+    pub solana_vault_address: Option<String>,
+    #[cbor(n(24), with = "crate::cbor::nat")]
+    pub reconciliation_tolerance: Nat,
+    #[n(25)]
+    pub retry_policy: RetryPolicy,
+    #[n(26)]
+    pub roles: Vec<RoleGrant>,
+    #[n(27)]
+    pub rate_limit: Option<RateLimit>,
+    #[n(28)]
+    pub paused: Option<String>,
+    #[n(29)]
+    pub task_lock_timeout_secs: u64,
+    #[n(30)]
+    pub previous_ecdsa_key: Option<PreviousEcdsaKey>,
+    #[n(31)]
+    pub proxy_token: ProxyTokenConfig,
+    #[n(32)]
+    pub ecdsa_key: Option<FetchedEcdsaKey>,
+    #[n(33)]
+    pub timer_intervals: TimerIntervals,
+    #[n(34)]
+    pub commitment: ConfirmationStatus,
+    #[cbor(n(35), with = "crate::cbor::nat::option")]
+    pub max_deposit_amount: Option<Nat>,
+    #[cbor(n(36), with = "crate::cbor::nat::option")]
+    pub max_withdrawal_amount: Option<Nat>,
+    #[n(37)]
+    pub cycles_monitor: CyclesMonitorConfig,
+    #[n(38)]
+    pub cycles_top_up: Option<CyclesTopUpConfig>,
+    #[n(39)]
+    pub upgrade_audit: UpgradeAudit,
+    #[n(40)]
+    pub log_level: LogLevel,
+    #[n(41)]
+    pub trusted_origins: Vec<String>,
+    #[n(42)]
+    pub assets: Vec<BridgedAsset>,
+    #[n(43)]
+    pub used_asset_burn_blocks: Vec<(String, u64)>,
+    #[n(44)]
+    pub subscriptions: Vec<Subscription>,
+    #[n(45)]
+    pub config_proposals: Vec<ConfigProposal>,
+    #[n(46)]
+    pub next_proposal_id: u64,
+    #[n(47)]
+    pub config_timelock_secs: u64,
+    #[n(48)]
+    pub mint_parallelism: u64,
+    /// In the order of their archival.
+    #[n(49)]
+    pub archived_invalid_events: Vec<SolanaSignature>,
+    #[n(50)]
+    pub invalid_events_retention_secs: u64,
+    #[cbor(n(51), with = "crate::cbor::nat")]
+    pub total_ledger_fees: Nat,
+    /// In the order of their signatures.
+    #[n(52)]
+    pub dead_letters: Vec<DeadLetter>,
+    #[cbor(n(53), with = "crate::cbor::nat::option")]
+    pub dual_signature_threshold: Option<Nat>,
+    #[cbor(n(54), with = "crate::cbor::nat::option")]
+    pub approval_threshold: Option<Nat>,
+    #[n(55)]
+    pub withdrawal_approvals: Vec<(u64, ApprovalStatus)>,
+    #[n(56)]
+    pub address_books: Vec<AddressBook>,
+    #[n(57)]
+    pub destination_activation_delay_secs: u64,
+    #[cbor(n(58), with = "crate::cbor::nat::option")]
+    pub release_delay_threshold: Option<Nat>,
+    #[n(59)]
+    pub release_delay_secs: u64,
+    #[n(60)]
+    pub time_locked_withdrawals: Vec<(u64, u64)>,
+    #[n(61)]
+    pub cancelled_withdrawals: Vec<WithdrawalEvent>,
+    #[n(62)]
+    pub revoked_coupons: Vec<u64>,
+    #[n(63)]
+    pub checkpoint_interval_events: u64,
+    #[n(64)]
+    pub checkpoint_interval_secs: u64,
+    #[cbor(n(65), with = "crate::cbor::principal::option")]
+    pub event_archive: Option<Principal>,
+    #[n(66)]
+    pub archived_blocks: Vec<ArchivedBlockRange>,
+    #[n(67)]
+    pub terminal_events_max_age_secs: Option<u64>,
+    #[n(68)]
+    pub pruned_signatures: Vec<String>,
+    #[n(69)]
+    pub event_log_max_bytes: Option<u64>,
+    #[n(70)]
+    pub solana_redeemed_coupons: Vec<u64>,
+}
+
+impl From<&State> for StateSnapshot {
+    fn from(state: &State) -> Self {
+        Self {
+            solana_rpc_url: state.solana_rpc_url.clone(),
+            solana_contract_address: state.solana_contract_address.clone(),
+            solana_initial_signature: state.solana_initial_signature.clone(),
+            ecdsa_key_name: state.ecdsa_key_name.clone(),
+            minimum_withdrawal_amount: Nat(state.minimum_withdrawal_amount.clone()),
+            ledger_id: state.ledger_id,
+            solana_last_known_signature: state.solana_last_known_signature.clone(),
+            solana_signature_ranges: state.solana_signature_ranges.values().cloned().collect(),
+            solana_signatures: state.solana_signatures.values().cloned().collect(),
+            invalid_events: state.invalid_events.values().cloned().collect(),
+            accepted_events: state.accepted_events.values().cloned().collect(),
+            withdrawal_burned_events: state.withdrawal_burned_events.values().cloned().collect(),
+            max_withdrawals_per_principal: state.max_withdrawals_per_principal as u64,
+            blocked_addresses: state.blocked_addresses.iter().cloned().collect(),
+            revoked_coupons: state.revoked_coupons.iter().copied().collect(),
+            solana_redeemed_coupons: state.solana_redeemed_coupons.iter().copied().collect(),
+            deposit_id_counter: state.deposit_id_counter,
+            burn_id_counter: state.burn_id_counter,
+            terminal_events_retention: state.terminal_events_retention,
+            terminal_events_max_age_secs: state.terminal_events_max_age_secs,
+            used_burn_block_indices: state.used_burn_block_indices.iter().copied().collect(),
+            used_asset_burn_blocks: state.used_asset_burn_blocks.iter().cloned().collect(),
+            pruned_signatures: state.pruned_signatures.iter().cloned().collect(),
+            total_minted: Nat(state.total_minted.clone()),
+            total_burned: Nat(state.total_burned.clone()),
+            total_fees: Nat(state.total_fees.clone()),
+            total_ledger_fees: Nat(state.total_ledger_fees.clone()),
+            dead_letters: state.dead_letters.values().cloned().collect(),
+            dual_signature_threshold: state.dual_signature_threshold.clone().map(Nat),
+            approval_threshold: state.approval_threshold.clone().map(Nat),
+            withdrawal_approvals: state
+                .withdrawal_approvals
+                .iter()
+                .map(|(burn_id, status)| (*burn_id, status.clone()))
+                .collect(),
+            address_books: state
+                .address_books
+                .keys()
+                .map(|owner| AddressBook {
+                    owner: *owner,
+                    destinations: state.destination_addresses(owner),
+                })
+                .collect(),
+            destination_activation_delay_secs: state.destination_activation_delay_secs,
+            release_delay_threshold: state.release_delay_threshold.clone().map(Nat),
+            release_delay_secs: state.release_delay_secs,
+            time_locked_withdrawals: state
+                .time_locked_withdrawals
+                .iter()
+                .map(|(burn_id, release_at)| (*burn_id, *release_at))
+                .collect(),
+            cancelled_withdrawals: state.cancelled_withdrawals.values().cloned().collect(),
+            solana_vault_address: state.solana_vault_address.clone(),
+            reconciliation_tolerance: Nat(state.reconciliation_tolerance.clone()),
+            retry_policy: state.retry_policy.clone(),
+            roles: state.role_grants(),
+            rate_limit: state.rate_limit.clone(),
+            paused: state.paused.clone(),
+            task_lock_timeout_secs: state.task_lock_timeout_secs,
+            previous_ecdsa_key: state.previous_ecdsa_key.clone(),
+            proxy_token: state.proxy_token.clone(),
+            ecdsa_key: state.fetched_ecdsa_key(),
+            timer_intervals: state.timer_intervals.clone(),
+            checkpoint_interval_events: state.checkpoint_interval_events,
+            checkpoint_interval_secs: state.checkpoint_interval_secs,
+            event_log_max_bytes: state.event_log_max_bytes,
+            event_archive: state.event_archive,
+            archived_blocks: state.archived_blocks.clone(),
+            commitment: state.commitment,
+            max_deposit_amount: state.max_deposit_amount.clone().map(Nat),
+            max_withdrawal_amount: state.max_withdrawal_amount.clone().map(Nat),
+            cycles_monitor: state.cycles_monitor.clone(),
+            cycles_top_up: state.cycles_top_up.clone(),
+            upgrade_audit: state.upgrade_audit,
+            log_level: state.log_level,
+            trusted_origins: state.trusted_origins.clone(),
+            assets: state.assets.values().cloned().collect(),
+            subscriptions: state.subscriptions.values().cloned().collect(),
+            config_proposals: state.config_proposals.values().cloned().collect(),
+            next_proposal_id: state.next_proposal_id,
+            config_timelock_secs: state.config_timelock_secs,
+            mint_parallelism: state.mint_parallelism as u64,
+            archived_invalid_events: state.archived_invalid_events.values(),
+            invalid_events_retention_secs: state.invalid_events_retention_secs,
+        }
+    }
+}
+
+impl From<StateSnapshot> for State {
+    fn from(snapshot: StateSnapshot) -> Self {
+        // checkpoints taken before the pruned signatures were recorded lack the archived ones
+        let mut pruned_signatures: BTreeSet<String> =
+            snapshot.pruned_signatures.into_iter().collect();
+        let archived_invalid_events = storage::archived_invalid_events().cleared();
+        for (index, sig) in snapshot.archived_invalid_events.into_iter().enumerate() {
+            pruned_signatures.insert(sig.sol_sig.to_string());
+            archived_invalid_events.insert(index as u64, sig);
+        }
+
+        let ecdsa_key_name = snapshot.ecdsa_key_name.clone();
+        let mut state = Self {
+            solana_rpc_url: snapshot.solana_rpc_url,
+            solana_contract_address: snapshot.solana_contract_address,
+            solana_initial_signature: snapshot.solana_initial_signature,
+            ecdsa_key_name: snapshot.ecdsa_key_name,
+            ecdsa_public_key: None,
+            ecdsa_proxy_public_key: None,
+            cosigner_public_key_hex: None,
+            previous_ecdsa_key: snapshot.previous_ecdsa_key,
+            minimum_withdrawal_amount: snapshot.minimum_withdrawal_amount.0,
+            ledger_id: snapshot.ledger_id,
+            solana_last_known_signature: snapshot.solana_last_known_signature,
+            solana_signature_ranges: snapshot
+                .solana_signature_ranges
+                .into_iter()
+                .map(|r| (range_key(&r.before_sol_sig, &r.until_sol_sig), r))
+                .collect(),
+            solana_signatures: snapshot
+                .solana_signatures
+                .into_iter()
+                .map(|s| (s.sol_sig.to_string(), s))
+                .collect(),
+            invalid_events: snapshot
+                .invalid_events
+                .into_iter()
+                .map(|s| (s.sol_sig.to_string(), s))
+                .collect(),
+            accepted_events: snapshot
+                .accepted_events
+                .into_iter()
+                .map(|e| (e.sol_sig.to_string(), e))
+                .collect(),
+            // the stable maps already hold the events minted and redeemed after the
+            // checkpoint, replaying them again leaves the maps unchanged
+            minted_events: storage::minted_events(),
+            withdrawal_burned_events: snapshot
+                .withdrawal_burned_events
+                .into_iter()
+                .map(|e| (e.get_burn_id(), e))
+                .collect(),
+            withdrawal_redeemed_events: storage::redeemed_events(),
+            deposits_by_principal: Default::default(),
+            minted_deposit_ids: Default::default(),
+            withdrawals_by_principal: Default::default(),
+            withdrawals_by_sol_address: Default::default(),
+            withdrawing_principals: Default::default(),
+            withdrawal_locked_at: Default::default(),
+            max_withdrawals_per_principal: snapshot.max_withdrawals_per_principal as usize,
+            processing_burn_ids: Default::default(),
+            executing_config_proposals: Default::default(),
+            blocked_addresses: snapshot.blocked_addresses.into_iter().collect(),
+            revoked_coupons: snapshot.revoked_coupons.into_iter().collect(),
+            solana_redeemed_coupons: snapshot.solana_redeemed_coupons.into_iter().collect(),
+            paused: snapshot.paused,
+            roles: snapshot
+                .roles
+                .into_iter()
+                .map(|g| (g.principal, g.role))
+                .collect(),
+            terminal_events_retention: snapshot.terminal_events_retention,
+            terminal_events_max_age_secs: snapshot.terminal_events_max_age_secs,
+            used_burn_block_indices: snapshot.used_burn_block_indices.into_iter().collect(),
+            used_asset_burn_blocks: snapshot.used_asset_burn_blocks.into_iter().collect(),
+            pruned_signatures,
+            total_minted: snapshot.total_minted.0,
+            total_burned: snapshot.total_burned.0,
+            total_fees: snapshot.total_fees.0,
+            total_ledger_fees: snapshot.total_ledger_fees.0,
+            withdrawal_approvals: snapshot.withdrawal_approvals.into_iter().collect(),
+            address_books: snapshot
+                .address_books
+                .into_iter()
+                .map(|book| {
+                    let destinations = book
+                        .destinations
+                        .into_iter()
+                        .map(|d| (d.address, d.active_at))
+                        .collect();
+                    (book.owner, destinations)
+                })
+                .collect(),
+            destination_activation_delay_secs: snapshot.destination_activation_delay_secs,
+            time_locked_withdrawals: snapshot.time_locked_withdrawals.into_iter().collect(),
+            release_delay_secs: snapshot.release_delay_secs,
+            cancelled_withdrawals: snapshot
+                .cancelled_withdrawals
+                .into_iter()
+                .map(|e| (e.get_burn_id(), e))
+                .collect(),
+            dead_letters: snapshot
+                .dead_letters
+                .into_iter()
+                .map(|d| (d.deposit.sol_sig.to_string(), d))
+                .collect(),
+            solana_vault_address: snapshot.solana_vault_address,
+            reconciliation_tolerance: snapshot.reconciliation_tolerance.0,
+            last_reconciliation: None,
+            cycles_monitor: snapshot.cycles_monitor,
+            last_cycles_report: None,
+            cycles_top_up: snapshot.cycles_top_up,
+            upgrade_audit: snapshot.upgrade_audit,
+            log_level: snapshot.log_level,
+            trusted_origins: snapshot.trusted_origins,
+            assets: snapshot
+                .assets
+                .into_iter()
+                .map(|asset| (asset.spl_mint.clone(), asset))
+                .collect(),
+            subscriptions: snapshot
+                .subscriptions
+                .into_iter()
+                .map(|subscription| (subscription.subscriber, subscription))
+                .collect(),
+            delivery_failures: Default::default(),
+            pending_deliveries: Default::default(),
+            config_proposals: snapshot
+                .config_proposals
+                .into_iter()
+                .map(|proposal| (proposal.id, proposal))
+                .collect(),
+            next_proposal_id: snapshot.next_proposal_id,
+            config_timelock_secs: snapshot.config_timelock_secs,
+            mint_parallelism: snapshot.mint_parallelism as usize,
+            archived_invalid_events,
+            invalid_events_retention_secs: snapshot.invalid_events_retention_secs,
+            retry_policy: snapshot.retry_policy,
+            last_attempts: Default::default(),
+            rate_limit: snapshot.rate_limit,
+            call_windows: Default::default(),
+            task_lock_timeout_secs: snapshot.task_lock_timeout_secs,
+            proxy_token: snapshot.proxy_token,
+            timer_intervals: snapshot.timer_intervals,
+            checkpoint_interval_events: snapshot.checkpoint_interval_events,
+            checkpoint_interval_secs: snapshot.checkpoint_interval_secs,
+            event_log_max_bytes: snapshot.event_log_max_bytes,
+            event_archive: snapshot.event_archive,
+            archived_blocks: snapshot.archived_blocks,
+            commitment: snapshot.commitment,
+            max_deposit_amount: snapshot.max_deposit_amount.map(|amount| amount.0),
+            max_withdrawal_amount: snapshot.max_withdrawal_amount.map(|amount| amount.0),
+            dual_signature_threshold: snapshot.dual_signature_threshold.map(|amount| amount.0),
+            approval_threshold: snapshot.approval_threshold.map(|amount| amount.0),
+            release_delay_threshold: snapshot.release_delay_threshold.map(|amount| amount.0),
+            reported_invariant_violations: Default::default(),
+            deposit_id_counter: snapshot.deposit_id_counter,
+            burn_id_counter: snapshot.burn_id_counter,
+            http_request_counter: 0,
+            http_outcall_cycles: 0,
+            rpc_provider_stats: Default::default(),
+            ecdsa_sign_calls: 0,
+            ecdsa_sign_cycles: 0,
+            signature_cache_hits: 0,
+            signature_cache: Default::default(),
+            active_tasks: Default::default(),
+            task_generation: 0,
+            latest_solana_block: None,
+            ledger_fee: None,
+            task_runs: Default::default(),
+            last_finished_task_runs: Default::default(),
+            task_traces: Default::default(),
+            task_last_success: Default::default(),
+            draining_since: None,
+        };
+        // the per-principal indexes are derived from the event maps
+        state.rebuild_principal_indexes();
+        if let Some(key) = &snapshot.ecdsa_key {
+            state.record_fetched_ecdsa_key(&ecdsa_key_name, key);
+        }
+        state
+    }
+}
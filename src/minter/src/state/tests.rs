@@ -0,0 +1,534 @@
+use super::{invariants::check_invariants, range_key, State, StateTransitionError, STATE};
+use crate::events::{DepositEvent, SolanaSignature, SolanaSignatureRange, WithdrawalEvent};
+use crate::lifecycle::{InitArg, SolanaRpcUrl};
+use crate::spl::SolanaAddress;
+use crate::withdraw::{get_coupon, Coupon, CouponError, WithdrawError};
+
+use base64::prelude::*;
+use candid::{Nat, Principal};
+use num_bigint::BigUint;
+use proptest::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+// small pools, so that generated operations often hit existing entries
+const SIGNATURES: u8 = 12;
+const PRINCIPALS: u8 = 4;
+
+fn initial_state() -> State {
+    State::try_from(InitArg {
+        solana_rpc_url: SolanaRpcUrl::default(),
+        solana_contract_address: "HCq8BN8fUTXV4EWmHQdDeSH4ZkBKnR4CzwMYJNGLg3oP".to_string(),
+        solana_initial_signature: "initial".to_string(),
+        ecdsa_key_name: "dfx_test_key".to_string(),
+        minimum_withdrawal_amount: Nat::from(1u8),
+        ledger_id: None,
+    })
+    .expect("valid init arg")
+}
+
+fn signature(i: u8) -> String {
+    format!("sig{}", i % SIGNATURES)
+}
+
+fn principal(i: u8) -> Principal {
+    Principal::from_slice(&[i % PRINCIPALS + 1])
+}
+
+fn receiver() -> SolanaAddress {
+    SolanaAddress::parse("9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM").expect("valid address")
+}
+
+fn range(before: u8, until: u8) -> SolanaSignatureRange {
+    SolanaSignatureRange::new(signature(before), signature(until))
+}
+
+// deposit data as emitted by the bridge contract: padding, principal text, le amount
+fn deposit(id: u64, sig: &str, to: Principal, amount: u64) -> DepositEvent {
+    let mut data = vec![0u8; 12];
+    data.extend_from_slice(to.to_text().as_bytes());
+    data.extend_from_slice(&amount.to_le_bytes());
+    DepositEvent::new(id, sig, "sender", &BASE64_STANDARD.encode(data)).expect("valid deposit")
+}
+
+fn coupon(burn_id: u64) -> Coupon {
+    Coupon {
+        message: format!("burn {burn_id}"),
+        message_hash: String::new(),
+        signature_hex: String::new(),
+        icp_public_key_hex: String::new(),
+        recovery_id: None,
+        cosignature_hex: None,
+        cosigner_public_key_hex: None,
+        cosigner_recovery_id: None,
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    NewRange(u8, u8),
+    RetryRange(u8, u8, Option<(u8, u8)>),
+    AdvanceRange(u8, u8, u8),
+    RemoveRange(u8, u8),
+    Scrape(u8),
+    Invalidate(u8),
+    Accept(u8, u8, u64),
+    Mint(u8),
+    Burn(u8, u64),
+    RetryBurn(u8),
+    Redeem(u8),
+    Resign(u8),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let sig = 0..SIGNATURES;
+    prop_oneof![
+        1 => (sig.clone(), sig.clone()).prop_map(|(b, u)| Op::NewRange(b, u)),
+        1 => (
+            sig.clone(),
+            sig.clone(),
+            proptest::option::of((sig.clone(), sig.clone()))
+        )
+            .prop_map(|(b, u, sub)| Op::RetryRange(b, u, sub)),
+        1 => (sig.clone(), sig.clone(), sig.clone())
+            .prop_map(|(b, u, n)| Op::AdvanceRange(b, u, n)),
+        1 => (sig.clone(), sig.clone()).prop_map(|(b, u)| Op::RemoveRange(b, u)),
+        3 => sig.clone().prop_map(Op::Scrape),
+        1 => sig.clone().prop_map(Op::Invalidate),
+        3 => (sig.clone(), any::<u8>(), 1..1_000_000u64).prop_map(|(s, p, a)| Op::Accept(s, p, a)),
+        2 => sig.clone().prop_map(Op::Mint),
+        2 => (any::<u8>(), 1..1_000_000u64).prop_map(|(p, a)| Op::Burn(p, a)),
+        1 => any::<u8>().prop_map(Op::RetryBurn),
+        2 => any::<u8>().prop_map(Op::Redeem),
+        1 => any::<u8>().prop_map(Op::Resign),
+    ]
+}
+
+// the parts of the state touched by the transitions under test
+#[derive(Debug, PartialEq, Eq)]
+struct Fingerprint {
+    ranges: BTreeMap<String, u8>,
+    signatures: BTreeMap<String, u8>,
+    invalid: BTreeSet<String>,
+    accepted: BTreeMap<String, u8>,
+    minted: BTreeSet<String>,
+    burned: BTreeMap<u64, u8>,
+    redeemed: BTreeMap<u64, Option<Coupon>>,
+    total_minted: BigUint,
+    total_burned: BigUint,
+}
+
+impl Fingerprint {
+    fn of(state: &State) -> Self {
+        Self {
+            ranges: state
+                .solana_signature_ranges
+                .iter()
+                .map(|(k, r)| (k.clone(), r.retry.get_retries()))
+                .collect(),
+            signatures: state
+                .solana_signatures
+                .iter()
+                .map(|(k, s)| (k.clone(), s.retry.get_retries()))
+                .collect(),
+            invalid: state.invalid_events.keys().cloned().collect(),
+            accepted: state
+                .accepted_events
+                .iter()
+                .map(|(k, e)| (k.clone(), e.retry.get_retries()))
+                .collect(),
+            minted: state.minted_events.keys().into_iter().collect(),
+            burned: state
+                .withdrawal_burned_events
+                .iter()
+                .map(|(k, e)| (*k, e.retry.get_retries()))
+                .collect(),
+            redeemed: state
+                .withdrawal_redeemed_events
+                .with_iter(|iter| iter.map(|(k, e)| (k, e.get_coupon().cloned())).collect()),
+            total_minted: state.total_minted.clone(),
+            total_burned: state.total_burned.clone(),
+        }
+    }
+}
+
+// Applies the operation and checks its outcome against the state before it, operations
+// that cannot happen in the minter are skipped.
+fn apply(state: &mut State, op: &Op) -> Result<(), TestCaseError> {
+    let before = Fingerprint::of(state);
+
+    let (expected_ok, result) = match op {
+        Op::NewRange(b, u) => {
+            let range = range(*b, *u);
+            let key = range_key(&range.before_sol_sig, &range.until_sol_sig);
+            (
+                !before.ranges.contains_key(&key),
+                state.record_solana_signature_range(range),
+            )
+        }
+        Op::RetryRange(b, u, sub) => {
+            let range = range(*b, *u);
+            let key = range_key(&range.before_sol_sig, &range.until_sol_sig);
+            let sub = sub.map(|(sb, su)| self::range(sb, su));
+            let sub_ok = sub.as_ref().is_none_or(|sub| {
+                let sub_key = range_key(&sub.before_sol_sig, &sub.until_sol_sig);
+                sub_key == key || !before.ranges.contains_key(&sub_key)
+            });
+            (
+                before.ranges.contains_key(&key) && sub_ok,
+                state.retry_solana_signature_range(range, sub),
+            )
+        }
+        Op::AdvanceRange(b, u, n) => {
+            let range = range(*b, *u);
+            let key = range_key(&range.before_sol_sig, &range.until_sol_sig);
+            let new_key = range_key(&signature(*n), &range.until_sol_sig);
+            (
+                before.ranges.contains_key(&key)
+                    && (new_key == key || !before.ranges.contains_key(&new_key)),
+                state.advance_solana_signature_range(&range, &signature(*n)),
+            )
+        }
+        Op::RemoveRange(b, u) => {
+            let range = range(*b, *u);
+            let key = range_key(&range.before_sol_sig, &range.until_sol_sig);
+            (
+                before.ranges.contains_key(&key),
+                state.remove_solana_signature_range(&range),
+            )
+        }
+        Op::Scrape(i) => {
+            let sig = signature(*i);
+            // scraped ranges are disjoint, a processed signature is never scraped again
+            if before.invalid.contains(&sig)
+                || before.accepted.contains_key(&sig)
+                || before.minted.contains(&sig)
+            {
+                return Ok(());
+            }
+            state.record_or_retry_solana_signature(SolanaSignature::new(sig));
+            (true, Ok(()))
+        }
+        Op::Invalidate(i) => {
+            let sig = signature(*i);
+            (
+                before.signatures.contains_key(&sig) && !before.invalid.contains(&sig),
+                state.record_invalid_event(SolanaSignature::new(sig), None),
+            )
+        }
+        Op::Accept(i, p, amount) => {
+            let sig = signature(*i);
+            let id = state.next_deposit_id();
+            (
+                before.accepted.contains_key(&sig) || before.signatures.contains_key(&sig),
+                state.record_or_retry_accepted_event(deposit(id, &sig, principal(*p), *amount)),
+            )
+        }
+        Op::Mint(i) => {
+            let sig = signature(*i);
+            let event = state
+                .accepted_events
+                .get(&sig)
+                .cloned()
+                .unwrap_or_else(|| deposit(state.deposit_id_counter, &sig, principal(0), 1));
+            (
+                before.accepted.contains_key(&sig) && !before.minted.contains(&sig),
+                state.record_minted_event(event),
+            )
+        }
+        Op::Burn(p, amount) => {
+            let burn_id = state.next_burn_id();
+            let mut withdrawal =
+                WithdrawalEvent::new(burn_id, principal(*p), receiver(), Nat::from(*amount));
+            withdrawal.update_after_burn(burn_id, burn_id);
+            state.record_or_retry_withdrawal_burned_event(withdrawal);
+            (true, Ok(()))
+        }
+        Op::RetryBurn(i) => {
+            let burn_id = match before
+                .burned
+                .keys()
+                .nth(*i as usize % before.burned.len().max(1))
+            {
+                Some(burn_id) => *burn_id,
+                None => return Ok(()),
+            };
+            let withdrawal = state.withdrawal_burned_events[&burn_id].clone();
+            state.record_or_retry_withdrawal_burned_event(withdrawal);
+            (true, Ok(()))
+        }
+        Op::Redeem(i) => {
+            let burn_id = *i as u64 % (state.burn_id_counter + 1);
+            let mut withdrawal = match state.withdrawal_burned_events.get(&burn_id) {
+                Some(withdrawal) => withdrawal.clone(),
+                None => WithdrawalEvent::new(burn_id, principal(0), receiver(), Nat::from(1u8)),
+            };
+            withdrawal.update_after_redeem(coupon(burn_id));
+            (
+                before.burned.contains_key(&burn_id),
+                state.record_withdrawal_redeemed_event(withdrawal),
+            )
+        }
+        Op::Resign(i) => {
+            let burn_id = *i as u64 % (state.burn_id_counter + 1);
+            let mut withdrawal = match state.withdrawal_redeemed_events.get(&burn_id) {
+                Some(withdrawal) => withdrawal,
+                None => WithdrawalEvent::new(burn_id, principal(0), receiver(), Nat::from(1u8)),
+            };
+            withdrawal.update_after_redeem(coupon(burn_id + 1_000));
+            (
+                before.redeemed.contains_key(&burn_id),
+                state.record_resigned_coupon(withdrawal),
+            )
+        }
+    };
+
+    prop_assert_eq!(
+        result.is_ok(),
+        expected_ok,
+        "{:?} returned {:?}",
+        op,
+        result
+    );
+    if result.is_err() {
+        prop_assert_eq!(
+            &Fingerprint::of(state),
+            &before,
+            "{:?} changed the state",
+            op
+        );
+    }
+    Ok(())
+}
+
+fn check_state(state: &State) -> Result<(), TestCaseError> {
+    let violations = check_invariants(state);
+    prop_assert!(
+        violations.is_empty(),
+        "invariants violated: {:?}",
+        violations
+    );
+
+    let minted: BigUint = state
+        .minted_events
+        .values()
+        .iter()
+        .map(|e| e.amount.0.clone())
+        .sum();
+    prop_assert_eq!(&state.total_minted, &minted);
+
+    let burned: BigUint = state
+        .withdrawal_burned_events
+        .values()
+        .cloned()
+        .chain(state.withdrawal_redeemed_events.values())
+        .map(|e| e.amount.0)
+        .sum();
+    prop_assert_eq!(&state.total_burned, &burned);
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn random_transitions_preserve_invariants(ops in proptest::collection::vec(op(), 1..100)) {
+        let mut state = initial_state();
+        for op in &ops {
+            apply(&mut state, op)?;
+            check_state(&state)?;
+        }
+    }
+
+    #[test]
+    fn deposits_are_minted_once(
+        deposits in proptest::collection::vec((0..SIGNATURES, any::<u8>(), 1..1_000_000u64), 1..30)
+    ) {
+        let mut state = initial_state();
+        let mut expected = BigUint::from(0u8);
+        for (i, p, amount) in deposits {
+            let sig = signature(i);
+            if state.minted_events.contains_key(&sig) {
+                prop_assert_eq!(
+                    state.record_minted_event(deposit(0, &sig, principal(p), amount)),
+                    Err(StateTransitionError::UnknownAcceptedEvent(sig))
+                );
+                continue;
+            }
+            state.record_or_retry_solana_signature(SolanaSignature::new(sig.clone()));
+            let id = state.next_deposit_id();
+            state.record_or_retry_accepted_event(deposit(id, &sig, principal(p), amount))?;
+            let accepted = state.accepted_events[&sig].clone();
+            state.record_minted_event(accepted)?;
+            expected += amount;
+            prop_assert_eq!(&state.total_minted, &expected);
+        }
+        check_state(&state)?;
+    }
+
+    #[test]
+    fn retries_saturate(retries in 300..600usize) {
+        let mut state = initial_state();
+        let sig = SolanaSignature::new(signature(0));
+        for _ in 0..retries {
+            state.record_or_retry_solana_signature(sig.clone());
+        }
+        prop_assert_eq!(state.solana_signatures[&sig.sol_sig].retry.get_retries(), u8::MAX);
+    }
+}
+
+const DAY_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+fn burned_withdrawal(state: &mut State) -> u64 {
+    let burn_id = state.next_burn_id();
+    let mut withdrawal = WithdrawalEvent::new(burn_id, principal(0), receiver(), Nat::from(10u8));
+    withdrawal.update_after_burn(burn_id, burn_id);
+    state.record_or_retry_withdrawal_burned_event(withdrawal);
+    burn_id
+}
+
+fn cancelled_withdrawal(state: &mut State, cancelled_at: u64) -> u64 {
+    let burn_id = burned_withdrawal(state);
+    state
+        .time_lock_withdrawal(burn_id, cancelled_at + DAY_NANOS)
+        .expect("burned withdrawal");
+    state
+        .cancel_withdrawal(burn_id, Some(cancelled_at))
+        .expect("time-locked withdrawal");
+    burn_id
+}
+
+#[test]
+fn remint_retry_after_the_ledger_window_gets_a_fresh_time() {
+    let mut state = initial_state();
+    let cancelled_at = DAY_NANOS;
+    let burn_id = cancelled_withdrawal(&mut state, cancelled_at);
+
+    state
+        .record_remint_attempt(burn_id, Some(cancelled_at))
+        .unwrap();
+    state.record_remint_failure(burn_id).unwrap();
+    state
+        .record_remint_attempt(burn_id, Some(cancelled_at))
+        .unwrap();
+    state.record_remint_failure(burn_id).unwrap();
+
+    // a day later the retries still carry the time of the first attempt, which the ledger
+    // rejects as too old, and no attempt minted, so a fresh time cannot mint twice
+    let event = &state.cancelled_withdrawals[&burn_id];
+    assert_eq!(event.get_remint_created_at(), Some(cancelled_at));
+    assert!(!event.is_remint_unresolved());
+
+    let retried_at = cancelled_at + DAY_NANOS + 1;
+    state
+        .record_remint_attempt(burn_id, Some(retried_at))
+        .unwrap();
+    let event = &state.cancelled_withdrawals[&burn_id];
+    assert_eq!(event.get_remint_created_at(), Some(retried_at));
+    assert!(event.is_remint_unresolved());
+
+    state.record_withdrawal_reminted(burn_id).unwrap();
+    assert!(!state.cancelled_withdrawals.contains_key(&burn_id));
+}
+
+#[test]
+fn remint_attempt_without_outcome_stays_unresolved() {
+    let mut state = initial_state();
+    let cancelled_at = DAY_NANOS;
+    let burn_id = cancelled_withdrawal(&mut state, cancelled_at);
+
+    // withdrawals cancelled before the attempts were recorded may have been attempted
+    assert!(state.cancelled_withdrawals[&burn_id].is_remint_unresolved());
+
+    state.record_remint_attempt(burn_id, None).unwrap();
+    let event = &state.cancelled_withdrawals[&burn_id];
+    assert!(event.is_remint_unresolved());
+    assert_eq!(event.get_remint_created_at(), Some(cancelled_at));
+
+    assert_eq!(
+        state.record_remint_attempt(burn_id + 1, None),
+        Err(StateTransitionError::UnknownCancelledWithdrawal(
+            burn_id + 1
+        ))
+    );
+}
+
+#[test]
+fn get_coupon_is_refused_while_paused() {
+    let mut state = initial_state();
+    let burn_id = burned_withdrawal(&mut state);
+    state.paused = Some("supply drift".to_string());
+    STATE.with(|cell| *cell.borrow_mut() = Some(state));
+
+    assert_eq!(
+        futures::executor::block_on(get_coupon(principal(0), burn_id)),
+        Err(WithdrawError::Paused("supply drift".to_string()))
+    );
+}
+
+#[test]
+fn revoked_coupons_are_not_signed() {
+    let mut state = initial_state();
+    let burn_ids = [burned_withdrawal(&mut state), burned_withdrawal(&mut state)];
+    state.revoked_coupons.insert(burn_ids[0]);
+
+    let pending: Vec<u64> = state
+        .coupons_to_sign(0, 10)
+        .iter()
+        .map(WithdrawalEvent::get_burn_id)
+        .collect();
+    assert_eq!(pending, vec![burn_ids[1]]);
+}
+
+#[test]
+fn coupons_without_domain_are_refused_unless_stored_in_that_form() {
+    let mut state = initial_state();
+    let burn_id = burned_withdrawal(&mut state);
+    let mut withdrawal = state.withdrawal_burned_events[&burn_id].clone();
+    withdrawal.update_after_redeem(coupon(burn_id));
+    state
+        .record_withdrawal_redeemed_event(withdrawal)
+        .expect("burned withdrawal");
+    STATE.with(|cell| *cell.borrow_mut() = Some(state));
+
+    // the domain fields stripped from the JSON message of the coupon
+    let downgraded = Coupon {
+        message: serde_json::json!({
+            "from_icp_address": principal(0).to_text(),
+            "to_sol_address": receiver().to_string(),
+            "amount": "10",
+            "burn_id": burn_id,
+            "burn_timestamp": burn_id,
+            "icp_burn_block_index": burn_id,
+        })
+        .to_string(),
+        ..coupon(burn_id)
+    };
+    assert_eq!(
+        downgraded.verify(),
+        Err(CouponError::UnknownLegacyCoupon(burn_id))
+    );
+}
+
+#[test]
+fn coupons_reported_as_redeemed_are_not_listed_for_the_address() {
+    let mut state = initial_state();
+    let burn_ids = [burned_withdrawal(&mut state), burned_withdrawal(&mut state)];
+    for burn_id in burn_ids {
+        let mut withdrawal = state.withdrawal_burned_events[&burn_id].clone();
+        withdrawal.update_after_redeem(coupon(burn_id));
+        state
+            .record_withdrawal_redeemed_event(withdrawal)
+            .expect("burned withdrawal");
+    }
+    state.solana_redeemed_coupons.insert(burn_ids[0]);
+
+    let address = receiver().to_string();
+    assert_eq!(
+        state.coupons_for_address(&address, 10),
+        vec![coupon(burn_ids[1])]
+    );
+}
+
+impl From<StateTransitionError> for TestCaseError {
+    fn from(e: StateTransitionError) -> Self {
+        TestCaseError::fail(e.to_string())
+    }
+}
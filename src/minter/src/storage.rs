@@ -1,18 +1,75 @@
-use crate::state::event::{Event, EventType};
+//! Persistence of the minter's event log.
+//!
+//! Events are appended to a [StableLog] backed by virtual stable memory, so the
+//! log survives upgrades without going through `pre_upgrade` serialization and
+//! is never held on the heap as a whole.
+//!
+//! The terminal event maps of the state, minted deposits, redeemed
+//! withdrawals and archived invalid events, are kept in stable memory as well. They are
+//! still derived from the event log: replaying it from the `Init` event fills them again.
+//! Checkpoints hold the archived invalid events but not the minted and redeemed ones,
+//! which the maps keep across upgrades.
+
+use crate::certification::update_certified_tip;
+use crate::constants::{MAX_BLOCKS_HASHED_PER_BATCH, MAX_EVENTS_COPIED_PER_COMPACTION};
+use crate::events::{DepositEvent, SolanaSignature, WithdrawalEvent};
+use crate::icrc3;
+use crate::state::event::{Event, EventType, CURRENT_EVENT_VERSION};
+use candid::Principal;
 use ic_stable_structures::{
-    log::Log as StableLog,
+    log::{Log as StableLog, WriteError},
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
     storable::{Bound, Storable},
-    DefaultMemoryImpl,
+    DefaultMemoryImpl, Memory, StableBTreeMap, StableCell,
 };
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
 use std::cell::RefCell;
+use std::ops;
+use std::thread::LocalKey;
+use std::time::Duration;
+
+pub mod migration;
 
 const LOG_INDEX_MEMORY_ID: MemoryId = MemoryId::new(0);
 const LOG_DATA_MEMORY_ID: MemoryId = MemoryId::new(1);
+const MINTED_EVENTS_MEMORY_ID: MemoryId = MemoryId::new(2);
+const REDEEMED_EVENTS_MEMORY_ID: MemoryId = MemoryId::new(3);
+const ARCHIVED_INVALID_EVENTS_MEMORY_ID: MemoryId = MemoryId::new(4);
+const COMPACTED_EVENTS_MEMORY_ID: MemoryId = MemoryId::new(5);
+const BLOCK_HASHES_MEMORY_ID: MemoryId = MemoryId::new(6);
+const DELIVERY_CURSORS_MEMORY_ID: MemoryId = MemoryId::new(7);
+const ACTIVE_LOG_MEMORY_ID: MemoryId = MemoryId::new(8);
+const ALT_LOG_INDEX_MEMORY_ID: MemoryId = MemoryId::new(9);
+const ALT_LOG_DATA_MEMORY_ID: MemoryId = MemoryId::new(10);
+const LAST_CHECKPOINT_MEMORY_ID: MemoryId = MemoryId::new(11);
+
+// value of the last checkpoint cell until the log was scanned for its latest checkpoint,
+// i.e. on canisters upgraded from a version that did not record it
+const UNKNOWN_CHECKPOINT_BLOCK: u64 = u64::MAX;
+
+/// Index and data memories of the two event logs. One holds the log, compactions copy the
+/// retained events to the other one and swap them.
+const LOG_MEMORY_IDS: [(MemoryId, MemoryId); 2] = [
+    (LOG_INDEX_MEMORY_ID, LOG_DATA_MEMORY_ID),
+    (ALT_LOG_INDEX_MEMORY_ID, ALT_LOG_DATA_MEMORY_ID),
+];
+
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
 
 type VMem = VirtualMemory<DefaultMemoryImpl>;
 type EventLog = StableLog<Event, VMem, VMem>;
+type EventMap<K, V> = StableBTreeMap<K, V, VMem>;
+
+// Stable memory holds the source of truth of the minter, failures to read or write it
+// trap rather than being handled.
+fn decoding_failed(bytes: &[u8], reason: minicbor::decode::Error) -> ! {
+    panic!("failed to decode bytes {}: {reason}", hex::encode(bytes))
+}
+
+fn append_failed(error: WriteError) -> ! {
+    panic!("failed to append to the event log: {error:?}")
+}
 
 impl Storable for Event {
     fn to_bytes(&self) -> Cow<[u8]> {
@@ -22,51 +79,550 @@ impl Storable for Event {
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        minicbor::decode(bytes.as_ref())
-            .unwrap_or_else(|e| panic!("failed to decode event bytes {}: {e}", hex::encode(bytes)))
+        migration::decode_event(bytes.as_ref())
+            .unwrap_or_else(|e| decoding_failed(bytes.as_ref(), e))
     }
 
     const BOUND: Bound = Bound::Unbounded;
 }
 
+impl Storable for DepositEvent {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        encode_cbor(self)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        decode_cbor(bytes)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Storable for SolanaSignature {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        encode_cbor(self)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        decode_cbor(bytes)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Storable for WithdrawalEvent {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        encode_cbor(self)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        decode_cbor(bytes)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+fn encode_cbor<T: minicbor::Encode<()>>(value: &T) -> Cow<'static, [u8]> {
+    let mut buf = vec![];
+    minicbor::encode(value, &mut buf).expect("value encoding should always succeed");
+    Cow::Owned(buf)
+}
+
+fn decode_cbor<T: for<'b> minicbor::Decode<'b, ()>>(bytes: Cow<[u8]>) -> T {
+    minicbor::decode(bytes.as_ref()).unwrap_or_else(|e| decoding_failed(bytes.as_ref(), e))
+}
+
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
         MemoryManager::init(DefaultMemoryImpl::default())
     );
 
+    /// Index in `LOG_MEMORY_IDS` of the memories holding the event log.
+    static ACTIVE_LOG: RefCell<StableCell<u64, VMem>> = MEMORY_MANAGER
+        .with(|m| RefCell::new(
+            StableCell::init(m.borrow().get(ACTIVE_LOG_MEMORY_ID), 0)
+                .expect("failed to initialize the active event log index")
+        ));
+
     /// The log of the gSol state modifications.
-    static EVENTS: RefCell<EventLog> = MEMORY_MANAGER
-        .with(|m|
-              RefCell::new(
-                  StableLog::init(
-                      m.borrow().get(LOG_INDEX_MEMORY_ID),
-                      m.borrow().get(LOG_DATA_MEMORY_ID)
-                  ).expect("failed to initialize stable log")
-              )
-        );
+    static EVENTS: RefCell<EventLog> = {
+        let (index, data) = log_memories(active_log());
+        RefCell::new(StableLog::init(index, data).expect("failed to initialize stable log"))
+    };
+
+    /// Block id of the latest checkpoint of the log, 0 if the log has none past its first
+    /// event. Block ids survive compactions, unlike the indices in the log.
+    static LAST_CHECKPOINT: RefCell<StableCell<u64, VMem>> = MEMORY_MANAGER
+        .with(|m| RefCell::new(
+            StableCell::init(m.borrow().get(LAST_CHECKPOINT_MEMORY_ID), UNKNOWN_CHECKPOINT_BLOCK)
+                .expect("failed to initialize the last checkpoint block")
+        ));
+
+    /// Compaction in progress, None between compactions. It is not persisted: a compaction
+    /// interrupted by an upgrade starts over.
+    static COMPACTION: RefCell<Option<Compaction>> = RefCell::new(None);
+
+    static MINTED_EVENTS: RefCell<EventMap<String, DepositEvent>> = MEMORY_MANAGER
+        .with(|m| RefCell::new(StableBTreeMap::init(m.borrow().get(MINTED_EVENTS_MEMORY_ID))));
+
+    static REDEEMED_EVENTS: RefCell<EventMap<u64, WithdrawalEvent>> = MEMORY_MANAGER
+        .with(|m| RefCell::new(StableBTreeMap::init(m.borrow().get(REDEEMED_EVENTS_MEMORY_ID))));
+
+    static ARCHIVED_INVALID_EVENTS: RefCell<EventMap<u64, SolanaSignature>> = MEMORY_MANAGER
+        .with(|m| RefCell::new(StableBTreeMap::init(m.borrow().get(ARCHIVED_INVALID_EVENTS_MEMORY_ID))));
+
+    /// Number of events dropped from the front of the log by compactions. The index of an
+    /// event in the log plus this count is its ICRC-3 block id.
+    static COMPACTED_EVENTS: RefCell<StableCell<u64, VMem>> = MEMORY_MANAGER
+        .with(|m| RefCell::new(
+            StableCell::init(m.borrow().get(COMPACTED_EVENTS_MEMORY_ID), 0)
+                .expect("failed to initialize the compacted event count")
+        ));
+
+    /// ICRC-3 hashes of the blocks, keyed by block id.
+    static BLOCK_HASHES: RefCell<EventMap<u64, Vec<u8>>> = MEMORY_MANAGER
+        .with(|m| RefCell::new(StableBTreeMap::init(m.borrow().get(BLOCK_HASHES_MEMORY_ID))));
+
+    /// Block id of the next event pushed to each subscriber, keyed by the bytes of its
+    /// principal. Deliveries are not recorded in the event log.
+    static DELIVERY_CURSORS: RefCell<EventMap<Vec<u8>, u64>> = MEMORY_MANAGER
+        .with(|m| RefCell::new(StableBTreeMap::init(m.borrow().get(DELIVERY_CURSORS_MEMORY_ID))));
+}
+
+/// Handle to a map of terminal events held in stable memory. All copies of a
+/// handle refer to the same map.
+pub struct StableEventMap<K: 'static, V: 'static> {
+    map: &'static LocalKey<RefCell<EventMap<K, V>>>,
+    memory_id: MemoryId,
+}
+
+impl<K: Storable + Ord + Clone, V: Storable> StableEventMap<K, V> {
+    /// Drops all entries of the map and returns the handle.
+    pub fn cleared(self) -> Self {
+        self.map.with(|map| {
+            *map.borrow_mut() =
+                MEMORY_MANAGER.with(|m| StableBTreeMap::new(m.borrow().get(self.memory_id)))
+        });
+        self
+    }
+
+    pub fn len(&self) -> u64 {
+        self.map.with(|map| map.borrow().len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.with(|map| map.borrow().contains_key(key))
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.map.with(|map| map.borrow().get(key))
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.map.with(|map| map.borrow_mut().insert(key, value))
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.map.with(|map| map.borrow_mut().remove(key))
+    }
+
+    /// Returns at most `limit` values whose key is at least `start`, in key order.
+    pub fn values_from(&self, start: K, limit: usize) -> Vec<V> {
+        self.map.with(|map| {
+            map.borrow()
+                .range(start..)
+                .take(limit)
+                .map(|(_, v)| v)
+                .collect()
+        })
+    }
+
+    /// Returns at most `limit` entries whose key follows `cursor`, in key order.
+    pub fn entries_after(&self, cursor: Option<K>, limit: usize) -> Vec<(K, V)> {
+        let start = cursor.map_or(ops::Bound::Unbounded, ops::Bound::Excluded);
+        self.map.with(|map| {
+            map.borrow()
+                .range((start, ops::Bound::Unbounded))
+                .take(limit)
+                .collect()
+        })
+    }
+
+    /// Calls `f` with an iterator over the entries of the map, in key order.
+    pub fn with_iter<R>(&self, f: impl FnOnce(&mut dyn Iterator<Item = (K, V)>) -> R) -> R {
+        self.map.with(|map| f(&mut map.borrow().iter()))
+    }
+
+    pub fn keys(&self) -> Vec<K> {
+        self.with_iter(|iter| iter.map(|(k, _)| k).collect())
+    }
+
+    pub fn values(&self) -> Vec<V> {
+        self.with_iter(|iter| iter.map(|(_, v)| v).collect())
+    }
+}
+
+impl<K, V> Clone for StableEventMap<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map,
+            memory_id: self.memory_id,
+        }
+    }
+}
+
+impl<K, V> PartialEq for StableEventMap<K, V>
+where
+    K: Storable + Ord + Clone + PartialEq,
+    V: Storable + PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.with_iter(|entries| other.with_iter(|other_entries| entries.eq(other_entries)))
+    }
+}
+
+impl<K, V> std::fmt::Debug for StableEventMap<K, V>
+where
+    K: Storable + Ord + Clone + std::fmt::Debug,
+    V: Storable + std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.with_iter(|iter| f.debug_map().entries(iter).finish())
+    }
+}
+
+/// Minted deposits keyed by their Solana signature.
+pub fn minted_events() -> StableEventMap<String, DepositEvent> {
+    StableEventMap {
+        map: &MINTED_EVENTS,
+        memory_id: MINTED_EVENTS_MEMORY_ID,
+    }
+}
+
+/// Redeemed withdrawals keyed by their burn id.
+pub fn redeemed_events() -> StableEventMap<u64, WithdrawalEvent> {
+    StableEventMap {
+        map: &REDEEMED_EVENTS,
+        memory_id: REDEEMED_EVENTS_MEMORY_ID,
+    }
+}
+
+/// Invalid events moved out of the heap, keyed by the order of their archival.
+pub fn archived_invalid_events() -> StableEventMap<u64, SolanaSignature> {
+    StableEventMap {
+        map: &ARCHIVED_INVALID_EVENTS,
+        memory_id: ARCHIVED_INVALID_EVENTS_MEMORY_ID,
+    }
 }
 
 /// Appends the event to the event log.
 pub fn record_event(payload: EventType) {
+    let checkpoint = matches!(payload, EventType::Checkpoint(_)).then(block_count);
     EVENTS
         .with(|events| {
             events.borrow().append(&Event {
                 timestamp: ic_cdk::api::time(),
                 payload,
+                version: Some(CURRENT_EVENT_VERSION),
             })
         })
-        .expect("recording an event should succeed");
+        .unwrap_or_else(append_failed);
+    if let Some(block) = checkpoint {
+        set_last_checkpoint(block);
+    }
+    hash_missing_blocks();
+}
+
+/// Number of events dropped from the front of the log by compactions.
+pub fn compacted_event_count() -> u64 {
+    COMPACTED_EVENTS.with(|count| *count.borrow().get())
+}
+
+/// Number of blocks of the ICRC-3 view of the log, including the compacted ones.
+pub fn block_count() -> u64 {
+    compacted_event_count() + total_event_count()
+}
+
+/// Id and hash of the last hashed block, None until a block is hashed.
+pub fn last_hashed_block() -> Option<(u64, [u8; 32])> {
+    BLOCK_HASHES
+        .with(|hashes| hashes.borrow().last_key_value())
+        .and_then(|(id, hash)| Some((id, hash.try_into().ok()?)))
+}
+
+pub fn block_hash(id: u64) -> Option<[u8; 32]> {
+    BLOCK_HASHES
+        .with(|hashes| hashes.borrow().get(&id))
+        .and_then(|hash| hash.try_into().ok())
+}
+
+/// Id of the first block recorded after `timestamp`, the block count if there is none.
+/// Compacted blocks are not considered.
+pub fn first_block_after(timestamp: u64) -> u64 {
+    // events are recorded in time order
+    let index = EVENTS.with(|events| {
+        let events = events.borrow();
+        let (mut low, mut high) = (0, events.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match events.get(mid) {
+                Some(event) if event.timestamp <= timestamp => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        low
+    });
+    compacted_event_count() + index
+}
+
+/// Block id of the next event pushed to the subscriber, None if no delivery was recorded
+/// since the cursors are kept in stable memory.
+pub fn delivery_cursor(subscriber: &Principal) -> Option<u64> {
+    DELIVERY_CURSORS.with(|cursors| cursors.borrow().get(&subscriber.as_slice().to_vec()))
+}
+
+pub fn set_delivery_cursor(subscriber: &Principal, cursor: u64) {
+    DELIVERY_CURSORS.with(|cursors| {
+        cursors
+            .borrow_mut()
+            .insert(subscriber.as_slice().to_vec(), cursor)
+    });
+}
+
+pub fn remove_delivery_cursor(subscriber: &Principal) {
+    DELIVERY_CURSORS.with(|cursors| cursors.borrow_mut().remove(&subscriber.as_slice().to_vec()));
+}
+
+/// Returns the event of the block `id` and the hash of its parent block, None if the
+/// event was compacted.
+pub fn get_block(id: u64) -> Option<(Event, Option<[u8; 32]>)> {
+    let index = id.checked_sub(compacted_event_count())?;
+    let event = EVENTS.with(|events| events.borrow().get(index))?;
+    Some((event, id.checked_sub(1).and_then(block_hash)))
+}
+
+/// Hashes at most `MAX_BLOCKS_HASHED_PER_BATCH` of the blocks appended since the last
+/// call, chaining each one to its parent, and certifies the last of them as the tip.
+/// Blocks whose parent was compacted before it was hashed start a new chain. Returns
+/// whether blocks remain to be hashed.
+pub fn hash_missing_blocks() -> bool {
+    let first = last_hashed_block()
+        .map(|(id, _)| id + 1)
+        .unwrap_or(0)
+        .max(compacted_event_count());
+    let end = block_count().min(first.saturating_add(MAX_BLOCKS_HASHED_PER_BATCH));
+
+    for id in first..end {
+        let (event, phash) = get_block(id).expect("the block is in the log");
+        let hash = icrc3::event_block(&event, phash).hash();
+        BLOCK_HASHES.with(|hashes| hashes.borrow_mut().insert(id, hash.to_vec()));
+    }
+    if end > first {
+        update_certified_tip();
+    }
+    end < block_count()
+}
+
+/// Hashes the missing blocks one batch per timer tick, e.g. the events recorded before
+/// the blocks were hashed.
+pub fn hash_missing_blocks_in_batches() {
+    if hash_missing_blocks() {
+        ic_cdk_timers::set_timer(Duration::from_secs(0), hash_missing_blocks_in_batches);
+    }
+}
+
+// drops the hashes of the compacted blocks but the last one, the parent of the first
+// retained block
+fn prune_block_hashes(compacted: u64) {
+    BLOCK_HASHES.with(|hashes| {
+        let mut hashes = hashes.borrow_mut();
+        let pruned: Vec<u64> = hashes
+            .range(..compacted.saturating_sub(1))
+            .map(|(id, _)| id)
+            .collect();
+        for id in pruned {
+            hashes.remove(&id);
+        }
+    });
 }
 
 pub fn get_storage_events() -> Vec<Event> {
-    let mut events = Vec::new();
-    EVENTS.with(|events_cell| {
-        let events_ref = events_cell.borrow();
-        for event in events_ref.iter() {
-            events.push(event.clone());
+    with_event_iter(|iter| iter.collect())
+}
+
+/// Returns at most `length` events starting at index `start`.
+pub fn get_events(start: u64, length: u64) -> Vec<Event> {
+    EVENTS.with(|events| {
+        let events = events.borrow();
+        let end = start.saturating_add(length).min(events.len());
+        (start..end).filter_map(|index| events.get(index)).collect()
+    })
+}
+
+/// Returns the CBOR encoding of at most `limit` consecutive events starting at index
+/// `start`, as a CBOR array, together with the number of exported events and the running
+/// hash after the last of them. Stops before the encoded events exceed `max_bytes`, but
+/// always exports at least one event.
+///
+/// The running hash chains the events from `prev_hash`, the running hash of the events
+/// preceding `start`: `hash = SHA-256(hash || event)` over the stable encoding of each event.
+pub fn export_events_cbor(
+    start: u64,
+    limit: u64,
+    max_bytes: usize,
+    prev_hash: [u8; 32],
+) -> (Vec<u8>, u64, [u8; 32]) {
+    let (events, running_hash) = EVENTS.with(|events| {
+        let events = events.borrow();
+        let mut exported: Vec<Event> = Vec::new();
+        let mut running_hash = prev_hash;
+        let mut size = 0;
+        for index in start..events.len() {
+            if exported.len() as u64 >= limit {
+                break;
+            }
+            let event = match events.get(index) {
+                Some(event) => event,
+                None => break,
+            };
+            let bytes = event.to_bytes();
+            size += bytes.len();
+            if size > max_bytes && !exported.is_empty() {
+                break;
+            }
+            running_hash = Sha256::new()
+                .chain_update(running_hash)
+                .chain_update(&bytes)
+                .finalize()
+                .into();
+            exported.push(event);
+        }
+        (exported, running_hash)
+    });
+
+    let mut buf = vec![];
+    minicbor::encode(&events, &mut buf).expect("event encoding should always succeed");
+    (buf, events.len() as u64, running_hash)
+}
+
+/// Copy of the events retained by a compaction to the log that is not in use.
+struct Compaction {
+    /// Index in the log in use of the checkpoint the copy starts from.
+    checkpoint: u64,
+    log: EventLog,
+}
+
+fn active_log() -> usize {
+    ACTIVE_LOG.with(|cell| *cell.borrow().get() as usize)
+}
+
+fn log_memories(log: usize) -> (VMem, VMem) {
+    let (index, data) = LOG_MEMORY_IDS[log];
+    MEMORY_MANAGER.with(|m| {
+        let m = m.borrow();
+        (m.get(index), m.get(data))
+    })
+}
+
+fn set_last_checkpoint(block: u64) {
+    LAST_CHECKPOINT.with(|cell| {
+        cell.borrow_mut()
+            .set(block)
+            .expect("the last checkpoint block should fit its cell");
+    });
+}
+
+// index in the log of the latest checkpoint, None if it is the first event of the log
+fn last_checkpoint_index() -> Option<usize> {
+    let mut block = LAST_CHECKPOINT.with(|cell| *cell.borrow().get());
+    if block == UNKNOWN_CHECKPOINT_BLOCK {
+        let index = with_event_iter(|iter| {
+            iter.enumerate()
+                .filter(|(_, event)| matches!(event.payload, EventType::Checkpoint(_)))
+                .map(|(index, _)| index as u64)
+                .last()
+        });
+        block = index.map_or(0, |index| compacted_event_count() + index);
+        set_last_checkpoint(block);
+    }
+    block
+        .checked_sub(compacted_event_count())
+        .map(|index| index as usize)
+        .filter(|index| *index > 0)
+}
+
+/// Block id of the latest checkpoint, None if compacting the log would not drop any event.
+pub fn last_checkpoint_block() -> Option<u64> {
+    last_checkpoint_index().map(|index| compacted_event_count() + index as u64)
+}
+
+/// Drops all events preceding the latest checkpoint, which becomes the first event of
+/// the log. The events from the checkpoint on are copied to the other log, at most
+/// `MAX_EVENTS_COPIED_PER_COMPACTION` per call, events appended meanwhile included, and
+/// the logs are swapped once the copy caught up. Returns the number of dropped events
+/// once the log is compacted, None while the copy is in progress.
+pub fn compact_events() -> Option<u64> {
+    let compaction = match COMPACTION.with(|c| c.borrow_mut().take()) {
+        Some(compaction) => compaction,
+        None => {
+            let Some(checkpoint) = last_checkpoint_index() else {
+                return Some(0);
+            };
+            let (index, data) = log_memories(1 - active_log());
+            Compaction {
+                checkpoint: checkpoint as u64,
+                log: StableLog::new(index, data),
+            }
+        }
+    };
+    // the retained checkpoint is chained to the hash of the last dropped block, unless
+    // the dropped blocks are not all hashed by the time the copy caught up
+    hash_missing_blocks();
+
+    let start = compaction.checkpoint + compaction.log.len();
+    let end = total_event_count().min(start + MAX_EVENTS_COPIED_PER_COMPACTION);
+    EVENTS.with(|events| {
+        let events = events.borrow();
+        for index in start..end {
+            let event = events
+                .get(index)
+                .expect("events below the log length should exist");
+            compaction.log.append(&event).unwrap_or_else(append_failed);
         }
     });
-    events
+    if end < total_event_count() {
+        COMPACTION.with(|c| *c.borrow_mut() = Some(compaction));
+        return None;
+    }
+
+    let Compaction { checkpoint, log } = compaction;
+    let compacted_log = 1 - active_log();
+    EVENTS.with(|events| *events.borrow_mut() = log);
+    ACTIVE_LOG.with(|cell| {
+        cell.borrow_mut()
+            .set(compacted_log as u64)
+            .expect("the active event log index should fit its cell");
+    });
+    COMPACTED_EVENTS.with(|count| {
+        let mut count = count.borrow_mut();
+        let compacted = *count.get() + checkpoint;
+        count
+            .set(compacted)
+            .expect("the compacted event count should fit its cell");
+        prune_block_hashes(compacted);
+    });
+
+    Some(checkpoint)
+}
+
+/// Returns true while a compaction copies the retained events.
+pub fn is_compacting() -> bool {
+    COMPACTION.with(|c| c.borrow().is_some())
 }
 
 /// Returns the total number of events in the audit log.
@@ -80,3 +636,37 @@ where
 {
     EVENTS.with(|events| f(Box::new(events.borrow().iter())))
 }
+
+/// Returns the number of stable memory bytes allocated for the index and data of the
+/// event log in use.
+pub fn event_log_memory_bytes() -> (u64, u64) {
+    let (index, data) = log_memories(active_log());
+    (index.size() * WASM_PAGE_SIZE, data.size() * WASM_PAGE_SIZE)
+}
+
+/// Returns the number of bytes used by the event log index and data. Unlike the allocated
+/// memory, it shrinks when the log is compacted.
+pub fn event_log_size_bytes() -> u64 {
+    EVENTS.with(|events| {
+        let events = events.borrow();
+        events.index_size_bytes() + events.data_size_bytes()
+    })
+}
+
+/// Returns the number of bytes of stable memory allocated by the canister.
+pub fn stable_memory_bytes() -> u64 {
+    ic_cdk::api::stable::stable64_size() * WASM_PAGE_SIZE
+}
+
+/// Returns the number of bytes of heap memory allocated by the canister.
+pub fn heap_memory_bytes() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        core::arch::wasm32::memory_size(0) as u64 * WASM_PAGE_SIZE
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        0
+    }
+}
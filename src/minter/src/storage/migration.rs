@@ -0,0 +1,41 @@
+//! Decoding of events recorded with older versions of the event schema.
+//!
+//! Every event is stored together with the schema version it was encoded with.
+//! Events recorded before the schema was versioned carry no version and are
+//! decoded as version 0. When the layout of a persisted type such as
+//! [DepositEvent](crate::events::DepositEvent) or
+//! [WithdrawalEvent](crate::events::WithdrawalEvent) changes incompatibly, bump
+//! [CURRENT_EVENT_VERSION], keep the previous layout in a `v<N>` submodule of
+//! this module and convert it in [decode_event].
+
+use crate::state::event::{Event, CURRENT_EVENT_VERSION};
+use minicbor::{decode::Error, Decode};
+
+/// The part of the [Event] encoding that identifies its schema version.
+#[derive(Decode)]
+struct EventHeader {
+    #[n(2)]
+    version: Option<u32>,
+}
+
+/// Returns the schema version of the encoded event.
+pub fn event_version(bytes: &[u8]) -> Result<u32, Error> {
+    let header: EventHeader = minicbor::decode(bytes)?;
+    Ok(header.version.unwrap_or(0))
+}
+
+/// Decodes an event recorded with any supported schema version into the
+/// current layout.
+pub fn decode_event(bytes: &[u8]) -> Result<Event, Error> {
+    match event_version(bytes)? {
+        // the layout of version 0 events is identical to version 1
+        0..=CURRENT_EVENT_VERSION => {
+            let mut event: Event = minicbor::decode(bytes)?;
+            event.version = Some(CURRENT_EVENT_VERSION);
+            Ok(event)
+        }
+        version => Err(Error::message(format!(
+            "unsupported event version {version}, the minter supports up to {CURRENT_EVENT_VERSION}"
+        ))),
+    }
+}
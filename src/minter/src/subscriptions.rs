@@ -0,0 +1,179 @@
+use crate::{
+    constants::{
+        MAX_DELIVERY_FAILURES, MAX_EXAMINED_EVENTS_PER_PUSH, MAX_PUSHED_EVENTS_PER_BATCH,
+        SUBSCRIBER_REPLY_TIMEOUT,
+    },
+    endpoints::CandidEvent,
+    guard::TimerGuard,
+    logs::INFO,
+    state::{
+        audit::process_event,
+        event::{Event, EventType},
+        mutate_state, read_state, TaskType,
+    },
+    storage,
+};
+
+use candid::{CandidType, Deserialize, Principal};
+use minicbor::{Decode, Encode};
+
+/// Kinds of events pushed to a subscriber.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct SubscriptionFilter {
+    /// Accepted and minted deposits.
+    #[n(0)]
+    pub deposits: bool,
+    /// Burned, redeemed and rejected withdrawals.
+    #[n(1)]
+    pub withdrawals: bool,
+}
+
+impl SubscriptionFilter {
+    pub fn matches(&self, payload: &EventType) -> bool {
+        match payload {
+            EventType::AcceptedEvent { .. } | EventType::MintedEvent { .. } => self.deposits,
+            EventType::WithdrawalBurnedEvent { .. }
+            | EventType::WithdrawalRedeemedEvent { .. }
+            | EventType::WithdrawalRejected { .. } => self.withdrawals,
+            _ => false,
+        }
+    }
+}
+
+/// Canister receiving batches of new events through its `on_minter_events` method.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct Subscription {
+    #[cbor(n(0), with = "crate::cbor::principal")]
+    pub subscriber: Principal,
+    #[n(1)]
+    pub filter: SubscriptionFilter,
+    /// Events recorded after this time are pushed to the subscriber. Subscriptions of
+    /// earlier versions hold the timestamp of the last delivered event instead.
+    #[n(2)]
+    pub cursor: u64,
+}
+
+// Block id of the next event pushed to the subscriber. Subscriptions without a delivery
+// cursor in stable memory resume after the time of their cursor.
+fn next_block(subscription: &Subscription) -> u64 {
+    storage::delivery_cursor(&subscription.subscriber)
+        .unwrap_or_else(|| storage::first_block_after(subscription.cursor))
+}
+
+// Events of the subscription from block `start` on, at most MAX_PUSHED_EVENTS_PER_BATCH
+// of the next MAX_EXAMINED_EVENTS_PER_PUSH events, together with the block id following
+// the last examined event.
+fn pending_events(subscription: &Subscription, start: u64) -> (Vec<Event>, u64) {
+    let compacted = storage::compacted_event_count();
+    if start < compacted {
+        crate::logs::log!(
+            INFO,
+            "\nBlocks {start} to {compacted} were compacted before being pushed to {}",
+            subscription.subscriber
+        );
+    }
+    let start = start.max(compacted);
+    let end = storage::block_count().min(start.saturating_add(MAX_EXAMINED_EVENTS_PER_PUSH));
+
+    let mut events = Vec::new();
+    let mut next = start;
+    for event in storage::get_events(start - compacted, end - start) {
+        next += 1;
+        if subscription.filter.matches(&event.payload) {
+            events.push(event);
+            if events.len() >= MAX_PUSHED_EVENTS_PER_BATCH {
+                break;
+            }
+        }
+    }
+    (events, next)
+}
+
+// push the events recorded since the last delivery to every subscriber. Each subscriber
+// has at most one delivery waiting for its reply, deliveries not replied to within
+// SUBSCRIBER_REPLY_TIMEOUT count as failed on every run. Subscribers failing
+// MAX_DELIVERY_FAILURES deliveries in a row are unsubscribed.
+pub fn push_events() {
+    let guard = match TimerGuard::new(TaskType::PushEvents) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    let subscriptions: Vec<Subscription> =
+        read_state(|s| s.subscriptions.values().cloned().collect());
+    let now = ic_cdk::api::time();
+
+    for subscription in subscriptions {
+        let subscriber = subscription.subscriber;
+        if let Some(sent_at) = read_state(|s| s.pending_deliveries.get(&subscriber).copied()) {
+            if now.saturating_sub(sent_at) > SUBSCRIBER_REPLY_TIMEOUT.as_nanos() as u64 {
+                record_failed_delivery(subscriber, "no reply to the pending delivery".to_string());
+            }
+            continue;
+        }
+
+        let (events, next) = pending_events(&subscription, next_block(&subscription));
+        if events.is_empty() {
+            storage::set_delivery_cursor(&subscriber, next);
+            continue;
+        }
+
+        guard.record_items(events.len());
+        guard.record_outcall();
+        mutate_state(|s| s.pending_deliveries.insert(subscriber, now));
+        ic_cdk::spawn(deliver(subscriber, events, next, now));
+    }
+}
+
+async fn deliver(subscriber: Principal, events: Vec<Event>, next: u64, sent_at: u64) {
+    let batch: Vec<CandidEvent> = events.iter().map(CandidEvent::from).collect();
+    let result: Result<(), _> = ic_cdk::call(subscriber, "on_minter_events", (batch,)).await;
+
+    // unsubscribed while the delivery was pending
+    let pending = mutate_state(|s| {
+        if s.pending_deliveries.get(&subscriber) != Some(&sent_at) {
+            return false;
+        }
+        s.pending_deliveries.remove(&subscriber);
+        true
+    });
+    if !pending {
+        return;
+    }
+
+    match result {
+        Ok(()) => {
+            mutate_state(|s| s.delivery_failures.remove(&subscriber));
+            storage::set_delivery_cursor(&subscriber, next);
+        }
+        Err((code, msg)) => record_failed_delivery(subscriber, format!("{code:?}: {msg}")),
+    }
+}
+
+fn record_failed_delivery(subscriber: Principal, reason: String) {
+    crate::logs::log!(
+        INFO,
+        "\nFailed to push events to subscriber {subscriber}: {reason}"
+    );
+    let unsubscribed = mutate_state(|s| {
+        let failures = s.delivery_failures.entry(subscriber).or_default();
+        *failures += 1;
+        if *failures < MAX_DELIVERY_FAILURES {
+            return false;
+        }
+        s.delivery_failures.remove(&subscriber);
+        process_event(
+            s,
+            EventType::Unsubscribed {
+                subscriber,
+                reason: Some(format!(
+                    "{MAX_DELIVERY_FAILURES} deliveries failed, last: {reason}"
+                )),
+            },
+        );
+        true
+    });
+    if unsubscribed {
+        storage::remove_delivery_cursor(&subscriber);
+    }
+}
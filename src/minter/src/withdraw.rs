@@ -1,11 +1,28 @@
 use crate::{
-    events::WithdrawalEvent,
-    get_derivation_path,
-    guard::retrieve_sol_guard,
-    logs::DEBUG,
-    state::{audit::process_event, event::EventType, mutate_state, read_state, State},
+    assets::BridgedAsset,
+    certification::update_certified_coupons_of,
+    constants::{
+        COUPON_DOMAIN_SEPARATOR, MAX_CONCURRENT_SIGNINGS, SIGN_WITH_ECDSA_CYCLES,
+        WITHDRAW_RETRY_AFTER,
+    },
+    coupon_payload::{chain_tag, CouponPayload, SplPayout, NO_EXPIRY},
+    deposit::mint_batch,
+    escda,
+    events::{DepositEvent, WithdrawalEvent},
+    get_cosigner_derivation_path, get_derivation_path, get_network,
+    guard::{retrieve_sol_guard, BurnGuard, Guard, PendingRetrieveSolRequests, TimerGuard},
+    icrc3::{get_block, BurnBlock},
+    logs::{DEBUG, INFO},
+    sol_rpc_client::LedgerMemo,
+    spl::{decode_pubkey, get_associated_token_address, SolanaAddress, SplError},
+    state::{
+        audit::process_event, event::EventType, lazy_call_ecdsa_public_key, mutate_state,
+        read_state, ApprovalStatus, CachedSignature, DestinationAddress, EcdsaKeyError,
+        RetryTarget, State, TaskType,
+    },
 };
 
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as base64_url, Engine};
 use candid::CandidType;
 use candid::Nat;
 use candid::Principal;
@@ -15,159 +32,277 @@ use ic_cdk::api::{
         sign_with_ecdsa, EcdsaCurve, EcdsaKeyId, SignWithEcdsaArgument, SignWithEcdsaResponse,
     },
 };
+use icrc_ledger_types::icrc1::{
+    account::Account,
+    transfer::{TransferArg, TransferError},
+};
 use icrc_ledger_types::icrc2::transfer_from::TransferFromError;
 use k256::ecdsa::{signature::Verifier, RecoveryId, Signature, VerifyingKey};
 use minicbor::{Decode, Encode};
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
-#[derive(CandidType, Debug, Clone, PartialEq, Eq)]
+#[derive(CandidType, Debug, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum WithdrawError {
+    #[error("Failed to burn gSOL: {0:?}")]
     BurningGSolFailed(TransferFromError),
+    #[error("Failed to send a message to the ledger {ledger_id}: {code:?}: {msg}")]
     SendingMessageToLedgerFailed {
         ledger_id: String,
         code: i32,
         msg: String,
     },
+    #[error("Failed to sign with ECDSA for burn_id: {burn_id} error: {code:?}: {msg}")]
     SigningWithEcdsaFailed {
         burn_id: u64,
         code: RejectionCode,
         msg: String,
     },
-    CouponError {
-        burn_id: u64,
-        err: CouponError,
-    },
+    #[error("Failed to generate a coupon for burn_id {burn_id} error: {err}")]
+    CouponError { burn_id: u64, err: CouponError },
+    #[error("Unknown burn_id {0}")]
     UnknownBurnId(u64),
+    #[error("Redeemed event does NOT hold coupon: {0}")]
     RedeemedEventError(u64),
+    #[error("Invalid Solana destination address: {0}")]
+    InvalidSolanaAddress(String),
+    #[error("Invalid SPL mint address: {0}")]
+    InvalidSplMint(String),
+    #[error("SPL mint {0} is not bridged by the minter")]
+    UnsupportedAsset(String),
+    #[error("Withdrawals to {0} are not allowed")]
+    DestinationBlocked(String),
+    #[error("burn_id {0} is already being processed")]
+    AlreadyProcessing(u64),
+    #[error("Burn block index is required to withdraw")]
+    MissingBurnBlockIndex,
+    #[error("Failed to verify burn block {block_index}: {reason}")]
+    BurnVerificationFailed { block_index: u64, reason: String },
+    #[error("Withdraw amount is less than minimum withdrawal amount {minimum}")]
+    AmountTooLow { minimum: Nat },
+    #[error("Withdraw amount is greater than maximum withdrawal amount {maximum}")]
+    AmountTooHigh { maximum: Nat },
+    #[error("Invalid withdraw amount: {0}")]
+    InvalidAmount(String),
+    #[error("Anonymous principal is not allowed")]
+    AnonymousCaller,
+    #[error("Withdrawal is temporarily unavailable, retry after {retry_after}s")]
+    TemporarilyUnavailable {
+        // seconds
+        retry_after: u64,
+    },
+    #[error("Too many calls, retry after {retry_after}s")]
+    RateLimited {
+        // seconds
+        retry_after: u64,
+    },
+    #[error("Withdrawals are paused: {0}")]
+    Paused(String),
+    #[error("Coupon for burn_id {0} is queued for signing, retrieve it with get_coupon")]
+    CouponPending(u64),
+    #[error("Coupons cannot be signed: {0}")]
+    EcdsaKeyUnavailable(EcdsaKeyError),
+    #[error("Withdrawal with burn_id {0} is awaiting an operator's approval")]
+    AwaitingApproval(u64),
+    #[error("Withdrawal with burn_id {burn_id} was rejected by an operator: {reason}")]
+    ApprovalRejected { burn_id: u64, reason: String },
+    #[error("{0} is not in the address book of the caller")]
+    DestinationNotRegistered(String),
+    #[error("{address} cannot receive withdrawals before {active_at}")]
+    DestinationNotYetActive { address: String, active_at: u64 },
+    #[error("Coupon for burn_id {burn_id} is released at {release_at}")]
+    CouponTimeLocked { burn_id: u64, release_at: u64 },
+    #[error("Coupon for burn_id {0} was revoked")]
+    CouponRevoked(u64),
 }
 
-impl std::fmt::Display for WithdrawError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            WithdrawError::BurningGSolFailed(err) => {
-                write!(f, "Failed to burn gSOL: {err:?}")
-            }
-            WithdrawError::SendingMessageToLedgerFailed {
-                ledger_id,
-                code,
-                msg,
-            } => {
-                write!(
-                    f,
-                    "Failed to send a message to the ledger {ledger_id}: {code:?}: {msg}",
-                )
-            }
-            WithdrawError::SigningWithEcdsaFailed { burn_id, code, msg } => {
-                write!(
-                    f,
-                    "Failed to sign with ECDSA for burn_id: {burn_id} error: {code:?}: {msg}",
-                )
-            }
-            WithdrawError::CouponError { burn_id, err } => {
-                write!(
-                    f,
-                    "Failed to generate a coupon for burn_id {burn_id} error: {err}"
-                )
-            }
-            WithdrawError::UnknownBurnId(burn_id) => {
-                write!(f, "Unknown burn_id {burn_id}")
-            }
-            WithdrawError::RedeemedEventError(burn_id) => {
-                write!(f, "Redeemed event does NOT hold coupon: {burn_id}")
-            }
-        }
-    }
-}
-
-#[derive(CandidType, Debug, Clone, PartialEq, Eq)]
+#[derive(CandidType, Debug, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum CouponError {
+    #[error("Failed to hex-decode")]
     HexDecodingError,
+    #[error("Failed to deserialize")]
     DeserializationError,
+    #[error("Failed to recover key")]
     RecoveryError,
+    #[error("Failed to recover the parity bit from a signature: {signature}, pubkey: {pubkey}")]
     ParityRecoveryFailed { signature: String, pubkey: String },
-}
-
-impl std::fmt::Display for CouponError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            CouponError::HexDecodingError => {
-                write!(f, "Failed to hex-decode")
-            }
-            CouponError::DeserializationError => {
-                write!(f, "Failed to deserialize")
-            }
-            CouponError::RecoveryError => {
-                write!(f, "Failed to recover key")
-            }
-            CouponError::ParityRecoveryFailed { signature, pubkey } => {
-                write!(f, "Failed to recover the parity bit from a signature: {signature}, pubkey: {pubkey}")
-            }
-        }
-    }
+    #[error("Coupon was issued for domain {found}, expected {expected}")]
+    InvalidDomain { expected: String, found: String },
+    #[error("Coupon was signed by an untrusted key {0}")]
+    UntrustedPublicKey(String),
+    #[error("Compact coupon version {0} is not supported")]
+    UnsupportedCompactVersion(u8),
+    #[error("Invalid coupon payload: {0}")]
+    InvalidPayload(String),
+    #[error("Coupon pays out more than the dual signature threshold and lacks a cosignature")]
+    MissingCosignature,
+    #[error("Coupon was cosigned by an untrusted key {0}")]
+    UntrustedCosignerKey(String),
+    #[error("Coupon of burn {0} has no domain and is not the legacy coupon stored for the burn")]
+    UnknownLegacyCoupon(u64),
 }
 
 pub async fn get_withdraw_info(user: Principal) -> UserWithdrawInfo {
-    let withdrawal_redeemed_events = read_state(|s| s.withdrawal_redeemed_events.clone());
     let mut coupons = Vec::new();
+    let mut burn_ids = Vec::new();
 
-    for (_, event) in withdrawal_redeemed_events.iter() {
-        if event.from_icp_address == user {
+    read_state(|s| {
+        for event in s.withdrawals_of(&user) {
+            let burn_id = event.get_burn_id();
+            if s.withdrawal_burned_events.contains_key(&burn_id) {
+                burn_ids.push(burn_id);
+                continue;
+            }
+            if s.is_revoked_coupon(burn_id) {
+                continue;
+            }
             match event.get_coupon() {
                 Some(coupon) => coupons.push(coupon.clone()),
-                None => ic_canister_log::log!(DEBUG, "Redeemed event does NOT hold coupon"),
+                None => crate::logs::log!(DEBUG, "Redeemed event does NOT hold coupon"),
             }
         }
-    }
-
-    let withdrawal_burned_events = read_state(|s| s.withdrawal_burned_events.clone());
-    let mut burn_ids = Vec::new();
-
-    withdrawal_burned_events.iter().for_each(|(_, event)| {
-        if event.from_icp_address == user {
-            burn_ids.push(event.get_burn_id());
-        }
     });
 
     UserWithdrawInfo { coupons, burn_ids }
 }
 
+fn withdraw_guard(from: Principal) -> Result<Guard<PendingRetrieveSolRequests>, WithdrawError> {
+    retrieve_sol_guard(from).map_err(|e| {
+        crate::logs::log!(
+            DEBUG,
+            "Failed retrieving guard for principal {}: {:?}",
+            from,
+            e
+        );
+        WithdrawError::TemporarilyUnavailable {
+            retry_after: WITHDRAW_RETRY_AFTER.as_secs(),
+        }
+    })
+}
+
 pub async fn withdraw_gsol(
     from: Principal,
     to: String,
     amount: Nat,
+    spl_mint: Option<String>,
+    burn_block_index: Option<u64>,
 ) -> Result<Coupon, WithdrawError> {
-    let _guard = retrieve_sol_guard(from).unwrap_or_else(|e| {
-        ic_cdk::trap(&format!(
-            "Failed retrieving guard for principal {}: {:?}",
-            from, e
-        ))
-    });
+    check_not_paused()?;
+    if read_state(|s| s.draining_since.is_some()) {
+        return Err(WithdrawError::TemporarilyUnavailable {
+            retry_after: WITHDRAW_RETRY_AFTER.as_secs(),
+        });
+    }
+    let _guard = withdraw_guard(from)?;
+
+    let to = SolanaAddress::parse(&to)
+        .map_err(|e| WithdrawError::InvalidSolanaAddress(e.to_string()))?;
+    let asset = match &spl_mint {
+        Some(mint) => Some(
+            read_state(|s| s.asset(mint).cloned())
+                .ok_or_else(|| WithdrawError::UnsupportedAsset(mint.clone()))?,
+        ),
+        None => None,
+    };
+    if let Some(asset) = &asset {
+        if amount < asset.minimum_withdrawal_amount {
+            return Err(WithdrawError::AmountTooLow {
+                minimum: asset.minimum_withdrawal_amount.clone(),
+            });
+        }
+    }
+    let spl_destination = match spl_mint {
+        Some(mint) => Some(resolve_spl_destination(&to, mint)?),
+        None => None,
+    };
 
-    let mut event = burn_gsol(&from, &to, amount).await.map_err(|err| err)?;
+    let destinations =
+        std::iter::once(to.as_str()).chain(spl_destination.as_ref().map(|(_, ata)| ata.as_str()));
+    for address in destinations {
+        if read_state(|s| s.is_blocked_address(address)) {
+            let err = WithdrawError::DestinationBlocked(address.to_string());
+            process_withdrawal_rejected_event(&from, to.as_str(), &err);
+            return Err(err);
+        }
+    }
+    if let Err(err) = check_address_book(&from, &to) {
+        process_withdrawal_rejected_event(&from, to.as_str(), &err);
+        return Err(err);
+    }
+
+    let mut event = burn_gsol(&from, &to, amount, asset, spl_destination, burn_block_index)
+        .await
+        .map_err(|err| err)?;
+    if read_state(|s| s.requires_release_delay(&event.payout_amount().0)) {
+        let burn_id = event.get_burn_id();
+        let delay_secs = read_state(|s| s.release_delay_secs);
+        let release_at =
+            ic_cdk::api::time().saturating_add(delay_secs.saturating_mul(1_000_000_000));
+        crate::logs::log!(
+            INFO,
+            "\nCoupon of withdrawal {burn_id} is released at {release_at}"
+        );
+        mutate_state(|s| {
+            process_event(
+                s,
+                EventType::WithdrawalTimeLocked {
+                    burn_id,
+                    release_at,
+                },
+            )
+        });
+    }
+    if read_state(|s| s.requires_approval(&event.payout_amount().0)) {
+        let burn_id = event.get_burn_id();
+        crate::logs::log!(INFO, "\nWithdrawal {burn_id} is held for approval");
+        mutate_state(|s| process_event(s, EventType::WithdrawalHeldForApproval { burn_id }));
+        return Err(WithdrawError::AwaitingApproval(burn_id));
+    }
+    check_release(event.get_burn_id())?;
+    if !has_signing_capacity() {
+        return Err(WithdrawError::CouponPending(event.get_burn_id()));
+    }
     let coupon = generate_coupon(&mut event).await.map_err(|err| err)?;
 
     Ok(coupon)
 }
 
 pub async fn get_coupon(from: Principal, burn_id: u64) -> Result<Coupon, WithdrawError> {
-    let _guard = retrieve_sol_guard(from).unwrap_or_else(|e| {
-        ic_cdk::trap(&format!(
-            "Failed retrieving guard for principal {}: {:?}",
-            from, e
-        ))
-    });
+    // issued coupons are still returned while the bridge is paused, but none is signed
+    if !read_state(|s| s.withdrawal_redeemed_events.contains_key(&burn_id)) {
+        check_not_paused()?;
+    }
+    let _guard = withdraw_guard(from)?;
 
-    let events = read_state(|s| s.withdrawal_redeemed_events.clone());
+    if read_state(|s| s.is_revoked_coupon(burn_id)) {
+        return Err(WithdrawError::CouponRevoked(burn_id));
+    }
 
-    match events.get(&burn_id) {
-        Some(redeemed_event) => match redeemed_event.get_coupon() {
-            Some(coupon) => Ok(coupon.clone()),
-            None => Err(WithdrawError::RedeemedEventError(burn_id)),
-        },
+    match read_state(|s| s.withdrawal_redeemed_events.get(&burn_id)) {
+        Some(mut redeemed_event) => {
+            let coupon = match redeemed_event.get_coupon() {
+                Some(coupon) => coupon.clone(),
+                None => return Err(WithdrawError::RedeemedEventError(burn_id)),
+            };
+            // coupons signed before a key rotation, or lacking a cosignature required since,
+            // are re-signed with the current key
+            lazy_call_ecdsa_public_key().await;
+            let cosigned = coupon.is_cosigned()
+                || !read_state(|s| s.requires_cosignature(&redeemed_event.payout_amount().0));
+            if cosigned
+                && read_state(|s| s.current_public_key_hex())
+                    == Some(coupon.icp_public_key_hex.clone())
+            {
+                return Ok(coupon);
+            }
+            resign_coupon(&mut redeemed_event).await
+        }
         None => {
             let burned_events = read_state(|s| s.withdrawal_burned_events.clone());
             match burned_events.get(&burn_id) {
+                Some(_) if !has_signing_capacity() => Err(WithdrawError::CouponPending(burn_id)),
                 Some(burned_event) => {
                     let mut event = burned_event.clone();
                     let coupon = generate_coupon(&mut event).await.map_err(|err| err)?;
@@ -179,11 +314,153 @@ pub async fn get_coupon(from: Principal, burn_id: u64) -> Result<Coupon, Withdra
     }
 }
 
+// Principals with an address book only withdraw to its active addresses, so that a stolen
+// session key cannot send funds to a new address before the owner notices.
+fn check_address_book(from: &Principal, to: &SolanaAddress) -> Result<(), WithdrawError> {
+    match read_state(|s| {
+        s.address_books
+            .get(from)
+            .map(|book| book.get(to.as_str()).copied())
+    }) {
+        None => Ok(()),
+        Some(None) => Err(WithdrawError::DestinationNotRegistered(to.to_string())),
+        Some(Some(active_at)) if ic_cdk::api::time() < active_at => {
+            Err(WithdrawError::DestinationNotYetActive {
+                address: to.to_string(),
+                active_at,
+            })
+        }
+        Some(Some(_)) => Ok(()),
+    }
+}
+
+/// Registers `address` in the address book of `owner`. Once a principal has an address
+/// book, it can only withdraw to the addresses in it whose activation delay elapsed.
+pub fn add_destination_address(
+    owner: Principal,
+    address: String,
+) -> Result<DestinationAddress, WithdrawError> {
+    let address = SolanaAddress::parse(&address)
+        .map_err(|e| WithdrawError::InvalidSolanaAddress(e.to_string()))?;
+    if read_state(|s| s.is_blocked_address(address.as_str())) {
+        return Err(WithdrawError::DestinationBlocked(address.to_string()));
+    }
+    let registered = read_state(|s| {
+        s.destination_addresses(&owner)
+            .into_iter()
+            .find(|destination| destination.address == address.as_str())
+    });
+    if let Some(destination) = registered {
+        return Ok(destination);
+    }
+
+    let delay_secs = read_state(|s| s.destination_activation_delay_secs);
+    let destination = DestinationAddress {
+        address: address.to_string(),
+        active_at: ic_cdk::api::time().saturating_add(delay_secs.saturating_mul(1_000_000_000)),
+    };
+    crate::logs::log!(
+        INFO,
+        "\n{owner} added {address} to its address book, active at {}",
+        destination.active_at
+    );
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::DestinationAddressAdded {
+                owner,
+                destination: destination.clone(),
+            },
+        )
+    });
+
+    Ok(destination)
+}
+
+/// Removes `address` from the address book of `owner`, withdrawals to it are rejected
+/// right away.
+pub fn remove_destination_address(owner: Principal, address: String) {
+    let registered = read_state(|s| {
+        s.address_books
+            .get(&owner)
+            .is_some_and(|book| book.contains_key(&address))
+    });
+    if registered {
+        mutate_state(|s| process_event(s, EventType::DestinationAddressRemoved { owner, address }));
+    }
+}
+
+// Returns the SPL mint together with the associated token account of `owner` that receives the tokens.
+fn resolve_spl_destination(
+    owner: &SolanaAddress,
+    mint: String,
+) -> Result<(String, String), WithdrawError> {
+    decode_pubkey(&mint).map_err(|e| WithdrawError::InvalidSplMint(e.to_string()))?;
+    // as with the token program clients, token accounts are only derived for wallet owners,
+    // an off-curve owner is most likely a token account given by mistake
+    if !owner.is_on_curve() {
+        return Err(WithdrawError::InvalidSolanaAddress(format!(
+            "{owner} is not a wallet address, SPL tokens are sent to the token account of a wallet"
+        )));
+    }
+
+    let token_account =
+        get_associated_token_address(owner.as_str(), &mint).map_err(|e| match e {
+            SplError::NoViableBumpSeed => WithdrawError::InvalidSolanaAddress(e.to_string()),
+            _ => WithdrawError::InvalidSplMint(e.to_string()),
+        })?;
+
+    Ok((mint, token_account))
+}
+
+/// Re-signs the coupons of the given burned but unredeemed withdrawals.
+pub async fn regenerate_coupons(burn_ids: Vec<u64>) -> Vec<(u64, Result<Coupon, WithdrawError>)> {
+    let mut results = Vec::with_capacity(burn_ids.len());
+
+    for burn_id in burn_ids {
+        let result = match read_state(|s| {
+            if s.is_revoked_coupon(burn_id) {
+                return Err(WithdrawError::CouponRevoked(burn_id));
+            }
+            s.withdrawal_burned_events
+                .get(&burn_id)
+                .cloned()
+                .ok_or(WithdrawError::UnknownBurnId(burn_id))
+        }) {
+            Ok(mut event) => generate_coupon(&mut event).await,
+            Err(err) => Err(err),
+        };
+
+        results.push((burn_id, result));
+    }
+
+    results
+}
+
 async fn burn_gsol(
     from: &Principal,
-    to: &String,
+    to: &SolanaAddress,
     amount: Nat,
+    asset: Option<BridgedAsset>,
+    spl_destination: Option<(String, String)>,
+    burn_block_index: Option<u64>,
 ) -> Result<WithdrawalEvent, WithdrawError> {
+    // Bridged assets are burned on their own ledger. Without a configured ledger the
+    // burn is executed and trusted by the calling canister.
+    let ledger_id = match &asset {
+        Some(asset) => Some(asset.ledger_id),
+        None => read_state(|s| s.ledger_id),
+    };
+    let burn_block_index = match ledger_id {
+        Some(ledger_id) => {
+            let block_index = burn_block_index.ok_or(WithdrawError::MissingBurnBlockIndex)?;
+            let spl_mint = asset.as_ref().map(|asset| asset.spl_mint.as_str());
+            verify_burn_block(ledger_id, spl_mint, from, to, &amount, block_index).await?;
+            block_index
+        }
+        None => 0,
+    };
+
     let mut event = WithdrawalEvent::new(
         mutate_state(State::next_burn_id),
         from.clone(),
@@ -191,8 +468,15 @@ async fn burn_gsol(
         amount,
     );
 
+    if let Some((mint, token_account)) = spl_destination {
+        event = event.with_spl_destination(mint, token_account);
+    }
+    if let Some(asset) = asset {
+        event = event.with_fee(asset.fee);
+    }
+
     // update event with the burn block index
-    event.update_after_burn(ic_cdk::api::time(), 0);
+    event.update_after_burn(ic_cdk::api::time(), burn_block_index);
 
     process_withdrawal_burn_event(&event, None);
 
@@ -239,10 +523,109 @@ async fn burn_gsol(
     // }
 }
 
+// Checks that the ledger holds a burn of `amount` from `from` whose memo is the destination's
+// public key, and that no other withdrawal was issued for the same block.
+async fn verify_burn_block(
+    ledger_id: Principal,
+    spl_mint: Option<&str>,
+    from: &Principal,
+    to: &SolanaAddress,
+    amount: &Nat,
+    block_index: u64,
+) -> Result<(), WithdrawError> {
+    let fail = |reason: String| WithdrawError::BurnVerificationFailed {
+        block_index,
+        reason,
+    };
+
+    let block = get_block(ledger_id, block_index)
+        .await
+        .map_err(|(code, msg)| WithdrawError::SendingMessageToLedgerFailed {
+            ledger_id: ledger_id.to_string(),
+            code: code as i32,
+            msg,
+        })?
+        .ok_or_else(|| fail("block not found".to_string()))?;
+
+    let burn = BurnBlock::try_from(&block).map_err(fail)?;
+
+    if burn.from != *from {
+        return Err(fail(format!("burned by {}, expected {from}", burn.from)));
+    }
+    if burn.amount != *amount {
+        return Err(fail(format!("burned {}, expected {amount}", burn.amount)));
+    }
+    let expected_memo = to.to_bytes().to_vec();
+    if burn.memo.as_ref() != Some(&expected_memo) {
+        return Err(fail(
+            "memo does not match the destination address".to_string(),
+        ));
+    }
+    if read_state(|s| s.is_burn_block_used(spl_mint, block_index)) {
+        return Err(fail("block was already used for a withdrawal".to_string()));
+    }
+
+    Ok(())
+}
+
+fn has_signing_capacity() -> bool {
+    read_state(|s| s.processing_burn_ids.len() < MAX_CONCURRENT_SIGNINGS)
+}
+
+/// Signs the coupons of queued burned withdrawals, at most [MAX_CONCURRENT_SIGNINGS]
+/// at the same time.
+pub async fn sign_pending_coupons() {
+    let _guard = match TimerGuard::new(TaskType::SignPendingCoupons) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    let pending: Vec<WithdrawalEvent> = read_state(|s| {
+        if s.is_paused() {
+            return vec![];
+        }
+        let capacity = MAX_CONCURRENT_SIGNINGS.saturating_sub(s.processing_burn_ids.len());
+        s.coupons_to_sign(ic_cdk::api::time(), capacity)
+    });
+
+    if pending.is_empty() {
+        return;
+    }
+    let now = ic_cdk::api::time();
+    mutate_state(|s| {
+        s.prune_attempts();
+        for event in &pending {
+            s.record_attempt(RetryTarget::Withdrawal(event.get_burn_id()), now);
+        }
+    });
+
+    crate::logs::log!(DEBUG, "\nSigning {} queued coupons", pending.len());
+
+    futures::future::join_all(
+        pending
+            .into_iter()
+            .map(|mut event| async move { generate_coupon(&mut event).await }),
+    )
+    .await;
+}
+
 async fn generate_coupon(event: &mut WithdrawalEvent) -> Result<Coupon, WithdrawError> {
+    check_not_paused()?;
+    // a revoked coupon is not signed, it would be returned once the revocation is lifted
+    if read_state(|s| s.is_revoked_coupon(event.get_burn_id())) {
+        return Err(WithdrawError::CouponRevoked(event.get_burn_id()));
+    }
+    check_approval(event.get_burn_id())?;
+    check_release(event.get_burn_id())?;
+    // the same burn must never be signed twice in parallel, it can only be redeemed once
+    let _guard = BurnGuard::new(event.get_burn_id())
+        .map_err(|_| WithdrawError::AlreadyProcessing(event.get_burn_id()))?;
+
     match event.to_coupon().await {
         Ok(coupon) => {
             event.update_after_redeem(coupon.clone());
+            event.update_redeemed_at(ic_cdk::api::time());
+            event.update_solana_block(read_state(|s| s.latest_solana_block));
             process_withdrawal_redeem_event(event);
             Ok(coupon)
         }
@@ -253,10 +636,283 @@ async fn generate_coupon(event: &mut WithdrawalEvent) -> Result<Coupon, Withdraw
     }
 }
 
+// Coupons release funds on Solana, none is signed while the bridge is paused.
+fn check_not_paused() -> Result<(), WithdrawError> {
+    match read_state(|s| s.paused.clone()) {
+        Some(reason) => Err(WithdrawError::Paused(reason)),
+        None => Ok(()),
+    }
+}
+
+// Coupons of withdrawals held for approval are only signed once an operator approved them.
+fn check_approval(burn_id: u64) -> Result<(), WithdrawError> {
+    match read_state(|s| s.withdrawal_approvals.get(&burn_id).cloned()) {
+        None => Ok(()),
+        Some(ApprovalStatus::Pending) => Err(WithdrawError::AwaitingApproval(burn_id)),
+        Some(ApprovalStatus::Rejected { reason }) => {
+            Err(WithdrawError::ApprovalRejected { burn_id, reason })
+        }
+    }
+}
+
+// Coupons of time-locked withdrawals are only signed once their release delay elapsed.
+fn check_release(burn_id: u64) -> Result<(), WithdrawError> {
+    let now = ic_cdk::api::time();
+    match read_state(|s| s.time_locked_withdrawals.get(&burn_id).copied()) {
+        Some(release_at) if now < release_at => Err(WithdrawError::CouponTimeLocked {
+            burn_id,
+            release_at,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Cancels a time-locked withdrawal before its coupon is released, or a rejected one, and
+/// re-mints the burned amount to the sender. Calling it again for a cancelled withdrawal
+/// whose re-mint failed retries the re-mint.
+pub async fn cancel_withdrawal(
+    burn_id: u64,
+    reason: String,
+    caller: Principal,
+) -> Result<(), String> {
+    // the coupon cannot be signed, nor the re-mint retried, while the withdrawal is cancelled
+    let _guard = BurnGuard::new(burn_id)
+        .map_err(|_| format!("withdrawal {burn_id} is already being processed"))?;
+
+    let cancelled_now = !read_state(|s| s.cancelled_withdrawals.contains_key(&burn_id));
+    if cancelled_now {
+        if !read_state(|s| {
+            s.is_time_locked(burn_id, ic_cdk::api::time()) || s.is_rejected_withdrawal(burn_id)
+        }) {
+            return Err(format!(
+                "withdrawal {burn_id} is neither time-locked nor rejected"
+            ));
+        }
+        crate::logs::log!(
+            INFO,
+            "\nWithdrawal {burn_id} cancelled by {caller}: {reason}"
+        );
+        mutate_state(|s| {
+            process_event(
+                s,
+                EventType::WithdrawalCancelled {
+                    burn_id,
+                    reason,
+                    caller,
+                    cancelled_at: Some(ic_cdk::api::time()),
+                },
+            )
+        });
+    }
+
+    let event = read_state(|s| s.cancelled_withdrawals.get(&burn_id).cloned())
+        .expect("the withdrawal was cancelled");
+    // no re-mint is attempted before the call cancelling the withdrawal
+    let unresolved = !cancelled_now && event.is_remint_unresolved();
+    let block_index = remint(&event, unresolved).await.map_err(|err| {
+        format!("withdrawal {burn_id} is cancelled but its re-mint failed: {err}")
+    })?;
+
+    crate::logs::log!(INFO, "\nCancelled withdrawal {burn_id} re-minted");
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::CancelledWithdrawalReminted {
+                burn_id,
+                block_index,
+            },
+        )
+    });
+
+    Ok(())
+}
+
+// Mints the burned amount back on the ledger it was burned on, or through the NFT canister
+// when the burn was trusted to the calling canister. Returns the block of the ledger mint.
+// Each attempt is recorded before the call, and its failure once known, `unresolved` tells
+// whether an earlier attempt may have minted without its outcome being recorded.
+//
+// Ledger mints carry the time of the first attempt and the burn id, so a retry after a lost
+// response is rejected as a duplicate of the first mint instead of minting twice. Once the
+// ledger rejects that time as too old, the mint is retried with a fresh time, unless an
+// earlier attempt may have minted, which the ledger no longer deduplicates. Mints through
+// the NFT canister are not deduplicated, they are refused while an earlier attempt may have
+// minted. A controller resolves such attempts with `resolve_remint`.
+async fn remint(event: &WithdrawalEvent, unresolved: bool) -> Result<Option<u64>, String> {
+    let burn_id = event.get_burn_id();
+    let ledger_id = match &event.spl_mint {
+        Some(mint) => Some(
+            read_state(|s| s.asset(mint).map(|asset| asset.ledger_id))
+                .ok_or_else(|| format!("SPL mint {mint} is not bridged by the minter"))?,
+        ),
+        None => read_state(|s| s.ledger_id),
+    };
+
+    let Some(ledger_id) = ledger_id else {
+        if unresolved {
+            return Err(
+                "an earlier re-mint through the NFT canister may have minted, resolve it with resolve_remint"
+                    .to_string(),
+            );
+        }
+        record_remint_attempt(burn_id, None);
+        let err = match mint_batch(&[DepositEvent::remint(event)]).await {
+            Ok(results) => match results.into_iter().next() {
+                Some(Ok(_)) => return Ok(None),
+                Some(Err((_, err))) => format!("failed to mint through the NFT canister: {err}"),
+                None => "the NFT canister returned no result".to_string(),
+            },
+            Err((code, msg)) => format!("{code:?}: {msg}"),
+        };
+        record_remint_failure(burn_id, &err);
+        return Err(err);
+    };
+
+    let mut created_at = event
+        .get_remint_created_at()
+        .unwrap_or_else(ic_cdk::api::time);
+    let mut refreshed = false;
+    loop {
+        record_remint_attempt(burn_id, Some(created_at));
+        let transfer = TransferArg {
+            from_subaccount: None,
+            to: Account {
+                owner: event.from_icp_address,
+                subaccount: None,
+            },
+            fee: None,
+            created_at_time: Some(created_at),
+            memo: Some(LedgerMemo(burn_id).into()),
+            amount: event.amount.clone(),
+        };
+        let result: Result<(Result<Nat, TransferError>,), _> =
+            ic_cdk::call(ledger_id, "icrc1_transfer", (transfer,)).await;
+        let err = match result {
+            // a duplicate is an earlier attempt that minted, its response was lost
+            Ok((Ok(block_index),))
+            | Ok((Err(TransferError::Duplicate {
+                duplicate_of: block_index,
+            }),)) => {
+                let block_index = block_index
+                    .0
+                    .to_u64()
+                    .ok_or("block index does not fit into u64")?;
+                return Ok(Some(block_index));
+            }
+            Ok((Err(TransferError::TooOld),)) if unresolved => {
+                return Err(
+                    "the ledger rejects the re-mint as too old and an earlier attempt may have minted, resolve it with resolve_remint"
+                        .to_string(),
+                );
+            }
+            // every attempt at `created_at` failed, a fresh time cannot mint twice
+            Ok((Err(TransferError::TooOld),)) if !refreshed => {
+                record_remint_failure(burn_id, "created_at_time too old");
+                created_at = ic_cdk::api::time();
+                refreshed = true;
+                continue;
+            }
+            Ok((Err(err),)) => format!("failed to mint on the ledger {ledger_id}: {err:?}"),
+            Err((code, msg)) => format!("{code:?}: {msg}"),
+        };
+        // the failure of this attempt leaves the outcome of an earlier one unknown
+        if !unresolved {
+            record_remint_failure(burn_id, &err);
+        }
+        return Err(err);
+    }
+}
+
+fn record_remint_attempt(burn_id: u64, created_at: Option<u64>) {
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::RemintAttempted {
+                burn_id,
+                created_at,
+            },
+        )
+    });
+}
+
+fn record_remint_failure(burn_id: u64, reason: &str) {
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::RemintFailed {
+                burn_id,
+                reason: reason.to_string(),
+            },
+        )
+    });
+}
+
+/// Resolves the re-mint of a cancelled withdrawal whose outcome is unknown, once a
+/// controller checked whether it minted. A minted withdrawal counts as re-minted,
+/// otherwise `cancel_withdrawal` retries the re-mint.
+pub fn resolve_remint(burn_id: u64, minted: bool, caller: Principal) -> Result<(), String> {
+    let _guard = BurnGuard::new(burn_id)
+        .map_err(|_| format!("withdrawal {burn_id} is already being processed"))?;
+
+    let unresolved = read_state(|s| {
+        s.cancelled_withdrawals
+            .get(&burn_id)
+            .map(WithdrawalEvent::is_remint_unresolved)
+    })
+    .ok_or_else(|| format!("withdrawal {burn_id} is not awaiting its re-mint"))?;
+    if !unresolved {
+        return Err(format!(
+            "the outcome of the re-mint attempts of withdrawal {burn_id} is known"
+        ));
+    }
+
+    crate::logs::log!(
+        INFO,
+        "\nRe-mint of withdrawal {burn_id} resolved by {caller}, minted: {minted}"
+    );
+    mutate_state(|s| {
+        let payload = if minted {
+            EventType::CancelledWithdrawalReminted {
+                burn_id,
+                block_index: None,
+            }
+        } else {
+            EventType::RemintFailed {
+                burn_id,
+                reason: format!("resolved by {caller}"),
+            }
+        };
+        process_event(s, payload)
+    });
+
+    Ok(())
+}
+
+async fn resign_coupon(event: &mut WithdrawalEvent) -> Result<Coupon, WithdrawError> {
+    check_not_paused()?;
+    let _guard = BurnGuard::new(event.get_burn_id())
+        .map_err(|_| WithdrawError::AlreadyProcessing(event.get_burn_id()))?;
+
+    let coupon = event.to_coupon().await?;
+    event.update_after_redeem(coupon.clone());
+    event.update_solana_block(read_state(|s| s.latest_solana_block));
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::CouponResigned {
+                event_source: event.clone(),
+            },
+        )
+    });
+    update_certified_coupons_of(&[event.get_burn_id()]);
+
+    Ok(coupon)
+}
+
 /// Process events
 fn process_withdrawal_burn_event(withdraw_event: &WithdrawalEvent, err: Option<WithdrawError>) {
     if let Some(err) = err.clone() {
-        ic_canister_log::log!(DEBUG, "{err}");
+        crate::logs::log!(DEBUG, "{err}");
     }
 
     mutate_state(|s| {
@@ -270,6 +926,21 @@ fn process_withdrawal_burn_event(withdraw_event: &WithdrawalEvent, err: Option<W
     });
 }
 
+fn process_withdrawal_rejected_event(from: &Principal, to: &str, err: &WithdrawError) {
+    crate::logs::log!(DEBUG, "\nWithdrawal from {from} rejected: {err}");
+
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::WithdrawalRejected {
+                from: *from,
+                to_sol_address: to.to_string(),
+                reason: err.to_string(),
+            },
+        )
+    });
+}
+
 fn process_withdrawal_redeem_event(withdraw_event: &WithdrawalEvent) {
     mutate_state(|s| {
         process_event(
@@ -279,6 +950,20 @@ fn process_withdrawal_redeem_event(withdraw_event: &WithdrawalEvent) {
             },
         )
     });
+
+    update_certified_coupons_of(&[withdraw_event.get_burn_id()]);
+}
+
+// Signatures (r, s) and (r, n - s) are both valid, verifiers that reject malleable
+// signatures only accept the one with s in the lower half of the curve order.
+fn normalize_low_s(signature: &[u8]) -> Result<Vec<u8>, CouponError> {
+    let signature =
+        Signature::try_from(signature).map_err(|_| CouponError::DeserializationError)?;
+    Ok(signature
+        .normalize_s()
+        .unwrap_or(signature)
+        .to_bytes()
+        .to_vec())
 }
 
 /// Types
@@ -290,12 +975,24 @@ pub struct Coupon {
     pub message: String,
     #[n(1)]
     pub message_hash: String,
+    /// 64 byte (r, s) signature in hex format, always normalized to the low-S form
+    /// (s <= n/2) so that verifiers rejecting malleable signatures accept it.
     #[n(2)]
     pub signature_hex: String,
     #[n(3)]
     pub icp_public_key_hex: String,
+    /// secp256k1 recovery id (y parity of the signature's R point), lets verifiers
+    /// recover the signer's key ecrecover-style instead of embedding it.
     #[n(4)]
     pub recovery_id: Option<u8>,
+    /// Second signature of the message by the cosigner derivation path, only present on
+    /// coupons paying out at least the dual signature threshold. Low-S as `signature_hex`.
+    #[n(5)]
+    pub cosignature_hex: Option<String>,
+    #[n(6)]
+    pub cosigner_public_key_hex: Option<String>,
+    #[n(7)]
+    pub cosigner_recovery_id: Option<u8>,
 }
 
 impl Coupon {
@@ -312,60 +1009,398 @@ impl Coupon {
             signature_hex,
             icp_public_key_hex,
             recovery_id: None,
+            cosignature_hex: None,
+            cosigner_public_key_hex: None,
+            cosigner_recovery_id: None,
+        }
+    }
+
+    pub fn with_cosignature(
+        mut self,
+        cosignature_hex: String,
+        cosigner_public_key_hex: String,
+    ) -> Result<Self, CouponError> {
+        self.cosigner_recovery_id = Some(recovery_id_of(
+            &self.signed_bytes()?,
+            &cosignature_hex,
+            &cosigner_public_key_hex,
+        )?);
+        self.cosignature_hex = Some(cosignature_hex);
+        self.cosigner_public_key_hex = Some(cosigner_public_key_hex);
+        Ok(self)
+    }
+
+    pub fn is_cosigned(&self) -> bool {
+        self.cosignature_hex.is_some()
+    }
+
+    // Coupons issued before the binary payload layout sign their JSON message.
+    fn is_legacy(&self) -> bool {
+        self.message.starts_with('{')
+    }
+
+    /// Bytes covered by the signature: the hex decoded payload, or the JSON message of
+    /// legacy coupons.
+    pub fn signed_bytes(&self) -> Result<Vec<u8>, CouponError> {
+        if self.is_legacy() {
+            return Ok(self.message.as_bytes().to_vec());
         }
+        hex::decode(&self.message).map_err(|_| CouponError::HexDecodingError)
     }
 
     pub fn y_parity(&mut self) -> Result<u8, CouponError> {
-        let signature_bytes =
-            hex::decode(&self.signature_hex).map_err(|_| CouponError::HexDecodingError)?;
-        let signature = Signature::try_from(signature_bytes.as_slice())
-            .map_err(|_| CouponError::DeserializationError)?;
-        let pubkey_bytes =
-            hex::decode(&self.icp_public_key_hex).map_err(|_| CouponError::HexDecodingError)?;
-        let orig_key = VerifyingKey::from_sec1_bytes(&pubkey_bytes)
+        let parity = recovery_id_of(
+            &self.signed_bytes()?,
+            &self.signature_hex,
+            &self.icp_public_key_hex,
+        )?;
+        self.recovery_id = Some(parity);
+        Ok(parity)
+    }
+
+    /// Reconstructs the signer's public key from the signature and the recovery id, like
+    /// an ecrecover-style verifier does. Returns it uncompressed in hex format, so that it
+    /// can be compared to `icp_public_key_hex`.
+    pub fn recover_public_key(&self) -> Result<String, CouponError> {
+        let recovery_id = self.recovery_id.ok_or(CouponError::RecoveryError)?;
+        recover_key(&self.signed_bytes()?, &self.signature_hex, recovery_id)
+    }
+
+    /// Encodes the coupon as a single URL-safe base64 string that fits in a QR code: a version
+    /// byte, the recovery id, the 64 byte signature and the signed bytes. Cosigned coupons
+    /// carry the cosigner recovery id and cosignature after the signature. The message hash
+    /// and the public keys are derived from them by [Coupon::from_compact].
+    pub fn to_compact(&self) -> Result<String, CouponError> {
+        let recovery_id = self.recovery_id.ok_or(CouponError::RecoveryError)?;
+        let mut bytes = vec![COMPACT_COUPON_VERSION, recovery_id];
+        bytes.extend_from_slice(&signature_bytes(&self.signature_hex)?);
+        if let Some(cosignature_hex) = &self.cosignature_hex {
+            bytes[0] = COSIGNED_COMPACT_COUPON_VERSION;
+            bytes.push(
+                self.cosigner_recovery_id
+                    .ok_or(CouponError::RecoveryError)?,
+            );
+            bytes.extend_from_slice(&signature_bytes(cosignature_hex)?);
+        }
+        bytes.extend_from_slice(&self.signed_bytes()?);
+        Ok(base64_url.encode(bytes))
+    }
+
+    pub fn from_compact(compact: &str) -> Result<Self, CouponError> {
+        let bytes = base64_url
+            .decode(compact.trim())
             .map_err(|_| CouponError::DeserializationError)?;
+        let (version, bytes) = bytes
+            .split_first()
+            .ok_or(CouponError::DeserializationError)?;
+        let signatures = match *version {
+            COMPACT_COUPON_VERSION => 1,
+            COSIGNED_COMPACT_COUPON_VERSION => 2,
+            version => return Err(CouponError::UnsupportedCompactVersion(version)),
+        };
+        if bytes.len() < signatures * (1 + 64) {
+            return Err(CouponError::DeserializationError);
+        }
+        let (recovery_id, bytes) = (bytes[0], &bytes[1..]);
+        let (signature_bytes, bytes) = bytes.split_at(64);
+        let (cosignature, signed_bytes) = match signatures {
+            2 => {
+                let (cosignature_bytes, signed_bytes) = bytes[1..].split_at(64);
+                (Some((bytes[0], cosignature_bytes)), signed_bytes)
+            }
+            _ => (None, bytes),
+        };
+        let message = if signed_bytes.starts_with(b"{") {
+            String::from_utf8(signed_bytes.to_vec())
+                .map_err(|_| CouponError::DeserializationError)?
+        } else {
+            hex::encode(signed_bytes)
+        };
+
+        let mut coupon = Coupon::new(
+            message,
+            hex::encode(Sha256::digest(signed_bytes)),
+            hex::encode(signature_bytes),
+            String::new(),
+        );
+        coupon.recovery_id = Some(recovery_id);
+        coupon.icp_public_key_hex = coupon.recover_public_key()?;
+        if let Some((cosigner_recovery_id, cosignature_bytes)) = cosignature {
+            let cosignature_hex = hex::encode(cosignature_bytes);
+            coupon.cosigner_public_key_hex = Some(recover_key(
+                signed_bytes,
+                &cosignature_hex,
+                cosigner_recovery_id,
+            )?);
+            coupon.cosignature_hex = Some(cosignature_hex);
+            coupon.cosigner_recovery_id = Some(cosigner_recovery_id);
+        }
+        Ok(coupon)
+    }
+
+    pub fn verify(&self) -> Result<bool, CouponError> {
+        self.verify_domain()?;
+        let burn_id = self.burn_id()?;
+        if read_state(|s| s.is_revoked_coupon(burn_id)) {
+            return Ok(false);
+        }
+        if !read_state(|s| s.is_trusted_public_key(&self.icp_public_key_hex, ic_cdk::api::time())) {
+            return Err(CouponError::UntrustedPublicKey(
+                self.icp_public_key_hex.clone(),
+            ));
+        }
 
-        let message_bytes = self.message.as_bytes();
+        let message_bytes = self.signed_bytes()?;
+        if !verify_signature(
+            &message_bytes,
+            &self.signature_hex,
+            &self.icp_public_key_hex,
+        )? {
+            return Ok(false);
+        }
+        self.verify_cosignature(&message_bytes)
+    }
 
-        for parity in [0u8, 1] {
-            let rec_id = RecoveryId::try_from(parity).unwrap();
-            let recovered_key = VerifyingKey::recover_from_msg(&message_bytes, &signature, rec_id)
-                .map_err(|_| CouponError::RecoveryError)?;
+    // High-value coupons must also be signed by the cosigner derivation path, so that a
+    // single compromised signing path cannot issue them.
+    fn verify_cosignature(&self, message_bytes: &[u8]) -> Result<bool, CouponError> {
+        match (&self.cosignature_hex, &self.cosigner_public_key_hex) {
+            (Some(cosignature_hex), Some(cosigner_public_key_hex)) => {
+                if *cosigner_public_key_hex == self.icp_public_key_hex
+                    || !read_state(|s| {
+                        s.is_trusted_cosigner_key(cosigner_public_key_hex, ic_cdk::api::time())
+                    })
+                {
+                    return Err(CouponError::UntrustedCosignerKey(
+                        cosigner_public_key_hex.clone(),
+                    ));
+                }
+                verify_signature(message_bytes, cosignature_hex, cosigner_public_key_hex)
+            }
+            _ => {
+                let amount = self.signed_amount()?;
+                if read_state(|s| s.requires_cosignature(&amount)) {
+                    return Err(CouponError::MissingCosignature);
+                }
+                Ok(true)
+            }
+        }
+    }
 
-            if recovered_key.eq(&orig_key) {
-                self.recovery_id = Some(parity);
-                return Ok(parity);
+    // Amount paid out by the coupon, as signed into its message.
+    fn signed_amount(&self) -> Result<BigUint, CouponError> {
+        if !self.is_legacy() {
+            let payload = CouponPayload::decode(&self.signed_bytes()?)
+                .map_err(|err| CouponError::InvalidPayload(err.to_string()))?;
+            return Ok(BigUint::from(payload.amount));
+        }
+        let payload: WithdrawalEventWithoutCbor =
+            serde_json::from_str(&self.message).map_err(|_| CouponError::DeserializationError)?;
+        payload
+            .amount
+            .parse()
+            .map_err(|_| CouponError::DeserializationError)
+    }
+
+    fn burn_id(&self) -> Result<u64, CouponError> {
+        if !self.is_legacy() {
+            let payload = CouponPayload::decode(&self.signed_bytes()?)
+                .map_err(|err| CouponError::InvalidPayload(err.to_string()))?;
+            return Ok(payload.burn_id);
+        }
+        let payload: WithdrawalEventWithoutCbor =
+            serde_json::from_str(&self.message).map_err(|_| CouponError::DeserializationError)?;
+        Ok(payload.burn_id)
+    }
+
+    // Rejects coupons that were signed for another minter, network or coupon format.
+    fn verify_domain(&self) -> Result<(), CouponError> {
+        if !self.is_legacy() {
+            let payload = CouponPayload::decode(&self.signed_bytes()?)
+                .map_err(|err| CouponError::InvalidPayload(err.to_string()))?;
+            let expected = CouponDomain::current().chain_tag();
+            if payload.chain_tag != expected {
+                return Err(CouponError::InvalidDomain {
+                    expected: hex::encode(expected),
+                    found: hex::encode(payload.chain_tag),
+                });
             }
+            return Ok(());
         }
 
-        Err(CouponError::ParityRecoveryFailed {
-            signature: self.signature_hex.to_string(),
-            pubkey: self.icp_public_key_hex.to_string(),
-        })
+        let payload: WithdrawalEventWithoutCbor =
+            serde_json::from_str(&self.message).map_err(|_| CouponError::DeserializationError)?;
+
+        let found = match (payload.domain, payload.network, payload.minter_id) {
+            // issued before the domain separator was introduced, only accepted as stored with
+            // the withdrawal so that other coupons cannot be downgraded to this form
+            (None, None, None) => {
+                let stored = read_state(|s| s.withdrawal_redeemed_events.get(&payload.burn_id))
+                    .and_then(|event| event.get_coupon().map(|coupon| coupon.message.clone()));
+                if stored.as_ref() != Some(&self.message) {
+                    return Err(CouponError::UnknownLegacyCoupon(payload.burn_id));
+                }
+                return Ok(());
+            }
+            (domain, network, minter_id) => CouponDomain {
+                domain: domain.unwrap_or_default(),
+                network: network.unwrap_or_default(),
+                minter_id: minter_id.unwrap_or(Principal::anonymous()),
+            },
+        };
+
+        let expected = CouponDomain::current();
+        if found != expected {
+            return Err(CouponError::InvalidDomain {
+                expected: expected.to_string(),
+                found: found.to_string(),
+            });
+        }
+
+        Ok(())
     }
+}
 
-    pub fn verify(&self) -> Result<bool, CouponError> {
-        let signature_bytes =
-            hex::decode(&self.signature_hex).map_err(|_| CouponError::HexDecodingError)?;
-        let pubkey_bytes =
-            hex::decode(&self.icp_public_key_hex).map_err(|_| CouponError::HexDecodingError)?;
-        let message_bytes = self.message.as_bytes();
+// Format of the compact coupon encoding, bumped whenever its layout changes.
+const COMPACT_COUPON_VERSION: u8 = 1;
+// Compact encoding of cosigned coupons, with the cosignature after the signature.
+const COSIGNED_COMPACT_COUPON_VERSION: u8 = 2;
 
-        let signature = Signature::try_from(signature_bytes.as_slice())
-            .map_err(|_| CouponError::DeserializationError)?;
+fn signature_bytes(signature_hex: &str) -> Result<Vec<u8>, CouponError> {
+    let bytes = hex::decode(signature_hex).map_err(|_| CouponError::HexDecodingError)?;
+    if bytes.len() != 64 {
+        return Err(CouponError::DeserializationError);
+    }
+    Ok(bytes)
+}
+
+// Returns the y parity of the signature's R point for the given uncompressed public key.
+fn recovery_id_of(
+    message_bytes: &[u8],
+    signature_hex: &str,
+    public_key_hex: &str,
+) -> Result<u8, CouponError> {
+    let signature = Signature::try_from(signature_bytes(signature_hex)?.as_slice())
+        .map_err(|_| CouponError::DeserializationError)?;
+    let pubkey_bytes = hex::decode(public_key_hex).map_err(|_| CouponError::HexDecodingError)?;
+    let orig_key = VerifyingKey::from_sec1_bytes(&pubkey_bytes)
+        .map_err(|_| CouponError::DeserializationError)?;
+
+    for parity in [0u8, 1] {
+        let rec_id = RecoveryId::try_from(parity).unwrap();
+        let recovered_key = VerifyingKey::recover_from_msg(message_bytes, &signature, rec_id)
+            .map_err(|_| CouponError::RecoveryError)?;
+
+        if recovered_key.eq(&orig_key) {
+            return Ok(parity);
+        }
+    }
+
+    Err(CouponError::ParityRecoveryFailed {
+        signature: signature_hex.to_string(),
+        pubkey: public_key_hex.to_string(),
+    })
+}
+
+// Recovers the uncompressed public key in hex format from a signature and its recovery id.
+fn recover_key(
+    message_bytes: &[u8],
+    signature_hex: &str,
+    recovery_id: u8,
+) -> Result<String, CouponError> {
+    let recovery_id = RecoveryId::try_from(recovery_id).map_err(|_| CouponError::RecoveryError)?;
+    let signature = Signature::try_from(signature_bytes(signature_hex)?.as_slice())
+        .map_err(|_| CouponError::DeserializationError)?;
+
+    let key = VerifyingKey::recover_from_msg(message_bytes, &signature, recovery_id)
+        .map_err(|_| CouponError::RecoveryError)?;
+
+    Ok(hex::encode(key.to_encoded_point(false).as_bytes()))
+}
+
+fn verify_signature(
+    message_bytes: &[u8],
+    signature_hex: &str,
+    public_key_hex: &str,
+) -> Result<bool, CouponError> {
+    let signature_bytes = hex::decode(signature_hex).map_err(|_| CouponError::HexDecodingError)?;
+    let pubkey_bytes = hex::decode(public_key_hex).map_err(|_| CouponError::HexDecodingError)?;
 
-        Ok(VerifyingKey::from_sec1_bytes(&pubkey_bytes)
-            .map_err(|_| CouponError::DeserializationError)?
-            .verify(message_bytes, &signature)
-            .is_ok())
+    let signature = Signature::try_from(signature_bytes.as_slice())
+        .map_err(|_| CouponError::DeserializationError)?;
+    // the minter only issues low-S signatures
+    if signature.normalize_s().is_some() {
+        return Ok(false);
+    }
+
+    Ok(VerifyingKey::from_sec1_bytes(&pubkey_bytes)
+        .map_err(|_| CouponError::DeserializationError)?
+        .verify(message_bytes, &signature)
+        .is_ok())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CouponDomain {
+    domain: String,
+    network: String,
+    minter_id: Principal,
+}
+
+impl CouponDomain {
+    fn current() -> Self {
+        Self {
+            domain: COUPON_DOMAIN_SEPARATOR.to_string(),
+            network: get_network().to_string(),
+            minter_id: ic_cdk::id(),
+        }
+    }
+
+    fn chain_tag(&self) -> [u8; 32] {
+        chain_tag(&self.domain, &self.network, &self.minter_id)
+    }
+}
+
+impl std::fmt::Display for CouponDomain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}/{}", self.domain, self.network, self.minter_id)
     }
 }
 
 impl WithdrawalEvent {
+    // The payload is derived from the burn only, so a regenerated coupon signs the same bytes
+    // as the original one and the Solana program can reject a second redemption of the burn id.
+    fn coupon_payload(&self, domain: &CouponDomain) -> Result<CouponPayload, String> {
+        let amount = self.payout_amount();
+        let amount = amount
+            .0
+            .to_u64()
+            .ok_or_else(|| format!("payout amount {amount} does not fit into u64"))?;
+        let spl = match (&self.spl_mint, &self.to_token_account) {
+            (Some(mint), Some(token_account)) => Some(SplPayout {
+                mint: decode_pubkey(mint).map_err(|err| err.to_string())?,
+                token_account: decode_pubkey(token_account).map_err(|err| err.to_string())?,
+            }),
+            _ => None,
+        };
+
+        Ok(CouponPayload {
+            chain_tag: domain.chain_tag(),
+            burn_id: self.get_burn_id(),
+            amount,
+            recipient: self
+                .to_sol_address
+                .to_bytes()
+                .map_err(|err| err.to_string())?,
+            expiry: NO_EXPIRY,
+            spl,
+        })
+    }
+
     pub async fn to_coupon(&self) -> Result<Coupon, WithdrawError> {
         match self.sign_with_ecdsa().await {
             Ok((serialized_coupon, message_hash, signature_hex)) => {
-                let icp_public_key_hex = read_state(|s| s.uncompressed_public_key());
+                let icp_public_key_hex = read_state(|s| s.uncompressed_public_key())
+                    .map_err(WithdrawError::EcdsaKeyUnavailable)?;
 
                 let mut response = Coupon::new(
                     serialized_coupon,
@@ -374,21 +1409,23 @@ impl WithdrawalEvent {
                     icp_public_key_hex,
                 );
 
-                let res = match response.y_parity() {
-                    Ok(_) => Ok(response),
-                    Err(err) => {
-                        return Err(WithdrawError::CouponError {
-                            burn_id: self.get_burn_id(),
-                            err,
-                        })
-                    }
-                };
+                if let Err(err) = response.y_parity() {
+                    return Err(WithdrawError::CouponError {
+                        burn_id: self.get_burn_id(),
+                        err,
+                    });
+                }
+
+                if read_state(|s| s.requires_cosignature(&self.payout_amount().0)) {
+                    response = self.cosign(response).await?;
+                }
+                let res = Ok(response);
 
                 _ = res
                     .clone()
                     .unwrap()
                     .verify()
-                    .map(|a| ic_canister_log::log!(DEBUG, "{a}"));
+                    .map(|a| crate::logs::log!(DEBUG, "{a}"));
 
                 res
             }
@@ -400,25 +1437,76 @@ impl WithdrawalEvent {
         }
     }
 
+    // Adds the signature of the cosigner derivation path to the coupon.
+    async fn cosign(&self, coupon: Coupon) -> Result<Coupon, WithdrawError> {
+        let burn_id = self.get_burn_id();
+        let signing_failed = |msg: String| WithdrawError::SigningWithEcdsaFailed {
+            burn_id,
+            code: RejectionCode::CanisterError,
+            msg,
+        };
+        let cosigner_public_key_hex = read_state(|s| s.cosigner_public_key_hex.clone()).ok_or(
+            WithdrawError::EcdsaKeyUnavailable(EcdsaKeyError::NotInitialized),
+        )?;
+        let hashed_coupon: [u8; 32] = hex::decode(&coupon.message_hash)
+            .ok()
+            .and_then(|hash| hash.try_into().ok())
+            .ok_or_else(|| signing_failed("invalid coupon message hash".to_string()))?;
+        let key_name = read_state(|s| s.ecdsa_key_name.clone());
+
+        let cosignature =
+            match mutate_state(|s| s.cached_cosignature(burn_id, &key_name, &hashed_coupon)) {
+                Some(cosignature) => cosignature,
+                None => {
+                    let signature =
+                        escda::sign_with(&key_name, get_cosigner_derivation_path(), hashed_coupon)
+                            .await
+                            .map_err(signing_failed)?;
+                    let cosignature = normalize_low_s(&signature)
+                        .map_err(|err| signing_failed(err.to_string()))?;
+                    mutate_state(|s| {
+                        if let Some(cached) = s.signature_cache.get_mut(&burn_id) {
+                            cached.cosignature = Some(cosignature.clone());
+                        }
+                    });
+                    cosignature
+                }
+            };
+
+        coupon
+            .with_cosignature(hex::encode(cosignature), cosigner_public_key_hex)
+            .map_err(|err| WithdrawError::CouponError { burn_id, err })
+    }
+
     async fn sign_with_ecdsa(&self) -> Result<(String, String, String), (RejectionCode, String)> {
-        // Serialize the coupon
-        let serialized_coupon: String = serde_json::to_string(&WithdrawalEventWithoutCbor {
-            from_icp_address: self.from_icp_address.clone(),
-            to_sol_address: self.to_sol_address.clone(),
-            amount: self.amount.to_string(),
-            burn_id: self.get_burn_id(),
-            burn_timestamp: self.get_burn_timestamp().unwrap(),
-            icp_burn_block_index: self.get_icp_burn_block_index().unwrap(),
-        })
-        .unwrap();
+        let payload = self
+            .coupon_payload(&CouponDomain::current())
+            .map_err(|err| (RejectionCode::CanisterError, err))?;
+        crate::logs::log!(DEBUG, "{payload:?}");
 
-        ic_canister_log::log!(DEBUG, "{serialized_coupon}");
+        // Serialize the coupon, the message holds the hex encoded payload
+        let payload_bytes = payload.encode();
+        let serialized_coupon = hex::encode(&payload_bytes);
 
         // Hash the serialized coupon using SHA-256
         let mut hasher = Sha256::new();
-        hasher.update(serialized_coupon.clone());
+        hasher.update(&payload_bytes);
         let hashed_coupon = hasher.finalize().to_vec();
 
+        let burn_id = self.get_burn_id();
+        let key_name = read_state(|s| s.ecdsa_key_name.clone());
+
+        // the message is deterministic, a signature obtained earlier can be reused
+        if let Some(signature) =
+            mutate_state(|s| s.cached_signature(burn_id, &key_name, &hashed_coupon))
+        {
+            return Ok((
+                serialized_coupon,
+                hex::encode(hashed_coupon),
+                hex::encode(signature),
+            ));
+        }
+
         let args = SignWithEcdsaArgument {
             message_hash: hashed_coupon.clone(),
             derivation_path: get_derivation_path()
@@ -427,18 +1515,34 @@ impl WithdrawalEvent {
                 .collect(),
             key_id: EcdsaKeyId {
                 curve: EcdsaCurve::Secp256k1,
-                name: read_state(|s| s.ecdsa_key_name.clone()),
+                name: key_name.clone(),
             },
         };
+        mutate_state(|s| s.record_ecdsa_sign_call(SIGN_WITH_ECDSA_CYCLES));
         let response: Result<(SignWithEcdsaResponse,), (RejectionCode, String)> =
             sign_with_ecdsa(args).await;
 
         match response {
-            Ok(res) => Ok((
-                serialized_coupon,
-                hex::encode(hashed_coupon),
-                hex::encode(&res.0.signature),
-            )),
+            Ok(res) => {
+                let signature = normalize_low_s(&res.0.signature)
+                    .map_err(|err| (RejectionCode::CanisterError, err.to_string()))?;
+                mutate_state(|s| {
+                    s.signature_cache.insert(
+                        burn_id,
+                        CachedSignature {
+                            key_name,
+                            message_hash: hashed_coupon.clone(),
+                            signature: signature.clone(),
+                            cosignature: None,
+                        },
+                    )
+                });
+                Ok((
+                    serialized_coupon,
+                    hex::encode(hashed_coupon),
+                    hex::encode(&signature),
+                ))
+            }
             Err((code, msg)) => Err((code, msg)),
         }
     }
@@ -454,12 +1558,29 @@ pub struct UserWithdrawInfo {
     pub burn_ids: Vec<u64>,
 }
 
-#[derive(Serialize)]
+/// JSON message of the coupons issued before [CouponPayload], still accepted by `verify`.
+#[derive(Serialize, Deserialize)]
 pub struct WithdrawalEventWithoutCbor {
+    // domain separation: tag, network and minter canister the coupon is bound to, absent
+    // from the coupons issued before the separator was introduced
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub network: Option<String>,
+    #[serde(default)]
+    pub minter_id: Option<Principal>,
+    // unique per burn, prevents a second redemption of the same withdrawal
+    #[serde(default)]
+    pub nonce: Option<String>,
     pub from_icp_address: Principal,
-    pub to_sol_address: String,
+    pub to_sol_address: SolanaAddress,
     pub amount: String,
     pub burn_id: u64,
     pub burn_timestamp: u64,
     pub icp_burn_block_index: u64,
+    // only present for SPL withdrawals, keeps the native SOL coupon layout unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spl_mint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_token_account: Option<String>,
 }
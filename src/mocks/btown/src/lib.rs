@@ -0,0 +1,46 @@
+//! BTown NFT canister stub recording the deposits minted by the minter.
+
+use ic_cdk_macros::{query, update};
+use serde_bytes::ByteBuf;
+use serde_cbor::Value;
+use std::cell::RefCell;
+
+thread_local! {
+    static MINTED: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+/// Accepts the CBOR encoded deposit events of the minter, deposits are identified by
+/// their Solana signature and minted once.
+#[update]
+fn deposit_coins_from_solana(events: ByteBuf) -> Vec<Result<String, (String, String)>> {
+    let events: Vec<Value> =
+        serde_cbor::from_slice(&events).expect("the minter should send a CBOR array of events");
+
+    events
+        .iter()
+        .map(|event| {
+            let sol_sig = match event {
+                Value::Map(fields) => fields.iter().find_map(|(key, value)| match (key, value) {
+                    (Value::Text(key), Value::Text(sig)) if key == "sol_sig" => Some(sig.clone()),
+                    _ => None,
+                }),
+                _ => None,
+            }
+            .expect("every deposit event should have a sol_sig");
+
+            MINTED.with(|minted| {
+                let mut minted = minted.borrow_mut();
+                if !minted.contains(&sol_sig) {
+                    minted.push(sol_sig.clone());
+                }
+            });
+            Ok(sol_sig)
+        })
+        .collect()
+}
+
+/// Returns the signatures of the minted deposits, in the order of their first mint.
+#[query]
+fn get_minted_deposits() -> Vec<String> {
+    MINTED.with(|minted| minted.borrow().clone())
+}
@@ -0,0 +1,100 @@
+//! Ledger stub holding burn blocks in the ICRC-3 format read by the minter.
+
+use candid::{CandidType, Deserialize, Int, Nat, Principal};
+use ic_cdk_macros::{query, update};
+use serde_bytes::ByteBuf;
+use std::cell::RefCell;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum Value {
+    Blob(ByteBuf),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksArgs {
+    pub start: Nat,
+    pub length: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BlockWithId {
+    pub id: Nat,
+    pub block: Value,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchivedBlocks {
+    pub args: Vec<GetBlocksArgs>,
+    pub callback: candid::Func,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksResult {
+    pub log_length: Nat,
+    pub blocks: Vec<BlockWithId>,
+    pub archived_blocks: Vec<ArchivedBlocks>,
+}
+
+thread_local! {
+    static BLOCKS: RefCell<Vec<Value>> = RefCell::new(Vec::new());
+    static TOTAL_SUPPLY: RefCell<Nat> = RefCell::new(Nat::from(0u8));
+}
+
+/// Appends a burn of `amount` tokens of `from` and returns its block index.
+#[update]
+fn burn(from: Principal, amount: Nat, memo: ByteBuf) -> u64 {
+    let tx = Value::Map(vec![
+        ("op".to_string(), Value::Text("burn".to_string())),
+        (
+            "from".to_string(),
+            Value::Array(vec![Value::Blob(ByteBuf::from(from.as_slice().to_vec()))]),
+        ),
+        ("amt".to_string(), Value::Nat(amount)),
+        ("memo".to_string(), Value::Blob(memo)),
+    ]);
+    let block = Value::Map(vec![
+        ("ts".to_string(), Value::Nat(Nat::from(ic_cdk::api::time()))),
+        ("tx".to_string(), tx),
+    ]);
+
+    BLOCKS.with(|blocks| {
+        let mut blocks = blocks.borrow_mut();
+        blocks.push(block);
+        blocks.len() as u64 - 1
+    })
+}
+
+#[query]
+fn icrc3_get_blocks(args: Vec<GetBlocksArgs>) -> GetBlocksResult {
+    BLOCKS.with(|blocks| {
+        let blocks = blocks.borrow();
+        let in_range = |index: usize| {
+            let index = Nat::from(index);
+            args.iter()
+                .any(|arg| arg.start <= index && index < arg.start.clone() + arg.length.clone())
+        };
+        GetBlocksResult {
+            log_length: Nat::from(blocks.len()),
+            blocks: blocks
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| in_range(*index))
+                .map(|(index, block)| BlockWithId {
+                    id: Nat::from(index),
+                    block: block.clone(),
+                })
+                .collect(),
+            archived_blocks: vec![],
+        }
+    })
+}
+
+#[query]
+fn icrc1_total_supply() -> Nat {
+    TOTAL_SUPPLY.with(|supply| supply.borrow().clone())
+}